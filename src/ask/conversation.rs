@@ -0,0 +1,178 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::{ConfigManager, LlmConfig};
+
+/// Earlier questions and answers sent along with a follow-up; older ones are forgotten
+const MAX_TURNS: usize = 6;
+/// A question asked after this long starts a new conversation
+const SESSION_IDLE_SECS: u64 = 30 * 60;
+/// Answers are cut to this many characters when rewriting, which only needs their gist
+const REWRITE_ANSWER_CHARS: usize = 600;
+const REWRITE_TIMEOUT_SECS: u64 = 10;
+const REWRITE_PROMPT: &str = "Rewrite the last question of this conversation about a codebase \
+as a standalone search query, replacing pronouns and references to earlier questions with what \
+they refer to. Reply with the query only.";
+
+/// A question asked in ask mode and the answer it got.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Turn {
+    pub question: String,
+    /// What was searched for, the question made standalone
+    pub query: String,
+    pub answer: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Session {
+    turns: Vec<Turn>,
+    /// Seconds since the epoch of the last answer
+    updated: u64,
+}
+
+/// The questions asked about a single project root so far in this session, shared by
+/// `sema ask` and `?` queries in the TUI.
+pub struct Conversation {
+    path: PathBuf,
+    session: Session,
+    /// Where a conversation file that couldn't be read was moved when this one started
+    set_aside: Option<PathBuf>,
+}
+
+impl Conversation {
+    /// The conversation of the project at `project_root`. A file that can't be parsed is
+    /// moved aside, as `set_aside` tells, and the conversation starts afresh.
+    pub fn open(project_root: &Path) -> Result<Self> {
+        let path = ConfigManager::get_project_dir(project_root).join("conversation.json");
+
+        let mut set_aside = None;
+        let session: Session = if path.exists() {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read conversation: {:?}", path))?;
+            match serde_json::from_str(&content) {
+                Ok(session) => session,
+                Err(_) => {
+                    let aside = path.with_extension("json.corrupt");
+                    fs::rename(&path, &aside)
+                        .with_context(|| format!("Failed to move aside {:?}", path))?;
+                    set_aside = Some(aside);
+                    Session::default()
+                }
+            }
+        } else {
+            Session::default()
+        };
+        let session = if now().saturating_sub(session.updated) > SESSION_IDLE_SECS {
+            Session::default()
+        } else {
+            session
+        };
+
+        Ok(Self {
+            path,
+            session,
+            set_aside,
+        })
+    }
+
+    /// Where an unreadable conversation file was moved when this conversation started.
+    pub fn set_aside(&self) -> Option<&Path> {
+        self.set_aside.as_deref()
+    }
+
+    pub fn turns(&self) -> &[Turn] {
+        &self.session.turns
+    }
+
+    pub fn record(&mut self, turn: Turn) -> Result<()> {
+        self.session.turns.push(turn);
+        let excess = self.session.turns.len().saturating_sub(MAX_TURNS);
+        self.session.turns.drain(..excess);
+        self.session.updated = now();
+        self.save()
+    }
+
+    /// Forgets the questions so far, so the next one is read on its own.
+    pub fn clear(&mut self) -> Result<()> {
+        self.session = Session::default();
+        if self.path.exists() {
+            fs::remove_file(&self.path)
+                .with_context(|| format!("Failed to remove conversation: {:?}", self.path))?;
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let content = serde_json::to_string_pretty(&self.session)
+            .context("Failed to serialize conversation")?;
+        fs::write(&self.path, content)
+            .with_context(|| format!("Failed to write conversation: {:?}", self.path))?;
+
+        Ok(())
+    }
+}
+
+/// Turns a follow-up like "what about the writer side?" into a query that finds the right
+/// chunks without the conversation before it. The first question of a conversation is
+/// searched for as asked.
+pub async fn standalone_query(llm: &LlmConfig, turns: &[Turn], question: &str) -> Result<String> {
+    if turns.is_empty() {
+        return Ok(question.to_string());
+    }
+
+    let mut transcript = format!("{}\n\n", REWRITE_PROMPT);
+    for turn in turns {
+        let answer: String = turn.answer.chars().take(REWRITE_ANSWER_CHARS).collect();
+        transcript.push_str(&format!(
+            "Question: {}\nAnswer: {}\n\n",
+            turn.question, answer
+        ));
+    }
+    transcript.push_str(&format!("Question: {}", question));
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(REWRITE_TIMEOUT_SECS))
+        .build()?;
+    let mut request = client.post(&llm.endpoint).json(&json!({
+        "model": llm.model,
+        "messages": [{ "role": "user", "content": transcript }],
+        "stream": false,
+    }));
+    if let Some(api_key) = &llm.api_key {
+        request = request.bearer_auth(api_key);
+    }
+
+    let response: serde_json::Value = request
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach {}", llm.endpoint))?
+        .error_for_status()?
+        .json()
+        .await?;
+    let query = response["choices"][0]["message"]["content"]
+        .as_str()
+        .context("LLM response did not contain a completion")?
+        .trim()
+        .trim_matches('"')
+        .to_string();
+
+    if query.is_empty() {
+        anyhow::bail!("LLM returned an empty completion");
+    }
+
+    Ok(query)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
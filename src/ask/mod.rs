@@ -0,0 +1,326 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde_json::json;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{Config, LlmConfig};
+use crate::once;
+use crate::storage::secrets;
+use crate::types::Chunk;
+
+pub mod conversation;
+
+use conversation::{Conversation, Turn};
+
+const CONNECT_TIMEOUT_SECS: u64 = 10;
+/// Local models can think for a while over a long prompt before the first word arrives
+const READ_TIMEOUT_SECS: u64 = 120;
+const ANSWER_PROMPT: &str = "Answer the question about a codebase using only the numbered \
+sources below. Cite the sources each statement relies on as [1], [2] and so on. If the sources \
+don't answer the question, say so rather than guessing.";
+
+/// `[2]` or `[1, 3]`; `[1][2]` is two of them
+static CITATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[(\d+(?:\s*,\s*\d+)*)\]").expect("citation pattern is valid"));
+
+/// A sentence of an answer with the sources it cites.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Citation {
+    /// The sentence without its citation markers
+    pub sentence: String,
+    /// 1-based source numbers, in the order cited
+    pub sources: Vec<usize>,
+}
+
+/// The sentences of an answer that cite sources, each with the numbers it cites. Markers
+/// left after the closing full stop, as in `Retries back off. [2]`, count for the sentence
+/// before them.
+pub fn citations(answer: &str) -> Vec<Citation> {
+    let mut citations: Vec<Citation> = Vec::new();
+    for sentence in sentences(answer) {
+        let sources: Vec<usize> = CITATION
+            .captures_iter(sentence)
+            .flat_map(|captures| {
+                captures[1]
+                    .split(',')
+                    .filter_map(|number| number.trim().parse().ok())
+                    .collect::<Vec<usize>>()
+            })
+            .filter(|number| *number > 0)
+            .collect();
+        if sources.is_empty() {
+            continue;
+        }
+
+        let text = CITATION.replace_all(sentence, "");
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let text = text.trim_end_matches([' ', '.', ',', ';', ':']).to_string();
+        match citations.last_mut() {
+            Some(previous) if text.is_empty() => previous.sources.extend(sources),
+            _ => citations.push(Citation {
+                sentence: text,
+                sources,
+            }),
+        }
+    }
+    for citation in &mut citations {
+        let mut seen = Vec::new();
+        citation.sources.retain(|number| {
+            let first = !seen.contains(number);
+            seen.push(*number);
+            first
+        });
+    }
+    citations
+}
+
+/// Splits at line breaks and after `.`, `!` or `?` followed by whitespace.
+fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let end = match c {
+            '\n' => Some(index),
+            '.' | '!' | '?' if chars.peek().is_some_and(|(_, next)| next.is_whitespace()) => {
+                Some(index + c.len_utf8())
+            }
+            _ => None,
+        };
+        if let Some(end) = end {
+            if !text[start..end].trim().is_empty() {
+                sentences.push(&text[start..end]);
+            }
+            start = end;
+        }
+    }
+    if !text[start..].trim().is_empty() {
+        sentences.push(&text[start..]);
+    }
+    sentences
+}
+
+/// A source as cited under an answer: its lines, relative to the project.
+pub fn source_label(chunk: &Chunk, root: &Path) -> String {
+    let path = chunk
+        .file_path
+        .strip_prefix(root)
+        .unwrap_or(&chunk.file_path);
+    format!("{}:{}-{}", path.display(), chunk.start_line, chunk.end_line)
+}
+
+/// The first `limit` of `chunks` to send the model as sources with the number passed
+/// over: chunks that look like they hold a credential are kept from the endpoint, as
+/// from the vector index, unless `[indexing] embed_secrets` is on.
+pub fn select_sources(
+    chunks: impl IntoIterator<Item = Chunk>,
+    limit: usize,
+    embed_secrets: bool,
+) -> (Vec<Chunk>, usize) {
+    let mut sources = Vec::new();
+    let mut withheld = 0;
+    for chunk in chunks {
+        if sources.len() == limit {
+            break;
+        }
+        if !embed_secrets && secrets::contains_secret(&chunk.content) {
+            withheld += 1;
+        } else {
+            sources.push(chunk);
+        }
+    }
+    (sources, withheld)
+}
+
+fn prompt(question: &str, sources: &[Chunk], root: &Path) -> String {
+    let mut prompt = format!("{}\n\n", ANSWER_PROMPT);
+    for (index, chunk) in sources.iter().enumerate() {
+        prompt.push_str(&format!(
+            "[{}] {}\n```\n{}\n```\n\n",
+            index + 1,
+            source_label(chunk, root),
+            chunk.content
+        ));
+    }
+    prompt.push_str(&format!("Question: {}", question));
+    prompt
+}
+
+/// Sends the question with its numbered sources to the `[llm]` endpoint, after the
+/// earlier turns of the conversation, and hands the answer to `on_text` piece by piece as
+/// it streams in. Servers that ignore `stream` and reply in one piece work too. Returns
+/// early, without an error, once `cancel` fires.
+pub async fn stream_answer(
+    llm: &LlmConfig,
+    history: &[Turn],
+    question: &str,
+    sources: &[Chunk],
+    root: &Path,
+    cancel: &CancellationToken,
+    mut on_text: impl FnMut(&str),
+) -> Result<()> {
+    let client = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS))
+        .read_timeout(Duration::from_secs(READ_TIMEOUT_SECS))
+        .build()?;
+
+    let mut messages = Vec::new();
+    for turn in history {
+        messages.push(json!({ "role": "user", "content": turn.question }));
+        messages.push(json!({ "role": "assistant", "content": turn.answer }));
+    }
+    messages.push(json!({ "role": "user", "content": prompt(question, sources, root) }));
+
+    let mut request = client.post(&llm.endpoint).json(&json!({
+        "model": llm.model,
+        "messages": messages,
+        "stream": true,
+    }));
+    if let Some(api_key) = &llm.api_key {
+        request = request.bearer_auth(api_key);
+    }
+
+    let mut response = tokio::select! {
+        response = request.send() => response
+            .with_context(|| format!("Failed to reach {}", llm.endpoint))?
+            .error_for_status()?,
+        _ = cancel.cancelled() => return Ok(()),
+    };
+
+    let streamed = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("text/event-stream"));
+    if !streamed {
+        let reply: serde_json::Value = response.json().await?;
+        let answer = reply["choices"][0]["message"]["content"]
+            .as_str()
+            .context("LLM response did not contain a completion")?;
+        on_text(answer);
+        return Ok(());
+    }
+
+    // Server-sent events, one `data: {...}` line per piece of the answer
+    let mut pending = Vec::new();
+    loop {
+        let received = tokio::select! {
+            received = response.chunk() => received?,
+            _ = cancel.cancelled() => return Ok(()),
+        };
+        let Some(received) = received else {
+            return Ok(());
+        };
+        pending.extend_from_slice(&received);
+
+        while let Some(end) = pending.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim().strip_prefix("data:") else {
+                continue;
+            };
+            let data = data.trim();
+            if data == "[DONE]" {
+                return Ok(());
+            }
+            let event: serde_json::Value =
+                serde_json::from_str(data).context("Malformed event in the LLM response")?;
+            if let Some(text) = event["choices"][0]["delta"]["content"].as_str() {
+                on_text(text);
+            }
+        }
+    }
+}
+
+/// Answers `question` from the index of `root`, printing the answer as it streams in and
+/// then the sources it was given to cite. A follow-up to the questions asked recently is
+/// read in their light, unless `new_conversation` starts afresh.
+pub async fn run(
+    root: PathBuf,
+    config: Config,
+    question: &str,
+    new_conversation: bool,
+) -> Result<()> {
+    let limit = config.llm.answer_sources.max(1);
+    let llm = config.llm.clone();
+
+    let mut conversation = Conversation::open(&root)?;
+    if let Some(aside) = conversation.set_aside() {
+        eprintln!(
+            "Warning: starting a new conversation; the unreadable one was moved to {}",
+            aside.display()
+        );
+    }
+    if new_conversation {
+        conversation.clear()?;
+    }
+    let query = match conversation::standalone_query(&llm, conversation.turns(), question).await {
+        Ok(query) => query,
+        Err(e) => {
+            eprintln!("Searching for the question as asked: {:#}", e);
+            question.to_string()
+        }
+    };
+    if query != question {
+        eprintln!("Searching for: {}", query);
+    }
+
+    let embed_secrets = config.indexing.embed_secrets;
+    // Withheld sources are made up from the next matches
+    let results = once::search(
+        &root,
+        config,
+        &[query.clone()],
+        limit * 2,
+        Vec::new(),
+        false,
+    )
+    .await?;
+    let (sources, withheld) = select_sources(
+        results.into_iter().flatten().map(|(chunk, _)| chunk),
+        limit,
+        embed_secrets,
+    );
+    if withheld > 0 {
+        eprintln!(
+            "Withheld {} matching source(s) that look like they hold credentials; set \
+             [indexing] embed_secrets to send them",
+            withheld
+        );
+    }
+    if sources.is_empty() {
+        anyhow::bail!("Nothing in the index matches the question");
+    }
+
+    let mut stdout = std::io::stdout();
+    let mut answer = String::new();
+    stream_answer(
+        &llm,
+        conversation.turns(),
+        question,
+        &sources,
+        &root,
+        &CancellationToken::new(),
+        |text| {
+            answer.push_str(text);
+            let _ = write!(stdout, "{}", text);
+            let _ = stdout.flush();
+        },
+    )
+    .await?;
+    conversation.record(Turn {
+        question: question.to_string(),
+        query,
+        answer,
+    })?;
+
+    println!("\n\nSources:");
+    for (index, chunk) in sources.iter().enumerate() {
+        println!("[{}] {}", index + 1, source_label(chunk, &root));
+    }
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{Config, ConfigManager};
+use crate::crawler::FileCrawler;
+use crate::storage::{SharedStorage, StorageManager};
+use crate::types::{CrawlerConfig, QueryMode};
+
+pub mod models;
+
+/// Chunks fetched per query; several usually come from the same file, so this leaves room
+/// for `k` distinct files
+const CHUNKS_PER_FILE: usize = 4;
+
+/// One labelled query of a qrels file: the files that answer it, relative to the project
+/// root, e.g. `{"query": "retry with backoff", "files": ["src/net/retry.rs"]}`.
+#[derive(Debug, Deserialize)]
+pub struct Judgement {
+    pub query: String,
+    pub files: Vec<PathBuf>,
+}
+
+/// Retrieval quality of one query mode over every judged query.
+#[derive(Debug, Default)]
+pub struct ModeScore {
+    /// Mean reciprocal rank of the first relevant file within the top `k`
+    pub mrr: f64,
+    /// Mean share of each query's relevant files found in the top `k`
+    pub recall: f64,
+    /// The index that actually answered, when the mode fell back to keyword search
+    pub answered_by: Option<QueryMode>,
+}
+
+pub fn load_qrels(path: &Path) -> Result<Vec<Judgement>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let judgements: Vec<Judgement> = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    if judgements.is_empty() {
+        anyhow::bail!("{} has no judged queries", path.display());
+    }
+    Ok(judgements)
+}
+
+/// Brings the index for `root` up to date, runs every judged query in each mode and
+/// prints MRR and recall at `k` per mode.
+pub async fn run(root: PathBuf, config: Config, qrels: &Path, k: usize) -> Result<()> {
+    let judgements = load_qrels(qrels)?;
+    let k = k.max(1);
+
+    let storage: SharedStorage = Arc::new(Mutex::new(
+        StorageManager::new(&ConfigManager::get_data_dir(), &config).await?,
+    ));
+    let cancel = CancellationToken::new();
+    let crawl_started = Instant::now();
+    let (files, stats) = FileCrawler::new(CrawlerConfig::from(&config.general))
+        .crawl_directory(&root, cancel.clone())
+        .await?;
+    storage.lock().await.record_crawl(crawl_started.elapsed());
+    eprintln!("Indexing {} files...", files.len());
+    StorageManager::process_and_index_files(&storage, files, stats.size_limits, &cancel).await?;
+    if let Some(profile) = storage.lock().await.index_profile() {
+        eprintln!("{}", profile.report());
+    }
+
+    println!(
+        "{} queries from {}, k = {}",
+        judgements.len(),
+        qrels.display(),
+        k
+    );
+    println!("{:<10} {:>8} {:>10}", "mode", "MRR", "recall@k");
+    for mode in [QueryMode::Keyword, QueryMode::Semantic, QueryMode::Hybrid] {
+        let score = score_mode(&storage, &root, &judgements, mode, k).await?;
+        let fallback = match score.answered_by {
+            Some(answered_by) => format!("  (answered by {} search)", answered_by.label()),
+            None => String::new(),
+        };
+        println!(
+            "{:<10} {:>8.3} {:>10.3}{}",
+            mode.label(),
+            score.mrr,
+            score.recall,
+            fallback
+        );
+    }
+
+    Ok(())
+}
+
+async fn score_mode(
+    storage: &SharedStorage,
+    root: &Path,
+    judgements: &[Judgement],
+    mode: QueryMode,
+    k: usize,
+) -> Result<ModeScore> {
+    let mut storage = storage.lock().await;
+    let mut score = ModeScore::default();
+
+    for judgement in judgements {
+        let query = format!("{}{}", mode.prefix(), judgement.query);
+        let answered_by = storage.answered_by(&query);
+        if answered_by != mode {
+            score.answered_by = Some(answered_by);
+        }
+
+        let results = storage
+            .search(&query, k * CHUNKS_PER_FILE, &CancellationToken::new())
+            .await?;
+        let mut ranked_files: Vec<PathBuf> = Vec::new();
+        for (chunk, _) in results {
+            let file = chunk
+                .file_path
+                .strip_prefix(root)
+                .unwrap_or(&chunk.file_path)
+                .to_path_buf();
+            if !ranked_files.contains(&file) {
+                ranked_files.push(file);
+            }
+        }
+        ranked_files.truncate(k);
+
+        let relevant: HashSet<&PathBuf> = judgement.files.iter().collect();
+        if let Some(rank) = ranked_files.iter().position(|file| relevant.contains(file)) {
+            score.mrr += 1.0 / (rank + 1) as f64;
+        }
+        if !relevant.is_empty() {
+            let found = ranked_files
+                .iter()
+                .filter(|file| relevant.contains(file))
+                .count();
+            score.recall += found as f64 / relevant.len() as f64;
+        }
+    }
+
+    score.mrr /= judgements.len() as f64;
+    score.recall /= judgements.len() as f64;
+    Ok(score)
+}
@@ -0,0 +1,321 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{Config, ModelCandidate, SemanticConfig};
+use crate::crawler::FileCrawler;
+use crate::semantic::embeddings::{EMBEDDING_DIM, MODEL_ID, VectorStore};
+use crate::storage::processor::{FileProcessor, ProcessedItem};
+use crate::types::{Chunk, CrawlerConfig, ProcessingConfig};
+
+const SAMPLE_CHANNEL_CAPACITY: usize = 256;
+/// With fewer titled chunks the retrieval scores say more about the sample than the model
+const MIN_TITLED_CHUNKS: usize = 10;
+
+/// A model's results on the sampled chunks.
+#[derive(Debug)]
+pub struct ModelScore {
+    pub dimensions: usize,
+    pub load_time: Duration,
+    pub chunks_per_second: f64,
+    /// Vectors for the whole project, extrapolated from the sample
+    pub index_bytes: u64,
+    /// Mean reciprocal rank of each titled chunk when searching for its title
+    pub mrr: Option<f64>,
+    /// Share of titled chunks ranked first for their title
+    pub top1: Option<f64>,
+}
+
+/// Chunks of an evenly spread sample of the project, embedded by each model in turn.
+struct Sample {
+    chunks: Vec<Chunk>,
+    /// Function, type or heading names, each with the chunk it titles
+    titles: Vec<(String, usize)>,
+    /// Chunks the whole project would produce, going by the sampled files
+    expected_chunks: u64,
+}
+
+/// Embeds a sample of the project at `root` with the configured model, the
+/// `[[semantic.candidates]]` and the models in `model_dirs`, and prints a table comparing
+/// them.
+pub async fn run(
+    root: PathBuf,
+    config: Config,
+    sample_size: usize,
+    model_dirs: &[PathBuf],
+) -> Result<()> {
+    let (files, _) = FileCrawler::new(CrawlerConfig::from(&config.general))
+        .crawl_directory(&root, CancellationToken::new())
+        .await?;
+    if files.is_empty() {
+        anyhow::bail!("No files to sample in {}", root.display());
+    }
+
+    let sample = sample(files, &config, sample_size.max(1)).await?;
+    if sample.chunks.is_empty() {
+        anyhow::bail!("No chunks to sample in {}", root.display());
+    }
+    println!(
+        "{} chunks sampled, {} of them titled by a symbol or heading; about {} chunks in the project",
+        sample.chunks.len(),
+        sample.titles.len(),
+        sample.expected_chunks
+    );
+
+    let models = models(&config.semantic, model_dirs);
+    let name_width = models
+        .iter()
+        .map(|(name, _)| name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("model".len());
+    println!(
+        "{:<name_width$} {:>5} {:>7} {:>9} {:>11} {:>6} {:>6}",
+        "model", "dims", "load", "chunks/s", "index size", "MRR", "top-1",
+    );
+
+    let mut other_dimensions = false;
+    for (name, semantic) in models {
+        let score = match bench_model(semantic, &sample).await {
+            Ok(score) => score,
+            Err(e) => {
+                println!("{:<name_width$} failed: {:#}", name, e);
+                continue;
+            }
+        };
+        let quality = |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{:.3}", v));
+        let marker = if score.dimensions == EMBEDDING_DIM {
+            ""
+        } else {
+            other_dimensions = true;
+            " *"
+        };
+        println!(
+            "{:<name_width$} {:>5} {:>6.1}s {:>9.1} {:>8.1} MB {:>6} {:>6}{}",
+            name,
+            score.dimensions,
+            score.load_time.as_secs_f64(),
+            score.chunks_per_second,
+            score.index_bytes as f64 / 1_048_576.0,
+            quality(score.mrr),
+            quality(score.top1),
+            marker
+        );
+    }
+
+    if sample.titles.len() < MIN_TITLED_CHUNKS {
+        println!(
+            "\nToo few titled chunks to score retrieval; sample more with --sample or add source files"
+        );
+    }
+    if other_dimensions {
+        println!(
+            "\n* The index stores {}-dimensional vectors, so this model can be compared but not indexed with",
+            EMBEDDING_DIM
+        );
+    }
+    Ok(())
+}
+
+/// The configured model first, then the candidates, each with the settings to load it.
+fn models(semantic: &SemanticConfig, model_dirs: &[PathBuf]) -> Vec<(String, SemanticConfig)> {
+    let configured = match &semantic.model_dir {
+        Some(model_dir) => directory_name(model_dir),
+        None => MODEL_ID.to_string(),
+    };
+    let mut models = vec![(configured, semantic.clone())];
+
+    let from_arguments = model_dirs.iter().map(|model_dir| ModelCandidate {
+        model_dir: model_dir.clone(),
+        ..ModelCandidate::default()
+    });
+    for candidate in semantic.candidates.iter().cloned().chain(from_arguments) {
+        let name = if candidate.name.is_empty() {
+            directory_name(&candidate.model_dir)
+        } else {
+            candidate.name
+        };
+        models.push((
+            name,
+            SemanticConfig {
+                model_dir: Some(candidate.model_dir),
+                document_prompt: candidate.document_prompt,
+                query_prompt: candidate.query_prompt,
+                candidates: Vec::new(),
+                ..semantic.clone()
+            },
+        ));
+    }
+    models
+}
+
+fn directory_name(path: &Path) -> String {
+    path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
+
+/// Chunks up to `size` files spread evenly over the project and keeps `size` chunks spread
+/// evenly over those.
+async fn sample(files: Vec<PathBuf>, config: &Config, size: usize) -> Result<Sample> {
+    let total_files = files.len();
+    let stride = (total_files / size).max(1);
+    let sampled_files: Vec<PathBuf> = files.into_iter().step_by(stride).take(size).collect();
+
+    let processing_config = ProcessingConfig::from(config);
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(SAMPLE_CHANNEL_CAPACITY);
+    let producer = tokio::task::spawn_blocking(move || {
+        FileProcessor::stream_files(sampled_files, &processing_config, sender)
+    });
+
+    let mut chunks = Vec::new();
+    let mut chunked_files = 0;
+    while let Some(item) = receiver.recv().await {
+        match item {
+            ProcessedItem::Chunk(chunk) => chunks.push(chunk),
+            ProcessedItem::FileDone(_, _) => chunked_files += 1,
+            ProcessedItem::Failed(_, _) => {}
+        }
+    }
+    producer.await?;
+
+    let per_file = chunks.len() as f64 / chunked_files.max(1) as f64;
+    let expected_chunks = (per_file * total_files as f64).round() as u64;
+
+    // Files are chunked in parallel; sorting keeps the sample the same from run to run
+    chunks.sort_by(|a, b| (&a.file_path, a.start_line).cmp(&(&b.file_path, b.start_line)));
+    if chunks.len() > size {
+        let total = chunks.len();
+        let mut index = 0;
+        chunks.retain(|_| {
+            let keep = index * size / total != (index + 1) * size / total;
+            index += 1;
+            keep
+        });
+    }
+
+    let titles = chunks
+        .iter()
+        .enumerate()
+        .filter_map(|(index, chunk)| Some((title(chunk)?, index)))
+        .collect();
+    Ok(Sample {
+        chunks,
+        titles,
+        expected_chunks,
+    })
+}
+
+/// What a chunk defines or is headed by, as words a person might search for:
+/// `parseConfigFile` reads as "parse config file".
+fn title(chunk: &Chunk) -> Option<String> {
+    let name = chunk.symbol.as_deref().or(chunk.section.as_deref())?;
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut previous_lowercase = false;
+    for c in name.chars() {
+        if !c.is_alphanumeric() {
+            words.extend((!word.is_empty()).then(|| std::mem::take(&mut word)));
+            previous_lowercase = false;
+            continue;
+        }
+        if c.is_uppercase() && previous_lowercase {
+            words.push(std::mem::take(&mut word));
+        }
+        previous_lowercase = c.is_lowercase() || c.is_ascii_digit();
+        word.extend(c.to_lowercase());
+    }
+    words.extend((!word.is_empty()).then_some(word));
+
+    let title = words.join(" ");
+    (title.len() >= 3).then_some(title)
+}
+
+/// Loads the model, embeds every sampled chunk and title, and ranks the chunks for each
+/// title.
+async fn bench_model(semantic: SemanticConfig, sample: &Sample) -> Result<ModelScore> {
+    let documents: Vec<String> = sample
+        .chunks
+        .iter()
+        .map(|chunk| format!("{}{}", semantic.document_prompt, chunk.content))
+        .collect();
+    let queries: Vec<String> = sample
+        .titles
+        .iter()
+        .map(|(title, _)| format!("{}{}", semantic.query_prompt, title))
+        .collect();
+
+    let loading_started = Instant::now();
+    let vector_store =
+        Arc::new(tokio::task::spawn_blocking(move || VectorStore::new(&semantic, None)).await??);
+    let load_time = loading_started.elapsed();
+
+    let (document_embeddings, embedding_time, query_embeddings) =
+        tokio::task::spawn_blocking(move || {
+            let cancel = CancellationToken::new();
+            let embedding_started = Instant::now();
+            let documents = vector_store.embed_batch(&documents, &cancel)?;
+            let embedding_time = embedding_started.elapsed();
+            let queries = vector_store.embed_batch(&queries, &cancel)?;
+            anyhow::Ok((documents, embedding_time, queries))
+        })
+        .await??;
+
+    let dimensions = document_embeddings
+        .iter()
+        .flatten()
+        .next()
+        .map_or(0, |embedding| embedding.len());
+    if dimensions == 0 {
+        anyhow::bail!("The model embedded none of the sampled chunks");
+    }
+
+    let (mrr, top1) = if sample.titles.len() < MIN_TITLED_CHUNKS {
+        (None, None)
+    } else {
+        let ranks: Vec<usize> = sample
+            .titles
+            .iter()
+            .zip(&query_embeddings)
+            .filter_map(|((_, target), query)| {
+                let query = query.as_ref()?;
+                let target_score = dot(query, document_embeddings[*target].as_ref()?);
+                Some(
+                    document_embeddings
+                        .iter()
+                        .flatten()
+                        .filter(|document| dot(query, document) > target_score)
+                        .count(),
+                )
+            })
+            .collect();
+        let count = ranks.len().max(1) as f64;
+        (
+            Some(
+                ranks
+                    .iter()
+                    .map(|rank| 1.0 / (rank + 1) as f64)
+                    .sum::<f64>()
+                    / count,
+            ),
+            Some(ranks.iter().filter(|rank| **rank == 0).count() as f64 / count),
+        )
+    };
+
+    Ok(ModelScore {
+        dimensions,
+        load_time,
+        chunks_per_second: sample.chunks.len() as f64 / embedding_time.as_secs_f64().max(1e-9),
+        index_bytes: sample.expected_chunks * (dimensions * std::mem::size_of::<f32>()) as u64,
+        mrr,
+        top1,
+    })
+}
+
+/// Cosine similarity of two embeddings, which the model has already normalised.
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
@@ -0,0 +1,154 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::ConfigManager;
+use crate::types::Chunk;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub file_path: PathBuf,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub content: String,
+    pub query: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedQuery {
+    pub name: String,
+    pub query: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Bookmarks {
+    pub queries: Vec<SavedQuery>,
+    pub results: Vec<Bookmark>,
+}
+
+pub enum BookmarkEntry<'a> {
+    Query(&'a SavedQuery),
+    Result(&'a Bookmark),
+}
+
+/// Bookmarked results and saved queries for a single project root.
+pub struct BookmarkStore {
+    path: PathBuf,
+    bookmarks: Bookmarks,
+}
+
+impl BookmarkStore {
+    pub fn open(project_root: &Path) -> Result<Self> {
+        let path = ConfigManager::get_project_dir(project_root).join("bookmarks.json");
+
+        let bookmarks = if path.exists() {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read bookmarks: {:?}", path))?;
+            serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse bookmarks: {:?}", path))?
+        } else {
+            Bookmarks::default()
+        };
+
+        Ok(Self { path, bookmarks })
+    }
+
+    pub fn bookmarks(&self) -> &Bookmarks {
+        &self.bookmarks
+    }
+
+    /// Saved queries first, then bookmarked results, in the order they were added.
+    pub fn entries(&self) -> Vec<BookmarkEntry<'_>> {
+        self.bookmarks
+            .queries
+            .iter()
+            .map(BookmarkEntry::Query)
+            .chain(self.bookmarks.results.iter().map(BookmarkEntry::Result))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bookmarks.queries.len() + self.bookmarks.results.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds the chunk as a bookmark, or removes it if it is already bookmarked.
+    /// Returns whether the chunk is bookmarked afterwards.
+    pub fn toggle_result(&mut self, chunk: &Chunk, query: &str) -> Result<bool> {
+        let existing = self
+            .bookmarks
+            .results
+            .iter()
+            .position(|b| b.file_path == chunk.file_path && b.start_line == chunk.start_line);
+
+        let bookmarked = match existing {
+            Some(index) => {
+                self.bookmarks.results.remove(index);
+                false
+            }
+            None => {
+                self.bookmarks.results.push(Bookmark {
+                    file_path: chunk.file_path.clone(),
+                    start_line: chunk.start_line,
+                    end_line: chunk.end_line,
+                    content: chunk.content.clone(),
+                    query: query.to_string(),
+                });
+                true
+            }
+        };
+
+        self.save()?;
+        Ok(bookmarked)
+    }
+
+    pub fn is_bookmarked(&self, chunk: &Chunk) -> bool {
+        self.bookmarks
+            .results
+            .iter()
+            .any(|b| b.file_path == chunk.file_path && b.start_line == chunk.start_line)
+    }
+
+    pub fn save_query(&mut self, name: &str, query: &str) -> Result<()> {
+        match self.bookmarks.queries.iter_mut().find(|q| q.name == name) {
+            Some(saved) => saved.query = query.to_string(),
+            None => self.bookmarks.queries.push(SavedQuery {
+                name: name.to_string(),
+                query: query.to_string(),
+            }),
+        }
+
+        self.save()
+    }
+
+    /// Removes the entry at `index` in [`Self::entries`] order.
+    pub fn remove(&mut self, index: usize) -> Result<()> {
+        let query_count = self.bookmarks.queries.len();
+        if index < query_count {
+            self.bookmarks.queries.remove(index);
+        } else if index - query_count < self.bookmarks.results.len() {
+            self.bookmarks.results.remove(index - query_count);
+        } else {
+            return Ok(());
+        }
+
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let content = serde_json::to_string_pretty(&self.bookmarks)
+            .context("Failed to serialize bookmarks")?;
+        fs::write(&self.path, content)
+            .with_context(|| format!("Failed to write bookmarks: {:?}", self.path))?;
+
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -8,10 +8,47 @@ use std::path::PathBuf;
 )]
 #[command(version = "0.1.0")]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+
     /// Directory path to crawl
     #[arg(help = "Directory path to crawl")]
     pub directory: Option<PathBuf>,
 
+    /// Where indexes are stored
+    #[arg(
+        long,
+        global = true,
+        help = "Directory for indexes and per-project data (default: $SEMA_DATA_DIR, then the platform data directory)"
+    )]
+    pub data_dir: Option<PathBuf>,
+
+    /// How much goes to the log file
+    #[arg(
+        long,
+        global = true,
+        default_value = "info",
+        value_parser = ["off", "error", "warn", "info", "debug", "trace"],
+        help = "Detail written to sema.log in the data directory"
+    )]
+    pub log_level: String,
+
+    /// Named preset from the config file
+    #[arg(
+        long,
+        global = true,
+        value_name = "NAME",
+        help = "Apply the [profile.NAME] settings from config.toml, with an index of its own"
+    )]
+    pub profile: Option<String>,
+
+    /// Index the launch directory even inside a repository
+    #[arg(
+        long,
+        help = "Don't offer to index from the enclosing repository root when launched in a subdirectory"
+    )]
+    pub no_auto_root: bool,
+
     /// Override maximum file size in bytes
     #[arg(long, help = "Maximum file size to process (in bytes)")]
     pub max_file_size: Option<u64>,
@@ -32,6 +69,14 @@ pub struct Cli {
     )]
     pub extensions: Option<Vec<String>>,
 
+    /// Index every text file regardless of extension
+    #[arg(
+        long,
+        conflicts_with = "extensions",
+        help = "Index every text file, including extensionless ones like Dockerfile and Makefile"
+    )]
+    pub all_types: bool,
+
     /// Additional patterns to exclude
     #[arg(
         long,
@@ -40,7 +85,252 @@ pub struct Cli {
     )]
     pub exclude: Option<Vec<String>>,
 
+    /// Limit how deep the crawl goes
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Crawl at most N directory levels below the project root (0 for no limit)"
+    )]
+    pub max_depth: Option<usize>,
+
+    /// Stop crawling after this many files
+    #[arg(
+        long,
+        value_name = "N",
+        help = "In the interface, stop crawling after N files and ask before indexing them (0 for no limit, default 100000)"
+    )]
+    pub max_files: Option<usize>,
+
+    /// Descend into archives
+    #[arg(long, help = "Index the contents of zip, tar and gzip archives")]
+    pub archives: bool,
+
+    /// Index image files
+    #[arg(
+        long,
+        help = "Index PNG, JPEG, GIF, BMP and WebP files by name, format and size"
+    )]
+    pub images: bool,
+
     /// Ignore files listed in .gitignore files
     #[arg(long, help = "Ignore files and patterns listed in .gitignore files")]
     pub ignore_gitignore: bool,
+
+    /// Override minimum similarity for semantic results
+    #[arg(
+        long,
+        help = "Minimum cosine similarity (0.0-1.0) for semantic results"
+    )]
+    pub min_score: Option<f32>,
+
+    /// Skip embeddings entirely
+    #[arg(
+        long,
+        help = "Build only the keyword index and treat every query as a keyword search"
+    )]
+    pub no_semantic: bool,
+
+    /// Never download the embedding model
+    #[arg(
+        long,
+        help = "Load the embedding model only from [semantic] model_dir or the local cache, never the network"
+    )]
+    pub offline: bool,
+
+    /// Number of embedding workers
+    #[arg(
+        short = 'j',
+        long,
+        value_name = "N",
+        help = "Embed with N workers in parallel, each loading its own copy of the model (default: up to two, sharing the cores)"
+    )]
+    pub jobs: Option<usize>,
+
+    /// Time the indexing stages
+    #[arg(
+        long,
+        help = "Time crawling, hashing, chunking, embedding and index writes, and print the breakdown when indexing finishes"
+    )]
+    pub profile_index: bool,
+
+    /// Print the selected result on exit
+    #[arg(
+        long,
+        help = "Print the selected result as path:line to stdout when quitting"
+    )]
+    pub print_on_exit: bool,
+
+    /// Index piped input
+    #[arg(
+        long,
+        help = "Index text piped on stdin alongside the directory, e.g. `make 2>&1 | sema --stdin`"
+    )]
+    pub stdin: bool,
+
+    /// Name for piped input
+    #[arg(
+        long,
+        requires = "stdin",
+        help = "Show the piped input as stdin:<NAME>, chunked like a file of that name"
+    )]
+    pub name: Option<String>,
+
+    /// Queries to search when the interface opens
+    #[arg(
+        short = 'q',
+        long = "query",
+        help = "Search this when the interface opens; repeat to search several queries together and merge their results"
+    )]
+    pub queries: Vec<String>,
+
+    /// Search without the interface
+    #[arg(
+        long,
+        requires = "queries",
+        help = "Index, print the matches of the -q queries as path:line: text and exit: 0 if any matched, 1 if none, 2 on error"
+    )]
+    pub once: bool,
+
+    /// Search every indexed project
+    #[arg(
+        long,
+        help = "Search every project indexed under the data directory, not just this one, labelling each result with its project"
+    )]
+    pub all_projects: bool,
+
+    /// Write the result set to a file
+    #[arg(
+        long,
+        help = "Write the last result set to this file on exit (.csv for CSV, otherwise a Markdown table)"
+    )]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+pub enum Commands {
+    /// Keep the project's indexes and embedding model loaded and serve searches to sema
+    /// instances started later
+    Daemon,
+    /// Answer a question about the project with the `[llm]` model, citing the best-matching
+    /// chunks it was given
+    Ask {
+        #[arg(required = true)]
+        question: Vec<String>,
+        /// Forget the questions asked before instead of reading this one as a follow-up
+        #[arg(long)]
+        new: bool,
+    },
+    /// Measure retrieval quality against labelled queries
+    Bench {
+        /// JSON list of {"query": ..., "files": [...]} with paths relative to the project
+        #[arg(long)]
+        qrels: PathBuf,
+        /// Number of top-ranked files considered per query
+        #[arg(short, long, default_value_t = 10)]
+        k: usize,
+    },
+    /// Manage the embedding models in the Hugging Face cache and compare them
+    Models {
+        #[command(subcommand)]
+        action: ModelsAction,
+    },
+    /// List lines that look like API keys, tokens or passwords; indexing keeps the chunks
+    /// holding them out of the vector index unless `[indexing] embed_secrets` is on
+    Secrets,
+    /// Summarise what gets indexed: files per language, largest directories and the files
+    /// left out, by reason
+    Status {
+        /// List every skipped file with the reason, e.g. gitignored, too large or binary
+        #[arg(long)]
+        skipped: bool,
+    },
+    /// List, rename and forget the projects in the index, which holds every project
+    /// indexed under the data directory
+    Projects {
+        #[command(subcommand)]
+        action: ProjectsAction,
+    },
+    /// Manage bookmarked results and saved queries
+    Bookmarks {
+        #[command(subcommand)]
+        action: BookmarksAction,
+    },
+    /// Read, change and check settings in config.toml
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// Print a setting, e.g. `search.min_similarity`, or a whole table such as `general`
+    Get { key: String },
+    /// Change a setting; the value is read as TOML (`0.3`, `true`, `["rs", "md"]`) or else
+    /// as a string
+    Set { key: String, value: String },
+    /// Open config.toml in $VISUAL or $EDITOR, then check it
+    Edit,
+    /// Print where config.toml is
+    Path,
+    /// Report syntax errors, values of the wrong type and unknown settings by line
+    Validate,
+}
+
+#[derive(Subcommand)]
+pub enum ModelsAction {
+    /// List the models in the Hugging Face cache with their revisions and disk usage
+    List,
+    /// Fetch a model's ONNX export and tokenizer, e.g. `BAAI/bge-small-en-v1.5`
+    Download {
+        model: String,
+        /// Branch, tag or commit to fetch instead of the default branch
+        #[arg(long)]
+        revision: Option<String>,
+    },
+    /// Delete a model from the Hugging Face cache
+    Remove { model: String },
+    /// Embed with this model from now on, pinned to the commit fetched; the index is
+    /// rebuilt with it on the next run. Models whose vectors aren't the size the index
+    /// stores are refused
+    Use {
+        model: String,
+        /// Branch, tag or commit to pin instead of the latest on the default branch
+        #[arg(long)]
+        revision: Option<String>,
+    },
+    /// Embed a sample of the project with the configured model and each of
+    /// `[[semantic.candidates]]`, reporting throughput, index size and how well chunks are
+    /// found by their function or heading names
+    Bench {
+        /// Chunks to embed with each model
+        #[arg(long, default_value_t = 500)]
+        sample: usize,
+        /// Another model directory to compare; repeat for several
+        #[arg(long = "model-dir")]
+        model_dirs: Vec<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ProjectsAction {
+    /// List the indexed projects with their chunks, disk usage and when each was last indexed
+    List,
+    /// Delete a project's chunks from every index along with its bookmarks, conversation
+    /// and caches; the project is given by name or by root
+    Rm { project: String },
+    /// Change the name a project's results are labelled with when searching every project
+    Rename { project: String, name: String },
+    /// Forget every project whose root no longer exists
+    Gc {
+        /// List the projects that would be forgotten without deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum BookmarksAction {
+    /// List bookmarks and saved queries for the project
+    List,
 }
@@ -0,0 +1,237 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+use toml::{Table, Value};
+use toml_edit::{DocumentMut, Item, TableLike};
+
+use super::{Config, ConfigManager};
+
+/// A `[table]` or `[[array]]` header, capturing the dotted name
+static TABLE_HEADER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^\s*\[\[?\s*([^\]]+?)\s*\]\]?"#).expect("table header pattern is valid")
+});
+/// The key of a `key = value` line, possibly dotted or quoted
+static KEY_LINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^\s*([A-Za-z0-9_\-."' ]+?)\s*="#).expect("key pattern is valid")
+});
+
+/// Something wrong with the config file, at a 1-based line when it can be placed.
+#[derive(Debug)]
+pub struct ConfigProblem {
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl ConfigManager {
+    pub fn config_file(&self) -> &Path {
+        &self.config_file
+    }
+
+    /// The value of a dotted key such as `search.min_similarity`, with defaults filled in
+    /// for settings the file leaves out.
+    pub fn get_value(&self, key: &str) -> Result<Value> {
+        let config = Value::try_from(self.load_config()?)?;
+        lookup(&config, key)
+            .cloned()
+            .with_context(|| format!("Unknown setting: {}", key))
+    }
+
+    /// Sets a dotted key in the config file. `raw` is read as a TOML value, like `0.3`,
+    /// `true` or `["rs", "md"]`, and anything else as a string. Nothing is written unless
+    /// the result is a valid config that knows the key. The rest of the file, comments
+    /// and layout included, is kept as it was.
+    pub fn set_value(&self, key: &str, raw: &str) -> Result<()> {
+        let content = fs::read_to_string(&self.config_file).unwrap_or_default();
+        let mut document: DocumentMut = content
+            .parse()
+            .with_context(|| format!("Failed to parse config file: {:?}", self.config_file))?;
+
+        let value = raw
+            .trim()
+            .parse::<toml_edit::Value>()
+            .unwrap_or_else(|_| raw.into());
+        insert(&mut document, key, value)?;
+
+        let updated = document.to_string();
+        let config: Config =
+            toml::from_str(&updated).with_context(|| format!("Invalid value for {}", key))?;
+        if lookup(&Value::try_from(config)?, key).is_none() {
+            anyhow::bail!("Unknown setting: {}", key);
+        }
+
+        fs::write(&self.config_file, updated)
+            .with_context(|| format!("Failed to write config file: {:?}", self.config_file))
+    }
+
+    /// Syntax errors, values of the wrong type and keys sema doesn't know, by line.
+    pub fn validate(&self) -> Result<Vec<ConfigProblem>> {
+        let content = fs::read_to_string(&self.config_file)
+            .with_context(|| format!("Failed to read config file: {:?}", self.config_file))?;
+        let line_of =
+            |offset: usize| content[..offset.min(content.len())].matches('\n').count() + 1;
+
+        let table: Table = match toml::from_str(&content) {
+            Ok(table) => table,
+            Err(e) => {
+                return Ok(vec![ConfigProblem {
+                    line: e.span().map(|span| line_of(span.start)),
+                    message: e.message().to_string(),
+                }]);
+            }
+        };
+        // Parsed again from the text, as only that keeps the location of a bad value
+        let config: Config = match toml::from_str(&content) {
+            Ok(config) => config,
+            Err(e) => {
+                return Ok(vec![ConfigProblem {
+                    line: e.span().map(|span| line_of(span.start)),
+                    message: e.message().to_string(),
+                }]);
+            }
+        };
+
+        // Profiles round trip whole, so their settings are checked against the base ones
+        let key_lines = key_lines(&content);
+        let mut problems = Vec::new();
+        for name in config.profile.keys() {
+            if let Err(e) = config.clone().with_profile(name) {
+                let key = format!("profile.{}", name);
+                problems.push(ConfigProblem {
+                    line: key_lines.get(&key).copied(),
+                    message: format!("{:#}", e),
+                });
+            }
+        }
+
+        // Every setting the file uses survives a round trip through Config, unless sema
+        // doesn't know it
+        let mut known = Value::try_from(config)?;
+        let mut table = Value::Table(table);
+        let profiles = remove_profiles(&mut table);
+        remove_profiles(&mut known);
+        let mut unknown = Vec::new();
+        collect_unknown(&table, &known, "", &mut unknown);
+        for (name, profile) in &profiles {
+            let prefix = format!("profile.{}", name);
+            collect_unknown(profile, &known, &prefix, &mut unknown);
+        }
+
+        problems.extend(unknown.into_iter().map(|key| ConfigProblem {
+            line: key_lines.get(&key).copied(),
+            message: format!("unknown setting `{}`", key),
+        }));
+
+        let deprecated = "search.expansion_synonyms";
+        if lookup(&table, deprecated).is_some() {
+            problems.push(ConfigProblem {
+                line: key_lines.get(deprecated).copied(),
+                message: format!(
+                    "`{}` is deprecated; move its entries to [synonyms]",
+                    deprecated
+                ),
+            });
+        }
+        Ok(problems)
+    }
+}
+
+fn lookup<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    key.split('.')
+        .try_fold(value, |value, part| value.as_table()?.get(part))
+}
+
+/// Sets `key` in `document`, creating the tables on its path. A value it replaces keeps
+/// the comments around it.
+fn insert(document: &mut DocumentMut, key: &str, mut value: toml_edit::Value) -> Result<()> {
+    let mut parts: Vec<&str> = key.split('.').collect();
+    let last = parts.pop().filter(|last| !last.is_empty());
+    let Some(last) = last else {
+        anyhow::bail!("Empty setting name");
+    };
+
+    let mut table: &mut dyn TableLike = document.as_table_mut();
+    for part in parts {
+        let mut created = toml_edit::Table::new();
+        created.set_implicit(true);
+        table = table
+            .entry(part)
+            .or_insert(Item::Table(created))
+            .as_table_like_mut()
+            .with_context(|| format!("{} is not a table", part))?;
+    }
+    match table.get_mut(last) {
+        Some(Item::Value(old)) => {
+            *value.decor_mut() = old.decor().clone();
+            *old = value;
+        }
+        _ => {
+            table.insert(last, Item::Value(value));
+        }
+    }
+    Ok(())
+}
+
+/// Takes the `[profile.*]` tables out of a config value.
+fn remove_profiles(config: &mut Value) -> Table {
+    config
+        .as_table_mut()
+        .and_then(|table| table.remove("profile"))
+        .and_then(|profiles| match profiles {
+            Value::Table(profiles) => Some(profiles),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+fn collect_unknown(value: &Value, known: &Value, prefix: &str, unknown: &mut Vec<String>) {
+    let (Some(table), Some(known)) = (value.as_table(), known.as_table()) else {
+        return;
+    };
+
+    for (key, value) in table {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match known.get(key) {
+            Some(known) => collect_unknown(value, known, &path, unknown),
+            None => unknown.push(path),
+        }
+    }
+}
+
+/// The 1-based line defining each dotted key, for pointing at unknown keys. Keys inside
+/// inline tables aren't found and are reported without a line.
+fn key_lines(content: &str) -> HashMap<String, usize> {
+    let mut lines = HashMap::new();
+    let mut table = String::new();
+
+    for (index, line) in content.lines().enumerate() {
+        if let Some(captures) = TABLE_HEADER.captures(line) {
+            table = unquote(&captures[1]);
+            lines.entry(table.clone()).or_insert(index + 1);
+        } else if let Some(captures) = KEY_LINE.captures(line) {
+            let key = unquote(&captures[1]);
+            let path = if table.is_empty() {
+                key
+            } else {
+                format!("{}.{}", table, key)
+            };
+            lines.entry(path).or_insert(index + 1);
+        }
+    }
+
+    lines
+}
+
+/// `a . "b"` as `a.b`
+fn unquote(key: &str) -> String {
+    key.split('.')
+        .map(|part| part.trim().trim_matches(['"', '\'']))
+        .collect::<Vec<_>>()
+        .join(".")
+}
@@ -1,21 +1,468 @@
+pub mod edit;
+
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+const DATA_DIR_ENV: &str = "SEMA_DATA_DIR";
+/// Embedding workers run with `jobs = 0`; more copies of the model cost memory for little
+/// gain, since each worker's session already runs on several cores
+const DEFAULT_EMBEDDING_WORKERS: usize = 2;
+
+static DATA_DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Config {
     pub general: GeneralConfig,
+    #[serde(default)]
+    pub search: SearchConfig,
+    #[serde(default)]
+    pub llm: LlmConfig,
+    #[serde(default)]
+    pub indexing: IndexingConfig,
+    #[serde(default)]
+    pub resources: ResourcesConfig,
+    #[serde(default)]
+    pub daemon: DaemonConfig,
+    #[serde(default)]
+    pub preview: PreviewConfig,
+    #[serde(default)]
+    pub vector_index: VectorIndexConfig,
+    #[serde(default)]
+    pub semantic: SemanticConfig,
+    /// Per-extension overrides, e.g. `[languages.vue] syntax = "html"`
+    #[serde(default)]
+    pub languages: BTreeMap<String, LanguageOverride>,
+    /// Team vocabulary expanded in every query, e.g. `k8s = "kubernetes"`, or with
+    /// alternatives, e.g. `auth = ["authentication", "login"]`
+    #[serde(default)]
+    pub synonyms: BTreeMap<String, Synonym>,
+    /// Named presets selected with `--profile`, e.g. `[profile.docs.general]`, each
+    /// overriding any of the settings above
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profile: BTreeMap<String, toml::Table>,
+    /// The preset applied by `with_profile`, whose index is kept apart from the others
+    #[serde(skip)]
+    pub active_profile: Option<String>,
+}
+
+impl Config {
+    /// These settings with `[profile.<name>]` laid over them. Tables merge key by key;
+    /// any other value, lists included, replaces the one underneath.
+    pub fn with_profile(self, name: &str) -> Result<Config> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            anyhow::bail!(
+                "Invalid profile name {:?}: use letters, digits, '-' and '_'",
+                name
+            );
+        }
+        let Some(overrides) = self.profile.get(name).cloned() else {
+            let known: Vec<&str> = self.profile.keys().map(String::as_str).collect();
+            if known.is_empty() {
+                anyhow::bail!("No [profile.{}] in the config file", name);
+            }
+            anyhow::bail!(
+                "No [profile.{}] in the config file (profiles: {})",
+                name,
+                known.join(", ")
+            );
+        };
+
+        let toml::Value::Table(mut table) =
+            toml::Value::try_from(&self).context("Failed to serialize configuration")?
+        else {
+            anyhow::bail!("Configuration didn't serialize to a table");
+        };
+        merge_tables(&mut table, overrides);
+        let mut config: Config = toml::Value::Table(table)
+            .try_into()
+            .with_context(|| format!("Invalid [profile.{}]", name))?;
+        config.active_profile = Some(name.to_string());
+        Ok(config)
+    }
+
+    /// The alternatives of every `[synonyms]` word, with the words of the deprecated
+    /// `search.expansion_synonyms` that `[synonyms]` doesn't define.
+    pub fn all_synonyms(&self) -> BTreeMap<String, Vec<String>> {
+        let mut synonyms: BTreeMap<String, Vec<String>> = self
+            .search
+            .expansion_synonyms
+            .iter()
+            .map(|(word, alternatives)| (word.clone(), alternatives.clone()))
+            .collect();
+        for (word, synonym) in &self.synonyms {
+            synonyms.insert(word.clone(), synonym.alternatives());
+        }
+        synonyms
+    }
+}
+
+fn merge_tables(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(value)) => {
+                merge_tables(base, value)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LanguageOverride {
+    /// Syntect syntax name or extension used to highlight previews
+    pub syntax: Option<String>,
+    /// How files with this extension are split into chunks; by default Markdown-like
+    /// files are split at headings, JSON, YAML and TOML at keys, `.log` files by
+    /// timestamp, and the rest by size
+    pub chunker: Option<ChunkStrategy>,
+}
+
+/// What a word of `[synonyms]` stands for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Synonym {
+    Expansion(String),
+    /// The first is what the word stands for; the rest are embedded as variants of the
+    /// query when `query_expansion = "synonyms"`
+    Alternatives(Vec<String>),
+}
+
+impl Synonym {
+    pub fn alternatives(&self) -> Vec<String> {
+        match self {
+            Synonym::Expansion(expansion) => vec![expansion.clone()],
+            Synonym::Alternatives(alternatives) => alternatives.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChunkStrategy {
+    /// Fixed-size byte windows with overlap
+    Bytes,
+    /// Whole lines up to the chunk size, streamed from disk
+    Lines,
+    /// Sections between Markdown, AsciiDoc or RST headings, labelled with the heading path
+    Markdown,
+    /// Top-level keys of JSON, YAML or TOML files, labelled with the key path
+    Structured,
+    /// Log entries grouped by timestamp proximity, labelled with their time range
+    Log,
+}
+
+/// Normalizes a `[languages]` key so `.Vue` and `vue` match the same files.
+pub fn normalize_extension(extension: &str) -> String {
+    extension.trim_start_matches('.').to_lowercase()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreviewConfig {
+    /// Lines shown before and after the matched chunk in the chunk-only preview
+    pub context_lines: usize,
+    /// Tint the lines of a semantic match that are most similar to the query
+    pub semantic_highlights: bool,
+    /// How image files are drawn in the preview
+    pub images: ImageProtocol,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageProtocol {
+    /// kitty graphics in kitty and Ghostty, iTerm2 inline images in iTerm2 and WezTerm,
+    /// otherwise a description
+    #[default]
+    Auto,
+    Kitty,
+    Iterm,
+    /// Always describe images instead of drawing them
+    Off,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        Self {
+            context_lines: 3,
+            semantic_highlights: true,
+            images: ImageProtocol::Auto,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndexingConfig {
+    /// Chunks embedded and committed per batch; progress is checkpointed after each commit
+    pub commit_interval: usize,
+    /// Time each indexing stage and report the breakdown when indexing finishes
+    pub profile: bool,
+    /// Embed chunks that look like they hold credentials. Off by default, so keys and
+    /// tokens only reach the local keyword index, never the model or a vector server.
+    pub embed_secrets: bool,
+    /// Embed with fewer workers and smaller batches on battery or when the machine is busy
+    pub throttle: bool,
+    /// Load average per core, not counting indexing itself, above which indexing is throttled
+    pub throttle_load: f64,
+    /// Embedding workers while throttled
+    pub throttled_jobs: usize,
+    /// Chunks per batch while throttled
+    pub throttled_batch: usize,
+    /// New or changed files above which indexing waits for confirmation; 0 to never ask
+    pub confirm_files: usize,
+    /// Size in MB of new or changed files above which indexing waits for confirmation; 0
+    /// to never ask
+    pub confirm_mb: u64,
+}
+
+impl Default for IndexingConfig {
+    fn default() -> Self {
+        Self {
+            commit_interval: 256,
+            profile: false,
+            embed_secrets: false,
+            throttle: true,
+            throttle_load: 1.0,
+            throttled_jobs: 1,
+            throttled_batch: 32,
+            confirm_files: 20_000,
+            confirm_mb: 1024,
+        }
+    }
+}
+
+/// Memory the indexes may use while writing, for machines with little RAM to spare
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResourcesConfig {
+    /// Heap shared by the keyword index writer threads, in MB; at least 15 MB per thread
+    pub text_writer_heap_mb: usize,
+    /// Keyword index writer threads; 0 picks one per core, up to 8, as the heap allows
+    pub text_writer_threads: usize,
+    /// Chunks embedded and written to the vector index at a time
+    pub vector_write_batch: usize,
+}
+
+impl Default for ResourcesConfig {
+    fn default() -> Self {
+        Self {
+            text_writer_heap_mb: 200,
+            text_writer_threads: 0,
+            vector_write_batch: 256,
+        }
+    }
+}
+
+/// Settings of `sema daemon`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DaemonConfig {
+    /// When to crawl and reconcile the whole project again: a local time of day such as
+    /// `"03:00"`, or an interval such as `"30m"`, `"6h"` or `"1d"`; empty to only index on start
+    pub reindex: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GeneralConfig {
     pub max_file_size: u64,
+    /// Extensions to index; when empty, every file that looks like text is indexed
     pub file_extensions: Vec<String>,
     pub exclude_patterns: Vec<String>,
     pub follow_symlinks: bool,
+    /// Maximum directory depth when following symlinks
+    #[serde(default = "default_max_symlink_depth")]
+    pub max_symlink_depth: usize,
     pub include_hidden: bool,
     pub ignore_gitignore: bool,
+    /// What to do with files larger than `max_file_size`
+    #[serde(default)]
+    pub oversized_files: OversizedFiles,
+    /// How much of an oversized file to index, in KiB
+    #[serde(default = "default_oversized_index_kib")]
+    pub oversized_index_kib: u64,
+    /// Index the contents of zip, tar and gzip archives
+    #[serde(default)]
+    pub index_archives: bool,
+    /// Index PNG, JPEG, GIF, BMP and WebP files by name, format and size, so they show up
+    /// in results and previews
+    #[serde(default)]
+    pub index_images: bool,
+    /// Deepest directory level crawled below the project root; 0 for no limit
+    #[serde(default)]
+    pub max_depth: usize,
+    /// Files after which the interface's crawl stops and asks before indexing; 0 for no
+    /// limit. Commands that can't ask crawl every file
+    #[serde(default = "default_max_files")]
+    pub max_files: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OversizedFiles {
+    /// Leave oversized files out of the index
+    #[default]
+    Skip,
+    /// Index the beginning of the file
+    Head,
+    /// Index evenly spaced windows across the whole file
+    Sample,
+}
+
+fn default_oversized_index_kib() -> u64 {
+    1024
+}
+
+fn default_max_symlink_depth() -> usize {
+    32
+}
+
+fn default_max_files() -> usize {
+    100_000
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchConfig {
+    /// Vector hits with a cosine similarity below this are dropped
+    pub min_similarity: f32,
+    /// Extra query variants embedded alongside the original query
+    pub query_expansion: QueryExpansion,
+    /// Deprecated: alternatives per query word, now read as `[synonyms]` entries
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub expansion_synonyms: BTreeMap<String, Vec<String>>,
+    /// Weight of keyword matches in comments and docstrings relative to the chunk text;
+    /// natural-language queries tend to match documentation better than code
+    pub docs_boost: f32,
+    /// Chunks of the same file merged into each result on either side of the match, so it
+    /// reads as a whole unit; 0 returns matched chunks as they were indexed
+    pub neighbor_chunks: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryExpansion {
+    #[default]
+    None,
+    Synonyms,
+    Hyde,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum VectorBackend {
+    /// A LanceDB index in the local data directory
+    #[default]
+    Lance,
+    /// A Qdrant server, so a team can share one index
+    Qdrant,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VectorIndexConfig {
+    pub backend: VectorBackend,
+    /// Qdrant server URL
+    pub url: String,
+    /// Qdrant collection holding the chunks
+    pub collection: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+}
+
+/// Whether and from where the embedding model is loaded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SemanticConfig {
+    /// Embed chunks for semantic search; when off, only the keyword index is built and
+    /// unprefixed queries are keyword searches
+    pub enabled: bool,
+    /// Hugging Face model whose ONNX export is embedded with, set by `sema models use`;
+    /// changing it rebuilds the index
+    pub model: String,
+    /// Commit, branch or tag of `model`; `sema models use` pins the commit, so an update
+    /// upstream can't change the embeddings under an existing index
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+    /// Directory holding `model.onnx` (or `onnx/model.onnx`) and `tokenizer.json`, used
+    /// instead of the Hugging Face cache
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_dir: Option<PathBuf>,
+    /// Never contact the Hugging Face hub; the model must already be cached or in `model_dir`
+    pub offline: bool,
+    /// Prepended to every chunk before it is embedded, for instruction-tuned models such as
+    /// bge or e5 (e.g. "passage: "); changing it rebuilds the index
+    pub document_prompt: String,
+    /// Prepended to every query before it is embedded (e.g. "query: ")
+    pub query_prompt: String,
+    /// Embedding workers while indexing, each with its own copy of the model; 0 runs up
+    /// to two, which split the cores between them. Queries are embedded on a copy of
+    /// their own
+    pub jobs: usize,
+    /// Run the embedding workers at a lower CPU priority, so indexing doesn't slow down
+    /// the interface and searches (Linux only)
+    pub low_priority: bool,
+    /// Experimental: also store a vector per token of each chunk and rerank the final
+    /// candidates by late interaction (MaxSim), at many times the index size; LanceDB
+    /// only, and changing it rebuilds the index
+    pub late_interaction: bool,
+    /// Also weigh the vocabulary terms of each chunk with a learned-sparse (SPLADE) model
+    /// and fuse their matches into hybrid search, which helps with rare identifiers dense
+    /// vectors blur; changing it rebuilds the index
+    pub sparse: bool,
+    /// Hugging Face model with an ONNX export of a SPLADE model
+    pub sparse_model: String,
+    /// Directory holding the sparse model's `model.onnx` and `tokenizer.json`, used instead
+    /// of the Hugging Face cache
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sparse_model_dir: Option<PathBuf>,
+    /// Other models for `sema models bench` to compare with the configured one
+    pub candidates: Vec<ModelCandidate>,
+}
+
+/// A local embedding model to benchmark, from `[[semantic.candidates]]`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelCandidate {
+    /// Shown in the benchmark table; defaults to the directory name
+    pub name: String,
+    /// Directory holding `model.onnx` (or `onnx/model.onnx`) and `tokenizer.json`
+    pub model_dir: PathBuf,
+    pub document_prompt: String,
+    pub query_prompt: String,
+}
+
+impl SemanticConfig {
+    /// How many embedding workers to run, with `jobs = 0` resolved.
+    pub fn workers(&self) -> usize {
+        match self.jobs {
+            0 => num_cpus::get()
+                .saturating_sub(1)
+                .clamp(1, DEFAULT_EMBEDDING_WORKERS),
+            jobs => jobs,
+        }
+    }
+}
+
+/// OpenAI-compatible chat completions endpoint, e.g. a local Ollama server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LlmConfig {
+    pub endpoint: String,
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// Best-matching chunks sent along with a question asked with `sema ask` or `?`
+    pub answer_sources: usize,
 }
 
 pub struct ConfigManager {
@@ -109,8 +556,70 @@ impl Default for GeneralConfig {
                 "*.log".to_string(),
             ],
             follow_symlinks: false,
+            max_symlink_depth: default_max_symlink_depth(),
             include_hidden: false,
             ignore_gitignore: true,
+            oversized_files: OversizedFiles::Skip,
+            oversized_index_kib: default_oversized_index_kib(),
+            index_archives: false,
+            index_images: false,
+            max_depth: 0,
+            max_files: default_max_files(),
+        }
+    }
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            min_similarity: 0.2,
+            query_expansion: QueryExpansion::None,
+            expansion_synonyms: BTreeMap::new(),
+            docs_boost: 2.0,
+            neighbor_chunks: 0,
+        }
+    }
+}
+
+impl Default for VectorIndexConfig {
+    fn default() -> Self {
+        Self {
+            backend: VectorBackend::Lance,
+            url: "http://localhost:6333".to_string(),
+            collection: "sema".to_string(),
+            api_key: None,
+        }
+    }
+}
+
+impl Default for SemanticConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            model: crate::semantic::embeddings::MODEL_ID.to_string(),
+            revision: None,
+            model_dir: None,
+            offline: false,
+            document_prompt: String::new(),
+            query_prompt: String::new(),
+            jobs: 0,
+            low_priority: true,
+            late_interaction: false,
+            sparse: false,
+            sparse_model: crate::semantic::sparse::SPARSE_MODEL_ID.to_string(),
+            sparse_model_dir: None,
+            candidates: Vec::new(),
+        }
+    }
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "http://localhost:11434/v1/chat/completions".to_string(),
+            model: "llama3.2".to_string(),
+            api_key: None,
+            answer_sources: 8,
         }
     }
 }
@@ -131,6 +640,73 @@ impl ConfigManager {
         Ok(home_dir.join(".sema"))
     }
 
+    /// Points the data directory elsewhere for the rest of the process, as `--data-dir` does.
+    pub fn set_data_dir(data_dir: PathBuf) {
+        let _ = DATA_DIR_OVERRIDE.set(data_dir);
+    }
+
+    /// Directory holding the search indexes: `--data-dir`, then `$SEMA_DATA_DIR`, then the
+    /// platform data directory (e.g. `~/.local/share/sema`).
+    pub fn get_data_dir() -> PathBuf {
+        if let Some(data_dir) = Self::data_dir_override() {
+            return data_dir;
+        }
+        Self::default_data_dir()
+    }
+
+    fn data_dir_override() -> Option<PathBuf> {
+        DATA_DIR_OVERRIDE.get().cloned().or_else(|| {
+            std::env::var_os(DATA_DIR_ENV)
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+        })
+    }
+
+    fn default_data_dir() -> PathBuf {
+        match dirs::data_dir() {
+            Some(dir) => dir,
+            None => match std::env::current_dir() {
+                Ok(dir) => dir,
+                Err(_) => PathBuf::from("."),
+            },
+        }
+        .join("sema")
+    }
+
+    /// Moves indexes from their old home under the platform config directory to the data
+    /// directory. Does nothing when the data directory is overridden or already exists.
+    pub fn migrate_legacy_data_dir() -> Result<()> {
+        if Self::data_dir_override().is_some() {
+            return Ok(());
+        }
+        let Some(legacy_dir) = dirs::config_dir().map(|dir| dir.join("sema")) else {
+            return Ok(());
+        };
+        let data_dir = Self::default_data_dir();
+        if legacy_dir == data_dir || !legacy_dir.is_dir() || data_dir.exists() {
+            return Ok(());
+        }
+
+        if let Some(parent) = data_dir.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(&legacy_dir, &data_dir).with_context(|| {
+            format!(
+                "Failed to move the index from {} to {}",
+                legacy_dir.display(),
+                data_dir.display()
+            )
+        })
+    }
+
+    /// Directory holding per-project state such as bookmarks, keyed by the project root.
+    pub fn get_project_dir(project_root: &Path) -> PathBuf {
+        let key = xxhash_rust::xxh3::xxh3_64(project_root.to_string_lossy().as_bytes());
+        Self::get_data_dir()
+            .join("projects")
+            .join(format!("{:016x}", key))
+    }
+
     pub fn init(&self) -> Result<()> {
         if !self.config_dir.exists() {
             fs::create_dir_all(&self.config_dir).with_context(|| {
@@ -156,8 +732,12 @@ impl ConfigManager {
         let config_content = fs::read_to_string(&self.config_file)
             .with_context(|| format!("Failed to read config file: {:?}", self.config_file))?;
 
-        let config: Config = toml::from_str(&config_content)
-            .with_context(|| format!("Failed to parse config file: {:?}", self.config_file))?;
+        let config: Config = toml::from_str(&config_content).with_context(|| {
+            format!(
+                "Failed to parse config file: {:?} (`sema config validate` points at the problem)",
+                self.config_file
+            )
+        })?;
 
         Ok(config)
     }
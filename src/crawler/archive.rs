@@ -0,0 +1,213 @@
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::config::ConfigManager;
+
+/// Separates an archive from an entry inside it in virtual paths, e.g. `sdk.zip!/src/lib.rs`.
+const ENTRY_SEPARATOR: &str = "!";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+    Gz,
+}
+
+impl ArchiveFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else if name.ends_with(".gz") {
+            Some(ArchiveFormat::Gz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Where entries of the archives found under `root` are extracted.
+pub fn cache_dir(root: &Path) -> PathBuf {
+    ConfigManager::get_project_dir(root).join("archives")
+}
+
+/// Maps an extracted entry back to where it lives inside its archive, e.g.
+/// `root/vendor/sdk.zip!/src/lib.rs`. Returns `None` for files outside the archive cache.
+pub fn virtual_path(path: &Path, root: &Path) -> Option<PathBuf> {
+    let relative = path.strip_prefix(cache_dir(root)).ok()?;
+    Some(root.join(relative))
+}
+
+/// Extracts the entries of an archive that `accept` allows and that fit in
+/// `max_entry_size`, returning the extracted files. A previous extraction is reused while
+/// the archive is unchanged.
+pub fn extract(
+    archive: &Path,
+    root: &Path,
+    max_entry_size: u64,
+    accept: impl Fn(&Path) -> bool,
+) -> Result<Vec<PathBuf>> {
+    let format = ArchiveFormat::from_path(archive)
+        .with_context(|| format!("Unsupported archive: {:?}", archive))?;
+
+    let relative = archive
+        .strip_prefix(root)
+        .ok()
+        .or_else(|| archive.file_name().map(Path::new))
+        .with_context(|| format!("Invalid archive path: {:?}", archive))?;
+    let mut dir_name = relative.as_os_str().to_owned();
+    dir_name.push(ENTRY_SEPARATOR);
+    let target = cache_dir(root).join(dir_name);
+
+    if is_fresh(archive, &target) {
+        return Ok(list_files(&target));
+    }
+
+    if target.exists() {
+        fs::remove_dir_all(&target)
+            .with_context(|| format!("Failed to clear archive cache: {:?}", target))?;
+    }
+    fs::create_dir_all(&target)
+        .with_context(|| format!("Failed to create archive cache: {:?}", target))?;
+
+    let file =
+        File::open(archive).with_context(|| format!("Failed to open archive: {:?}", archive))?;
+    let extracted = match format {
+        ArchiveFormat::Zip => extract_zip(file, &target, max_entry_size, &accept),
+        ArchiveFormat::Tar => extract_tar(file, &target, max_entry_size, &accept),
+        ArchiveFormat::TarGz => extract_tar(GzDecoder::new(file), &target, max_entry_size, &accept),
+        ArchiveFormat::Gz => extract_gz(file, archive, &target, max_entry_size, &accept),
+    };
+
+    // A half-extracted cache would otherwise look fresh on the next run
+    if let Err(e) = extracted {
+        let _ = fs::remove_dir_all(&target);
+        return Err(e.context(format!("Failed to extract archive: {:?}", archive)));
+    }
+
+    Ok(list_files(&target))
+}
+
+fn extract_zip(
+    file: File,
+    target: &Path,
+    max_entry_size: u64,
+    accept: &impl Fn(&Path) -> bool,
+) -> Result<()> {
+    let mut archive = zip::ZipArchive::new(file)?;
+
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        if entry.is_dir() || entry.size() > max_entry_size {
+            continue;
+        }
+        let Some(name) = entry.enclosed_name() else {
+            continue;
+        };
+        if accept(&name) {
+            write_entry(&mut entry, &target.join(name), max_entry_size)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn extract_tar(
+    reader: impl Read,
+    target: &Path,
+    max_entry_size: u64,
+    accept: &impl Fn(&Path) -> bool,
+) -> Result<()> {
+    let mut archive = tar::Archive::new(reader);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() || entry.header().size()? > max_entry_size {
+            continue;
+        }
+        let Some(name) = enclosed_name(&entry.path()?) else {
+            continue;
+        };
+        if accept(&name) {
+            write_entry(&mut entry, &target.join(name), max_entry_size)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A plain `.gz` holds a single file, named after the archive without its extension.
+fn extract_gz(
+    file: File,
+    archive: &Path,
+    target: &Path,
+    max_entry_size: u64,
+    accept: &impl Fn(&Path) -> bool,
+) -> Result<()> {
+    let Some(name) = archive.file_stem().map(Path::new) else {
+        return Ok(());
+    };
+    if accept(name) {
+        write_entry(
+            &mut GzDecoder::new(file),
+            &target.join(name),
+            max_entry_size,
+        )?;
+    }
+    Ok(())
+}
+
+/// Copies an entry to disk, dropping it if it turns out larger than `max_entry_size`.
+fn write_entry(reader: &mut impl Read, path: &Path, max_entry_size: u64) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut output = File::create(path)?;
+    let written = io::copy(&mut reader.take(max_entry_size + 1), &mut output)?;
+    if written > max_entry_size {
+        drop(output);
+        fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+/// Keeps entry paths inside the extraction directory, rejecting absolute paths and `..`.
+fn enclosed_name(path: &Path) -> Option<PathBuf> {
+    let mut name = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => name.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!name.as_os_str().is_empty()).then_some(name)
+}
+
+fn is_fresh(archive: &Path, target: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    match (modified(archive), modified(target)) {
+        (Some(archive_time), Some(cache_time)) => cache_time >= archive_time,
+        _ => false,
+    }
+}
+
+fn list_files(dir: &Path) -> Vec<PathBuf> {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .collect()
+}
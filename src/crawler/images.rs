@@ -0,0 +1,116 @@
+use std::io::Read;
+use std::path::Path;
+
+/// Extensions indexed and previewed as images rather than text
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "gif", "bmp", "webp"];
+/// Bytes read for the header, enough to reach the frame of a JPEG after its metadata
+const HEADER_BYTES: u64 = 65_536;
+
+/// Whether `path` is indexed and previewed as an image.
+pub fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Format and pixel size read from an image's header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageInfo {
+    pub format: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ImageInfo {
+    /// Reads the header of a PNG, GIF, BMP, JPEG or WebP file.
+    pub fn probe(data: &[u8]) -> Option<Self> {
+        let be16 = |at: usize| Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?));
+        let le16 = |at: usize| Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?));
+        let le24 = |at: usize| {
+            let bytes = data.get(at..at + 3)?;
+            Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]))
+        };
+        let byte = |at: usize| data.get(at).copied().map(u32::from);
+        let info = |format, width: u32, height: u32| Self {
+            format,
+            width,
+            height,
+        };
+
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            let width = u32::from_be_bytes(data.get(16..20)?.try_into().ok()?);
+            let height = u32::from_be_bytes(data.get(20..24)?.try_into().ok()?);
+            return Some(info("PNG", width, height));
+        }
+        if data.starts_with(b"GIF8") {
+            return Some(info("GIF", le16(6)?.into(), le16(8)?.into()));
+        }
+        if data.starts_with(b"BM") {
+            let width = i32::from_le_bytes(data.get(18..22)?.try_into().ok()?);
+            // Negative for images stored top-down
+            let height = i32::from_le_bytes(data.get(22..26)?.try_into().ok()?);
+            return Some(info("BMP", width.unsigned_abs(), height.unsigned_abs()));
+        }
+        if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+            return match data.get(12..16)? {
+                b"VP8X" => Some(info("WebP", le24(24)? + 1, le24(27)? + 1)),
+                b"VP8 " => Some(info(
+                    "WebP",
+                    (le16(26)? & 0x3fff).into(),
+                    (le16(28)? & 0x3fff).into(),
+                )),
+                b"VP8L" => {
+                    let (b1, b2, b3, b4) = (byte(21)?, byte(22)?, byte(23)?, byte(24)?);
+                    let width = 1 + (b1 | ((b2 & 0x3f) << 8));
+                    let height = 1 + ((b2 >> 6) | (b3 << 2) | ((b4 & 0x0f) << 10));
+                    Some(info("WebP", width, height))
+                }
+                _ => None,
+            };
+        }
+        if data.starts_with(b"\xff\xd8") {
+            // The frame header follows any number of metadata segments
+            let mut at = 2;
+            while at + 9 < data.len() {
+                if data[at] != 0xff {
+                    return None;
+                }
+                let marker = data[at + 1];
+                match marker {
+                    0xff => at += 1,
+                    0x01 | 0xd0..=0xd9 => at += 2,
+                    0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                        return Some(info("JPEG", be16(at + 7)?.into(), be16(at + 5)?.into()));
+                    }
+                    _ => at += 2 + usize::from(be16(at + 2)?),
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The text an image is indexed under: the words of its name, then its file name, format
+/// and pixel size, so it is found by what it shows as far as its name tells.
+pub fn describe(path: &Path) -> std::io::Result<String> {
+    let mut header = Vec::new();
+    std::fs::File::open(path)?
+        .take(HEADER_BYTES)
+        .read_to_end(&mut header)?;
+
+    let words = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().replace(['-', '_', '.'], " "))
+        .unwrap_or_default();
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    Ok(match ImageInfo::probe(&header) {
+        Some(info) => format!(
+            "{}\n{}: {} image, {} × {} pixels",
+            words, name, info.format, info.width, info.height
+        ),
+        None => format!("{}\n{}: image", words, name),
+    })
+}
@@ -1,10 +1,117 @@
-use std::collections::HashSet;
+pub mod archive;
+pub mod images;
+pub mod overrides;
+pub mod piped;
+pub mod root;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
 
 use anyhow::{Context, Result};
 use ignore::WalkBuilder;
+use ignore::gitignore::Gitignore;
+use tokio_util::sync::CancellationToken;
 
+use crate::config::OversizedFiles;
 use crate::types::CrawlerConfig;
+use overrides::{CrawlRules, DirectoryOverrides};
+
+const TEXT_SNIFF_BYTES: usize = 8192;
+
+/// Where a file outside the project really comes from, for files the crawler extracted
+/// from an archive or captured from stdin.
+pub fn virtual_path(path: &Path, root: &Path) -> Option<PathBuf> {
+    archive::virtual_path(path, root).or_else(|| piped::virtual_path(path, root))
+}
+
+/// Why a file of the project is not indexed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SkipReason {
+    /// Hidden by a `.gitignore` or `.ignore` file; ignored directories are listed whole
+    Ignored,
+    /// Matched by `exclude_patterns`, or excluded by a `.sema.toml` in one of its directories
+    Excluded,
+    TooLarge,
+    Empty,
+    /// Not one of the allowed extensions
+    Extension,
+    Binary,
+    /// Unreadable metadata, or an archive that could not be extracted
+    Unreadable,
+    /// Crawled, but reading or chunking it failed while indexing
+    ReadError,
+    /// Another link to a file already crawled
+    Duplicate,
+    /// Anything other than a regular file, such as a socket or a link not followed
+    Special,
+}
+
+impl SkipReason {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Ignored => "gitignored",
+            Self::Excluded => "excluded",
+            Self::TooLarge => "too large",
+            Self::Empty => "empty",
+            Self::Extension => "extension not indexed",
+            Self::Binary => "binary",
+            Self::Unreadable => "unreadable",
+            Self::ReadError => "read error",
+            Self::Duplicate => "duplicate link",
+            Self::Special => "not a regular file",
+        }
+    }
+}
+
+/// A file left out of the index, with the error that made it unreadable if there was one.
+#[derive(Debug, Clone)]
+pub struct SkippedFile {
+    pub path: PathBuf,
+    pub reason: SkipReason,
+    pub detail: Option<String>,
+}
+
+/// The files a crawl skipped. Hidden files left out by `include_hidden` and directories
+/// below `max_depth` are never walked, so they are not listed.
+#[derive(Debug, Clone, Default)]
+pub struct CrawlStats {
+    pub skipped: Vec<SkippedFile>,
+    /// Set to `max_files` when the crawl stopped there, leaving the rest of the tree unseen
+    pub file_limit: Option<usize>,
+    /// Files a `.sema.toml` gives a size limit other than the global one, with that limit
+    pub size_limits: HashMap<PathBuf, u64>,
+}
+
+impl CrawlStats {
+    fn skip(&mut self, path: &Path, reason: SkipReason) {
+        self.skipped.push(SkippedFile {
+            path: path.to_owned(),
+            reason,
+            detail: None,
+        });
+    }
+
+    pub fn counts(&self) -> BTreeMap<SkipReason, usize> {
+        let mut counts = BTreeMap::new();
+        for file in &self.skipped {
+            *counts.entry(file.reason).or_default() += 1;
+        }
+        counts
+    }
+
+    /// Sorted by reason, then path, for listing.
+    pub fn sort(&mut self) {
+        self.skipped
+            .sort_by(|a, b| a.reason.cmp(&b.reason).then_with(|| a.path.cmp(&b.path)));
+    }
+}
+
+#[cfg(unix)]
+type FileId = (u64, u64);
+#[cfg(not(unix))]
+type FileId = PathBuf;
 
 pub struct FileCrawler {
     config: CrawlerConfig,
@@ -15,90 +122,371 @@ impl FileCrawler {
         Self { config }
     }
 
-    pub async fn crawl_directory(&self, root_path: &Path) -> Result<Vec<PathBuf>> {
+    /// Crawls `root_path`, listing the files skipped along the way and the size limits
+    /// `.sema.toml` files set.
+    pub async fn crawl_directory(
+        &self,
+        root_path: &Path,
+        cancel: CancellationToken,
+    ) -> Result<(Vec<PathBuf>, CrawlStats)> {
         let root_path = root_path.to_owned();
         let config = self.config.clone();
 
-        tokio::task::spawn_blocking(move || Self::crawl(root_path, config))
+        tokio::task::spawn_blocking(move || Self::crawl(root_path, config, false, cancel))
             .await
             .context("Crawler task failed")?
     }
 
-    fn crawl(root_path: PathBuf, config: CrawlerConfig) -> Result<Vec<PathBuf>> {
-        let allowed_extensions: Option<HashSet<String>> = if !config.file_extensions.is_empty() {
-            Some(
-                config
-                    .file_extensions
-                    .iter()
-                    .map(|ext| {
-                        ext.trim_start_matches("*.")
-                            .trim_start_matches('.')
-                            .to_lowercase()
-                    })
-                    .collect(),
-            )
-        } else {
-            None
-        };
+    /// Crawls like `crawl_directory`, also listing the files the project's ignore files
+    /// hid, which takes a second walk of the directories.
+    pub async fn crawl_directory_with_stats(
+        &self,
+        root_path: &Path,
+        cancel: CancellationToken,
+    ) -> Result<(Vec<PathBuf>, CrawlStats)> {
+        let root_path = root_path.to_owned();
+        let config = self.config.clone();
+
+        tokio::task::spawn_blocking(move || Self::crawl(root_path, config, true, cancel))
+            .await
+            .context("Crawler task failed")?
+    }
+
+    #[tracing::instrument(skip_all, fields(root = %root_path.display()))]
+    fn crawl(
+        root_path: PathBuf,
+        config: CrawlerConfig,
+        find_ignored: bool,
+        cancel: CancellationToken,
+    ) -> Result<(Vec<PathBuf>, CrawlStats)> {
+        // `.sema.toml` files below the root adjust these for their subtrees as they are
+        // found. The walk is sequential, so a directory is visited before its entries are
+        // filtered
+        let overrides = Arc::new(Mutex::new(DirectoryOverrides::new(CrawlRules::new(
+            &config.file_extensions,
+            config.max_file_size,
+        ))));
+
+        let excludes = Arc::new(overrides::global_excludes(
+            &root_path,
+            &config.exclude_patterns,
+        )?);
 
         let mut walker = WalkBuilder::new(&root_path);
         walker
             .follow_links(config.follow_symlinks)
             .hidden(!config.include_hidden)
-            .max_filesize(Some(config.max_file_size))
             .skip_stdout(true)
             .git_ignore(config.ignore_gitignore)
             .same_file_system(true);
 
-        for pattern in &config.exclude_patterns {
-            walker.add_ignore(format!("!{}", pattern));
-        }
+        // Symlink farms can nest arbitrarily deep or loop back on themselves, so bound the
+        // depth and never descend into the same directory twice.
+        let visited_dirs: Option<Arc<Mutex<HashSet<FileId>>>> =
+            config.follow_symlinks.then(Arc::default);
+        walker.max_depth(Self::max_depth(&config));
+
+        // Excluded directories are pruned rather than walked, and reported once the walk is done
+        let excluded: Arc<Mutex<Vec<(PathBuf, bool)>>> = Arc::default();
+        let filter_excludes = excludes.clone();
+        let filter_overrides = overrides.clone();
+        let filter_excluded = excluded.clone();
+        walker.filter_entry(move |entry| {
+            let is_dir = entry.file_type().is_some_and(|ft| ft.is_dir());
+            if entry.depth() > 0
+                && (filter_excludes.matched(entry.path(), is_dir).is_ignore()
+                    || filter_overrides
+                        .lock()
+                        .is_ok_and(|overrides| overrides.is_excluded(entry.path(), is_dir)))
+            {
+                if let Ok(mut excluded) = filter_excluded.lock() {
+                    excluded.push((entry.path().to_owned(), is_dir));
+                }
+                return false;
+            }
+
+            let Some(visited_dirs) = &visited_dirs else {
+                return true;
+            };
+            if !is_dir {
+                return true;
+            }
+            match Self::file_id(entry.path()) {
+                Some(id) => visited_dirs
+                    .lock()
+                    .map(|mut visited| visited.insert(id))
+                    .unwrap_or(true),
+                None => true,
+            }
+        });
 
         let walk_results = walker.build();
         let mut files = Vec::new();
+        let mut seen_files: HashSet<FileId> = HashSet::new();
+        let mut stats = CrawlStats::default();
+        let mut walked: HashSet<PathBuf> = HashSet::new();
 
         for entry_result in walk_results {
+            if cancel.is_cancelled() {
+                break;
+            }
+            // Archives may have added more than one file at a time
+            if config.max_files > 0 && files.len() >= config.max_files {
+                files.truncate(config.max_files);
+                stats.file_limit = Some(config.max_files);
+                tracing::warn!(
+                    max_files = config.max_files,
+                    "Crawl stopped at the file limit"
+                );
+                break;
+            }
+
             if let Ok(entry) = entry_result {
-                if let Some(file_path) =
-                    Self::process_entry(&entry, &allowed_extensions, config.max_file_size)
+                if find_ignored {
+                    walked.insert(entry.path().to_owned());
+                }
+                let mut overrides = overrides.lock().unwrap_or_else(PoisonError::into_inner);
+                // Walked before anything inside, so the overrides are in place for it
+                if entry.file_type().is_some_and(|ft| ft.is_dir()) {
+                    if let Err(e) = overrides.visit_dir(entry.path()) {
+                        tracing::warn!("Ignoring crawler overrides: {:#}", e);
+                    }
+                    continue;
+                }
+                let rules = overrides.rules_for(entry.path());
+                // The processor cuts oversized files down against the limit in effect for them
+                let size_limit =
+                    (rules.max_file_size != config.max_file_size).then_some(rules.max_file_size);
+                let allowed_extensions = &rules.allowed_extensions;
+
+                if config.index_archives
+                    && entry.file_type().is_some_and(|ft| ft.is_file())
+                    && archive::ArchiveFormat::from_path(entry.path()).is_some()
+                {
+                    let accept =
+                        |name: &Path| Self::has_allowed_extension(name, allowed_extensions);
+                    match archive::extract(entry.path(), &root_path, rules.max_file_size, accept) {
+                        Ok(extracted) => {
+                            for path in extracted {
+                                if allowed_extensions.is_none() && !Self::is_text_file(&path) {
+                                    continue;
+                                }
+                                if let Some(size_limit) = size_limit {
+                                    stats.size_limits.insert(path.clone(), size_limit);
+                                }
+                                files.push(path);
+                            }
+                        }
+                        Err(e) => {
+                            stats.skip(entry.path(), SkipReason::Unreadable);
+                            tracing::debug!(
+                                archive = %entry.path().display(),
+                                "Skipped unreadable archive: {:#}",
+                                e
+                            )
+                        }
+                    }
+                    continue;
+                }
+
+                // Only the name and header of an image are indexed, so neither the allowed
+                // extensions nor the size limit apply
+                if config.index_images
+                    && entry.file_type().is_some_and(|ft| ft.is_file())
+                    && images::is_image(entry.path())
                 {
-                    files.push(file_path);
+                    files.push(entry.path().to_owned());
+                    continue;
+                }
+
+                // Oversized files are still crawled when they are going to be partially indexed
+                let max_size = match config.oversized_files {
+                    OversizedFiles::Skip => rules.max_file_size,
+                    OversizedFiles::Head | OversizedFiles::Sample => u64::MAX,
+                };
+                match Self::process_entry(&entry, allowed_extensions, max_size) {
+                    Ok(file_path) => {
+                        if config.follow_symlinks
+                            && let Some(id) = Self::file_id(&file_path)
+                            && !seen_files.insert(id)
+                        {
+                            stats.skip(&file_path, SkipReason::Duplicate);
+                            continue;
+                        }
+                        if let Some(size_limit) = size_limit {
+                            stats.size_limits.insert(file_path.clone(), size_limit);
+                        }
+                        files.push(file_path);
+                    }
+                    Err(reason) => stats.skip(entry.path(), reason),
                 }
             }
         }
 
-        Ok(files)
+        let excluded = excluded
+            .lock()
+            .map(|mut excluded| std::mem::take(&mut *excluded))
+            .unwrap_or_default();
+        for (path, is_dir) in excluded {
+            stats.skip(&path, SkipReason::Excluded);
+            if is_dir && let Some(file) = stats.skipped.last_mut() {
+                file.detail = Some("whole directory".to_string());
+            }
+        }
+
+        // Looking for ignored files would walk the rest of an oversized tree after all
+        if find_ignored && !cancel.is_cancelled() && stats.file_limit.is_none() {
+            Self::find_ignored(&root_path, &config, &excludes, Arc::new(walked), &mut stats);
+        }
+
+        files.extend(config.extra_files);
+        tracing::info!(
+            files = files.len(),
+            skipped = stats.skipped.len(),
+            "Crawl finished"
+        );
+
+        Ok((files, stats))
+    }
+
+    /// Walks the directories of a crawl again with ignore files off, listing what the crawl
+    /// didn't see. Ignored directories are listed without descending into them, and the
+    /// excluded ones, already reported, are left out.
+    fn find_ignored(
+        root_path: &Path,
+        config: &CrawlerConfig,
+        excludes: &Arc<Gitignore>,
+        walked: Arc<HashSet<PathBuf>>,
+        stats: &mut CrawlStats,
+    ) {
+        // Those a `.sema.toml` excluded were pruned, so the global excludes don't cover them
+        let excluded: HashSet<PathBuf> = stats
+            .skipped
+            .iter()
+            .filter(|file| file.reason == SkipReason::Excluded)
+            .map(|file| file.path.clone())
+            .collect();
+
+        let mut walker = WalkBuilder::new(root_path);
+        walker
+            .hidden(!config.include_hidden)
+            .ignore(false)
+            .git_ignore(false)
+            .git_global(false)
+            .git_exclude(false)
+            .skip_stdout(true)
+            .same_file_system(true)
+            .max_depth(Self::max_depth(config));
+
+        let walked_dirs = walked.clone();
+        let excludes = excludes.clone();
+        walker.filter_entry(move |entry| {
+            if entry.depth() == 0 {
+                return true;
+            }
+            let is_dir = entry.file_type().is_some_and(|ft| ft.is_dir());
+            !excludes.matched(entry.path(), is_dir).is_ignore()
+                && !excluded.contains(entry.path())
+                && entry
+                    .path()
+                    .parent()
+                    .is_some_and(|parent| walked_dirs.contains(parent))
+        });
+
+        for entry in walker.build().flatten() {
+            if entry.path_is_symlink() || walked.contains(entry.path()) {
+                continue;
+            }
+            stats.skip(entry.path(), SkipReason::Ignored);
+            if entry.file_type().is_some_and(|ft| ft.is_dir())
+                && let Some(file) = stats.skipped.last_mut()
+            {
+                file.detail = Some("whole directory".to_string());
+            }
+        }
+    }
+
+    /// The tighter of `max_depth` and, when following symlinks, `max_symlink_depth`.
+    fn max_depth(config: &CrawlerConfig) -> Option<usize> {
+        let max_depth = (config.max_depth > 0).then_some(config.max_depth);
+        let max_symlink_depth = config.follow_symlinks.then_some(config.max_symlink_depth);
+        max_depth.into_iter().chain(max_symlink_depth).min()
+    }
+
+    /// Identifies the file a path resolves to, so links to the same target compare equal.
+    #[cfg(unix)]
+    fn file_id(path: &Path) -> Option<FileId> {
+        use std::os::unix::fs::MetadataExt;
+        let metadata = std::fs::metadata(path).ok()?;
+        Some((metadata.dev(), metadata.ino()))
+    }
+
+    #[cfg(not(unix))]
+    fn file_id(path: &Path) -> Option<FileId> {
+        path.canonicalize().ok()
     }
 
     fn process_entry(
         entry: &ignore::DirEntry,
         allowed_extensions: &Option<HashSet<String>>,
         max_size: u64,
-    ) -> Option<PathBuf> {
+    ) -> Result<PathBuf, SkipReason> {
         let path = entry.path();
-        let metadata = match entry.metadata() {
-            Ok(m) => m,
-            Err(_) => return None,
-        };
+        let metadata = entry.metadata().map_err(|_| SkipReason::Unreadable)?;
+
+        if !metadata.is_file() {
+            return Err(SkipReason::Special);
+        }
+        if metadata.len() == 0 {
+            return Err(SkipReason::Empty);
+        }
+        if metadata.len() > max_size {
+            return Err(SkipReason::TooLarge);
+        }
 
-        if !metadata.is_file() || metadata.len() == 0 || metadata.len() > max_size {
-            return None;
+        if !Self::has_allowed_extension(path, allowed_extensions) {
+            return Err(SkipReason::Extension);
         }
 
-        if let Some(ext_set) = allowed_extensions {
-            let extension_os = match path.extension() {
-                Some(ext) => ext,
-                None => return None,
-            };
-            let extension = match extension_os.to_str() {
-                Some(s) => s.to_lowercase(),
-                None => return None,
-            };
-            if !ext_set.contains(&extension) {
-                return None;
-            }
+        // Without an allowlist every file is a candidate, so binaries are sniffed out instead
+        if allowed_extensions.is_none() && !Self::is_text_file(path) {
+            return Err(SkipReason::Binary);
         }
 
-        Some(path.to_owned())
+        Ok(path.to_owned())
+    }
+
+    /// Treats a file as text when its first few KiB are valid UTF-8 without NUL bytes.
+    fn is_text_file(path: &Path) -> bool {
+        let Ok(file) = std::fs::File::open(path) else {
+            return false;
+        };
+        let mut head = Vec::with_capacity(TEXT_SNIFF_BYTES);
+        if file
+            .take(TEXT_SNIFF_BYTES as u64)
+            .read_to_end(&mut head)
+            .is_err()
+        {
+            return false;
+        }
+
+        if head.contains(&0) {
+            return false;
+        }
+        match std::str::from_utf8(&head) {
+            Ok(_) => true,
+            // The sample may end partway through a multi-byte character
+            Err(e) => e.error_len().is_none(),
+        }
+    }
+
+    fn has_allowed_extension(path: &Path, allowed_extensions: &Option<HashSet<String>>) -> bool {
+        let Some(ext_set) = allowed_extensions else {
+            return true;
+        };
+
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext_set.contains(&ext.to_lowercase()))
     }
 }
@@ -0,0 +1,310 @@
+use anyhow::{Context, Result};
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Adjusts how the directory holding it, and everything below, is crawled
+pub const OVERRIDE_FILE: &str = ".sema.toml";
+
+/// What a `.sema.toml` may change. Settings left out are inherited from the nearest
+/// `.sema.toml` above, or from the global config.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct OverrideFile {
+    /// Replaces the inherited extensions; an empty list indexes every text file
+    file_extensions: Option<Vec<String>>,
+    max_file_size: Option<u64>,
+    /// Gitignore-style patterns relative to the directory; `!pattern` brings back files a
+    /// `.sema.toml` further up excluded
+    exclude_patterns: Vec<String>,
+}
+
+/// Crawler settings in effect for one subtree.
+#[derive(Debug, Clone)]
+pub struct CrawlRules {
+    /// Lowercase extensions without the dot, or None to accept every text file
+    pub allowed_extensions: Option<HashSet<String>>,
+    pub max_file_size: u64,
+}
+
+impl CrawlRules {
+    pub fn new(file_extensions: &[String], max_file_size: u64) -> Self {
+        let allowed_extensions = (!file_extensions.is_empty()).then(|| {
+            file_extensions
+                .iter()
+                .map(|ext| {
+                    ext.trim_start_matches("*.")
+                        .trim_start_matches('.')
+                        .to_lowercase()
+                })
+                .collect()
+        });
+
+        Self {
+            allowed_extensions,
+            max_file_size,
+        }
+    }
+}
+
+/// Builds the `exclude_patterns` of the config into a matcher for paths under `root`.
+/// Patterns follow `.gitignore` syntax relative to the root: `target` excludes every file
+/// or directory of that name, `*.tmp` is a glob and `/build` only matches at the root.
+/// Absolute paths inside the root are made relative to it; any other pattern starting
+/// with `/` is anchored to the root, as in `.gitignore`.
+pub fn global_excludes(root: &Path, patterns: &[String]) -> Result<Gitignore> {
+    let mut excludes = GitignoreBuilder::new(root);
+    for pattern in patterns {
+        let pattern = match Path::new(pattern).strip_prefix(root) {
+            Ok(relative) => format!("/{}", relative.display()),
+            Err(_) => pattern.clone(),
+        };
+        excludes
+            .add_line(None, &pattern)
+            .with_context(|| format!("Invalid exclude pattern: {}", pattern))?;
+    }
+    Ok(excludes.build()?)
+}
+
+struct Scope {
+    dir: PathBuf,
+    rules: CrawlRules,
+    excludes: Gitignore,
+}
+
+/// The `.sema.toml` files found so far in a crawl, layered over the global rules.
+pub struct DirectoryOverrides {
+    global: CrawlRules,
+    scopes: Vec<Scope>,
+}
+
+impl DirectoryOverrides {
+    pub fn new(global: CrawlRules) -> Self {
+        Self {
+            global,
+            scopes: Vec::new(),
+        }
+    }
+
+    /// Loads the `.sema.toml` in `dir`, if there is one, on top of the rules in effect
+    /// there. Directories must be visited before anything inside them is looked up.
+    pub fn visit_dir(&mut self, dir: &Path) -> Result<()> {
+        let path = dir.join(OVERRIDE_FILE);
+        if !path.is_file() {
+            return Ok(());
+        }
+
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let file: OverrideFile =
+            toml::from_str(&content).with_context(|| format!("Invalid {}", path.display()))?;
+
+        let mut rules = self.rules_for(dir).clone();
+        if let Some(file_extensions) = &file.file_extensions {
+            rules.allowed_extensions = CrawlRules::new(file_extensions, 0).allowed_extensions;
+        }
+        if let Some(max_file_size) = file.max_file_size {
+            rules.max_file_size = max_file_size;
+        }
+
+        let mut excludes = GitignoreBuilder::new(dir);
+        for pattern in &file.exclude_patterns {
+            excludes
+                .add_line(None, pattern)
+                .with_context(|| format!("Invalid pattern in {}: {}", path.display(), pattern))?;
+        }
+
+        self.scopes.push(Scope {
+            dir: dir.to_owned(),
+            rules,
+            excludes: excludes.build()?,
+        });
+        Ok(())
+    }
+
+    /// The rules of the innermost `.sema.toml` above `path`, or the global ones.
+    pub fn rules_for(&self, path: &Path) -> &CrawlRules {
+        self.scopes_above(path)
+            .next()
+            .map_or(&self.global, |scope| &scope.rules)
+    }
+
+    /// Whether a `.sema.toml` above `path` excludes it. The innermost one with a pattern
+    /// matching the path, or a directory above it, decides.
+    pub fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        for scope in self.scopes_above(path) {
+            match scope.excludes.matched_path_or_any_parents(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+
+    /// Scopes whose directory contains `path`, innermost first.
+    fn scopes_above(&self, path: &Path) -> impl Iterator<Item = &Scope> {
+        let mut scopes: Vec<&Scope> = self
+            .scopes
+            .iter()
+            .filter(|scope| path.starts_with(&scope.dir))
+            .collect();
+        scopes.sort_by_key(|scope| std::cmp::Reverse(scope.dir.components().count()));
+        scopes.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GeneralConfig;
+    use crate::crawler::FileCrawler;
+    use crate::types::CrawlerConfig;
+    use tokio_util::sync::CancellationToken;
+
+    fn excludes(patterns: &[&str]) -> Gitignore {
+        let patterns: Vec<String> = patterns.iter().map(|pattern| pattern.to_string()).collect();
+        global_excludes(Path::new("/project"), &patterns).unwrap()
+    }
+
+    fn excluded(excludes: &Gitignore, path: &str, is_dir: bool) -> bool {
+        excludes
+            .matched_path_or_any_parents(Path::new("/project").join(path), is_dir)
+            .is_ignore()
+    }
+
+    /// A directory under the system's temporary one, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("sema-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir.canonicalize().unwrap())
+        }
+
+        fn write(&self, path: &str, content: &str) {
+            let path = self.0.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn directory_names_exclude_at_any_depth() {
+        let excludes = excludes(&["target"]);
+        assert!(excluded(&excludes, "target", true));
+        assert!(excluded(&excludes, "crates/core/target", true));
+        assert!(excluded(
+            &excludes,
+            "crates/core/target/debug/build.rs",
+            false
+        ));
+        assert!(!excluded(&excludes, "targets", true));
+        assert!(!excluded(&excludes, "src/target.rs", false));
+    }
+
+    #[test]
+    fn globs_and_anchored_patterns() {
+        let excludes = excludes(&["*.tmp", "/build"]);
+        assert!(excluded(&excludes, "notes.tmp", false));
+        assert!(excluded(&excludes, "src/cache/old.tmp", false));
+        assert!(!excluded(&excludes, "src/main.rs", false));
+        assert!(excluded(&excludes, "build", true));
+        assert!(excluded(&excludes, "build/output.rs", false));
+        assert!(!excluded(&excludes, "src/build", true));
+    }
+
+    #[test]
+    fn absolute_paths_are_anchored_to_the_root() {
+        let excludes = excludes(&["/project/vendor/lib", "/docs/generated"]);
+        assert!(excluded(&excludes, "vendor/lib", true));
+        assert!(excluded(&excludes, "vendor/lib/mod.rs", false));
+        assert!(!excluded(&excludes, "src/vendor/lib", true));
+        assert!(!excluded(&excludes, "vendor", true));
+        // Not under the root, so a pattern anchored to it
+        assert!(excluded(&excludes, "docs/generated", true));
+        assert!(!excluded(&excludes, "src/docs/generated", true));
+    }
+
+    #[test]
+    fn invalid_patterns_are_errors() {
+        let patterns = vec!["src/{a".to_string()];
+        assert!(global_excludes(Path::new("/project"), &patterns).is_err());
+    }
+
+    #[test]
+    fn project_overrides_cannot_bring_back_global_excludes() {
+        let root = TempDir::new("global-excludes");
+        root.write("main.rs", "fn main() {}\n");
+        root.write("debug.log", "started\n");
+        root.write(
+            "sub/.sema.toml",
+            "exclude_patterns = [\"!*.log\", \"generated\"]\n",
+        );
+        root.write("sub/lib.rs", "pub fn lib() {}\n");
+        root.write("sub/keep.log", "kept?\n");
+        root.write("sub/generated/out.rs", "pub fn out() {}\n");
+
+        let mut config = CrawlerConfig::from(&GeneralConfig::default());
+        config.file_extensions = Vec::new();
+        config.include_hidden = false;
+        config.exclude_patterns = vec!["*.log".to_string()];
+
+        let (mut files, _) =
+            FileCrawler::crawl(root.0.clone(), config, false, CancellationToken::new()).unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![root.0.join("main.rs"), root.0.join("sub/lib.rs")]
+        );
+    }
+
+    #[test]
+    fn project_overrides_prune_excluded_directories() {
+        let root = TempDir::new("override-prune");
+        root.write("main.rs", "fn main() {}\n");
+        root.write(
+            "sub/.sema.toml",
+            "max_file_size = 1\nexclude_patterns = [\"generated/\"]\n",
+        );
+        root.write("sub/lib.rs", "pub fn lib() {}\n");
+        root.write("sub/generated/out.rs", "pub fn out() {}\n");
+
+        let mut config = CrawlerConfig::from(&GeneralConfig::default());
+        config.file_extensions = Vec::new();
+        config.include_hidden = false;
+        config.oversized_files = crate::config::OversizedFiles::Head;
+
+        let (mut files, stats) =
+            FileCrawler::crawl(root.0.clone(), config, true, CancellationToken::new()).unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![root.0.join("main.rs"), root.0.join("sub/lib.rs")]
+        );
+        let skipped: Vec<(&Path, Option<&str>)> = stats
+            .skipped
+            .iter()
+            .map(|file| (file.path.as_path(), file.detail.as_deref()))
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![(
+                root.0.join("sub/generated").as_path(),
+                Some("whole directory")
+            )]
+        );
+        assert_eq!(stats.size_limits.get(&root.0.join("sub/lib.rs")), Some(&1));
+        assert!(!stats.size_limits.contains_key(&root.0.join("main.rs")));
+    }
+}
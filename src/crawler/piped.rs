@@ -0,0 +1,45 @@
+use anyhow::{Context, Result};
+use std::fs::{self, File};
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+
+use crate::config::ConfigManager;
+
+/// Name given to piped content when `--name` isn't set.
+pub const DEFAULT_NAME: &str = "stdin";
+const VIRTUAL_PREFIX: &str = "stdin:";
+
+/// Where piped content for the project under `root` is stored.
+pub fn capture_dir(root: &Path) -> PathBuf {
+    ConfigManager::get_project_dir(root).join("stdin")
+}
+
+/// Streams stdin to a file in the capture directory so it is chunked and indexed like any
+/// other file. A later capture with the same name replaces it.
+pub fn capture(root: &Path, name: &str) -> Result<PathBuf> {
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        anyhow::bail!("--stdin expects piped input, e.g. `kubectl logs my-pod | sema --stdin`");
+    }
+
+    // Only the final component is used so a name can't point outside the capture directory
+    let name = Path::new(name)
+        .file_name()
+        .unwrap_or_else(|| DEFAULT_NAME.as_ref());
+    let dir = capture_dir(root);
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create capture directory: {:?}", dir))?;
+
+    let path = dir.join(name);
+    let mut file =
+        File::create(&path).with_context(|| format!("Failed to create capture: {:?}", path))?;
+    io::copy(&mut stdin.lock(), &mut file).context("Failed to read piped input")?;
+
+    Ok(path)
+}
+
+/// Shows captured content as `stdin:<name>` rather than its location in the data directory.
+pub fn virtual_path(path: &Path, root: &Path) -> Option<PathBuf> {
+    let name = path.strip_prefix(capture_dir(root)).ok()?;
+    Some(root.join(format!("{}{}", VIRTUAL_PREFIX, name.display())))
+}
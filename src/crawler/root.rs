@@ -0,0 +1,91 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config::ConfigManager;
+
+/// Entries that mark the top of a working copy, checked from the launch directory upwards.
+const VCS_MARKERS: &[&str] = &[".git", ".hg", ".jj", ".svn"];
+
+/// What was decided about where to index from when sema was launched in a directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ProjectMetadata {
+    root: PathBuf,
+}
+
+/// The nearest ancestor of `dir`, or `dir` itself, containing a VCS marker.
+pub fn find_vcs_root(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|ancestor| {
+            VCS_MARKERS
+                .iter()
+                .any(|marker| ancestor.join(marker).exists())
+        })
+        .map(Path::to_path_buf)
+}
+
+/// Picks the directory to index for a launch from `launch_dir`. A root chosen on an earlier
+/// run is reused so relative paths stay the same; otherwise, when `launch_dir` sits inside
+/// a repository, the user is asked whether to index from its root and the answer is kept.
+/// Without a terminal to ask on, `launch_dir` is used as is.
+pub fn resolve(launch_dir: &Path, interactive: bool) -> Result<PathBuf> {
+    if let Some(root) = pinned_root(launch_dir) {
+        return Ok(root);
+    }
+
+    let Some(vcs_root) = find_vcs_root(launch_dir).filter(|root| root != launch_dir) else {
+        return Ok(launch_dir.to_path_buf());
+    };
+    if !interactive || !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+        return Ok(launch_dir.to_path_buf());
+    }
+
+    let root = if confirm(&format!(
+        "{} is inside the repository at {}. Index from the repository root? [Y/n] ",
+        launch_dir.display(),
+        vcs_root.display()
+    ))? {
+        vcs_root
+    } else {
+        launch_dir.to_path_buf()
+    };
+
+    if let Err(e) = pin_root(launch_dir, &root) {
+        eprintln!("Warning: {:#}", e);
+    }
+    Ok(root)
+}
+
+fn metadata_path(launch_dir: &Path) -> PathBuf {
+    ConfigManager::get_project_dir(launch_dir).join("project.json")
+}
+
+fn pinned_root(launch_dir: &Path) -> Option<PathBuf> {
+    let content = fs::read_to_string(metadata_path(launch_dir)).ok()?;
+    let metadata: ProjectMetadata = serde_json::from_str(&content).ok()?;
+    metadata.root.is_dir().then_some(metadata.root)
+}
+
+fn pin_root(launch_dir: &Path, root: &Path) -> Result<()> {
+    let path = metadata_path(launch_dir);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let metadata = ProjectMetadata {
+        root: root.to_path_buf(),
+    };
+    fs::write(&path, serde_json::to_string_pretty(&metadata)?)
+        .with_context(|| format!("Failed to record the project root: {:?}", path))
+}
+
+fn confirm(prompt: &str) -> Result<bool> {
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    let answer = answer.trim().to_lowercase();
+    Ok(answer.is_empty() || answer == "y" || answer == "yes")
+}
@@ -0,0 +1,331 @@
+pub mod schedule;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{Config, ConfigManager};
+use crate::crawler::FileCrawler;
+use crate::projects::ProjectRegistry;
+use crate::storage::annotations::Annotation;
+use crate::storage::{SharedStorage, StorageManager};
+use crate::types::{Chunk, CrawlerConfig, FileIndex, QueryMode, SearchTiming};
+
+const SOCKET_FILE: &str = "daemon.sock";
+
+/// One line of JSON sent by a client.
+#[derive(Debug, Serialize, Deserialize)]
+enum Request {
+    Search { query: String, limit: usize },
+    LineRelevance { query: String, chunk: Chunk },
+    FileInfo { file_path: PathBuf },
+    Annotations,
+}
+
+/// One line of JSON sent back for each request.
+#[derive(Debug, Serialize, Deserialize)]
+enum Reply {
+    Search(SearchReply),
+    LineRelevance(Vec<f32>),
+    FileInfo(Option<FileIndex>),
+    Annotations(Vec<Annotation>),
+    Error(String),
+}
+
+/// Results of a search run by the daemon, with what the UI shows next to them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchReply {
+    pub results: Vec<(Chunk, f32)>,
+    pub answered_by: QueryMode,
+    pub semantic_unavailable: Option<String>,
+    /// Respelling of a query that found nothing
+    #[serde(default)]
+    pub suggestion: Option<String>,
+    /// Where the daemon's time went, absent from older daemons
+    #[serde(default)]
+    pub timing: Option<SearchTiming>,
+}
+
+/// Where the daemon for the project under `root` listens.
+pub fn socket_path(root: &Path) -> PathBuf {
+    ConfigManager::get_project_dir(root).join(SOCKET_FILE)
+}
+
+/// Keeps the indexes and embedding model for `root` loaded and answers searches over a
+/// unix socket until interrupted. The project is indexed in the background on start, as
+/// the TUI does, and searches are answered from the existing index meanwhile. With
+/// `[daemon] reindex` set, the whole project is crawled and reconciled again on that
+/// schedule, catching changes made while nothing was watching.
+#[cfg(unix)]
+pub async fn run(root: PathBuf, config: Config) -> Result<()> {
+    let socket = socket_path(&root);
+    if DaemonClient::connect(&root).await.is_some() {
+        anyhow::bail!("A daemon is already serving {}", root.display());
+    }
+    let schedule = schedule::ReindexSchedule::parse(&config.daemon.reindex)
+        .context("Invalid [daemon] reindex setting")?;
+
+    let storage: SharedStorage = Arc::new(Mutex::new(
+        StorageManager::new(&ConfigManager::get_data_dir(), &config).await?,
+    ));
+    if let Some(parent) = socket.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Left behind by a daemon that didn't shut down cleanly, since nothing answered on it
+    let _ = std::fs::remove_file(&socket);
+    let listener = tokio::net::UnixListener::bind(&socket)
+        .with_context(|| format!("Failed to listen on {}", socket.display()))?;
+    eprintln!("Serving {} on {}", root.display(), socket.display());
+
+    let cancel = CancellationToken::new();
+    let crawler_config = CrawlerConfig::from(&config.general);
+    let mut indexing = spawn_indexing(&root, &crawler_config, &storage, &cancel);
+    let mut next_reindex =
+        schedule.map(|schedule| tokio::time::Instant::now() + schedule.next_delay());
+
+    loop {
+        tokio::select! {
+            _ = sleep_until(next_reindex) => {
+                if indexing.is_finished() {
+                    report_indexing(indexing.await);
+                    eprintln!("Reindexing {} on schedule", root.display());
+                    indexing = spawn_indexing(&root, &crawler_config, &storage, &cancel);
+                } else {
+                    eprintln!("Skipping the scheduled reindex: the last one is still running");
+                }
+                next_reindex =
+                    schedule.map(|schedule| tokio::time::Instant::now() + schedule.next_delay());
+            }
+            accepted = listener.accept() => {
+                // Failing to accept one connection, say when out of file descriptors,
+                // leaves the daemon serving the others
+                let stream = match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        eprintln!("Warning: Failed to accept a connection: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                let storage = storage.clone();
+                tokio::spawn(async move {
+                    let (reader, writer) = stream.into_split();
+                    if let Err(e) = serve_connection(reader, writer, storage).await {
+                        eprintln!("Warning: Daemon connection failed: {:#}", e);
+                    }
+                });
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    // Work indexed so far is committed before the task returns
+    cancel.cancel();
+    report_indexing(indexing.await);
+    let _ = std::fs::remove_file(&socket);
+
+    Ok(())
+}
+
+/// Crawls the project and indexes new and changed files in the background.
+#[cfg(unix)]
+fn spawn_indexing(
+    root: &Path,
+    crawler_config: &CrawlerConfig,
+    storage: &SharedStorage,
+    cancel: &CancellationToken,
+) -> tokio::task::JoinHandle<Result<usize>> {
+    let root = root.to_owned();
+    let crawler_config = crawler_config.clone();
+    let storage = storage.clone();
+    let cancel = cancel.clone();
+    tokio::spawn(async move {
+        let crawl_started = Instant::now();
+        let (files, stats) = FileCrawler::new(crawler_config)
+            .crawl_directory(&root, cancel.clone())
+            .await?;
+        if let Err(e) = ProjectRegistry::register(&root) {
+            eprintln!("Warning: {:#}", e);
+        }
+        storage.lock().await.record_crawl(crawl_started.elapsed());
+        let indexed =
+            StorageManager::process_and_index_files(&storage, files, stats.size_limits, &cancel)
+                .await;
+        if let Some(profile) = storage.lock().await.index_profile() {
+            eprintln!("{}", profile.report());
+        }
+        indexed
+    })
+}
+
+#[cfg(unix)]
+fn report_indexing(result: Result<Result<usize>, tokio::task::JoinError>) {
+    match result {
+        Ok(Err(e)) => eprintln!("Warning: Indexing failed: {:#}", e),
+        Err(e) => eprintln!("Warning: Indexing failed: {}", e),
+        Ok(Ok(_)) => {}
+    }
+}
+
+/// Waits for the deadline, or forever without one.
+#[cfg(unix)]
+async fn sleep_until(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn run(_root: PathBuf, _config: Config) -> Result<()> {
+    anyhow::bail!("The daemon needs unix sockets, which this platform doesn't have")
+}
+
+async fn serve_connection(
+    reader: impl AsyncRead + Unpin,
+    mut writer: impl AsyncWrite + Unpin,
+    storage: SharedStorage,
+) -> Result<()> {
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        let reply = match serde_json::from_str(&line) {
+            Ok(request) => handle(request, &storage).await,
+            Err(e) => Reply::Error(format!("Malformed request: {}", e)),
+        };
+
+        let mut reply = serde_json::to_string(&reply)?;
+        reply.push('\n');
+        writer.write_all(reply.as_bytes()).await?;
+    }
+
+    Ok(())
+}
+
+/// Answers a request. Searches take the storage only to read the indexes, so clients
+/// searching at once don't wait on each other's query embedding.
+async fn handle(request: Request, storage: &SharedStorage) -> Reply {
+    match request {
+        Request::Search { query, limit } => {
+            let searched =
+                StorageManager::search_shared(storage, &query, limit, &CancellationToken::new())
+                    .await;
+            match searched {
+                Ok((results, timing)) => {
+                    let storage = storage.lock().await;
+                    Reply::Search(SearchReply {
+                        suggestion: results
+                            .is_empty()
+                            .then(|| storage.suggest_correction(&query))
+                            .flatten(),
+                        results,
+                        answered_by: storage.answered_by(&query),
+                        semantic_unavailable: storage.semantic_unavailable().map(str::to_string),
+                        timing: Some(timing),
+                    })
+                }
+                Err(e) => Reply::Error(format!("{:#}", e)),
+            }
+        }
+        Request::LineRelevance { query, chunk } => {
+            match storage.lock().await.line_relevance(&query, &chunk).await {
+                Ok(similarities) => Reply::LineRelevance(similarities),
+                Err(e) => Reply::Error(format!("{:#}", e)),
+            }
+        }
+        Request::FileInfo { file_path } => {
+            Reply::FileInfo(storage.lock().await.file_info(&file_path).await)
+        }
+        Request::Annotations => match storage.lock().await.annotations() {
+            Ok(annotations) => Reply::Annotations(annotations),
+            Err(e) => Reply::Error(format!("{:#}", e)),
+        },
+    }
+}
+
+/// A connection to the daemon serving a project, used instead of opening the indexes
+/// in-process when one is running.
+pub struct DaemonClient {
+    lines: Lines<BufReader<Pin<Box<dyn AsyncRead + Send>>>>,
+    writer: Pin<Box<dyn AsyncWrite + Send>>,
+}
+
+impl DaemonClient {
+    /// Connects to the daemon for `root`, or returns None when none is running.
+    #[cfg(unix)]
+    pub async fn connect(root: &Path) -> Option<Self> {
+        let stream = tokio::net::UnixStream::connect(socket_path(root))
+            .await
+            .ok()?;
+        let (reader, writer) = stream.into_split();
+        Some(Self {
+            lines: BufReader::new(Box::pin(reader) as Pin<Box<dyn AsyncRead + Send>>).lines(),
+            writer: Box::pin(writer),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub async fn connect(_root: &Path) -> Option<Self> {
+        None
+    }
+
+    pub async fn search(&mut self, query: &str, limit: usize) -> Result<SearchReply> {
+        let request = Request::Search {
+            query: query.to_string(),
+            limit,
+        };
+        match self.request(&request).await? {
+            Reply::Search(reply) => Ok(reply),
+            reply => anyhow::bail!("Unexpected reply from daemon: {:?}", reply),
+        }
+    }
+
+    pub async fn line_relevance(&mut self, query: &str, chunk: &Chunk) -> Result<Vec<f32>> {
+        let request = Request::LineRelevance {
+            query: query.to_string(),
+            chunk: chunk.clone(),
+        };
+        match self.request(&request).await? {
+            Reply::LineRelevance(similarities) => Ok(similarities),
+            reply => anyhow::bail!("Unexpected reply from daemon: {:?}", reply),
+        }
+    }
+
+    pub async fn file_info(&mut self, file_path: &Path) -> Result<Option<FileIndex>> {
+        let request = Request::FileInfo {
+            file_path: file_path.to_owned(),
+        };
+        match self.request(&request).await? {
+            Reply::FileInfo(file_index) => Ok(file_index),
+            reply => anyhow::bail!("Unexpected reply from daemon: {:?}", reply),
+        }
+    }
+
+    pub async fn annotations(&mut self) -> Result<Vec<Annotation>> {
+        match self.request(&Request::Annotations).await? {
+            Reply::Annotations(annotations) => Ok(annotations),
+            reply => anyhow::bail!("Unexpected reply from daemon: {:?}", reply),
+        }
+    }
+
+    async fn request(&mut self, request: &Request) -> Result<Reply> {
+        let mut line = serde_json::to_string(request)?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes()).await?;
+
+        let reply = self
+            .lines
+            .next_line()
+            .await?
+            .context("The daemon closed the connection")?;
+        match serde_json::from_str(&reply)? {
+            Reply::Error(e) => anyhow::bail!(e),
+            reply => Ok(reply),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::{Context, Result};
+use chrono::Timelike;
+use std::time::Duration;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+/// Shorter intervals would keep the daemon reconciling without a break on large projects
+const MIN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// When the daemon crawls and reconciles the whole project again, from `[daemon] reindex`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReindexSchedule {
+    /// Every day at this many seconds past local midnight
+    Daily(u64),
+    Every(Duration),
+}
+
+impl ReindexSchedule {
+    /// Reads `"HH:MM"` as a daily time and `"<n>s"`, `"<n>m"`, `"<n>h"` or `"<n>d"` as an
+    /// interval. An empty setting means no schedule.
+    pub fn parse(spec: &str) -> Result<Option<Self>> {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            return Ok(None);
+        }
+
+        if let Some((hours, minutes)) = spec.split_once(':') {
+            let hours: u64 = hours
+                .parse()
+                .ok()
+                .filter(|hours| *hours < 24)
+                .with_context(|| {
+                    format!("Invalid reindex time {:?}: use HH:MM, e.g. \"03:00\"", spec)
+                })?;
+            let minutes: u64 = minutes
+                .parse()
+                .ok()
+                .filter(|minutes| *minutes < 60)
+                .with_context(|| {
+                    format!("Invalid reindex time {:?}: use HH:MM, e.g. \"03:00\"", spec)
+                })?;
+            return Ok(Some(Self::Daily(hours * 3600 + minutes * 60)));
+        }
+
+        let unit_start = spec
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(spec.len());
+        let (count, unit) = spec.split_at(unit_start);
+        let seconds = match unit.trim() {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            "d" => SECONDS_PER_DAY,
+            _ => anyhow::bail!(
+                "Invalid reindex interval {:?}: end it with s, m, h or d, e.g. \"6h\"",
+                spec
+            ),
+        };
+        let count: u64 = count
+            .parse()
+            .with_context(|| format!("Invalid reindex interval {:?}", spec))?;
+        let interval = Duration::from_secs(count.saturating_mul(seconds));
+        if interval < MIN_INTERVAL {
+            anyhow::bail!(
+                "Reindex interval {:?} is shorter than a minute; use \"1m\" or more",
+                spec
+            );
+        }
+        Ok(Some(Self::Every(interval)))
+    }
+
+    /// Time from now until the next reindex is due.
+    pub fn next_delay(&self) -> Duration {
+        match self {
+            Self::Every(interval) => *interval,
+            Self::Daily(at) => {
+                let now = local_seconds_of_day();
+                let delay = (at + SECONDS_PER_DAY - now) % SECONDS_PER_DAY;
+                // Due right now means it just ran, so the next one is tomorrow
+                Duration::from_secs(if delay == 0 { SECONDS_PER_DAY } else { delay })
+            }
+        }
+    }
+}
+
+/// Seconds since local midnight, following the system time zone.
+fn local_seconds_of_day() -> u64 {
+    u64::from(chrono::Local::now().num_seconds_from_midnight())
+}
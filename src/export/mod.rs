@@ -0,0 +1,155 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+
+use crate::crawler;
+use crate::types::SearchResult;
+
+const SNIPPET_MAX_CHARS: usize = 120;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Csv,
+}
+
+impl ExportFormat {
+    /// Picks the format from the file extension, defaulting to Markdown.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => ExportFormat::Csv,
+            _ => ExportFormat::Markdown,
+        }
+    }
+}
+
+/// Writes the result set as a Markdown table or CSV, depending on the file extension.
+pub fn write_results(
+    path: &Path,
+    query: &str,
+    results: &[&SearchResult],
+    root: &Path,
+) -> Result<()> {
+    let content = match ExportFormat::from_path(path) {
+        ExportFormat::Markdown => to_markdown_table(query, results, root),
+        ExportFormat::Csv => to_csv(results, root),
+    };
+
+    std::fs::write(path, content).with_context(|| format!("Failed to write results: {:?}", path))
+}
+
+pub fn to_markdown_table(query: &str, results: &[&SearchResult], root: &Path) -> String {
+    let mut output = format!(
+        "# Search results for \"{}\"\n\n| File | Lines | Score | Snippet |\n| --- | --- | --- | --- |\n",
+        query
+    );
+
+    for result in results {
+        let chunk = &result.chunk;
+        output.push_str(&format!(
+            "| {} | {}-{} | {:.2} | `{}` |\n",
+            display_path(&chunk.file_path, root).replace('|', "\\|"),
+            chunk.start_line,
+            chunk.end_line,
+            result.score,
+            snippet(&chunk.content)
+                .replace('|', "\\|")
+                .replace('`', "'")
+        ));
+    }
+
+    output
+}
+
+pub fn to_csv(results: &[&SearchResult], root: &Path) -> String {
+    let mut output = String::from("file,start_line,end_line,score,snippet\n");
+
+    for result in results {
+        let chunk = &result.chunk;
+        output.push_str(&format!(
+            "{},{},{},{:.4},{}\n",
+            csv_field(&display_path(&chunk.file_path, root)),
+            chunk.start_line,
+            chunk.end_line,
+            result.score,
+            csv_field(&snippet(&chunk.content))
+        ));
+    }
+
+    output
+}
+
+/// Renders results as a Markdown document with one fenced snippet per result.
+pub fn to_markdown(query: &str, results: &[&SearchResult], root: &Path) -> String {
+    let mut output = format!("# Search results for \"{}\"\n", query);
+
+    for result in results {
+        let chunk = &result.chunk;
+        let language = chunk
+            .file_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("");
+
+        output.push_str(&format!(
+            "\n## {} (L{}-{}, {:.0}%)\n\n```{}\n{}\n```\n",
+            display_path(&chunk.file_path, root),
+            chunk.start_line,
+            chunk.end_line,
+            result.score.clamp(0.0, 1.0) * 100.0,
+            language,
+            chunk.content.trim_end()
+        ));
+    }
+
+    output
+}
+
+/// Renders results in the `path:line:col: text` errorformat understood by Vim's quickfix.
+pub fn to_quickfix(results: &[&SearchResult]) -> String {
+    results
+        .iter()
+        .map(|result| {
+            let chunk = &result.chunk;
+            let summary = chunk
+                .content
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .unwrap_or("");
+            format!(
+                "{}:{}:1: {}\n",
+                chunk.file_path.display(),
+                chunk.start_line,
+                summary
+            )
+        })
+        .collect()
+}
+
+fn display_path(file_path: &Path, root: &Path) -> String {
+    let file_path = &crawler::virtual_path(file_path, root).unwrap_or_else(|| file_path.into());
+    file_path
+        .strip_prefix(root)
+        .unwrap_or(file_path)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Collapses a chunk to a single line of text, truncated for tabular output.
+fn snippet(content: &str) -> String {
+    let collapsed = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    if collapsed.chars().count() <= SNIPPET_MAX_CHARS {
+        return collapsed;
+    }
+
+    let truncated: String = collapsed.chars().take(SNIPPET_MAX_CHARS).collect();
+    format!("{}…", truncated)
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
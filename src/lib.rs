@@ -1,8 +1,17 @@
+pub mod ask;
+pub mod bench;
+pub mod bookmarks;
 pub mod cli;
 pub mod config;
 pub mod crawler;
+pub mod daemon;
+pub mod export;
+pub mod logging;
+pub mod once;
+pub mod projects;
 pub mod semantic;
 pub mod storage;
+pub mod throttle;
 pub mod tui;
 pub mod types;
 
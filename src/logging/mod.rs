@@ -0,0 +1,121 @@
+use anyhow::{Context, Result};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::{self, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+const LOG_FILE: &str = "sema.log";
+/// A log larger than this is rotated out when sema starts
+const MAX_LOG_BYTES: u64 = 10 * 1024 * 1024;
+/// Rotated logs kept next to the current one, as `sema.log.1` (newest) and so on
+const KEPT_LOGS: usize = 3;
+/// Warnings nobody collects, as when no TUI is running, stop piling up past this
+const MAX_PENDING_WARNINGS: usize = 100;
+
+/// Warnings logged by sema and not yet taken by the interface, oldest first.
+static PENDING_WARNINGS: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Where the log is written, in the data directory.
+pub fn log_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(LOG_FILE)
+}
+
+/// Sends tracing output from sema at `level` (and warnings from its dependencies) to
+/// `sema.log` in the data directory. Nothing goes to the terminal, which the TUI owns;
+/// sema's own warnings are also kept for [`take_warnings`], even when the log can't be
+/// opened.
+pub fn init(data_dir: &Path, level: &str) -> Result<()> {
+    let filter = EnvFilter::try_new(format!("warn,sema={}", level))
+        .with_context(|| format!("Invalid log level: {}", level))?;
+    let (file_layer, opened) = match open_log(data_dir) {
+        Ok(file) => {
+            let layer = tracing_subscriber::fmt::layer()
+                .with_writer(Mutex::new(file))
+                .with_ansi(false)
+                .with_filter(filter);
+            (Some(layer), Ok(()))
+        }
+        Err(e) => (None, Err(e)),
+    };
+    let warnings = Targets::new().with_target(env!("CARGO_CRATE_NAME"), Level::WARN);
+
+    tracing_subscriber::registry()
+        .with(file_layer)
+        .with(WarningCapture.with_filter(warnings))
+        .try_init()
+        .map_err(|e| anyhow::anyhow!("Failed to set up logging: {}", e))?;
+    opened
+}
+
+/// Warnings logged since the last call, oldest first, for showing in the interface.
+pub fn take_warnings() -> Vec<String> {
+    PENDING_WARNINGS
+        .lock()
+        .map(|mut pending| pending.drain(..).collect())
+        .unwrap_or_default()
+}
+
+fn open_log(data_dir: &Path) -> Result<File> {
+    fs::create_dir_all(data_dir)?;
+    let path = log_path(data_dir);
+    rotate(&path)?;
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))
+}
+
+/// Queues the message of every event it sees in `PENDING_WARNINGS`.
+struct WarningCapture;
+
+impl<S: Subscriber> Layer<S> for WarningCapture {
+    fn on_event(&self, event: &Event<'_>, _ctx: layer::Context<'_, S>) {
+        let mut visitor = MessageVisitor(String::new());
+        event.record(&mut visitor);
+
+        if let Ok(mut pending) = PENDING_WARNINGS.lock() {
+            if pending.len() >= MAX_PENDING_WARNINGS {
+                pending.pop_front();
+            }
+            pending.push_back(visitor.0);
+        }
+    }
+}
+
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+/// Shifts `sema.log` to `sema.log.1`, and older logs up by one, once it has grown past
+/// `MAX_LOG_BYTES`. The oldest is dropped.
+fn rotate(path: &Path) -> Result<()> {
+    let too_large = fs::metadata(path).is_ok_and(|metadata| metadata.len() > MAX_LOG_BYTES);
+    if !too_large {
+        return Ok(());
+    }
+
+    let rotated = |index: usize| PathBuf::from(format!("{}.{}", path.display(), index));
+    let _ = fs::remove_file(rotated(KEPT_LOGS));
+    for index in (1..KEPT_LOGS).rev() {
+        let from = rotated(index);
+        if from.exists() {
+            fs::rename(&from, rotated(index + 1))?;
+        }
+    }
+    fs::rename(path, rotated(1))?;
+    Ok(())
+}
@@ -1,20 +1,512 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
-use sema::cli::Cli;
-use sema::config::{Config, ConfigManager};
+use sema::ask;
+use sema::bench;
+use sema::bookmarks::BookmarkStore;
+use sema::cli::{BookmarksAction, Cli, Commands, ConfigAction, ModelsAction, ProjectsAction};
+use sema::config::{Config, ConfigManager, SemanticConfig};
+use sema::crawler::{FileCrawler, piped, root};
+use sema::daemon;
+use sema::logging;
+use sema::once;
+use sema::projects::{self, ProjectRegistry};
+use sema::semantic::embeddings::{EMBEDDING_DIM, VectorStore};
+use sema::semantic::models;
+use sema::storage::failures::ReadFailures;
+use sema::storage::text_indexer::TANTIVY_DIR;
+use sema::storage::{StorageManager, secrets};
 use sema::tui::App;
+use sema::tui::summary::CorpusSummary;
+use sema::tui::ui::UI;
+use sema::types::CrawlerConfig;
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use tokio_util::sync::CancellationToken;
+
+/// Printed when forgetting a project left its vectors in a shared Qdrant collection
+const QDRANT_KEPT: &str = "Its vectors are left in the Qdrant collection, which is shared; \
+sema embeds the files again if the project is indexed again";
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
     let cli = Cli::parse();
+
+    if let Some(data_dir) = &cli.data_dir {
+        ConfigManager::set_data_dir(std::path::absolute(data_dir)?);
+    }
+    if let Err(e) = ConfigManager::migrate_legacy_data_dir() {
+        eprintln!("Warning: {:#}", e);
+    }
+    if let Err(e) = logging::init(&ConfigManager::get_data_dir(), &cli.log_level) {
+        eprintln!("Warning: {:#}", e);
+    }
+
+    if let Some(command) = &cli.command {
+        run_command(command, &cli).await?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if cli.once {
+        // Exit codes follow grep, so errors are told apart from finding nothing
+        return Ok(match run_once(&cli).await {
+            Ok(true) => ExitCode::SUCCESS,
+            Ok(false) => ExitCode::from(1),
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                ExitCode::from(2)
+            }
+        });
+    }
+
     let config = load_config(&cli).await?;
-    let target_directory = resolve_directory(&cli)?;
+    let target_directory = resolve_directory(&cli, !cli.stdin)?;
+    let extra_files = capture_stdin(&cli, &target_directory)?;
+
+    let mut app = App::new_with_directory(target_directory, config)?
+        .with_print_on_exit(cli.print_on_exit)
+        .with_output(cli.output.clone())
+        .with_initial_queries(&cli.queries)
+        .with_all_projects(cli.all_projects)
+        .with_extra_files(extra_files);
 
-    let mut app = App::new_with_directory(target_directory, config)?;
+    // Reloaded with the same command-line overrides on top
+    let config_file = ConfigManager::new()?.config_file().to_owned();
+    app = app.with_config_reload(
+        config_file,
+        Box::new(move || {
+            let mut config = select_profile(ConfigManager::new()?.load_config()?, &cli)?;
+            apply_cli_overrides(&mut config, &cli);
+            Ok(config)
+        }),
+    );
     app.run().await?;
 
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Searches the `-q` queries without the interface, returning whether anything matched.
+async fn run_once(cli: &Cli) -> Result<bool> {
+    let config = load_config(cli).await?;
+    // Nobody may be there to answer, as in CI
+    let target_directory = resolve_directory(cli, false)?;
+    let extra_files = capture_stdin(cli, &target_directory)?;
+    once::run(
+        target_directory,
+        config,
+        &cli.queries,
+        extra_files,
+        cli.all_projects,
+    )
+    .await
+}
+
+/// Saves piped input under the project when `--stdin` is given, to index with it.
+fn capture_stdin(cli: &Cli, target_directory: &Path) -> Result<Vec<PathBuf>> {
+    if !cli.stdin {
+        return Ok(Vec::new());
+    }
+    let name = cli.name.as_deref().unwrap_or(piped::DEFAULT_NAME);
+    Ok(vec![piped::capture(target_directory, name)?])
+}
+
+async fn run_command(command: &Commands, cli: &Cli) -> Result<()> {
+    match command {
+        Commands::Daemon => {
+            let config = load_config(cli).await?;
+            daemon::run(resolve_directory(cli, false)?, config).await
+        }
+        Commands::Ask { question, new } => {
+            let config = load_config(cli).await?;
+            ask::run(
+                resolve_directory(cli, false)?,
+                config,
+                &question.join(" "),
+                *new,
+            )
+            .await
+        }
+        Commands::Bench { qrels, k } => {
+            let config = load_config(cli).await?;
+            bench::run(resolve_directory(cli, false)?, config, qrels, *k).await
+        }
+        Commands::Models { action } => run_models_command(action, cli).await,
+        Commands::Secrets => {
+            let config = load_config(cli).await?;
+            let root = resolve_directory(cli, false)?;
+            let (files, _) = FileCrawler::new(CrawlerConfig::from(&config.general))
+                .crawl_directory(&root, CancellationToken::new())
+                .await?;
+
+            let mut found = 0;
+            for file in &files {
+                // Binary and unreadable files can't be indexed either
+                let Ok(content) = std::fs::read_to_string(file) else {
+                    continue;
+                };
+                let display_path = file.strip_prefix(&root).unwrap_or(file);
+                for secret in secrets::find(&content) {
+                    println!(
+                        "{}:{}: {} ({})",
+                        display_path.display(),
+                        secret.line + 1,
+                        secret.kind,
+                        secret.preview
+                    );
+                    found += 1;
+                }
+            }
+
+            if found == 0 {
+                println!("No likely secrets in {}", root.display());
+            } else if !config.indexing.embed_secrets {
+                println!(
+                    "{} likely secrets; their chunks are searched by keyword only and never embedded",
+                    found
+                );
+            }
+            Ok(())
+        }
+        Commands::Status { skipped } => {
+            let config = load_config(cli).await?;
+            let root = resolve_directory(cli, false)?;
+            let (files, mut stats) = FileCrawler::new(CrawlerConfig::from(&config.general))
+                .crawl_directory_with_stats(&root, CancellationToken::new())
+                .await?;
+            // Files that failed to read are only known from the last indexing run
+            let index_dir = StorageManager::index_dir(&ConfigManager::get_data_dir(), &config);
+            stats
+                .skipped
+                .extend(ReadFailures::load(&index_dir).skipped_files(&files));
+            stats.sort();
+
+            if !*skipped {
+                for line in CorpusSummary::new(&root, &files, &stats, None).lines() {
+                    println!("{}", line);
+                }
+                if !stats.skipped.is_empty() {
+                    println!("\nRun `sema status --skipped` to list the skipped files");
+                }
+                return Ok(());
+            }
+
+            if stats.skipped.is_empty() {
+                println!("No files skipped in {}", root.display());
+            }
+            for file in &stats.skipped {
+                let path = file.path.strip_prefix(&root).unwrap_or(&file.path);
+                match &file.detail {
+                    Some(detail) => {
+                        println!("{}: {} ({})", path.display(), file.reason.label(), detail)
+                    }
+                    None => println!("{}: {}", path.display(), file.reason.label()),
+                }
+            }
+            Ok(())
+        }
+        Commands::Config { action } => run_config_command(action),
+        Commands::Projects { action } => run_projects_command(action, cli).await,
+        Commands::Bookmarks {
+            action: BookmarksAction::List,
+        } => {
+            let root = resolve_directory(cli, false)?;
+            let store = BookmarkStore::open(&root)?;
+            let bookmarks = store.bookmarks();
+
+            if store.is_empty() {
+                println!("No bookmarks for {}", root.display());
+                return Ok(());
+            }
+
+            if !bookmarks.queries.is_empty() {
+                println!("Saved queries:");
+                for saved in &bookmarks.queries {
+                    if saved.name == saved.query {
+                        println!("  {}", saved.name);
+                    } else {
+                        println!("  {}: {}", saved.name, saved.query);
+                    }
+                }
+            }
+
+            if !bookmarks.results.is_empty() {
+                println!("Bookmarked results:");
+                for bookmark in &bookmarks.results {
+                    println!(
+                        "  {}:{}-{}  ({})",
+                        bookmark.file_path.display(),
+                        bookmark.start_line,
+                        bookmark.end_line,
+                        bookmark.query
+                    );
+                }
+            }
+
+            Ok(())
+        }
+    }
+}
+
+fn run_config_command(action: &ConfigAction) -> Result<()> {
+    let manager = ConfigManager::new()?;
+    manager.init()?;
+
+    match action {
+        ConfigAction::Get { key } => match manager.get_value(key)? {
+            // Strings print bare, so the output can be used in scripts
+            toml::Value::String(value) => println!("{}", value),
+            toml::Value::Table(table) => print!("{}", toml::to_string_pretty(&table)?),
+            value => println!("{}", value),
+        },
+        ConfigAction::Set { key, value } => manager.set_value(key, value)?,
+        ConfigAction::Edit => {
+            let editor = env::var("VISUAL")
+                .or_else(|_| env::var("EDITOR"))
+                .unwrap_or_else(|_| "vi".to_string());
+            // Editors are often set with arguments, such as `code -w`
+            let mut words = editor.split_whitespace();
+            let program = words.next().unwrap_or("vi");
+            let status = std::process::Command::new(program)
+                .args(words)
+                .arg(manager.config_file())
+                .status()
+                .with_context(|| format!("Failed to start {}", editor))?;
+            if !status.success() {
+                anyhow::bail!("{} exited with {}", editor, status);
+            }
+            report_config_problems(&manager)?;
+        }
+        ConfigAction::Path => println!("{}", manager.config_file().display()),
+        ConfigAction::Validate => report_config_problems(&manager)?,
+    }
+
+    Ok(())
+}
+
+async fn run_models_command(action: &ModelsAction, cli: &Cli) -> Result<()> {
+    let config = load_config(cli).await?;
+    let semantic = &config.semantic;
+    let in_use = semantic
+        .model_dir
+        .is_none()
+        .then_some(semantic.model.as_str());
+    let megabytes = |bytes: u64| bytes as f64 / 1_048_576.0;
+
+    match action {
+        ModelsAction::List => {
+            let cached = models::cached()?;
+            if cached.is_empty() {
+                println!("No models in the Hugging Face cache");
+            }
+            for model in &cached {
+                let mut notes = Vec::new();
+                if in_use == Some(model.id.as_str()) {
+                    notes.push("in use");
+                }
+                if !model.usable {
+                    notes.push("no ONNX export");
+                }
+                let notes = if notes.is_empty() {
+                    String::new()
+                } else {
+                    format!(" ({})", notes.join(", "))
+                };
+                println!("{}  {:.1} MB{}", model.id, megabytes(model.size), notes);
+                for (revision, commit) in &model.revisions {
+                    if revision == commit {
+                        println!("  {}", commit);
+                    } else {
+                        println!("  {} -> {}", revision, commit);
+                    }
+                }
+            }
+            if let Some(model_dir) = &semantic.model_dir {
+                println!("\nEmbedding with the model in {}", model_dir.display());
+            }
+        }
+        ModelsAction::Download { model, revision } => {
+            let commit = models::download(model, revision.as_deref())?;
+            println!("Downloaded {} at {}", model, commit);
+        }
+        ModelsAction::Remove { model } => {
+            let freed = models::remove(model)?;
+            println!("Removed {}, freeing {:.1} MB", model, megabytes(freed));
+            if in_use == Some(model.as_str()) {
+                println!("It is still configured, so indexing downloads it again");
+            }
+        }
+        ModelsAction::Use { model, revision } => {
+            // Pinning a model already fetched works offline
+            let commit = match models::download(model, revision.as_deref()) {
+                Ok(commit) => commit,
+                Err(e) => models::cached_commit(model, revision.as_deref()).ok_or(e)?,
+            };
+            // Every index stores vectors of one size, so a model embedding at another
+            // can't be indexed with
+            let probe = SemanticConfig {
+                model: model.clone(),
+                revision: Some(commit.clone()),
+                model_dir: None,
+                jobs: 1,
+                ..semantic.clone()
+            };
+            let dimensions = tokio::task::spawn_blocking(move || {
+                let vector_store = VectorStore::new(&probe, None)?;
+                anyhow::Ok(vector_store.generate_embedding("sema")?.len())
+            })
+            .await?
+            .with_context(|| format!("Failed to load {}", model))?;
+            if dimensions != EMBEDDING_DIM {
+                anyhow::bail!(
+                    "{} embeds {}-dimensional vectors, but the index stores {}-dimensional ones",
+                    model,
+                    dimensions,
+                    EMBEDDING_DIM
+                );
+            }
+            // Held there even if the revision is later taken out of the config
+            let mut lock = models::ModelLock::load()?;
+            lock.pin(model, &commit)?;
+            lock.save()?;
+            let manager = ConfigManager::new()?;
+            manager.init()?;
+            manager.set_value(
+                "semantic.model",
+                &toml::Value::String(model.clone()).to_string(),
+            )?;
+            manager.set_value(
+                "semantic.revision",
+                &toml::Value::String(commit.clone()).to_string(),
+            )?;
+            println!(
+                "Embedding with {} at {}; the index is rebuilt with it on the next run",
+                model, commit
+            );
+            if let Some(model_dir) = &semantic.model_dir {
+                println!(
+                    "[semantic] model_dir = {} still takes precedence; remove it to use this model",
+                    model_dir.display()
+                );
+            }
+        }
+        ModelsAction::Bench { sample, model_dirs } => {
+            let root = resolve_directory(cli, false)?;
+            bench::models::run(root, config.clone(), *sample, model_dirs).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_projects_command(action: &ProjectsAction, cli: &Cli) -> Result<()> {
+    let config = load_config(cli).await?;
+    // Chunks are removed from the index of every profile, so the settings are read
+    // without the one selected
+    let manager = ConfigManager::new()?;
+    let settings = manager.load_config()?;
+    let registry = ProjectRegistry::load()?;
+    let megabytes = |bytes: u64| bytes as f64 / 1_048_576.0;
+    let find = |registry: &ProjectRegistry, query: &str| {
+        registry
+            .find(query)
+            .with_context(|| format!("No indexed project is named or rooted at {}", query))
+    };
+
+    match action {
+        ProjectsAction::List => {
+            let listed = registry.projects();
+            if listed.is_empty() {
+                println!("No projects indexed");
+                return Ok(());
+            }
+            let data_dir = ConfigManager::get_data_dir();
+            let index_dir = StorageManager::index_dir(&data_dir, &config);
+            // Opening an index that was never built would create it
+            let storage = if index_dir.join(TANTIVY_DIR).exists() {
+                Some(StorageManager::new(&data_dir, &config).await?)
+            } else {
+                None
+            };
+            let total_chunks = storage
+                .as_ref()
+                .and_then(StorageManager::total_chunk_count)
+                .unwrap_or(0);
+            let index_size = projects::index_size(&config);
+
+            for project in &listed {
+                let chunks = storage
+                    .as_ref()
+                    .and_then(|storage| storage.chunk_count(&project.root))
+                    .unwrap_or(0);
+                // The index is shared, so each project is charged its share of chunks
+                let size =
+                    index_size * chunks / total_chunks.max(1) + projects::state_size(&project.root);
+                let missing = if project.root.exists() {
+                    ""
+                } else {
+                    " (root no longer exists)"
+                };
+                println!("{}  {}{}", project.name, project.root.display(), missing);
+                println!(
+                    "  {} chunks  {:.1} MB  last indexed {}",
+                    chunks,
+                    megabytes(size),
+                    UI::format_age(project.last_used)
+                );
+            }
+        }
+        ProjectsAction::Rm { project } => {
+            let project = find(&registry, project)?;
+            let shared = projects::forget(&settings, &project.root).await?;
+            println!("Forgot {} ({})", project.name, project.root.display());
+            if shared {
+                println!("{}", QDRANT_KEPT);
+            }
+        }
+        ProjectsAction::Rename { project, name } => {
+            let project = find(&registry, project)?;
+            ProjectRegistry::update(|registry| registry.rename(&project.root, name))?;
+            println!("Renamed {} to {}", project.root.display(), name.trim());
+        }
+        ProjectsAction::Gc { dry_run } => {
+            let missing = registry.missing();
+            if missing.is_empty() {
+                println!("Every indexed project's root still exists");
+            }
+            let mut shared = false;
+            for project in &missing {
+                if !*dry_run {
+                    shared |= projects::forget(&settings, &project.root).await?;
+                }
+                println!("Forgot {}", project.root.display());
+            }
+            if shared {
+                println!("{}", QDRANT_KEPT);
+            }
+            if *dry_run && !missing.is_empty() {
+                println!("\nNothing was deleted; run without --dry-run to forget them");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints each problem as `path:line: message`, failing when there are any.
+fn report_config_problems(manager: &ConfigManager) -> Result<()> {
+    let problems = manager.validate()?;
+    let path = manager.config_file().display();
+    for problem in &problems {
+        match problem.line {
+            Some(line) => eprintln!("{}:{}: {}", path, line, problem.message),
+            None => eprintln!("{}: {}", path, problem.message),
+        }
+    }
+
+    if !problems.is_empty() {
+        anyhow::bail!("{} has {} problem(s)", path, problems.len());
+    }
+    println!("{} is valid", path);
     Ok(())
 }
 
@@ -22,12 +514,19 @@ async fn load_config(cli: &Cli) -> Result<Config> {
     let manager = ConfigManager::new()?;
     manager.init()?;
 
-    let mut config = manager.load_config()?;
+    let mut config = select_profile(manager.load_config()?, cli)?;
     apply_cli_overrides(&mut config, cli);
 
     Ok(config)
 }
 
+fn select_profile(config: Config, cli: &Cli) -> Result<Config> {
+    match &cli.profile {
+        Some(name) => config.with_profile(name),
+        None => Ok(config),
+    }
+}
+
 fn apply_cli_overrides(config: &mut Config, cli: &Cli) {
     if let Some(max_size) = cli.max_file_size {
         config.general.max_file_size = max_size;
@@ -41,6 +540,22 @@ fn apply_cli_overrides(config: &mut Config, cli: &Cli) {
         config.general.follow_symlinks = true;
     }
 
+    if cli.archives {
+        config.general.index_archives = true;
+    }
+
+    if cli.images {
+        config.general.index_images = true;
+    }
+
+    if let Some(max_depth) = cli.max_depth {
+        config.general.max_depth = max_depth;
+    }
+
+    if let Some(max_files) = cli.max_files {
+        config.general.max_files = max_files;
+    }
+
     if cli.ignore_gitignore {
         config.general.ignore_gitignore = true;
     }
@@ -49,6 +564,30 @@ fn apply_cli_overrides(config: &mut Config, cli: &Cli) {
         config.general.file_extensions = extensions.clone();
     }
 
+    if cli.all_types {
+        config.general.file_extensions.clear();
+    }
+
+    if cli.no_semantic {
+        config.semantic.enabled = false;
+    }
+
+    if cli.offline {
+        config.semantic.offline = true;
+    }
+
+    if cli.profile_index {
+        config.indexing.profile = true;
+    }
+
+    if let Some(jobs) = cli.jobs {
+        config.semantic.jobs = jobs;
+    }
+
+    if let Some(min_score) = cli.min_score {
+        config.search.min_similarity = min_score.clamp(0.0, 1.0);
+    }
+
     if let Some(exclude_patterns) = &cli.exclude {
         for pattern in exclude_patterns {
             if !config.general.exclude_patterns.contains(pattern) {
@@ -58,7 +597,9 @@ fn apply_cli_overrides(config: &mut Config, cli: &Cli) {
     }
 }
 
-fn resolve_directory(cli: &Cli) -> Result<PathBuf> {
+/// The directory to index: the one given on the command line, or the working directory
+/// widened to its repository root as `root::resolve` decides. `interactive` allows asking.
+fn resolve_directory(cli: &Cli, interactive: bool) -> Result<PathBuf> {
     let target_directory = if let Some(dir) = &cli.directory {
         dir.clone()
     } else {
@@ -82,5 +623,8 @@ fn resolve_directory(cli: &Cli) -> Result<PathBuf> {
         ));
     }
 
-    Ok(canonical_path)
+    if cli.directory.is_some() || cli.no_auto_root {
+        return Ok(canonical_path);
+    }
+    root::resolve(&canonical_path, interactive)
 }
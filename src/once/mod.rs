@@ -0,0 +1,150 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{Config, ConfigManager};
+use crate::crawler::{self, FileCrawler};
+use crate::daemon::DaemonClient;
+use crate::projects::ProjectRegistry;
+use crate::storage::scope::PathScope;
+use crate::storage::{SharedStorage, StorageManager};
+use crate::types::{Chunk, CrawlerConfig};
+
+/// Results printed per query, as many as the interface lists
+const RESULTS_LIMIT: usize = 50;
+
+/// Brings the index for `root` up to date, searches each query once and prints every
+/// match as `path:line: text`, for scripts and CI checks. A running daemon answers
+/// instead, without indexing. With `all_projects`, matches from every indexed project are
+/// printed, each prefixed with the name of its project. Returns whether anything matched.
+pub async fn run(
+    root: PathBuf,
+    config: Config,
+    queries: &[String],
+    extra_files: Vec<PathBuf>,
+    all_projects: bool,
+) -> Result<bool> {
+    let results = search(
+        &root,
+        config,
+        queries,
+        RESULTS_LIMIT,
+        extra_files,
+        all_projects,
+    )
+    .await?;
+    let registry = if all_projects {
+        ProjectRegistry::load()?
+    } else {
+        ProjectRegistry::default()
+    };
+
+    let mut printed = HashSet::new();
+    for (chunk, _) in results.into_iter().flatten() {
+        // A chunk found by several queries is listed once
+        if !printed.insert(chunk.id.clone()) {
+            continue;
+        }
+        match registry.project_of(&chunk.file_path) {
+            Some(project) => println!("[{}] {}", project.name, format_match(&chunk, &project.root)),
+            None => println!("{}", format_match(&chunk, &root)),
+        }
+    }
+
+    Ok(!printed.is_empty())
+}
+
+/// Results of each query, from a running daemon or else after bringing the index for
+/// `root` up to date. The index holds every project, so results are kept to `root` unless
+/// `all_projects` is set.
+pub async fn search(
+    root: &Path,
+    config: Config,
+    queries: &[String],
+    limit: usize,
+    extra_files: Vec<PathBuf>,
+    all_projects: bool,
+) -> Result<Vec<Vec<(Chunk, f32)>>> {
+    // The project scope goes before the query, so an `in:` typed into it wins
+    let queries: Vec<String> = queries
+        .iter()
+        .map(|query| {
+            if all_projects {
+                query.clone()
+            } else {
+                format!("{} {}", PathScope::term(root), query)
+            }
+        })
+        .collect();
+
+    match DaemonClient::connect(root).await {
+        Some(mut daemon) => {
+            let mut results = Vec::new();
+            for query in &queries {
+                results.push(daemon.search(query, limit).await?.results);
+            }
+            Ok(results)
+        }
+        None => search_locally(root, config, &queries, limit, extra_files).await,
+    }
+}
+
+async fn search_locally(
+    root: &Path,
+    config: Config,
+    queries: &[String],
+    limit: usize,
+    extra_files: Vec<PathBuf>,
+) -> Result<Vec<Vec<(Chunk, f32)>>> {
+    let storage: SharedStorage = Arc::new(Mutex::new(
+        StorageManager::new(&ConfigManager::get_data_dir(), &config).await?,
+    ));
+    let cancel = CancellationToken::new();
+    let mut crawler_config = CrawlerConfig::from(&config.general);
+    crawler_config.extra_files = extra_files;
+
+    let crawl_started = Instant::now();
+    let (files, stats) = FileCrawler::new(crawler_config)
+        .crawl_directory(root, cancel.clone())
+        .await?;
+    if let Err(e) = ProjectRegistry::register(root) {
+        eprintln!("Warning: {:#}", e);
+    }
+    storage.lock().await.record_crawl(crawl_started.elapsed());
+    StorageManager::process_and_index_files(&storage, files, stats.size_limits, &cancel).await?;
+
+    let mut storage = storage.lock().await;
+    if let Some(profile) = storage.index_profile() {
+        eprintln!("{}", profile.report());
+    }
+
+    let mut results = Vec::new();
+    for query in queries {
+        results.push(storage.search(query, limit, &cancel).await?);
+    }
+    Ok(results)
+}
+
+/// The chunk's location relative to the project and its first non-blank line, like grep.
+fn format_match(chunk: &Chunk, root: &Path) -> String {
+    let file_path =
+        crawler::virtual_path(&chunk.file_path, root).unwrap_or_else(|| chunk.file_path.clone());
+    let path = file_path.strip_prefix(root).unwrap_or(&file_path);
+    let (offset, text) = chunk
+        .content
+        .lines()
+        .enumerate()
+        .find(|(_, line)| !line.trim().is_empty())
+        .unwrap_or((0, ""));
+
+    format!(
+        "{}:{}: {}",
+        path.display(),
+        chunk.start_line + offset,
+        text.trim()
+    )
+}
@@ -0,0 +1,301 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::{Config, ConfigManager, VectorBackend};
+use crate::storage::StorageManager;
+use crate::storage::lance_indexer::LANCE_DIR;
+use crate::storage::text_indexer::TANTIVY_DIR;
+
+const PROJECTS_FILE: &str = "projects.json";
+
+/// A project whose files are in the index, which holds the chunks of every project.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Project {
+    pub root: PathBuf,
+    /// The name given with `sema projects rename`, or else the root's directory name, with
+    /// its parent when another project shares it
+    pub name: String,
+    /// Seconds since the Unix epoch of the last indexing run on the project
+    pub last_used: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ProjectEntry {
+    root: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(default)]
+    last_used: u64,
+}
+
+/// The roots of every project indexed under the data directory, so searches across all of
+/// them can tell which project each result comes from and `sema projects` can forget them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ProjectRegistry {
+    #[serde(default)]
+    projects: Vec<ProjectEntry>,
+}
+
+impl ProjectRegistry {
+    pub fn load() -> Result<Self> {
+        let path = Self::path();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read the project list: {:?}", path))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse the project list: {:?}", path))
+    }
+
+    fn path() -> PathBuf {
+        ConfigManager::get_data_dir().join(PROJECTS_FILE)
+    }
+
+    /// Records `root` as indexed, if it isn't already, and as used just now.
+    pub fn register(root: &Path) -> Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        Self::update(|registry| {
+            match registry.entry_mut(root) {
+                Some(entry) => entry.last_used = now,
+                None => {
+                    registry.projects.push(ProjectEntry {
+                        root: root.to_path_buf(),
+                        name: None,
+                        last_used: now,
+                    });
+                    registry.projects.sort_by(|a, b| a.root.cmp(&b.root));
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Loads the list, changes it with `change` and saves it, holding a lock on it
+    /// throughout, so the TUI, the daemon and `sema` runs indexing other projects at the
+    /// same time don't drop each other's entries. Nothing is saved when `change` fails.
+    pub fn update<T>(change: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let lock_path = path.with_extension("lock");
+        let lock_file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("Failed to open {:?}", lock_path))?;
+        match lock_file.lock() {
+            Ok(()) => {}
+            // Filesystems without locking are treated as unshared
+            Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {}
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to lock {:?}", lock_path));
+            }
+        }
+
+        let mut registry = Self::load()?;
+        let changed = change(&mut registry)?;
+        registry.save(&path)?;
+        Ok(changed)
+    }
+
+    /// Writes the list beside `path` and moves it into place, so a reader never sees
+    /// it half written.
+    fn save(&self, path: &Path) -> Result<()> {
+        let content =
+            serde_json::to_string_pretty(self).context("Failed to serialize the project list")?;
+        let temporary = path.with_extension("json.tmp");
+        fs::write(&temporary, content)
+            .with_context(|| format!("Failed to write the project list: {:?}", temporary))?;
+        fs::rename(&temporary, path)
+            .with_context(|| format!("Failed to write the project list: {:?}", path))
+    }
+
+    /// Every project, by root.
+    pub fn projects(&self) -> Vec<Project> {
+        self.projects
+            .iter()
+            .map(|entry| self.project(entry))
+            .collect()
+    }
+
+    /// The project holding `path`: the innermost one, when projects are nested.
+    pub fn project_of(&self, path: &Path) -> Option<Project> {
+        self.projects
+            .iter()
+            .filter(|entry| path.starts_with(&entry.root))
+            .max_by_key(|entry| entry.root.components().count())
+            .map(|entry| self.project(entry))
+    }
+
+    /// The project named `query`, or else the one whose root it is, relative paths being
+    /// read from the current directory.
+    pub fn find(&self, query: &str) -> Option<Project> {
+        let projects = self.projects();
+        if let Some(project) = projects.iter().find(|project| project.name == query) {
+            return Some(project.clone());
+        }
+        let path = std::path::absolute(query).ok()?;
+        let path = path.canonicalize().unwrap_or(path);
+        projects.into_iter().find(|project| project.root == path)
+    }
+
+    /// Projects whose roots no longer exist, such as deleted checkouts.
+    pub fn missing(&self) -> Vec<Project> {
+        self.projects()
+            .into_iter()
+            .filter(|project| !project.root.exists())
+            .collect()
+    }
+
+    /// Names the project at `root` `name` in results from every project.
+    pub fn rename(&mut self, root: &Path, name: &str) -> Result<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            anyhow::bail!("A project name can't be empty");
+        }
+        if let Some(other) = self
+            .projects()
+            .into_iter()
+            .find(|project| project.name == name && project.root != root)
+        {
+            anyhow::bail!("{} is already named {}", other.root.display(), name);
+        }
+        let entry = self
+            .entry_mut(root)
+            .with_context(|| format!("{} is not an indexed project", root.display()))?;
+        entry.name = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Forgets the project at `root`; its chunks and state are left to the caller.
+    pub fn remove(&mut self, root: &Path) {
+        self.projects.retain(|entry| entry.root != root);
+    }
+
+    fn entry_mut(&mut self, root: &Path) -> Option<&mut ProjectEntry> {
+        self.projects.iter_mut().find(|entry| entry.root == root)
+    }
+
+    fn project(&self, entry: &ProjectEntry) -> Project {
+        Project {
+            root: entry.root.clone(),
+            name: entry.name.clone().unwrap_or_else(|| self.name(&entry.root)),
+            last_used: entry.last_used,
+        }
+    }
+
+    fn name(&self, root: &Path) -> String {
+        let last = |path: &Path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        };
+        let Some(name) = last(root) else {
+            return root.display().to_string();
+        };
+        let shared = self
+            .projects
+            .iter()
+            .filter(|other| last(other.root.as_path()).as_deref() == Some(name.as_str()))
+            .count()
+            > 1;
+        match root.parent().and_then(last) {
+            Some(parent) if shared => format!("{}/{}", parent, name),
+            _ => name,
+        }
+    }
+}
+
+/// The settings of every index built under the data directory: with and without
+/// embeddings, for `config` and for each of its profiles. `config` has no profile applied.
+fn index_configs(config: &Config) -> Result<Vec<Config>> {
+    let mut configs = vec![config.clone()];
+    for name in config.profile.keys() {
+        configs.push(config.clone().with_profile(name)?);
+    }
+
+    let data_dir = ConfigManager::get_data_dir();
+    let mut built = Vec::new();
+    for config in configs {
+        for enabled in [true, false] {
+            let mut config = config.clone();
+            config.semantic.enabled = enabled;
+            if StorageManager::index_dir(&data_dir, &config)
+                .join(TANTIVY_DIR)
+                .exists()
+            {
+                built.push(config);
+            }
+        }
+    }
+    Ok(built)
+}
+
+/// Deletes the chunks of the project at `root` from every index, then its bookmarks and
+/// conversation, and takes it off the project list. Projects nested in it
+/// keep their chunks. `config` has no profile applied, so the index of each profile is
+/// found. Returns whether chunks were left in a shared Qdrant collection, which is never
+/// deleted from.
+pub async fn forget(config: &Config, root: &Path) -> Result<bool> {
+    let nested: Vec<PathBuf> = ProjectRegistry::load()?
+        .projects()
+        .into_iter()
+        .map(|project| project.root)
+        .filter(|other| other != root && other.starts_with(root))
+        .collect();
+
+    let data_dir = ConfigManager::get_data_dir();
+    let mut shared = false;
+    for config in index_configs(config)? {
+        let index_dir = StorageManager::index_dir(&data_dir, &config);
+        let mut storage = StorageManager::new(&data_dir, &config).await?;
+        storage
+            .remove_directory(root, &nested)
+            .await
+            .with_context(|| format!("Failed to remove chunks from {}", index_dir.display()))?;
+        shared |= config.semantic.enabled && config.vector_index.backend == VectorBackend::Qdrant;
+    }
+
+    let project_dir = ConfigManager::get_project_dir(root);
+    if project_dir.exists() {
+        fs::remove_dir_all(&project_dir)
+            .with_context(|| format!("Failed to remove {}", project_dir.display()))?;
+    }
+
+    ProjectRegistry::update(|registry| {
+        registry.remove(root);
+        Ok(())
+    })?;
+    Ok(shared)
+}
+
+/// Bytes on disk of the index for `config`, so each project can be charged its share.
+pub fn index_size(config: &Config) -> u64 {
+    let index_dir = StorageManager::index_dir(&ConfigManager::get_data_dir(), config);
+    directory_size(&index_dir.join(TANTIVY_DIR)) + directory_size(&index_dir.join(LANCE_DIR))
+}
+
+/// Bytes on disk of the bookmarks, conversation and caches of the project at `root`.
+pub fn state_size(root: &Path) -> u64 {
+    directory_size(&ConfigManager::get_project_dir(root))
+}
+
+fn directory_size(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => directory_size(&entry.path()),
+            _ => entry.metadata().map_or(0, |metadata| metadata.len()),
+        })
+        .sum()
+}
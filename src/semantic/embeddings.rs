@@ -1,29 +1,268 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use hf_hub::api::Progress;
 use hf_hub::api::sync::Api;
+use hf_hub::{Cache, Repo};
 use ort::{inputs, session::Session, value::TensorRef};
-use std::path::PathBuf;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
 use tokenizers::Tokenizer;
+use tokio_util::sync::CancellationToken;
 
+use super::models::{self, ModelLock};
+use crate::config::SemanticConfig;
+use crate::throttle;
+
+/// Tokens the model reads at once; longer texts are embedded a window at a time
 const MAX_LENGTH: usize = 256;
+/// Tokens each window of a long text shares with the window before it
+const WINDOW_OVERLAP_TOKENS: usize = 32;
+pub const EMBEDDING_DIM: usize = 384;
+pub const MODEL_ID: &str = "sentence-transformers/all-MiniLM-L6-v2";
+/// Where a repository may keep its ONNX export, in order of preference
+pub const MODEL_FILES: [&str; 2] = ["onnx/model.onnx", "model.onnx"];
+pub const TOKENIZER_FILE: &str = "tokenizer.json";
+
+/// Bytes received and expected for the model file currently being downloaded.
+static DOWNLOAD_PROGRESS: Mutex<Option<(u64, u64)>> = Mutex::new(None);
+
+/// Progress of the first-run model download, if one is under way.
+pub fn download_progress() -> Option<(u64, u64)> {
+    *DOWNLOAD_PROGRESS.lock().ok()?
+}
 
+/// Embedding sessions for a pool of workers, and one for queries. Each session holds a
+/// copy of the model, so every worker costs its memory.
 pub struct VectorStore {
-    session: Session,
+    /// One per worker
+    sessions: Vec<Mutex<Session>>,
+    /// Embeds single texts such as queries, so they never wait for a batch to finish
+    query_session: Mutex<Session>,
+    /// Never truncates or pads, so long texts can be split into windows
     tokenizer: Tokenizer,
+    /// Tokens the model reads at once, special tokens included
+    max_tokens: usize,
+    /// Tokens such as `[CLS]` and `[SEP]` added around every text
+    special_tokens: usize,
+    low_priority: bool,
 }
 
 impl VectorStore {
-    pub fn new() -> Result<Self> {
-        let model_path = download_model()?;
-        let tokenizer_path = download_tokenizer()?;
+    /// Loads the configured model. With `index_dir`, its files must match those the index
+    /// there was embedded with.
+    pub fn new(config: &SemanticConfig, index_dir: Option<&Path>) -> Result<Self> {
+        let (model_path, tokenizer_path) = model_files(config, index_dir)?;
 
-        let session = Session::builder()?.commit_from_file(&model_path)?;
-        let tokenizer = Tokenizer::from_file(&tokenizer_path)
+        let workers = config.workers();
+        // Otherwise each session spreads over every core and the pool oversubscribes them
+        let intra_threads = (num_cpus::get() / workers).max(1);
+        let load_session = || -> Result<Mutex<Session>> {
+            let session = Session::builder()?
+                .with_intra_threads(intra_threads)?
+                .commit_from_file(&model_path)?;
+            Ok(Mutex::new(session))
+        };
+        let sessions = (0..workers)
+            .map(|_| load_session())
+            .collect::<Result<Vec<_>>>()?;
+        let query_session = load_session()?;
+        let mut tokenizer = Tokenizer::from_file(&tokenizer_path)
             .map_err(|e| anyhow::anyhow!("Failed to load tokenizer: {}", e))?;
+        // Some tokenizers truncate to fewer tokens than the model takes
+        let max_tokens = tokenizer.get_truncation().map_or(MAX_LENGTH, |truncation| {
+            truncation.max_length.min(MAX_LENGTH)
+        });
+        tokenizer
+            .with_truncation(None)
+            .map_err(|e| anyhow::anyhow!("Failed to configure tokenizer: {}", e))?;
+        tokenizer.with_padding(None);
+        let special_tokens = tokenizer
+            .encode("", true)
+            .map_err(|e| anyhow::anyhow!("Failed to encode text: {}", e))?
+            .len();
+        tracing::info!(workers, intra_threads, "Loaded the embedding model");
+
+        Ok(Self {
+            sessions,
+            query_session,
+            tokenizer,
+            max_tokens,
+            special_tokens,
+            low_priority: config.low_priority,
+        })
+    }
+
+    pub fn tokenizer(&self) -> &Tokenizer {
+        &self.tokenizer
+    }
+
+    pub fn max_tokens(&self) -> usize {
+        self.max_tokens
+    }
+
+    /// Embeds one text on the calling thread, with the query session.
+    pub fn generate_embedding(&self, text: &str) -> Result<Vec<f32>> {
+        self.embed(&mut *self.query_session()?, text)
+    }
+
+    /// A normalised vector for each token of one text, embedded on the calling thread.
+    pub fn token_embeddings(&self, text: &str) -> Result<Vec<Vec<f32>>> {
+        Ok(self
+            .embed_windows(&mut *self.query_session()?, text, true)?
+            .1)
+    }
+
+    fn query_session(&self) -> Result<MutexGuard<'_, Session>> {
+        self.query_session
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Embedding session poisoned"))
+    }
+
+    /// Embeds the texts on every session at once, each worker taking the next text from a
+    /// shared queue. Texts that fail to embed yield `None`. The workers are threads of their
+    /// own, so lowering their priority leaves the runtime's threads as they were.
+    pub fn embed_batch(
+        &self,
+        texts: &[String],
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Option<Vec<f32>>>> {
+        self.run_batch(texts, cancel, |session, text| self.embed(session, text))
+    }
 
-        Ok(Self { session, tokenizer })
+    /// Like `embed_batch`, with a normalised vector for each token of every text as well.
+    pub fn embed_batch_with_tokens(
+        &self,
+        texts: &[String],
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Option<(Vec<f32>, Vec<Vec<f32>>)>>> {
+        self.run_batch(texts, cancel, |session, text| {
+            self.embed_windows(session, text, true)
+        })
     }
 
-    pub fn generate_embedding(&mut self, text: &str) -> Result<Vec<f32>> {
+    fn run_batch<T: Send>(
+        &self,
+        texts: &[String],
+        cancel: &CancellationToken,
+        embed: impl Fn(&mut Session, &str) -> Result<T> + Sync,
+    ) -> Result<Vec<Option<T>>> {
+        let next = &AtomicUsize::new(0);
+        let embed = &embed;
+        let workers = throttle::workers(self.sessions.len()).min(texts.len());
+
+        let embedded: Vec<Vec<(usize, Option<T>)>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = self.sessions[..workers]
+                .iter()
+                .map(|session| {
+                    scope.spawn(move || {
+                        if self.low_priority {
+                            lower_thread_priority();
+                        }
+                        let Ok(mut session) = session.lock() else {
+                            return Vec::new();
+                        };
+                        let mut embedded = Vec::new();
+                        loop {
+                            let index = next.fetch_add(1, Ordering::Relaxed);
+                            if index >= texts.len() || cancel.is_cancelled() {
+                                break;
+                            }
+                            embedded.push((index, embed(&mut session, &texts[index]).ok()));
+                        }
+                        embedded
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or_default())
+                .collect()
+        });
+        if cancel.is_cancelled() {
+            anyhow::bail!("Embedding cancelled");
+        }
+
+        let mut embeddings: Vec<Option<T>> = texts.iter().map(|_| None).collect();
+        for (index, embedding) in embedded.into_iter().flatten() {
+            embeddings[index] = embedding;
+        }
+        Ok(embeddings)
+    }
+
+    fn embed(&self, session: &mut Session, text: &str) -> Result<Vec<f32>> {
+        Ok(self.embed_windows(session, text, false)?.0)
+    }
+
+    /// Embeds the text whole when the model reads all of it, or else each overlapping window
+    /// of it, averaging their embeddings so the end of a long text counts as much as its
+    /// start. With `with_tokens`, also returns the vectors of the text's tokens, window
+    /// after window.
+    fn embed_windows(
+        &self,
+        session: &mut Session,
+        text: &str,
+        with_tokens: bool,
+    ) -> Result<(Vec<f32>, Vec<Vec<f32>>)> {
+        let windows = self.windows(text)?;
+        if windows.len() <= 1 {
+            return self.embed_window(session, text, with_tokens);
+        }
+
+        let mut pooled: Vec<f32> = Vec::new();
+        let mut tokens = Vec::new();
+        for window in windows {
+            let (embedding, window_tokens) =
+                self.embed_window(session, &text[window], with_tokens)?;
+            tokens.extend(window_tokens);
+            if pooled.is_empty() {
+                pooled = embedding;
+            } else {
+                for (total, value) in pooled.iter_mut().zip(embedding) {
+                    *total += value;
+                }
+            }
+        }
+        normalize(&mut pooled);
+        Ok((pooled, tokens))
+    }
+
+    /// Byte ranges of `text` the model reads whole, overlapping by `WINDOW_OVERLAP_TOKENS`.
+    fn windows(&self, text: &str) -> Result<Vec<Range<usize>>> {
+        let encoding = self
+            .tokenizer
+            .encode(text, false)
+            .map_err(|e| anyhow::anyhow!("Failed to encode text: {}", e))?;
+        let offsets = encoding.get_offsets();
+        let size = self.max_tokens.saturating_sub(self.special_tokens).max(1);
+        if offsets.len() <= size {
+            return Ok(vec![0..text.len()]);
+        }
+
+        let step = size.saturating_sub(WINDOW_OVERLAP_TOKENS).max(1);
+        let mut windows = Vec::new();
+        let mut first = 0;
+        loop {
+            let start = if first == 0 { 0 } else { offsets[first].0 };
+            let end = offsets
+                .get(first + size)
+                .map_or(text.len(), |&(token_start, _)| token_start);
+            if end > start {
+                windows.push(start..end);
+            }
+            if first + size >= offsets.len() {
+                return Ok(windows);
+            }
+            first += step;
+        }
+    }
+
+    fn embed_window(
+        &self,
+        session: &mut Session,
+        text: &str,
+        with_tokens: bool,
+    ) -> Result<(Vec<f32>, Vec<Vec<f32>>)> {
         let encoding = self
             .tokenizer
             .encode(text, true)
@@ -45,19 +284,244 @@ impl VectorStore {
             attention_mask_f32[i] = mask as f32;
         }
 
-        let outputs = self.session.run(inputs![
+        let outputs = session.run(inputs![
             "input_ids" => TensorRef::from_array_view(&input_ids_array)?,
             "attention_mask" => TensorRef::from_array_view(&attention_mask_array)?,
             "token_type_ids" => TensorRef::from_array_view(&token_type_ids_array)?,
         ])?;
 
         let output_array = outputs[0].try_extract_array::<f32>()?;
-        let embedding = mean_pool(output_array, &attention_mask_f32);
+        let embedding = mean_pool(output_array.view(), &attention_mask_f32);
+        if !with_tokens {
+            return Ok((embedding, Vec::new()));
+        }
 
-        Ok(embedding)
+        // `[CLS]`, `[SEP]` and padding say nothing about the text itself
+        let hidden_size = output_array.shape()[2];
+        let tokens = encoding
+            .get_special_tokens_mask()
+            .iter()
+            .zip(attention_mask)
+            .take(MAX_LENGTH)
+            .enumerate()
+            .filter(|(_, (special, mask))| **special == 0 && **mask == 1)
+            .map(|(i, _)| {
+                let mut token: Vec<f32> =
+                    (0..hidden_size).map(|j| output_array[[0, i, j]]).collect();
+                normalize(&mut token);
+                token
+            })
+            .collect();
+
+        Ok((embedding, tokens))
     }
 }
 
+/// Loads the embedding model on first use and shares the session between index backends.
+/// A failed load is remembered so callers can fall back to keyword search without retrying.
+#[derive(Clone, Default)]
+pub struct Embedder {
+    config: SemanticConfig,
+    /// The index embedded into, whose record the model files are checked against
+    index_dir: PathBuf,
+    vector_store: Option<Arc<VectorStore>>,
+    load_error: Option<String>,
+}
+
+impl Embedder {
+    pub fn new(config: &SemanticConfig, index_dir: &Path) -> Self {
+        Self {
+            config: config.clone(),
+            index_dir: index_dir.to_owned(),
+            ..Self::default()
+        }
+    }
+
+    pub async fn vector_store(&mut self) -> Result<Arc<VectorStore>> {
+        if let Some(vector_store) = &self.vector_store {
+            return Ok(vector_store.clone());
+        }
+        if let Some(error) = &self.load_error {
+            anyhow::bail!("{}", error);
+        }
+
+        let config = self.config.clone();
+        let index_dir = self.index_dir.clone();
+        let vector_store =
+            tokio::task::spawn_blocking(move || VectorStore::new(&config, Some(&index_dir)))
+                .await?;
+        let vector_store = match vector_store {
+            Ok(vector_store) => Arc::new(vector_store),
+            Err(e) => {
+                self.load_error = Some(e.to_string());
+                return Err(e);
+            }
+        };
+        self.vector_store = Some(vector_store.clone());
+        Ok(vector_store)
+    }
+
+    /// A chunk's text as the model expects documents, with `[semantic] document_prompt`.
+    pub fn document_text(&self, text: &str) -> String {
+        format!("{}{}", self.config.document_prompt, text)
+    }
+
+    /// A query as the model expects it, with `[semantic] query_prompt`.
+    pub fn query_text(&self, query: &str) -> String {
+        format!("{}{}", self.config.query_prompt, query)
+    }
+
+    /// Why the model could not be loaded, once loading has failed.
+    pub fn load_error(&self) -> Option<&str> {
+        self.load_error.as_deref()
+    }
+
+    /// Embeds each query with the query session and `[semantic] query_prompt`, leaving out
+    /// those that fail to embed. Gives up between queries once `cancel` fires.
+    pub async fn embed_queries(
+        &mut self,
+        queries: &[String],
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Vec<f32>>> {
+        let vector_store = self.vector_store().await?;
+        let texts: Vec<String> = queries.iter().map(|query| self.query_text(query)).collect();
+        let embedding_cancel = cancel.clone();
+        let embeddings = tokio::task::spawn_blocking(move || {
+            texts
+                .iter()
+                .take_while(|_| !embedding_cancel.is_cancelled())
+                .filter_map(|text| vector_store.generate_embedding(text).ok())
+                .collect()
+        })
+        .await?;
+        if cancel.is_cancelled() {
+            anyhow::bail!("Search cancelled");
+        }
+        Ok(embeddings)
+    }
+
+    /// A job embedding `texts` as documents, with a vector for each of their tokens as well
+    /// when `with_tokens`, for late interaction. Loads the model if it isn't yet.
+    pub async fn document_job(
+        &mut self,
+        texts: &[String],
+        with_tokens: bool,
+    ) -> Result<EmbeddingJob> {
+        Ok(EmbeddingJob {
+            vector_store: self.vector_store().await?,
+            texts: texts.iter().map(|text| self.document_text(text)).collect(),
+            with_tokens,
+        })
+    }
+
+    /// A vector for each token of the query, with `[semantic] query_prompt`.
+    pub async fn query_token_embeddings(&mut self, query: &str) -> Result<Vec<Vec<f32>>> {
+        let vector_store = self.vector_store().await?;
+        let query = self.query_text(query);
+        tokio::task::spawn_blocking(move || vector_store.token_embeddings(&query)).await?
+    }
+
+    /// Cosine similarity between the query and each line; blank lines score zero.
+    pub async fn line_similarities(&mut self, query: &str, lines: &[String]) -> Result<Vec<f32>> {
+        let vector_store = self.vector_store().await?;
+        let query = self.query_text(query);
+        let lines: Vec<String> = lines
+            .iter()
+            .map(|line| {
+                if line.trim().is_empty() {
+                    String::new()
+                } else {
+                    self.document_text(line)
+                }
+            })
+            .collect();
+
+        tokio::task::spawn_blocking(move || -> Result<Vec<f32>> {
+            let query_embedding = vector_store.generate_embedding(&query)?;
+
+            lines
+                .iter()
+                .map(|line| {
+                    if line.trim().is_empty() {
+                        return Ok(0.0);
+                    }
+                    let embedding = vector_store.generate_embedding(line)?;
+                    Ok(cosine_similarity(&query_embedding, &embedding))
+                })
+                .collect()
+        })
+        .await?
+    }
+}
+
+/// Texts to embed with a loaded model. Running it needs nothing from the index, so
+/// indexing embeds a batch without holding the storage lock.
+pub struct EmbeddingJob {
+    vector_store: Arc<VectorStore>,
+    texts: Vec<String>,
+    with_tokens: bool,
+}
+
+impl EmbeddingJob {
+    /// The embedding of each text, `None` for texts that fail to embed, and the vectors of
+    /// its tokens when asked for. Gives up between texts once `cancel` fires.
+    pub async fn run(
+        self,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Option<(Vec<f32>, Vec<Vec<f32>>)>>> {
+        let cancel = cancel.clone();
+        tokio::task::spawn_blocking(move || {
+            if self.with_tokens {
+                return self
+                    .vector_store
+                    .embed_batch_with_tokens(&self.texts, &cancel);
+            }
+            Ok(self
+                .vector_store
+                .embed_batch(&self.texts, &cancel)?
+                .into_iter()
+                .map(|embedding| embedding.map(|pooled| (pooled, Vec::new())))
+                .collect())
+        })
+        .await?
+    }
+}
+
+/// Lets the UI and searches come first while the embedding workers run. Only Linux sets
+/// the priority of a single thread; elsewhere this does nothing.
+fn lower_thread_priority() {
+    #[cfg(target_os = "linux")]
+    {
+        const EMBEDDING_NICENESS: libc::c_int = 10;
+        // SAFETY: setpriority only reads its arguments, and gettid names the calling thread
+        let result = unsafe {
+            libc::setpriority(
+                libc::PRIO_PROCESS,
+                libc::gettid() as libc::id_t,
+                EMBEDDING_NICENESS,
+            )
+        };
+        if result != 0 {
+            tracing::debug!(
+                "Failed to lower the embedding worker's priority: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+
+    (dot / (norm_a * norm_b)).clamp(0.0, 1.0)
+}
+
 fn mean_pool(token_embeddings: ndarray::ArrayViewD<f32>, attention_mask: &[f32]) -> Vec<f32> {
     let shape = token_embeddings.shape();
     let seq_len = shape[1];
@@ -80,24 +544,137 @@ fn mean_pool(token_embeddings: ndarray::ArrayViewD<f32>, attention_mask: &[f32])
         }
     }
 
-    let norm: f32 = pooled.iter().map(|x| x * x).sum::<f32>().sqrt();
+    normalize(&mut pooled);
+    pooled
+}
+
+fn normalize(vector: &mut [f32]) {
+    let norm: f32 = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
     if norm > 0.0 {
-        for val in &mut pooled {
+        for val in vector {
             *val /= norm;
         }
     }
+}
+
+/// The ONNX export and tokenizer of the configured model, from `model_dir` or the Hugging
+/// Face cache.
+pub(super) fn model_files(
+    config: &SemanticConfig,
+    index_dir: Option<&Path>,
+) -> Result<(PathBuf, PathBuf)> {
+    match &config.model_dir {
+        Some(model_dir) => local_model_files(model_dir),
+        None => hub_model_files(config, index_dir),
+    }
+}
+
+/// Model and tokenizer from a preseeded directory, laid out either like the Hugging Face
+/// repository or flat.
+fn local_model_files(model_dir: &Path) -> Result<(PathBuf, PathBuf)> {
+    let model_path = MODEL_FILES
+        .iter()
+        .map(|file| model_dir.join(file))
+        .find(|path| path.is_file())
+        .with_context(|| format!("No model.onnx in {}", model_dir.display()))?;
+
+    let tokenizer_path = model_dir.join(TOKENIZER_FILE);
+    if !tokenizer_path.is_file() {
+        anyhow::bail!("No {} in {}", TOKENIZER_FILE, model_dir.display());
+    }
+
+    Ok((model_path, tokenizer_path))
+}
+
+/// The `[semantic] model` export and tokenizer from the Hugging Face cache, downloaded
+/// when missing and then checked against the hub's checksums. Without a configured
+/// revision the model stays at the commit it was first loaded at. With `index_dir`, the
+/// files must match the checksums recorded there when the index was first embedded.
+fn hub_model_files(
+    config: &SemanticConfig,
+    index_dir: Option<&Path>,
+) -> Result<(PathBuf, PathBuf)> {
+    let mut lock = ModelLock::load()?;
+    let revision = config
+        .revision
+        .clone()
+        .or_else(|| lock.pinned(&config.model).map(str::to_string));
+    let repo = models::repo(&config.model, revision.as_deref());
+    let cached = Cache::default().repo(repo.clone());
+    let fetch_file = |file| fetch(&repo, file, config.offline).map(|path| (file, path));
+    let cached_model = MODEL_FILES
+        .iter()
+        .find_map(|file| Some((*file, cached.get(file)?)));
+    let tokenizer_cached = cached.get(TOKENIZER_FILE).is_some();
+    let downloaded = cached_model.is_none() || !tokenizer_cached;
+    let (model_file, model_path) = match cached_model {
+        Some(found) => found,
+        None => {
+            fetch_file(MODEL_FILES[0]).or_else(|e| fetch_file(MODEL_FILES[1]).map_err(|_| e))?
+        }
+    };
+    let tokenizer_path = fetch(&repo, TOKENIZER_FILE, config.offline)?;
+
+    let commit = models::cached_commit(&config.model, revision.as_deref())
+        .with_context(|| format!("No commit of {} in the Hugging Face cache", config.model))?;
+    let files = [
+        (model_file, model_path.as_path()),
+        (TOKENIZER_FILE, tokenizer_path.as_path()),
+    ];
+    if downloaded {
+        models::verify_published(&config.model, &commit, &files)?;
+    }
+    if let Some(index_dir) = index_dir {
+        models::verify_index(index_dir, &config.model, &commit, &files)?;
+    }
+    if revision.is_none() {
+        lock.pin(&config.model, &commit)?;
+        lock.save()?;
+    }
 
-    pooled
+    Ok((model_path, tokenizer_path))
 }
 
-fn download_model() -> Result<PathBuf> {
+/// Returns the cached file, downloading it with progress reporting when missing.
+fn fetch(repo: &Repo, filename: &str, offline: bool) -> Result<PathBuf> {
+    if let Some(path) = Cache::default().repo(repo.clone()).get(filename) {
+        return Ok(path);
+    }
+    if offline {
+        anyhow::bail!("{} is not cached and offline mode is on", filename);
+    }
+
+    tracing::info!(filename, "Downloading the embedding model");
     let api = Api::new()?;
-    let repo = api.model("sentence-transformers/all-MiniLM-L6-v2".to_string());
-    Ok(repo.get("onnx/model.onnx")?)
+    let path = api
+        .repo(repo.clone())
+        .download_with_progress(filename, DownloadProgress);
+    if let Ok(mut progress) = DOWNLOAD_PROGRESS.lock() {
+        *progress = None;
+    }
+    Ok(path?)
 }
 
-fn download_tokenizer() -> Result<PathBuf> {
-    let api = Api::new()?;
-    let repo = api.model("sentence-transformers/all-MiniLM-L6-v2".to_string());
-    Ok(repo.get("tokenizer.json")?)
+struct DownloadProgress;
+
+impl Progress for DownloadProgress {
+    fn init(&mut self, size: usize, _filename: &str) {
+        if let Ok(mut progress) = DOWNLOAD_PROGRESS.lock() {
+            *progress = Some((0, size as u64));
+        }
+    }
+
+    fn update(&mut self, size: usize) {
+        if let Ok(mut progress) = DOWNLOAD_PROGRESS.lock()
+            && let Some((downloaded, _)) = progress.as_mut()
+        {
+            *downloaded += size as u64;
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Ok(mut progress) = DOWNLOAD_PROGRESS.lock() {
+            *progress = None;
+        }
+    }
 }
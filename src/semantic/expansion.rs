@@ -0,0 +1,72 @@
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::config::{LlmConfig, QueryExpansion, SearchConfig};
+
+const LLM_TIMEOUT_SECS: u64 = 10;
+const HYDE_PROMPT: &str = "Write a short passage (at most five sentences or a small code snippet) \
+that would answer the following search query. Reply with the passage only.\n\nQuery: ";
+
+pub struct QueryExpander {
+    mode: QueryExpansion,
+    llm: LlmConfig,
+}
+
+impl QueryExpander {
+    pub fn new(search_config: &SearchConfig, llm_config: &LlmConfig) -> Self {
+        Self {
+            mode: search_config.query_expansion.clone(),
+            llm: llm_config.clone(),
+        }
+    }
+
+    /// Returns the original query followed by any generated variants. Synonym variants
+    /// come from the `[synonyms]` the query uses, so they are added by the caller.
+    pub async fn expand(&self, query: &str) -> Vec<String> {
+        let mut variants = vec![query.to_string()];
+
+        match self.mode {
+            QueryExpansion::None | QueryExpansion::Synonyms => {}
+            QueryExpansion::Hyde => {
+                if let Ok(passage) = self.hypothetical_answer(query).await {
+                    variants.push(passage);
+                }
+            }
+        }
+
+        // A variant may repeat any earlier one, not only the one before it
+        let mut seen = HashSet::new();
+        variants.retain(|variant| seen.insert(variant.clone()));
+        variants
+    }
+
+    async fn hypothetical_answer(&self, query: &str) -> Result<String> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(LLM_TIMEOUT_SECS))
+            .build()?;
+
+        let mut request = client.post(&self.llm.endpoint).json(&json!({
+            "model": self.llm.model,
+            "messages": [{ "role": "user", "content": format!("{}{}", HYDE_PROMPT, query) }],
+            "stream": false,
+        }));
+        if let Some(api_key) = &self.llm.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response: serde_json::Value = request.send().await?.error_for_status()?.json().await?;
+        let passage = response["choices"][0]["message"]["content"]
+            .as_str()
+            .context("LLM response did not contain a completion")?
+            .trim()
+            .to_string();
+
+        if passage.is_empty() {
+            anyhow::bail!("LLM returned an empty completion");
+        }
+
+        Ok(passage)
+    }
+}
@@ -1 +1,4 @@
 pub mod embeddings;
+pub mod expansion;
+pub mod models;
+pub mod sparse;
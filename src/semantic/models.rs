@@ -0,0 +1,339 @@
+use anyhow::{Context, Result};
+use hf_hub::api::sync::Api;
+use hf_hub::{Cache, Repo, RepoType};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::embeddings::{MODEL_FILES, TOKENIZER_FILE};
+use crate::config::ConfigManager;
+
+/// What a model follows before its first download pins it
+const DEFAULT_REVISION: &str = "main";
+const FOLDER_PREFIX: &str = "models--";
+const LOCK_FILE: &str = "models.lock.json";
+/// Kept in the index directory and removed with the index when it is rebuilt
+pub const INDEX_CHECKSUMS_FILE: &str = "model_checksums.json";
+
+/// The commit each model is held at, so an update upstream doesn't change the embeddings
+/// under an existing index.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ModelLock {
+    /// Commit each model is loaded at while `[semantic] revision` is unset, by model id
+    pinned: BTreeMap<String, String>,
+}
+
+impl ModelLock {
+    pub fn load() -> Result<Self> {
+        let path = Self::path();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read model lock: {:?}", path))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse model lock: {:?}", path))
+    }
+
+    fn path() -> PathBuf {
+        ConfigManager::get_data_dir().join(LOCK_FILE)
+    }
+
+    /// The commit `model` was first loaded at, if it has been.
+    pub fn pinned(&self, model: &str) -> Option<&str> {
+        self.pinned.get(model).map(String::as_str)
+    }
+
+    /// Holds `model` at `commit` from now on, recording the commit as a revision of the
+    /// cache so the pinned files load offline.
+    pub fn pin(&mut self, model: &str, commit: &str) -> Result<()> {
+        Cache::default()
+            .repo(repo(model, Some(commit)))
+            .create_ref(commit)?;
+        self.pinned.insert(model.to_string(), commit.to_string());
+        Ok(())
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content =
+            serde_json::to_string_pretty(self).context("Failed to serialize model lock")?;
+        fs::write(&path, content).with_context(|| format!("Failed to write model lock: {:?}", path))
+    }
+}
+
+/// Checks each file of `model` at `commit`, named by its path in the repository, against
+/// the hash the Hugging Face hub publishes for it: the SHA-256 of a file stored with Git
+/// LFS, or else its Git blob id.
+pub fn verify_published(model: &str, commit: &str, files: &[(&str, &Path)]) -> Result<()> {
+    let info: serde_json::Value = Api::new()?
+        .repo(repo(model, Some(commit)))
+        .info_request()
+        .query("blobs", "true")
+        .call()
+        .with_context(|| format!("Failed to look up the files of {} on the hub", model))?
+        .into_json()
+        .with_context(|| format!("Failed to read the files of {} from the hub", model))?;
+    let siblings = info["siblings"].as_array().cloned().unwrap_or_default();
+
+    for (name, path) in files {
+        let sibling = siblings
+            .iter()
+            .find(|sibling| sibling["rfilename"] == *name)
+            .with_context(|| format!("{} at {} has no {}", model, commit, name))?;
+        let matches = match sibling["lfs"]["sha256"].as_str() {
+            Some(expected) => sha256(path)? == expected,
+            None => Some(git_blob_id(path)?.as_str()) == sibling["blobId"].as_str(),
+        };
+        if !matches {
+            anyhow::bail!(
+                "{} of {} at {} doesn't match the checksum the Hugging Face hub publishes \
+                 for it, so the download is damaged. Run `sema models remove {}` to fetch \
+                 it afresh",
+                name,
+                model,
+                commit,
+                model
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Checks each file against the checksum recorded in `index_dir` when the index was first
+/// embedded with it, recording those it hasn't been yet, so the vectors of one index all
+/// come from the same weights. Rebuilding the index, as a change of model does, starts
+/// the record afresh.
+pub fn verify_index(
+    index_dir: &Path,
+    model: &str,
+    commit: &str,
+    files: &[(&str, &Path)],
+) -> Result<()> {
+    let record = index_dir.join(INDEX_CHECKSUMS_FILE);
+    let mut recorded: BTreeMap<String, String> = match fs::read_to_string(&record) {
+        Ok(content) => serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse model checksums: {:?}", record))?,
+        Err(_) => BTreeMap::new(),
+    };
+
+    let mut changed = false;
+    for (name, path) in files {
+        let checksum = sha256(path)?;
+        // The embedding and sparse models may both have a tokenizer.json
+        let key = format!("{}/{}", model, name);
+        match recorded.get(&key) {
+            Some(expected) if *expected != checksum => anyhow::bail!(
+                "{} of {} at {} doesn't match the checksum recorded when the index was \
+                 built with it, so the download is damaged or was replaced. Run `sema \
+                 models remove {}` to fetch it afresh",
+                name,
+                model,
+                commit,
+                model
+            ),
+            Some(_) => {}
+            None => {
+                recorded.insert(key, checksum);
+                changed = true;
+            }
+        }
+    }
+
+    if changed {
+        fs::create_dir_all(index_dir)?;
+        let content = serde_json::to_string_pretty(&recorded)
+            .context("Failed to serialize model checksums")?;
+        fs::write(&record, content)
+            .with_context(|| format!("Failed to write model checksums: {:?}", record))?;
+    }
+    Ok(())
+}
+
+/// The id Git gives a file's contents, as the hub lists files outside Git LFS.
+fn git_blob_id(path: &Path) -> Result<String> {
+    let mut file =
+        fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let length = file.metadata()?.len();
+    let mut hasher = Sha1::new();
+    hasher.update(format!("blob {}\0", length).as_bytes());
+    std::io::copy(&mut file, &mut hasher)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn sha256(path: &Path) -> Result<String> {
+    let mut file =
+        fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// An embedding model in the Hugging Face cache.
+#[derive(Debug)]
+pub struct CachedModel {
+    /// Repository id, e.g. `sentence-transformers/all-MiniLM-L6-v2`
+    pub id: String,
+    pub path: PathBuf,
+    /// Branches, tags and pinned commits fetched, each with the commit it resolved to
+    pub revisions: Vec<(String, String)>,
+    /// Bytes of every file downloaded, across revisions
+    pub size: u64,
+    /// Whether a snapshot holds an ONNX export and tokenizer sema can load
+    pub usable: bool,
+}
+
+/// The hub repository of `model` at `revision`, or at the default branch.
+pub fn repo(model: &str, revision: Option<&str>) -> Repo {
+    Repo::with_revision(
+        model.to_string(),
+        RepoType::Model,
+        revision.unwrap_or(DEFAULT_REVISION).to_string(),
+    )
+}
+
+/// Every model in the Hugging Face cache, by id.
+pub fn cached() -> Result<Vec<CachedModel>> {
+    let cache = Cache::default();
+    let Ok(entries) = fs::read_dir(cache.path()) else {
+        return Ok(Vec::new());
+    };
+
+    let mut models = Vec::new();
+    for entry in entries.flatten() {
+        let folder = entry.file_name().to_string_lossy().into_owned();
+        let Some(id) = folder.strip_prefix(FOLDER_PREFIX) else {
+            continue;
+        };
+        let path = entry.path();
+        let mut revisions = Vec::new();
+        collect_refs(&path.join("refs"), "", &mut revisions);
+        revisions.sort();
+
+        models.push(CachedModel {
+            // The owner and the name are joined with `--` in place of the slash
+            id: id.replacen("--", "/", 1),
+            size: directory_size(&path.join("blobs")),
+            usable: has_onnx_snapshot(&path.join("snapshots")),
+            revisions,
+            path,
+        });
+    }
+    models.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(models)
+}
+
+/// The commit `revision` of `model` resolved to when it was last downloaded.
+pub fn cached_commit(model: &str, revision: Option<&str>) -> Option<String> {
+    let path = Cache::default()
+        .path()
+        .join(repo(model, revision).folder_name())
+        .join("refs")
+        .join(revision.unwrap_or(DEFAULT_REVISION));
+    let commit = fs::read_to_string(path).ok()?;
+    Some(commit.trim().to_string())
+}
+
+/// Downloads the ONNX export and tokenizer of `model` at `revision` into the Hugging Face
+/// cache, with a progress bar, checks them against the hub's checksums and returns the
+/// commit they came from. Files already cached are kept. The commit is recorded as a
+/// revision of its own, so the model can be pinned to it and loaded offline.
+pub fn download(model: &str, revision: Option<&str>) -> Result<String> {
+    let api = Api::new()?;
+    let hub_repo = api.repo(repo(model, revision));
+    let info = hub_repo
+        .info()
+        .with_context(|| format!("Failed to look up {} on the Hugging Face hub", model))?;
+    let model_file = MODEL_FILES
+        .iter()
+        .find(|file| {
+            info.siblings
+                .iter()
+                .any(|sibling| sibling.rfilename == **file)
+        })
+        .with_context(|| {
+            format!(
+                "{} has no ONNX export ({})",
+                model,
+                MODEL_FILES.join(" or ")
+            )
+        })?;
+
+    let pinned = api.repo(repo(model, Some(&info.sha)));
+    let mut files = Vec::new();
+    for file in [*model_file, TOKENIZER_FILE] {
+        let path = pinned
+            .get(file)
+            .with_context(|| format!("Failed to download {} from {}", file, model))?;
+        files.push((file, path));
+    }
+    let files: Vec<(&str, &Path)> = files
+        .iter()
+        .map(|(file, path)| (*file, path.as_path()))
+        .collect();
+    verify_published(model, &info.sha, &files)?;
+    Cache::default()
+        .repo(repo(model, revision))
+        .create_ref(&info.sha)?;
+    Ok(info.sha)
+}
+
+/// Deletes `model` from the Hugging Face cache, returning the bytes freed.
+pub fn remove(model: &str) -> Result<u64> {
+    let path = Cache::default()
+        .path()
+        .join(repo(model, None).folder_name());
+    if !path.exists() {
+        anyhow::bail!("{} is not in the Hugging Face cache", model);
+    }
+    let size = directory_size(&path.join("blobs"));
+    fs::remove_dir_all(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    Ok(size)
+}
+
+/// Reads the ref files under `dir`; a revision like `refs/pr/1` spans folders.
+fn collect_refs(dir: &Path, prefix: &str, refs: &mut Vec<(String, String)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        let path = entry.path();
+        if path.is_dir() {
+            collect_refs(&path, &format!("{}/", name), refs);
+        } else if let Ok(commit) = fs::read_to_string(&path) {
+            refs.push((name, commit.trim().to_string()));
+        }
+    }
+}
+
+fn has_onnx_snapshot(snapshots: &Path) -> bool {
+    let Ok(entries) = fs::read_dir(snapshots) else {
+        return false;
+    };
+    entries.flatten().any(|snapshot| {
+        let path = snapshot.path();
+        path.join(TOKENIZER_FILE).exists()
+            && MODEL_FILES.iter().any(|file| path.join(file).exists())
+    })
+}
+
+/// Blobs hold the files themselves; snapshots only link to them.
+fn directory_size(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
@@ -0,0 +1,191 @@
+use anyhow::Result;
+use ndarray::{Axis, Ix3};
+use ort::{inputs, session::Session, value::TensorRef};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokenizers::Tokenizer;
+
+use super::embeddings::model_files;
+use crate::config::SemanticConfig;
+
+pub const SPARSE_MODEL_ID: &str = "Qdrant/Splade_PP_en_v1";
+/// Tokens the model reads at once; longer texts are encoded a window at a time
+const MAX_LENGTH: usize = 256;
+/// Terms kept for a text, heaviest first; the rest add little to a match
+const MAX_TERMS: usize = 256;
+
+/// Vocabulary terms with the weight a learned-sparse model gives them for a text, heaviest
+/// first.
+pub type SparseVector = Vec<(String, f32)>;
+
+/// A SPLADE-style model: a masked language model whose logits, pooled over the text, weigh
+/// every vocabulary term, including terms the text doesn't contain but implies.
+pub struct SparseModel {
+    session: Mutex<Session>,
+    tokenizer: Tokenizer,
+    /// BERT-style models take segment ids, others don't
+    token_type_ids: bool,
+}
+
+impl SparseModel {
+    /// Loads `[semantic] sparse_model` from `sparse_model_dir` or the Hugging Face cache,
+    /// checked against the files the index in `index_dir` was built with.
+    pub fn new(config: &SemanticConfig, index_dir: &Path) -> Result<Self> {
+        let (model_path, tokenizer_path) = model_files(
+            &SemanticConfig {
+                model: config.sparse_model.clone(),
+                model_dir: config.sparse_model_dir.clone(),
+                revision: None,
+                ..config.clone()
+            },
+            Some(index_dir),
+        )?;
+
+        let session = Session::builder()?.commit_from_file(&model_path)?;
+        let token_type_ids = session
+            .inputs
+            .iter()
+            .any(|input| input.name == "token_type_ids");
+        let mut tokenizer = Tokenizer::from_file(&tokenizer_path)
+            .map_err(|e| anyhow::anyhow!("Failed to load tokenizer: {}", e))?;
+        tokenizer
+            .with_truncation(None)
+            .map_err(|e| anyhow::anyhow!("Failed to configure tokenizer: {}", e))?;
+        tokenizer.with_padding(None);
+        tracing::info!("Loaded the sparse model");
+
+        Ok(Self {
+            session: Mutex::new(session),
+            tokenizer,
+            token_type_ids,
+        })
+    }
+
+    /// The terms of each text, `None` for texts that fail to encode, encoded off the
+    /// runtime's threads; `None` overall when the encoding task fails.
+    pub async fn encode_all(
+        self: Arc<Self>,
+        texts: Vec<String>,
+    ) -> Option<Vec<Option<SparseVector>>> {
+        tokio::task::spawn_blocking(move || {
+            texts.iter().map(|text| self.encode(text).ok()).collect()
+        })
+        .await
+        .ok()
+    }
+
+    /// The weight of each term is `log(1 + relu(logit))` at the position where it is
+    /// highest, as SPLADE pools; a long text takes the highest over all its windows.
+    pub fn encode(&self, text: &str) -> Result<SparseVector> {
+        let encoding = self
+            .tokenizer
+            .encode(text, true)
+            .map_err(|e| anyhow::anyhow!("Failed to encode text: {}", e))?;
+        let ids = encoding.get_ids();
+        if ids.len() < 3 {
+            return Ok(Vec::new());
+        }
+
+        // `[CLS]` and `[SEP]` open and close every window
+        let (first, body, last) = (ids[0], &ids[1..ids.len() - 1], ids[ids.len() - 1]);
+        let mut weights: Vec<f32> = Vec::new();
+        let mut session = self
+            .session
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Sparse model session poisoned"))?;
+        for window in body.chunks(MAX_LENGTH - 2) {
+            let input_ids: Vec<i64> = std::iter::once(first)
+                .chain(window.iter().copied())
+                .chain(std::iter::once(last))
+                .map(i64::from)
+                .collect();
+            let length = input_ids.len();
+            let input_ids = ndarray::Array2::from_shape_vec((1, length), input_ids)?;
+            let attention_mask = ndarray::Array2::<i64>::ones((1, length));
+            let token_type_ids = ndarray::Array2::<i64>::zeros((1, length));
+
+            let mut inputs = inputs![
+                "input_ids" => TensorRef::from_array_view(&input_ids)?,
+                "attention_mask" => TensorRef::from_array_view(&attention_mask)?,
+            ];
+            if self.token_type_ids {
+                inputs.push((
+                    "token_type_ids".into(),
+                    TensorRef::from_array_view(&token_type_ids)?.into(),
+                ));
+            }
+            let outputs = session.run(inputs)?;
+            let logits = outputs[0]
+                .try_extract_array::<f32>()?
+                .into_dimensionality::<Ix3>()?;
+
+            for position in logits.index_axis(Axis(0), 0).rows() {
+                if weights.is_empty() {
+                    weights = vec![0.0; position.len()];
+                }
+                for (weight, logit) in weights.iter_mut().zip(position) {
+                    *weight = weight.max(logit.max(0.0).ln_1p());
+                }
+            }
+        }
+
+        let mut terms: SparseVector = weights
+            .into_iter()
+            .enumerate()
+            .filter(|(_, weight)| *weight > 0.0)
+            .filter_map(|(id, weight)| Some((self.tokenizer.id_to_token(id as u32)?, weight)))
+            .collect();
+        terms.sort_by(|a, b| b.1.total_cmp(&a.1));
+        terms.truncate(MAX_TERMS);
+        Ok(terms)
+    }
+}
+
+/// Loads the sparse model on first use. A failed load is logged once and the sparse
+/// channel left out from then on, so indexing and search carry on without it.
+#[derive(Default)]
+pub struct SparseEncoder {
+    config: SemanticConfig,
+    index_dir: PathBuf,
+    model: Option<Arc<SparseModel>>,
+    failed: bool,
+}
+
+impl SparseEncoder {
+    pub fn new(config: &SemanticConfig, index_dir: &Path) -> Self {
+        Self {
+            config: config.clone(),
+            index_dir: index_dir.to_owned(),
+            ..Self::default()
+        }
+    }
+
+    /// The model, loading it on first use; `None` once loading it has failed.
+    pub async fn model(&mut self) -> Option<Arc<SparseModel>> {
+        if let Some(model) = &self.model {
+            return Some(model.clone());
+        }
+        if self.failed {
+            return None;
+        }
+
+        let config = self.config.clone();
+        let index_dir = self.index_dir.clone();
+        let loaded = tokio::task::spawn_blocking(move || SparseModel::new(&config, &index_dir))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|model| model);
+        match loaded {
+            Ok(model) => {
+                let model = Arc::new(model);
+                self.model = Some(model.clone());
+                Some(model)
+            }
+            Err(e) => {
+                tracing::warn!("Failed to load the sparse model, going without it: {:#}", e);
+                self.failed = true;
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// Team vocabulary from the `[synonyms]` config table, such as `k8s = "kubernetes"`, mapped
+/// onto the words the indexed files actually use.
+#[derive(Debug, Clone, Default)]
+pub struct QueryAliases {
+    /// Lowercased alias to its alternatives, the expansion first
+    aliases: HashMap<String, Vec<String>>,
+}
+
+impl QueryAliases {
+    pub fn new(synonyms: &BTreeMap<String, Vec<String>>) -> Self {
+        Self {
+            aliases: synonyms
+                .iter()
+                .map(|(alias, alternatives)| {
+                    let alternatives: Vec<String> = alternatives
+                        .iter()
+                        .map(|alternative| alternative.trim().to_string())
+                        .filter(|alternative| !alternative.is_empty())
+                        .collect();
+                    (alias.to_lowercase(), alternatives)
+                })
+                .filter(|(_, alternatives)| !alternatives.is_empty())
+                .collect(),
+        }
+    }
+
+    /// The query for the keyword index: each alias is kept, as files may use it too, and
+    /// followed by its alternatives, which the index matches too.
+    pub fn keyword_query(&self, query: &str) -> String {
+        self.rewrite(query, |word, alternatives| {
+            format!("{} {}", word, alternatives.join(" "))
+        })
+    }
+
+    /// The query to embed: each alias is replaced by its expansion, which the model is far
+    /// more likely to know.
+    pub fn semantic_query(&self, query: &str) -> String {
+        self.rewrite(query, |_, alternatives| alternatives[0].clone())
+    }
+
+    /// The query to embed with one alias replaced by another of its alternatives, for
+    /// each alias and alternative after the expansion.
+    pub fn semantic_variants(&self, query: &str) -> Vec<String> {
+        let words: Vec<String> = query
+            .split_whitespace()
+            .map(|word| self.semantic_query(word))
+            .collect();
+        let mut variants = Vec::new();
+        for (index, word) in query.split_whitespace().enumerate() {
+            let Some(alternatives) = self.alternatives(word) else {
+                continue;
+            };
+            for alternative in &alternatives[1..] {
+                let mut replaced = words.clone();
+                replaced[index] = alternative.clone();
+                variants.push(replaced.join(" "));
+            }
+        }
+        variants
+    }
+
+    /// The alternatives of a plain word of a query that is an alias.
+    fn alternatives(&self, word: &str) -> Option<&[String]> {
+        if !word.chars().all(char::is_alphanumeric) {
+            return None;
+        }
+        self.aliases.get(&word.to_lowercase()).map(Vec::as_slice)
+    }
+
+    /// Rewrites the plain words of a query that are aliases, leaving quoted phrases and
+    /// anything with query syntax as typed.
+    fn rewrite(&self, query: &str, expand: impl Fn(&str, &[String]) -> String) -> String {
+        if self.aliases.is_empty() {
+            return query.to_string();
+        }
+
+        query
+            .split_whitespace()
+            .map(|word| match self.alternatives(word) {
+                Some(alternatives) => expand(word, alternatives),
+                None => word.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
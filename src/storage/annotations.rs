@@ -0,0 +1,57 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+use crate::types::Chunk;
+
+/// Keyword query finding chunks that may hold an annotation; the pattern below confirms them.
+pub const ANNOTATION_QUERY: &str = "todo fixme hack";
+
+/// Upper-case tags only, so prose mentioning a "todo list" is left out.
+static ANNOTATION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b(TODO|FIXME|HACK)\b(?:\([^)]*\))?:?\s*(.*)")
+        .expect("annotation pattern is valid")
+});
+
+/// A TODO, FIXME or HACK left in a file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    pub file_path: PathBuf,
+    /// 1-based line number
+    pub line: usize,
+    pub tag: String,
+    /// What follows the tag on its line
+    pub text: String,
+}
+
+/// Every annotation in the chunks, ordered by file and line. Lines repeated by overlapping
+/// chunks are reported once.
+pub fn find(chunks: &[Chunk]) -> Vec<Annotation> {
+    let mut annotations = BTreeMap::new();
+
+    for chunk in chunks {
+        for (offset, line) in chunk.content.lines().enumerate() {
+            let Some(captures) = ANNOTATION.captures(line) else {
+                continue;
+            };
+            let line_number = chunk.start_line + offset;
+            annotations
+                .entry((chunk.file_path.clone(), line_number))
+                .or_insert_with(|| Annotation {
+                    file_path: chunk.file_path.clone(),
+                    line: line_number,
+                    tag: captures[1].to_string(),
+                    text: captures[2]
+                        .trim()
+                        .trim_end_matches("*/")
+                        .trim_end_matches("-->")
+                        .trim()
+                        .to_string(),
+                });
+        }
+    }
+
+    annotations.into_values().collect()
+}
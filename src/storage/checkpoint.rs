@@ -0,0 +1,35 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+const CHECKPOINT_FILE: &str = "index_checkpoint.json";
+
+/// Files whose chunks were partly committed when the last indexing run stopped. Their
+/// chunks are purged on the next run so the files can be reindexed from scratch, while
+/// every file recorded in the file index is skipped as usual.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct IndexCheckpoint {
+    pub in_progress: BTreeSet<PathBuf>,
+}
+
+impl IndexCheckpoint {
+    pub fn load(data_dir: &Path) -> Option<Self> {
+        let content = std::fs::read_to_string(data_dir.join(CHECKPOINT_FILE)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn save(&self, data_dir: &Path) -> Result<()> {
+        let content = serde_json::to_string(self)?;
+        std::fs::write(data_dir.join(CHECKPOINT_FILE), content)?;
+        Ok(())
+    }
+
+    pub fn clear(data_dir: &Path) -> Result<()> {
+        let path = data_dir.join(CHECKPOINT_FILE);
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
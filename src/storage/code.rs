@@ -0,0 +1,319 @@
+use regex::Regex;
+use std::path::Path;
+use std::sync::LazyLock;
+use syntect::parsing::SyntaxSet;
+
+use crate::types::{Chunk, ChunkKind};
+
+/// The syntaxes syntect ships with, which name the languages of chunks as the preview
+/// highlights them.
+pub static SYNTAX_SET: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+
+/// A line opening a function, type or module definition in most C-like and scripting
+/// languages. Functions without a keyword, as in C, are not recognised.
+static DEFINITION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(concat!(
+        r"^\s*(?:(?:pub(?:\([^)]*\))?|export|default|async|static|public|private|protected|",
+        r"internal|abstract|final|override|unsafe|const|extern(?:\s+\x22[^\x22]*\x22)?)\s+)*",
+        r"(?:fn|struct|enum|trait|union|mod|class|interface|def|function\*?|type|module|",
+        r"object|protocol|func(?:\s*\([^)]*\))?|impl(?:<[^>]*>)?(?:\s+[\w:]+(?:<[^>]*>)?\s+for)?)",
+        r"\s+([A-Za-z_$][\w$]*)"
+    ))
+    .expect("definition pattern is valid")
+});
+
+/// How a language marks comments, for telling comments and docs apart from code.
+struct Language {
+    /// The name of its syntect syntax, where syntect has one
+    name: &'static str,
+    /// Extensions syntect has no syntax for
+    extensions: &'static [&'static str],
+    /// Documentation files, whose chunks are all docs
+    prose: bool,
+    /// Checked before `block_comment` and `line_comments`, which may be their prefix
+    doc_comments: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    /// Checked before `line_comments`, as Lua's `--[[` starts with `--`
+    block_comment: Option<(&'static str, &'static str)>,
+    /// Opens and closes a docstring at the start of a line, as Python's triple quotes do
+    docstring: Option<&'static str>,
+}
+
+const fn code(
+    name: &'static str,
+    extensions: &'static [&'static str],
+    doc_comments: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+) -> Language {
+    Language {
+        name,
+        extensions,
+        prose: false,
+        doc_comments,
+        line_comments,
+        block_comment,
+        docstring: None,
+    }
+}
+
+const fn prose(name: &'static str, extensions: &'static [&'static str]) -> Language {
+    Language {
+        name,
+        extensions,
+        prose: true,
+        doc_comments: &[],
+        line_comments: &[],
+        block_comment: None,
+        docstring: None,
+    }
+}
+
+const C_BLOCK: Option<(&str, &str)> = Some(("/*", "*/"));
+
+const LANGUAGES: &[Language] = &[
+    code("Rust", &[], &["///", "//!"], &["//"], C_BLOCK),
+    code("C", &[], &[], &["//"], C_BLOCK),
+    code("C++", &[], &["///"], &["//"], C_BLOCK),
+    code("Objective-C", &[], &[], &["//"], C_BLOCK),
+    code("Objective-C++", &[], &[], &["//"], C_BLOCK),
+    code("C#", &[], &["///"], &["//"], C_BLOCK),
+    code("D", &[], &["///"], &["//"], C_BLOCK),
+    code("Go", &[], &[], &["//"], C_BLOCK),
+    code("Java", &[], &[], &["//"], C_BLOCK),
+    code("Groovy", &[], &[], &["//"], C_BLOCK),
+    code("Kotlin", &["kt", "kts"], &[], &["//"], C_BLOCK),
+    code("Scala", &[], &[], &["//"], C_BLOCK),
+    code("Swift", &["swift"], &["///"], &["//"], C_BLOCK),
+    code("Zig", &["zig"], &["///", "//!"], &["//"], None),
+    code("JavaScript", &["mjs", "cjs", "jsx"], &[], &["//"], C_BLOCK),
+    code(
+        "TypeScript",
+        &["ts", "mts", "cts", "tsx"],
+        &[],
+        &["//"],
+        C_BLOCK,
+    ),
+    code("PHP", &[], &[], &["//", "#"], C_BLOCK),
+    Language {
+        docstring: Some("\"\"\""),
+        ..code("Python", &[], &[], &["#"], None)
+    },
+    code("Ruby", &[], &[], &["#"], Some(("=begin", "=end"))),
+    code("Perl", &[], &[], &["#"], None),
+    code("R", &[], &[], &["#"], None),
+    code("Elixir", &["ex", "exs"], &[], &["#"], None),
+    code("Bourne Again Shell (bash)", &[], &[], &["#"], None),
+    code("Makefile", &[], &[], &["#"], None),
+    code("Lua", &[], &["---"], &["--"], Some(("--[[", "]]"))),
+    code("Haskell", &[], &["-- |"], &["--"], Some(("{-", "-}"))),
+    code("SQL", &[], &[], &["--"], C_BLOCK),
+    code("TOML", &["toml"], &[], &["#"], None),
+    code("YAML", &[], &[], &["#"], None),
+    prose("Markdown", &["mdx"]),
+    prose("AsciiDoc", &["adoc", "asciidoc"]),
+    prose("reStructuredText", &[]),
+    prose("Plain Text", &[]),
+];
+
+/// The language of a file: the syntect syntax for its extension, as the preview names it,
+/// or else a language syntect lacks from `LANGUAGES`.
+pub fn language_name(file_path: &Path) -> Option<&'static str> {
+    let extension = file_path.extension()?.to_str()?;
+    if let Some(syntax) = SYNTAX_SET.find_syntax_by_extension(extension) {
+        return Some(&syntax.name);
+    }
+    let extension = extension.to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|language| language.extensions.contains(&extension.as_str()))
+        .map(|language| language.name)
+}
+
+/// The name chunks of the language `name` are tagged with, matched by syntect's name or
+/// extension for it ignoring case, so `lang:rust`, `lang:py` and `lang:bash` all work.
+/// Unknown names are returned as they are.
+pub fn canonical_language(name: &str) -> String {
+    if let Some(syntax) = SYNTAX_SET.find_syntax_by_token(name) {
+        return syntax.name.clone();
+    }
+    LANGUAGES
+        .iter()
+        .find(|language| {
+            language.name.eq_ignore_ascii_case(name)
+                || language
+                    .extensions
+                    .iter()
+                    .any(|extension| extension.eq_ignore_ascii_case(name))
+        })
+        .map_or_else(|| name.to_string(), |language| language.name.to_string())
+}
+
+/// Fills in the language, kind and enclosing symbol of one file's chunks, which must be
+/// annotated in file order so definitions above a chunk are known.
+pub struct Annotator {
+    language: Option<&'static str>,
+    /// How the language marks comments; None for languages chunks are only tagged with
+    markers: Option<&'static Language>,
+    /// Definitions seen so far with their line numbers, from the one enclosing the last
+    /// chunk onwards
+    definitions: Vec<(usize, String)>,
+}
+
+impl Annotator {
+    pub fn new(file_path: &Path) -> Self {
+        let language = language_name(file_path);
+        Self {
+            language,
+            markers: language
+                .and_then(|name| LANGUAGES.iter().find(|language| language.name == name)),
+            definitions: Vec::new(),
+        }
+    }
+
+    pub fn annotate(&mut self, chunk: &mut Chunk) {
+        chunk.language = self.language.map(str::to_string);
+        let Some(language) = self.markers else {
+            return;
+        };
+        if language.prose {
+            chunk.kind = Some(ChunkKind::Doc);
+            return;
+        }
+
+        let enclosing = self
+            .definitions
+            .iter()
+            .rposition(|(line, _)| *line < chunk.start_line);
+        if let Some(index) = enclosing {
+            self.definitions.drain(..index);
+        }
+        let last_seen = self.definitions.last().map_or(0, |(line, _)| *line);
+
+        let mut counts = [0usize; ChunkKind::ALL.len()];
+        let mut docs = Vec::new();
+        let mut open_block: Option<(ChunkKind, &str)> = None;
+        let mut leading_definition = None;
+        let mut code_seen = false;
+
+        for (offset, line) in chunk.content.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+
+            let kind = if let Some((kind, close)) = open_block {
+                if trimmed.contains(close) {
+                    open_block = None;
+                }
+                kind
+            } else {
+                let (kind, block) = Self::classify(language, trimmed);
+                open_block = block;
+                kind
+            };
+            counts[kind as usize] += 1;
+
+            if matches!(kind, ChunkKind::Comment | ChunkKind::Doc) {
+                let text = Self::strip_markers(language, trimmed);
+                if !text.is_empty() {
+                    docs.push(text);
+                }
+                continue;
+            }
+            if kind != ChunkKind::Code {
+                continue;
+            }
+            let line_number = chunk.start_line + offset;
+            if let Some(captures) = DEFINITION.captures(line) {
+                let name = captures[1].to_string();
+                if !code_seen {
+                    leading_definition = Some(name.clone());
+                }
+                // Overlapping chunks see the same lines twice
+                if line_number > last_seen {
+                    self.definitions.push((line_number, name));
+                }
+            }
+            code_seen = true;
+        }
+
+        // Ties go to code, listed first
+        chunk.kind = ChunkKind::ALL
+            .into_iter()
+            .rev()
+            .max_by_key(|kind| counts[*kind as usize]);
+        chunk.docs = (!docs.is_empty()).then(|| docs.join("\n"));
+        chunk.symbol =
+            leading_definition.or_else(|| self.definitions.first().map(|(_, name)| name.clone()));
+    }
+
+    /// The words of a comment or docstring line, without the markers around them or the
+    /// asterisks that line up block comments.
+    fn strip_markers<'a>(language: &Language, trimmed: &'a str) -> &'a str {
+        let mut openers = language
+            .doc_comments
+            .iter()
+            .chain(language.block_comment.as_ref().map(|(open, _)| open))
+            .chain(language.line_comments)
+            .chain(&language.docstring);
+        let mut closers = language
+            .block_comment
+            .as_ref()
+            .map(|(_, close)| close)
+            .into_iter()
+            .chain(&language.docstring);
+
+        let mut text = trimmed;
+        if let Some(rest) = openers.find_map(|open| text.strip_prefix(open)) {
+            text = rest;
+        }
+        if let Some(rest) = closers.find_map(|close| text.strip_suffix(close)) {
+            text = rest;
+        }
+        text.trim_start_matches('*').trim()
+    }
+
+    /// The kind of a non-blank line outside any block, and the block it opens, if any, with
+    /// the delimiter that closes it.
+    fn classify(
+        language: &'static Language,
+        trimmed: &str,
+    ) -> (ChunkKind, Option<(ChunkKind, &'static str)>) {
+        if language
+            .doc_comments
+            .iter()
+            .any(|prefix| trimmed.starts_with(prefix))
+        {
+            return (ChunkKind::Doc, None);
+        }
+        if let Some((open, close)) = language.block_comment
+            && let Some(rest) = trimmed.strip_prefix(open)
+        {
+            let kind = if open == "/*" && rest.starts_with('*') && !rest.starts_with("*/") {
+                ChunkKind::Doc
+            } else {
+                ChunkKind::Comment
+            };
+            let block = (!rest.contains(close)).then_some((kind, close));
+            return (kind, block);
+        }
+        if language
+            .line_comments
+            .iter()
+            .any(|prefix| trimmed.starts_with(prefix))
+        {
+            return (ChunkKind::Comment, None);
+        }
+        if let Some(quotes) = language.docstring
+            && let Some(rest) = trimmed.strip_prefix(quotes)
+        {
+            let block = (!rest.contains(quotes)).then_some((ChunkKind::Doc, quotes));
+            return (ChunkKind::Doc, block);
+        }
+        if trimmed.starts_with(['"', '\'', '`']) {
+            return (ChunkKind::String, None);
+        }
+        (ChunkKind::Code, None)
+    }
+}
@@ -0,0 +1,79 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use crate::semantic::embeddings::Embedder;
+use crate::semantic::expansion::QueryExpander;
+use crate::semantic::sparse::{SparseModel, SparseVector};
+use crate::types::SearchTiming;
+
+/// The models a query is run through, taken from the storage under its lock and run
+/// without it, so a search embedding its query holds up neither other searches nor
+/// indexing.
+pub struct QueryModels {
+    /// The query as the models read it, with aliases expanded
+    pub query: String,
+    /// The query with other alternatives of its synonyms, when `query_expansion =
+    /// "synonyms"`, embedded as variants of it
+    pub synonym_variants: Vec<String>,
+    pub expander: Arc<QueryExpander>,
+    /// The loaded embedding model, for semantic and hybrid queries
+    pub embedder: Option<Embedder>,
+    pub late_interaction: bool,
+    /// The loaded sparse model, for hybrid queries while the sparse channel is on
+    pub sparse: Option<Arc<SparseModel>>,
+}
+
+/// What the models made of a query.
+#[derive(Default)]
+pub struct EmbeddedQuery {
+    /// The embedding of the query and of each variant it was expanded into
+    pub variants: Vec<Vec<f32>>,
+    /// A vector for each token of the query, for late interaction
+    pub tokens: Vec<Vec<f32>>,
+    pub sparse: Option<SparseVector>,
+    /// Time spent embedding, as vector time, and encoding sparse terms, as lexical time
+    pub timing: SearchTiming,
+}
+
+impl QueryModels {
+    /// Expands and embeds the query, giving up once `cancel` fires.
+    pub async fn run(self, cancel: &CancellationToken) -> Result<EmbeddedQuery> {
+        tokio::select! {
+            embedded = self.embed(cancel) => embedded,
+            _ = cancel.cancelled() => anyhow::bail!("Search cancelled"),
+        }
+    }
+
+    async fn embed(self, cancel: &CancellationToken) -> Result<EmbeddedQuery> {
+        let mut embedded = EmbeddedQuery::default();
+        if let Some(mut embedder) = self.embedder {
+            let started = Instant::now();
+            let mut variants = self.expander.expand(&self.query).await;
+            variants.extend(self.synonym_variants);
+            let mut seen = HashSet::new();
+            variants.retain(|variant| seen.insert(variant.clone()));
+            embedded.variants = embedder.embed_queries(&variants, cancel).await?;
+            if self.late_interaction {
+                // Without token vectors the candidates keep their order
+                embedded.tokens = embedder
+                    .query_token_embeddings(&self.query)
+                    .await
+                    .unwrap_or_default();
+            }
+            embedded.timing.vector = Some(started.elapsed());
+        }
+
+        if let Some(model) = self.sparse {
+            let started = Instant::now();
+            embedded.sparse = model
+                .encode_all(vec![self.query])
+                .await
+                .and_then(|terms| terms.into_iter().next().flatten());
+            embedded.timing.lexical = Some(started.elapsed());
+        }
+        Ok(embedded)
+    }
+}
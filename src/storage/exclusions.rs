@@ -0,0 +1,102 @@
+use crate::types::Chunk;
+
+const PATH_PREFIX: &str = "-path:";
+const EXTENSION_PREFIX: &str = "-ext:";
+
+/// A negated query term. Keyword search turns these into must-not clauses; every result,
+/// semantic ones included, is also filtered against them afterwards.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Exclusion {
+    /// `-path:vendor/` drops files whose path contains the text
+    Path(String),
+    /// `-ext:min.js` drops files whose name ends with `.min.js`
+    Extension(String),
+    /// `-"exact phrase"` drops chunks containing the phrase, ignoring case and punctuation
+    Phrase(String),
+}
+
+impl Exclusion {
+    /// Splits negated terms out of a query, returning the remaining query text and the
+    /// exclusions. Quoted phrases stay together, so `-"a b"` is one term.
+    pub fn extract(query: &str) -> (String, Vec<Exclusion>) {
+        let mut exclusions = Vec::new();
+        let mut terms = Vec::new();
+
+        for term in split_terms(query) {
+            let exclusion = if let Some(path) = term.strip_prefix(PATH_PREFIX) {
+                Exclusion::Path(path.to_string())
+            } else if let Some(extension) = term.strip_prefix(EXTENSION_PREFIX) {
+                Exclusion::Extension(extension.trim_start_matches('.').to_string())
+            } else if let Some(phrase) = term
+                .strip_prefix("-\"")
+                .and_then(|rest| rest.strip_suffix('"'))
+            {
+                Exclusion::Phrase(phrase.to_string())
+            } else {
+                terms.push(term);
+                continue;
+            };
+
+            if !exclusion.value().is_empty() {
+                exclusions.push(exclusion);
+            }
+        }
+
+        (terms.join(" "), exclusions)
+    }
+
+    pub fn value(&self) -> &str {
+        match self {
+            Exclusion::Path(value) | Exclusion::Extension(value) | Exclusion::Phrase(value) => {
+                value
+            }
+        }
+    }
+
+    pub fn excludes(&self, chunk: &Chunk) -> bool {
+        match self {
+            Exclusion::Path(path) => chunk.file_path.to_string_lossy().contains(path.as_str()),
+            Exclusion::Extension(extension) => chunk
+                .file_path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().ends_with(&format!(".{}", extension))),
+            Exclusion::Phrase(phrase) => {
+                format!(" {} ", words(&chunk.content)).contains(&format!(" {} ", words(phrase)))
+            }
+        }
+    }
+}
+
+/// Lowercase words separated by single spaces, roughly as the keyword index tokenizes text.
+fn words(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Splits on whitespace outside double quotes.
+pub fn split_terms(query: &str) -> Vec<&str> {
+    let mut terms = Vec::new();
+    let mut start = None;
+    let mut in_quotes = false;
+
+    for (index, c) in query.char_indices() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        }
+        if c.is_whitespace() && !in_quotes {
+            if let Some(term_start) = start.take() {
+                terms.push(&query[term_start..index]);
+            }
+        } else if start.is_none() {
+            start = Some(index);
+        }
+    }
+    if let Some(term_start) = start {
+        terms.push(&query[term_start..]);
+    }
+
+    terms
+}
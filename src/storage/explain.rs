@@ -0,0 +1,79 @@
+use crate::types::{FileIndex, QueryMode};
+
+use super::text_index::KeywordMatch;
+
+/// Why a result was found for a query, recomputed from the indexes on request.
+#[derive(Debug, Clone)]
+pub struct Explanation {
+    /// The index that answered the query
+    pub mode: QueryMode,
+    /// Candidates fetched from each index, which a rank is out of
+    pub candidates: usize,
+    /// None when the keyword index was not asked or the chunk was not among its matches
+    pub keyword: Option<KeywordMatch>,
+    /// None when the vector index was not asked
+    pub semantic: Option<SemanticMatch>,
+    /// None when the learned-sparse channel was not asked
+    pub sparse: Option<SparseMatch>,
+    /// Weight of the semantic score in the hybrid score; lexical scores make up the rest
+    pub semantic_weight: f32,
+    /// Share of the lexical part of the hybrid score given to BM25; the learned-sparse
+    /// score takes the rest
+    pub keyword_weight: f32,
+    /// The score after late interaction blended token similarity into it, when that is on
+    pub reranked: Option<f32>,
+    /// The file's entry in the file index
+    pub file_index: Option<FileIndex>,
+}
+
+/// How close a chunk's embedding is to the query's.
+#[derive(Debug, Clone)]
+pub struct SemanticMatch {
+    /// 1-based position among the nearest chunks; None when it was not among them, in
+    /// which case the similarity is of the chunk's text embedded anew
+    pub rank: Option<usize>,
+    pub similarity: f32,
+    /// Nearest chunks below this are dropped
+    pub min_similarity: f32,
+}
+
+/// How a chunk's learned-sparse terms match the query's.
+#[derive(Debug, Clone)]
+pub struct SparseMatch {
+    /// 1-based position among the sparse matches; None when it was not among them
+    pub rank: Option<usize>,
+    /// Dot product of the term weights, scaled against the best sparse match
+    pub score: f32,
+}
+
+impl Explanation {
+    /// The score a hybrid search ranks the chunk by: its similarity if it was retrieved
+    /// semantically, plus its keyword and sparse scores, each scaled against its best
+    /// match. Late interaction, when on, blends token similarity into it.
+    pub fn hybrid_score(&self) -> f32 {
+        if let Some(reranked) = self.reranked {
+            return reranked;
+        }
+        self.fused_score()
+    }
+
+    /// The hybrid score before any late-interaction reordering.
+    pub fn fused_score(&self) -> f32 {
+        let similarity = self
+            .semantic
+            .as_ref()
+            .filter(|semantic| semantic.rank.is_some())
+            .filter(|semantic| semantic.similarity >= semantic.min_similarity)
+            .map_or(0.0, |semantic| semantic.similarity);
+        let keyword = self.keyword.as_ref().map_or(0.0, |keyword| {
+            if keyword.top_score > 0.0 {
+                keyword.score / keyword.top_score
+            } else {
+                0.0
+            }
+        });
+        let sparse = self.sparse.as_ref().map_or(0.0, |sparse| sparse.score);
+        let lexical = keyword * self.keyword_weight + sparse * (1.0 - self.keyword_weight);
+        similarity * self.semantic_weight + lexical * (1.0 - self.semantic_weight)
+    }
+}
@@ -0,0 +1,52 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::crawler::{SkipReason, SkippedFile};
+
+const FAILURES_FILE: &str = "read_failures.json";
+
+/// Files that could not be read or chunked when they were last indexed, with the error.
+/// They count as indexed and are only retried once they change, so the errors are kept
+/// for reporting between runs.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ReadFailures {
+    pub files: BTreeMap<PathBuf, String>,
+}
+
+impl ReadFailures {
+    pub fn load(index_dir: &Path) -> Self {
+        std::fs::read_to_string(index_dir.join(FAILURES_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, index_dir: &Path) -> Result<()> {
+        let path = index_dir.join(FAILURES_FILE);
+        if self.files.is_empty() {
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+            return Ok(());
+        }
+        std::fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// The failures among `files`, as skipped files. Others belong to other projects or
+    /// to files that are gone.
+    pub fn skipped_files(&self, files: &[PathBuf]) -> Vec<SkippedFile> {
+        files
+            .iter()
+            .filter_map(|path| {
+                self.files.get(path).map(|error| SkippedFile {
+                    path: path.clone(),
+                    reason: SkipReason::ReadError,
+                    detail: Some(error.clone()),
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,192 @@
+use super::code;
+use super::exclusions::{Exclusion, split_terms};
+use super::logs::TimeFilter;
+use super::scope::PathScope;
+use crate::types::{Chunk, ChunkKind};
+
+const KIND_PREFIX: &str = "kind:";
+const LANGUAGE_PREFIX: &str = "lang:";
+const DOCS_PREFIX: &str = "docs:";
+
+/// A `kind:comment` or `lang:rust` query term keeping only chunks with that metadata.
+/// Several values of the same facet widen the match, so `kind:comment kind:doc` keeps both.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Facet {
+    Kind(ChunkKind),
+    /// The name chunks of the language are tagged with
+    Language(String),
+}
+
+impl Facet {
+    /// Splits facets out of a query, returning the remaining query text and the facets.
+    /// Unknown kinds stay in the query as ordinary terms.
+    pub fn extract(query: &str) -> (String, Vec<Facet>) {
+        let mut facets = Vec::new();
+        let mut terms = Vec::new();
+
+        for term in split_terms(query) {
+            let facet = if let Some(kind) = term.strip_prefix(KIND_PREFIX) {
+                ChunkKind::parse(kind).map(Facet::Kind)
+            } else if let Some(language) = term.strip_prefix(LANGUAGE_PREFIX) {
+                (!language.is_empty()).then(|| Facet::Language(code::canonical_language(language)))
+            } else {
+                None
+            };
+
+            match facet {
+                Some(facet) => facets.push(facet),
+                None => terms.push(term),
+            }
+        }
+
+        (terms.join(" "), facets)
+    }
+
+    fn matches(&self, chunk: &Chunk) -> bool {
+        match self {
+            Facet::Kind(kind) => chunk.kind == Some(*kind),
+            Facet::Language(language) => chunk.language.as_deref() == Some(language.as_str()),
+        }
+    }
+}
+
+/// Everything in a query that narrows the results rather than being searched for. The
+/// scope and facets are applied by the indexes as they retrieve, and exclusions by keyword
+/// search; every filter is checked again against each result after retrieval.
+#[derive(Debug, Clone, Default)]
+pub struct QueryFilters {
+    pub time: Vec<TimeFilter>,
+    pub exclusions: Vec<Exclusion>,
+    pub scope: Option<PathScope>,
+    pub facets: Vec<Facet>,
+    /// Set by a `docs:` term: only comments and docstrings are searched, in the docs field
+    /// of the keyword index
+    pub docs_only: bool,
+}
+
+impl QueryFilters {
+    /// Splits every filter out of a query, returning the text left to search for.
+    pub fn extract(query: &str) -> (String, Self) {
+        let (query, time) = TimeFilter::extract(query.trim());
+        let (query, exclusions) = Exclusion::extract(&query);
+        let (query, scope) = PathScope::extract(&query);
+        let (query, facets) = Facet::extract(&query);
+        let (query, docs_only) = Self::extract_docs_scope(&query);
+
+        let filters = Self {
+            time,
+            exclusions,
+            scope,
+            facets,
+            docs_only,
+        };
+        (query, filters)
+    }
+
+    /// The filter terms of a query as typed, such as `lang:rust` or `-test`, for showing
+    /// which are in effect.
+    pub fn terms(query: &str) -> Vec<&str> {
+        let (text, _) = Self::extract(query);
+        let mut searched = split_terms(&text);
+        split_terms(query)
+            .into_iter()
+            .filter(|term| match searched.iter().position(|kept| kept == term) {
+                Some(index) => {
+                    searched.remove(index);
+                    false
+                }
+                None => true,
+            })
+            .collect()
+    }
+
+    /// `docs:` may stand alone or prefix the first word, as in `docs:retry backoff`.
+    fn extract_docs_scope(query: &str) -> (String, bool) {
+        let mut docs_only = false;
+        let mut terms = Vec::new();
+
+        for term in split_terms(query) {
+            match term.strip_prefix(DOCS_PREFIX) {
+                Some(rest) => {
+                    docs_only = true;
+                    if !rest.is_empty() {
+                        terms.push(rest);
+                    }
+                }
+                None => terms.push(term),
+            }
+        }
+
+        (terms.join(" "), docs_only)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.time.is_empty()
+            && self.exclusions.is_empty()
+            && self.scope.is_none()
+            && self.facets.is_empty()
+            && !self.docs_only
+    }
+
+    /// Whether some filter is only applied after retrieval, so extra candidates must be
+    /// fetched to fill the results.
+    pub fn post_filtered(&self) -> bool {
+        !self.time.is_empty() || !self.exclusions.is_empty()
+    }
+
+    /// The kinds `kind:` terms keep, or none when every kind is kept.
+    pub fn kinds(&self) -> Vec<ChunkKind> {
+        self.facets
+            .iter()
+            .filter_map(|facet| match facet {
+                Facet::Kind(kind) => Some(*kind),
+                Facet::Language(_) => None,
+            })
+            .collect()
+    }
+
+    /// The languages `lang:` terms keep, or none when every language is kept.
+    pub fn languages(&self) -> Vec<&str> {
+        self.facets
+            .iter()
+            .filter_map(|facet| match facet {
+                Facet::Language(language) => Some(language.as_str()),
+                Facet::Kind(_) => None,
+            })
+            .collect()
+    }
+
+    /// Drops the results the filters reject and keeps at most `limit` of the rest.
+    pub fn apply(&self, results: &mut Vec<(Chunk, f32)>, limit: usize) {
+        if !self.is_empty() {
+            results.retain(|(chunk, _)| self.admits(chunk));
+        }
+        results.truncate(limit);
+    }
+
+    fn admits(&self, chunk: &Chunk) -> bool {
+        let facet_matches = |same_facet: fn(&Facet) -> bool| {
+            let mut facets = self
+                .facets
+                .iter()
+                .filter(|facet| same_facet(facet))
+                .peekable();
+            facets.peek().is_none() || facets.any(|facet| facet.matches(chunk))
+        };
+
+        self.time
+            .iter()
+            .all(|filter| filter.matches(chunk.section.as_deref()))
+            && !self
+                .exclusions
+                .iter()
+                .any(|exclusion| exclusion.excludes(chunk))
+            && self
+                .scope
+                .as_ref()
+                .is_none_or(|scope| scope.contains(chunk))
+            && facet_matches(|facet| matches!(facet, Facet::Kind(_)))
+            && facet_matches(|facet| matches!(facet, Facet::Language(_)))
+            && (!self.docs_only || chunk.docs.is_some())
+    }
+}
@@ -1,46 +1,109 @@
 use anyhow::Result;
 use arrow_array::types::Float32Type;
-use arrow_array::{FixedSizeListArray, RecordBatch, RecordBatchIterator, StringArray, UInt64Array};
+use arrow_array::{
+    Array, BooleanArray, FixedSizeListArray, Float32Array, RecordBatch, RecordBatchIterator,
+    StringArray, UInt64Array,
+};
 use arrow_schema::{DataType, Field, Schema};
+use async_trait::async_trait;
 use futures::TryStreamExt;
 use lancedb;
-use lancedb::query::{ExecutableQuery, QueryBase};
-use std::path::Path;
+use lancedb::DistanceType;
+use lancedb::query::{ExecutableQuery, QueryBase, Select};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-
-use crate::semantic::embeddings::VectorStore;
-use crate::types::{Chunk, FileIndex};
+use tokio_util::sync::CancellationToken;
+
+use super::filters::QueryFilters;
+use super::tokens::TokenBudget;
+use super::vector_index::{EmbeddedChunks, PendingChunks, VectorIndex, embedding_text};
+use crate::config::{ResourcesConfig, SemanticConfig};
+use crate::semantic::embeddings::{EMBEDDING_DIM, Embedder};
+use crate::types::{Chunk, ChunkKind, FileIndex};
+
+pub const LANCE_DIR: &str = "lancedb_chunks";
+const ALIASES_TABLE: &str = "chunk_aliases";
+/// A vector per token of each stored chunk, by content hash, for late interaction
+const TOKENS_TABLE: &str = "token_vectors";
+/// Share of a reranked score taken from MaxSim; the candidate's own score makes up the rest
+const LATE_INTERACTION_WEIGHT: f32 = 0.5;
 
 pub struct LanceIndexer {
     connection: lancedb::Connection,
+    embedder: Embedder,
+    /// Chunks embedded and appended to the table at a time
+    write_batch: usize,
+    /// Store token vectors and rerank by them, for `[semantic] late_interaction`
+    late_interaction: bool,
 }
 
 impl LanceIndexer {
-    pub async fn new(data_dir: &Path) -> Result<Self> {
-        let db_path = data_dir.join("lancedb_chunks");
+    pub async fn new(
+        data_dir: &Path,
+        semantic_config: &SemanticConfig,
+        resources: &ResourcesConfig,
+    ) -> Result<Self> {
+        let db_path = data_dir.join(LANCE_DIR);
         std::fs::create_dir_all(&db_path)?;
 
         let connection = lancedb::connect(&db_path.to_string_lossy())
             .execute()
             .await?;
 
-        Ok(Self { connection })
+        Ok(Self {
+            connection,
+            embedder: Embedder::new(semantic_config, data_dir),
+            write_batch: resources.vector_write_batch.max(1),
+            late_interaction: semantic_config.late_interaction,
+        })
     }
 
-    pub async fn index_chunks(&mut self, chunks: &[Chunk]) -> Result<()> {
-        if chunks.is_empty() {
-            return Ok(());
+    async fn append_to_table(&self, table_name: &str, batch: RecordBatch) -> Result<()> {
+        let schema = batch.schema();
+        let batches = RecordBatchIterator::new(vec![Ok(batch)].into_iter(), schema);
+
+        match self.connection.open_table(table_name).execute().await {
+            Ok(table) => {
+                table.add(Box::new(batches)).execute().await?;
+            }
+            Err(_) => {
+                self.connection
+                    .create_table(table_name, Box::new(batches))
+                    .execute()
+                    .await?;
+            }
         }
 
+        Ok(())
+    }
+
+    /// Appends embedded chunks to the chunks table in one batch.
+    async fn append_chunks(
+        &self,
+        chunks: &[Chunk],
+        hashes: &[String],
+        embeddings: &[Option<(Vec<f32>, Vec<Vec<f32>>)>],
+    ) -> Result<()> {
         let schema = Arc::new(Schema::new(vec![
             Field::new("id", DataType::Utf8, false),
             Field::new("file_path", DataType::Utf8, false),
             Field::new("start_line", DataType::UInt64, false),
             Field::new("end_line", DataType::UInt64, false),
             Field::new("content", DataType::Utf8, false),
+            Field::new("partial", DataType::Boolean, false),
+            Field::new("section", DataType::Utf8, true),
+            Field::new("language", DataType::Utf8, true),
+            Field::new("symbol", DataType::Utf8, true),
+            Field::new("kind", DataType::Utf8, true),
+            Field::new("docs", DataType::Utf8, true),
+            Field::new("content_hash", DataType::Utf8, false),
             Field::new(
                 "vector",
-                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 384),
+                DataType::FixedSizeList(
+                    Arc::new(Field::new("item", DataType::Float32, true)),
+                    EMBEDDING_DIM as i32,
+                ),
                 true,
             ),
         ]));
@@ -53,27 +116,29 @@ impl LanceIndexer {
         let start_lines: Vec<u64> = chunks.iter().map(|c| c.start_line as u64).collect();
         let end_lines: Vec<u64> = chunks.iter().map(|c| c.end_line as u64).collect();
         let contents: Vec<String> = chunks.iter().map(|c| c.content.clone()).collect();
+        let partials: Vec<bool> = chunks.iter().map(|c| c.partial).collect();
+        let sections: Vec<Option<String>> = chunks.iter().map(|c| c.section.clone()).collect();
+        let languages: Vec<Option<String>> = chunks.iter().map(|c| c.language.clone()).collect();
+        let symbols: Vec<Option<String>> = chunks.iter().map(|c| c.symbol.clone()).collect();
+        let docs: Vec<Option<String>> = chunks.iter().map(|c| c.docs.clone()).collect();
+        let kinds: Vec<Option<&str>> = chunks
+            .iter()
+            .map(|c| c.kind.map(ChunkKind::label))
+            .collect();
 
-        let chunks_for_embedding: Vec<String> = chunks.iter().map(|c| c.content.clone()).collect();
-
-        let vectors: Vec<Option<Vec<Option<f32>>>> =
-            tokio::task::spawn_blocking(move || -> Result<_> {
-                let mut vector_store = VectorStore::new()?;
-
-                Ok(chunks_for_embedding
-                    .iter()
-                    .map(|content| {
-                        vector_store
-                            .generate_embedding(content)
-                            .map(|embedding| embedding.into_iter().map(Some).collect())
-                            .ok()
-                    })
-                    .collect())
+        let vectors: Vec<Option<Vec<Option<f32>>>> = embeddings
+            .iter()
+            .map(|embedding| {
+                embedding
+                    .as_ref()
+                    .map(|(pooled, _)| pooled.iter().copied().map(Some).collect())
             })
-            .await??;
+            .collect();
 
-        let vector_array =
-            FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(vectors, 384);
+        let vector_array = FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+            vectors,
+            EMBEDDING_DIM as i32,
+        );
 
         let batch = RecordBatch::try_new(
             schema.clone(),
@@ -83,172 +148,483 @@ impl LanceIndexer {
                 Arc::new(UInt64Array::from(start_lines)),
                 Arc::new(UInt64Array::from(end_lines)),
                 Arc::new(StringArray::from(contents)),
+                Arc::new(BooleanArray::from(partials)),
+                Arc::new(StringArray::from(sections)),
+                Arc::new(StringArray::from(languages)),
+                Arc::new(StringArray::from(symbols)),
+                Arc::new(StringArray::from(kinds)),
+                Arc::new(StringArray::from(docs)),
+                Arc::new(StringArray::from(hashes.to_vec())),
                 Arc::new(vector_array),
             ],
         )?;
 
-        let batches = RecordBatchIterator::new(vec![Ok(batch)].into_iter(), schema.clone());
+        self.append_to_table("chunks", batch).await?;
+        self.append_token_vectors(hashes, embeddings).await
+    }
 
-        match self.connection.open_table("chunks").execute().await {
-            Ok(table) => {
-                table.add(Box::new(batches)).execute().await?;
-            }
-            Err(_) => {
-                self.connection
-                    .create_table("chunks", Box::new(batches))
-                    .execute()
-                    .await?;
+    /// Stores the token vectors of each chunk under its content hash, so aliases share
+    /// them as they share the chunk's embedding.
+    async fn append_token_vectors(
+        &self,
+        hashes: &[String],
+        embeddings: &[Option<(Vec<f32>, Vec<Vec<f32>>)>],
+    ) -> Result<()> {
+        let mut token_hashes = Vec::new();
+        let mut vectors = Vec::new();
+        for (hash, (_, tokens)) in hashes
+            .iter()
+            .zip(embeddings)
+            .filter_map(|(hash, embedding)| Some((hash, embedding.as_ref()?)))
+        {
+            for token in tokens {
+                token_hashes.push(hash.as_str());
+                vectors.push(Some(token.iter().copied().map(Some).collect::<Vec<_>>()));
             }
         }
+        if vectors.is_empty() {
+            return Ok(());
+        }
 
-        Ok(())
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("content_hash", DataType::Utf8, false),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(
+                    Arc::new(Field::new("item", DataType::Float32, true)),
+                    EMBEDDING_DIM as i32,
+                ),
+                true,
+            ),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringArray::from(token_hashes)),
+                Arc::new(
+                    FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+                        vectors,
+                        EMBEDDING_DIM as i32,
+                    ),
+                ),
+            ],
+        )?;
+
+        self.append_to_table(TOKENS_TABLE, batch).await
     }
 
-    pub async fn search(&mut self, query: &str, limit: usize) -> Result<Vec<Chunk>> {
-        let table = match self.connection.open_table("chunks").execute().await {
-            Ok(table) => table,
-            Err(_) => return Ok(Vec::new()),
+    /// The token vectors stored for each of `hashes`.
+    async fn token_vectors(&self, hashes: &[String]) -> Result<HashMap<String, Vec<Vec<f32>>>> {
+        let mut token_vectors: HashMap<String, Vec<Vec<f32>>> = HashMap::new();
+        let Ok(table) = self.connection.open_table(TOKENS_TABLE).execute().await else {
+            return Ok(token_vectors);
         };
 
-        let query_str = query.to_string();
-        let query_embedding = tokio::task::spawn_blocking(move || {
-            let mut vector_store = VectorStore::new().ok()?;
-            vector_store.generate_embedding(&query_str).ok()
-        })
-        .await?;
-
-        if let Some(query_embedding) = query_embedding {
-            let results = table
-                .query()
-                .nearest_to(query_embedding)?
-                .limit(limit)
-                .execute()
-                .await?;
-
-            let batches: Vec<_> = results.try_collect().await?;
-            let mut chunks = Vec::new();
-
-            for batch in batches {
-                let num_rows = batch.num_rows();
-                for i in 0..num_rows {
-                    if let Some(chunk) = self.extract_chunk_from_batch(&batch, i) {
-                        chunks.push(chunk);
-                    }
-                }
-            }
-
-            return Ok(chunks);
-        }
-
         let results = table
             .query()
-            .only_if(format!("content LIKE '%{}%'", query.replace("'", "''")))
-            .limit(limit)
+            .only_if(format!("content_hash IN ({})", quoted_list(hashes)))
             .execute()
             .await?;
-
         let batches: Vec<_> = results.try_collect().await?;
-        let mut chunks = Vec::new();
-
         for batch in batches {
-            let num_rows = batch.num_rows();
-            for i in 0..num_rows {
-                if let Some(chunk) = self.extract_chunk_from_batch(&batch, i) {
-                    chunks.push(chunk);
-                }
+            let hash_col = batch
+                .column_by_name("content_hash")
+                .and_then(|col| col.as_any().downcast_ref::<StringArray>());
+            let vector_col = batch
+                .column_by_name("vector")
+                .and_then(|col| col.as_any().downcast_ref::<FixedSizeListArray>());
+            let (Some(hash_col), Some(vector_col)) = (hash_col, vector_col) else {
+                continue;
+            };
+
+            for row in 0..batch.num_rows() {
+                let values = vector_col.value(row);
+                let Some(values) = values.as_any().downcast_ref::<Float32Array>() else {
+                    continue;
+                };
+                token_vectors
+                    .entry(hash_col.value(row).to_string())
+                    .or_default()
+                    .push(values.values().to_vec());
             }
         }
-
-        Ok(chunks)
+        Ok(token_vectors)
     }
 
-    pub async fn get_file_index(&self, file_path: &Path) -> Result<Option<FileIndex>> {
-        let file_table = match self.connection.open_table("file_index").execute().await {
+    async fn existing_content_hashes(&self, hashes: &[String]) -> Result<HashSet<String>> {
+        let mut existing = HashSet::new();
+        let table = match self.connection.open_table("chunks").execute().await {
             Ok(table) => table,
-            Err(_) => return Ok(None),
+            Err(_) => return Ok(existing),
         };
 
-        let path_str = file_path.to_string_lossy();
-        let results = file_table
+        let results = table
             .query()
-            .only_if(format!("file_path = '{}'", path_str.replace("'", "''")))
-            .limit(1)
+            .only_if(format!("content_hash IN ({})", quoted_list(hashes)))
+            .select(Select::Columns(vec!["content_hash".to_string()]))
             .execute()
             .await?;
 
         let batches: Vec<_> = results.try_collect().await?;
         for batch in batches {
-            if batch.num_rows() > 0 {
-                if let Some(file_index) = self.extract_file_index_from_batch(&batch, 0) {
-                    return Ok(Some(file_index));
-                }
+            if let Some(col) = batch
+                .column_by_name("content_hash")
+                .and_then(|col| col.as_any().downcast_ref::<StringArray>())
+            {
+                existing.extend(col.iter().flatten().map(str::to_string));
             }
         }
 
-        Ok(None)
+        Ok(existing)
     }
 
-    pub async fn update_file_index(&mut self, file_path: &Path, file_hash: &str) -> Result<()> {
+    async fn add_aliases(&self, aliases: &[(&Chunk, String)]) -> Result<()> {
+        if aliases.is_empty() {
+            return Ok(());
+        }
+
         let schema = Arc::new(Schema::new(vec![
+            Field::new("content_hash", DataType::Utf8, false),
+            Field::new("id", DataType::Utf8, false),
             Field::new("file_path", DataType::Utf8, false),
-            Field::new("hash", DataType::Utf8, false),
+            Field::new("start_line", DataType::UInt64, false),
+            Field::new("end_line", DataType::UInt64, false),
         ]));
 
-        let file_index = FileIndex {
-            file_path: file_path.to_owned(),
-            hash: file_hash.to_string(),
-        };
-
         let batch = RecordBatch::try_new(
-            schema.clone(),
+            schema,
             vec![
-                Arc::new(StringArray::from(vec![
-                    file_index.file_path.to_string_lossy().to_string(),
-                ])),
-                Arc::new(StringArray::from(vec![file_index.hash])),
+                Arc::new(StringArray::from_iter_values(
+                    aliases.iter().map(|(_, hash)| hash.as_str()),
+                )),
+                Arc::new(StringArray::from_iter_values(
+                    aliases.iter().map(|(chunk, _)| chunk.id.as_str()),
+                )),
+                Arc::new(StringArray::from_iter_values(
+                    aliases
+                        .iter()
+                        .map(|(chunk, _)| chunk.file_path.to_string_lossy().to_string()),
+                )),
+                Arc::new(UInt64Array::from_iter_values(
+                    aliases.iter().map(|(chunk, _)| chunk.start_line as u64),
+                )),
+                Arc::new(UInt64Array::from_iter_values(
+                    aliases.iter().map(|(chunk, _)| chunk.end_line as u64),
+                )),
             ],
         )?;
 
-        let batches = RecordBatchIterator::new(vec![Ok(batch)].into_iter(), schema.clone());
+        self.append_to_table(ALIASES_TABLE, batch).await
+    }
 
-        let path_str = file_path.to_string_lossy();
-        if let Ok(file_table) = self.connection.open_table("file_index").execute().await {
-            if (file_table
-                .delete(&format!("file_path = '{}'", path_str.replace("'", "''")))
-                .await)
-                .is_ok()
-            {
-                file_table.add(Box::new(batches)).execute().await?;
+    /// Adds a result for every alias of the matched chunks, with the same score.
+    async fn expand_aliases(&self, results: Vec<(Chunk, f32)>) -> Result<Vec<(Chunk, f32)>> {
+        let table = match self.connection.open_table(ALIASES_TABLE).execute().await {
+            Ok(table) => table,
+            Err(_) => return Ok(results),
+        };
+        if results.is_empty() {
+            return Ok(results);
+        }
+
+        let by_hash: HashMap<String, usize> = results
+            .iter()
+            .enumerate()
+            .map(|(i, (chunk, _))| (content_hash(&chunk.content), i))
+            .collect();
+        let hashes: Vec<String> = by_hash.keys().cloned().collect();
+
+        let alias_results = table
+            .query()
+            .only_if(format!("content_hash IN ({})", quoted_list(&hashes)))
+            .execute()
+            .await?;
+        let batches: Vec<_> = alias_results.try_collect().await?;
+
+        let mut expanded = results.clone();
+        for batch in batches {
+            let Some(hash_col) = batch
+                .column_by_name("content_hash")
+                .and_then(|col| col.as_any().downcast_ref::<StringArray>())
+            else {
+                continue;
+            };
+
+            for row in 0..batch.num_rows() {
+                let Some(&index) = by_hash.get(hash_col.value(row)) else {
+                    continue;
+                };
+                let (canonical, score) = &results[index];
+                if let Some(alias) = self.extract_alias_from_batch(&batch, row, canonical) {
+                    expanded.push((alias, *score));
+                }
             }
-        } else {
-            let _table = self
-                .connection
-                .create_table("file_index", Box::new(batches))
+        }
+
+        expanded.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(expanded)
+    }
+
+    /// Returns matching chunks paired with their cosine similarity in `0.0..=1.0`.
+    pub async fn search(&mut self, query: &str, limit: usize) -> Result<Vec<(Chunk, f32)>> {
+        let embeddings = self
+            .embedder
+            .embed_queries(&[query.to_string()], &CancellationToken::new())
+            .await?;
+        self.search_embeddings(&embeddings, &QueryFilters::default(), limit)
+            .await
+    }
+
+    /// The `where` clause selecting the chunks the scope and facets of `filters` keep, None
+    /// when they keep every chunk.
+    async fn filter_predicate(&self, filters: &QueryFilters) -> Result<Option<String>> {
+        let kinds: Vec<String> = filters
+            .kinds()
+            .into_iter()
+            .map(|kind| kind.label().to_string())
+            .collect();
+        let languages: Vec<String> = filters
+            .languages()
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+
+        let mut clauses: Vec<String> = self.scope_predicate(filters).await?.into_iter().collect();
+        for (column, values) in [("kind", kinds), ("language", languages)] {
+            if !values.is_empty() {
+                clauses.push(format!("{} IN ({})", column, quoted_list(&values)));
+            }
+        }
+        Ok((!clauses.is_empty()).then(|| {
+            clauses
+                .iter()
+                .map(|clause| format!("({})", clause))
+                .collect::<Vec<_>>()
+                .join(" AND ")
+        }))
+    }
+
+    /// The `where` clause selecting the chunks in the scope of `filters`, None without one.
+    /// A chunk is stored under the first file that held it, so chunks with an alias in the
+    /// scope are selected too, and their aliases kept once expanded.
+    async fn scope_predicate(&self, filters: &QueryFilters) -> Result<Option<String>> {
+        let Some(scope) = &filters.scope else {
+            return Ok(None);
+        };
+        let path_filter = format!(
+            "regexp_match(file_path, {})",
+            sql_string(&format!("^{}$", scope.pattern()))
+        );
+
+        let mut hashes = HashSet::new();
+        if let Ok(aliases_table) = self.connection.open_table(ALIASES_TABLE).execute().await {
+            let results = aliases_table
+                .query()
+                .only_if(path_filter.clone())
+                .select(Select::Columns(vec!["content_hash".to_string()]))
                 .execute()
                 .await?;
+            let batches: Vec<_> = results.try_collect().await?;
+            for batch in batches {
+                if let Some(col) = batch
+                    .column_by_name("content_hash")
+                    .and_then(|col| col.as_any().downcast_ref::<StringArray>())
+                {
+                    hashes.extend(col.iter().flatten().map(str::to_string));
+                }
+            }
         }
 
-        Ok(())
+        if hashes.is_empty() {
+            return Ok(Some(path_filter));
+        }
+        let hashes: Vec<String> = hashes.into_iter().collect();
+        Ok(Some(format!(
+            "({}) OR content_hash IN ({})",
+            path_filter,
+            quoted_list(&hashes)
+        )))
+    }
+
+    async fn nearest_chunks(
+        &self,
+        table: &lancedb::Table,
+        query_embedding: Vec<f32>,
+        predicate: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(Chunk, f32)>> {
+        let mut query = table
+            .query()
+            .nearest_to(query_embedding)?
+            .distance_type(DistanceType::Cosine)
+            .limit(limit);
+        if let Some(predicate) = predicate {
+            query = query.only_if(predicate);
+        }
+        let results = query.execute().await?;
+
+        let batches: Vec<_> = results.try_collect().await?;
+        let mut chunks = Vec::new();
+
+        for batch in batches {
+            let distances = batch
+                .column_by_name("_distance")
+                .and_then(|col| col.as_any().downcast_ref::<Float32Array>().cloned());
+
+            let num_rows = batch.num_rows();
+            for i in 0..num_rows {
+                if let Some(chunk) = self.extract_chunk_from_batch(&batch, i) {
+                    let distance = distances.as_ref().map_or(1.0, |d| d.value(i));
+                    chunks.push((chunk, cosine_similarity_from_distance(distance)));
+                }
+            }
+        }
+
+        Ok(chunks)
     }
 
-    pub async fn remove_file_chunks(&mut self, file_path: &Path) -> Result<()> {
+    /// Deletes the rows of the files `path_filter` selects from every table, promoting an
+    /// alias of each removed chunk found in other files in its place.
+    async fn remove_matching(&self, path_filter: &str) -> Result<()> {
+        if let Ok(aliases_table) = self.connection.open_table(ALIASES_TABLE).execute().await {
+            aliases_table.delete(path_filter).await?;
+        }
+
         if let Ok(chunks_table) = self.connection.open_table("chunks").execute().await {
-            let path_str = file_path.to_string_lossy();
-            chunks_table
-                .delete(&format!("file_path = '{}'", path_str.replace("'", "''")))
-                .await?;
+            let orphaned = self.promote_aliases(&chunks_table, path_filter).await?;
+            chunks_table.delete(path_filter).await?;
+
+            if !orphaned.is_empty()
+                && let Ok(tokens_table) = self.connection.open_table(TOKENS_TABLE).execute().await
+            {
+                tokens_table
+                    .delete(&format!("content_hash IN ({})", quoted_list(&orphaned)))
+                    .await?;
+            }
         }
 
         if let Ok(file_table) = self.connection.open_table("file_index").execute().await {
-            let path_str = file_path.to_string_lossy();
-            file_table
-                .delete(&format!("file_path = '{}'", path_str.replace("'", "''")))
-                .await?;
+            file_table.delete(path_filter).await?;
         }
 
         Ok(())
     }
 
+    /// Before a file's chunks are deleted, moves each chunk that other locations alias onto
+    /// one of those locations, so the shared embedding survives. Returns the content hashes
+    /// of the chunks no other location holds.
+    async fn promote_aliases(
+        &self,
+        chunks_table: &lancedb::Table,
+        path_filter: &str,
+    ) -> Result<Vec<String>> {
+        let removed = chunks_table
+            .query()
+            .only_if(path_filter)
+            .select(Select::Columns(vec!["content_hash".to_string()]))
+            .execute()
+            .await?;
+        let removed_batches: Vec<_> = removed.try_collect().await?;
+        let removed_hashes: Vec<String> = removed_batches
+            .iter()
+            .filter_map(|batch| {
+                batch
+                    .column_by_name("content_hash")?
+                    .as_any()
+                    .downcast_ref::<StringArray>()
+                    .cloned()
+            })
+            .flat_map(|col| col.iter().flatten().map(str::to_string).collect::<Vec<_>>())
+            .collect();
+        if removed_hashes.is_empty() {
+            return Ok(removed_hashes);
+        }
+        let Ok(aliases_table) = self.connection.open_table(ALIASES_TABLE).execute().await else {
+            return Ok(removed_hashes);
+        };
+
+        let aliases = aliases_table
+            .query()
+            .only_if(format!(
+                "content_hash IN ({})",
+                quoted_list(&removed_hashes)
+            ))
+            .execute()
+            .await?;
+        let alias_batches: Vec<_> = aliases.try_collect().await?;
+
+        let mut promoted = HashSet::new();
+        for batch in alias_batches {
+            let placeholder = Chunk::default();
+            for row in 0..batch.num_rows() {
+                let Some(alias) = self.extract_alias_from_batch(&batch, row, &placeholder) else {
+                    continue;
+                };
+                let Some(hash) = batch
+                    .column_by_name("content_hash")
+                    .and_then(|col| col.as_any().downcast_ref::<StringArray>())
+                    .map(|col| col.value(row).to_string())
+                else {
+                    continue;
+                };
+                if !promoted.insert(hash.clone()) {
+                    continue;
+                }
+
+                chunks_table
+                    .update()
+                    .only_if(format!("content_hash = '{}'", hash))
+                    .column("id", sql_string(&alias.id))
+                    .column("file_path", sql_string(&alias.file_path.to_string_lossy()))
+                    .column("start_line", alias.start_line.to_string())
+                    .column("end_line", alias.end_line.to_string())
+                    .execute()
+                    .await?;
+                aliases_table
+                    .delete(&format!("id = {}", sql_string(&alias.id)))
+                    .await?;
+            }
+        }
+
+        Ok(removed_hashes
+            .into_iter()
+            .filter(|hash| !promoted.contains(hash))
+            .collect())
+    }
+
+    /// Builds the chunk for an alias row, borrowing content from its canonical chunk.
+    fn extract_alias_from_batch(
+        &self,
+        batch: &RecordBatch,
+        row_index: usize,
+        canonical: &Chunk,
+    ) -> Option<Chunk> {
+        let id_col = batch
+            .column_by_name("id")?
+            .as_any()
+            .downcast_ref::<StringArray>()?;
+        let file_path_col = batch
+            .column_by_name("file_path")?
+            .as_any()
+            .downcast_ref::<StringArray>()?;
+        let start_line_col = batch
+            .column_by_name("start_line")?
+            .as_any()
+            .downcast_ref::<UInt64Array>()?;
+        let end_line_col = batch
+            .column_by_name("end_line")?
+            .as_any()
+            .downcast_ref::<UInt64Array>()?;
+
+        Some(Chunk {
+            id: id_col.value(row_index).to_string(),
+            file_path: std::path::PathBuf::from(file_path_col.value(row_index)),
+            start_line: start_line_col.value(row_index) as usize,
+            end_line: end_line_col.value(row_index) as usize,
+            ..canonical.clone()
+        })
+    }
+
     fn extract_chunk_from_batch(&self, batch: &RecordBatch, row_index: usize) -> Option<Chunk> {
         let id_col = batch
             .column_by_name("id")?
@@ -270,6 +646,17 @@ impl LanceIndexer {
             .column_by_name("content")?
             .as_any()
             .downcast_ref::<StringArray>()?;
+        let partial = batch
+            .column_by_name("partial")
+            .and_then(|col| col.as_any().downcast_ref::<BooleanArray>())
+            .is_some_and(|col| col.value(row_index));
+        let optional_string = |name: &str| {
+            batch
+                .column_by_name(name)
+                .and_then(|col| col.as_any().downcast_ref::<StringArray>())
+                .filter(|col| !col.is_null(row_index))
+                .map(|col| col.value(row_index).to_string())
+        };
 
         Some(Chunk {
             id: id_col.value(row_index).to_string(),
@@ -277,6 +664,12 @@ impl LanceIndexer {
             start_line: start_line_col.value(row_index) as usize,
             end_line: end_line_col.value(row_index) as usize,
             content: content_col.value(row_index).to_string(),
+            partial,
+            section: optional_string("section"),
+            language: optional_string("language"),
+            symbol: optional_string("symbol"),
+            kind: optional_string("kind").and_then(|kind| ChunkKind::parse(&kind)),
+            docs: optional_string("docs"),
         })
     }
 
@@ -293,10 +686,309 @@ impl LanceIndexer {
             .column_by_name("hash")?
             .as_any()
             .downcast_ref::<StringArray>()?;
+        let number = |name: &str| {
+            batch
+                .column_by_name(name)
+                .and_then(|col| col.as_any().downcast_ref::<UInt64Array>())
+                .map_or(0, |col| col.value(row_index))
+        };
 
         Some(FileIndex {
             file_path: std::path::PathBuf::from(file_path_col.value(row_index)),
             hash: hash_col.value(row_index).to_string(),
+            size: number("size"),
+            modified: number("modified"),
+            chunk_count: number("chunk_count") as usize,
         })
     }
 }
+
+#[async_trait]
+impl VectorIndex for LanceIndexer {
+    /// Chunks whose content is already in the table, or earlier in the batch, are stored
+    /// as aliases of it rather than embedded again.
+    #[tracing::instrument(skip_all, fields(chunks = chunks.len()))]
+    async fn prepare_chunks(&mut self, chunks: &[Chunk]) -> Result<PendingChunks> {
+        let hashes: Vec<String> = chunks.iter().map(|c| content_hash(&c.content)).collect();
+        let mut known_hashes = if chunks.is_empty() {
+            HashSet::new()
+        } else {
+            self.existing_content_hashes(&hashes).await?
+        };
+
+        let mut unique_chunks = Vec::new();
+        let mut aliases = Vec::new();
+        for (chunk, hash) in chunks.iter().zip(hashes) {
+            if known_hashes.insert(hash.clone()) {
+                unique_chunks.push(chunk.clone());
+            } else {
+                aliases.push((chunk, hash));
+            }
+        }
+
+        let texts: Vec<String> = unique_chunks.iter().map(embedding_text).collect();
+        let job = self
+            .embedder
+            .document_job(&texts, self.late_interaction)
+            .await?;
+        self.add_aliases(&aliases).await?;
+        Ok(PendingChunks::new(unique_chunks, job))
+    }
+
+    async fn store_chunks(&mut self, embedded: EmbeddedChunks) -> Result<()> {
+        let hashes: Vec<String> = embedded
+            .chunks
+            .iter()
+            .map(|c| content_hash(&c.content))
+            .collect();
+        for ((chunks, hashes), embeddings) in embedded
+            .chunks
+            .chunks(self.write_batch)
+            .zip(hashes.chunks(self.write_batch))
+            .zip(embedded.embeddings.chunks(self.write_batch))
+        {
+            self.append_chunks(chunks, hashes, embeddings).await?;
+        }
+        Ok(())
+    }
+
+    fn embedder(&mut self) -> &mut Embedder {
+        &mut self.embedder
+    }
+
+    fn embedding_error(&self) -> Option<&str> {
+        self.embedder.load_error()
+    }
+
+    async fn token_budget(&mut self) -> Option<TokenBudget> {
+        TokenBudget::load(&mut self.embedder).await
+    }
+
+    /// Blends MaxSim into the score of every candidate: each query token is matched with
+    /// the chunk token most like it, and the similarities are averaged. Candidates without
+    /// token vectors, such as keyword matches withheld from embedding, have a MaxSim of
+    /// zero, so every score is on the same scale.
+    async fn rerank(
+        &mut self,
+        query_tokens: &[Vec<f32>],
+        mut results: Vec<(Chunk, f32)>,
+    ) -> Result<Vec<(Chunk, f32)>> {
+        if !self.late_interaction || results.is_empty() || query_tokens.is_empty() {
+            return Ok(results);
+        }
+        let hashes: Vec<String> = results
+            .iter()
+            .map(|(chunk, _)| content_hash(&chunk.content))
+            .collect();
+        let token_vectors = self.token_vectors(&hashes).await?;
+
+        for ((_, score), hash) in results.iter_mut().zip(&hashes) {
+            let max_sim = token_vectors.get(hash).map_or(0.0, |chunk_tokens| {
+                let total: f32 = query_tokens
+                    .iter()
+                    .map(|query_token| {
+                        chunk_tokens
+                            .iter()
+                            .map(|chunk_token| dot(query_token, chunk_token))
+                            .fold(0.0, f32::max)
+                    })
+                    .sum();
+                (total / query_tokens.len() as f32).clamp(0.0, 1.0)
+            });
+            *score = *score * (1.0 - LATE_INTERACTION_WEIGHT) + max_sim * LATE_INTERACTION_WEIGHT;
+        }
+        results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(results)
+    }
+
+    async fn line_similarities(&mut self, query: &str, lines: &[String]) -> Result<Vec<f32>> {
+        self.embedder.line_similarities(query, lines).await
+    }
+
+    #[tracing::instrument(skip_all, fields(variants = embeddings.len(), limit))]
+    async fn search_embeddings(
+        &mut self,
+        embeddings: &[Vec<f32>],
+        filters: &QueryFilters,
+        limit: usize,
+    ) -> Result<Vec<(Chunk, f32)>> {
+        let table = match self.connection.open_table("chunks").execute().await {
+            Ok(table) => table,
+            Err(_) => return Ok(Vec::new()),
+        };
+        let predicate = self.filter_predicate(filters).await?;
+
+        let mut best_matches: HashMap<String, (Chunk, f32)> = HashMap::new();
+        for embedding in embeddings {
+            for (chunk, score) in self
+                .nearest_chunks(&table, embedding.clone(), predicate.as_deref(), limit)
+                .await?
+            {
+                match best_matches.get(&chunk.id) {
+                    Some((_, best_score)) if *best_score >= score => {}
+                    _ => {
+                        best_matches.insert(chunk.id.clone(), (chunk, score));
+                    }
+                }
+            }
+        }
+
+        let mut chunks: Vec<(Chunk, f32)> = best_matches.into_values().collect();
+        chunks.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        chunks.truncate(limit);
+        self.expand_aliases(chunks).await
+    }
+
+    async fn get_file_index(&self, file_path: &Path) -> Result<Option<FileIndex>> {
+        let file_table = match self.connection.open_table("file_index").execute().await {
+            Ok(table) => table,
+            Err(_) => return Ok(None),
+        };
+
+        let path_str = file_path.to_string_lossy();
+        let results = file_table
+            .query()
+            .only_if(format!("file_path = '{}'", path_str.replace("'", "''")))
+            .limit(1)
+            .execute()
+            .await?;
+
+        let batches: Vec<_> = results.try_collect().await?;
+        for batch in batches {
+            if batch.num_rows() > 0 {
+                if let Some(file_index) = self.extract_file_index_from_batch(&batch, 0) {
+                    return Ok(Some(file_index));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn indexed_files(&self, root: &Path) -> Result<Vec<PathBuf>> {
+        let Ok(file_table) = self.connection.open_table("file_index").execute().await else {
+            return Ok(Vec::new());
+        };
+        let prefix = root.join("");
+        let results = file_table
+            .query()
+            .only_if(format!(
+                "regexp_match(file_path, {})",
+                sql_string(&format!("^{}", regex::escape(&prefix.to_string_lossy())))
+            ))
+            .select(Select::Columns(vec!["file_path".to_string()]))
+            .execute()
+            .await?;
+
+        let batches: Vec<_> = results.try_collect().await?;
+        let mut files = Vec::new();
+        for batch in batches {
+            if let Some(col) = batch
+                .column_by_name("file_path")
+                .and_then(|col| col.as_any().downcast_ref::<StringArray>())
+            {
+                files.extend(col.iter().flatten().map(PathBuf::from));
+            }
+        }
+        Ok(files)
+    }
+
+    async fn update_file_index(&mut self, file_index: &FileIndex) -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("file_path", DataType::Utf8, false),
+            Field::new("hash", DataType::Utf8, false),
+            Field::new("size", DataType::UInt64, false),
+            Field::new("modified", DataType::UInt64, false),
+            Field::new("chunk_count", DataType::UInt64, false),
+        ]));
+
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec![
+                    file_index.file_path.to_string_lossy().to_string(),
+                ])),
+                Arc::new(StringArray::from(vec![file_index.hash.clone()])),
+                Arc::new(UInt64Array::from(vec![file_index.size])),
+                Arc::new(UInt64Array::from(vec![file_index.modified])),
+                Arc::new(UInt64Array::from(vec![file_index.chunk_count as u64])),
+            ],
+        )?;
+
+        let batches = RecordBatchIterator::new(vec![Ok(batch)].into_iter(), schema.clone());
+
+        let path_str = file_index.file_path.to_string_lossy();
+        if let Ok(file_table) = self.connection.open_table("file_index").execute().await {
+            if (file_table
+                .delete(&format!("file_path = '{}'", path_str.replace("'", "''")))
+                .await)
+                .is_ok()
+            {
+                file_table.add(Box::new(batches)).execute().await?;
+            }
+        } else {
+            let _table = self
+                .connection
+                .create_table("file_index", Box::new(batches))
+                .execute()
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn remove_file_chunks(&mut self, file_path: &Path) -> Result<()> {
+        self.remove_matching(&format!(
+            "file_path = {}",
+            sql_string(&file_path.to_string_lossy())
+        ))
+        .await
+    }
+
+    async fn remove_directory_chunks(
+        &mut self,
+        directory: &Path,
+        kept: &[PathBuf],
+        _files: &[PathBuf],
+    ) -> Result<()> {
+        // One pass over each table, which also catches files recorded without chunks
+        let under = |directory: &Path| {
+            let prefix = directory.join("");
+            format!(
+                "regexp_match(file_path, {})",
+                sql_string(&format!("^{}", regex::escape(&prefix.to_string_lossy())))
+            )
+        };
+        let mut path_filter = under(directory);
+        for kept in kept {
+            path_filter.push_str(&format!(" AND NOT {}", under(kept)));
+        }
+        self.remove_matching(&path_filter).await
+    }
+}
+
+/// LanceDB reports cosine distance in `0.0..=2.0`; map it onto a similarity in `0.0..=1.0`.
+fn cosine_similarity_from_distance(distance: f32) -> f32 {
+    (1.0 - distance).clamp(0.0, 1.0)
+}
+
+/// Cosine similarity of two vectors the model has already normalised.
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+fn content_hash(content: &str) -> String {
+    format!("{:x}", xxhash_rust::xxh3::xxh3_128(content.as_bytes()))
+}
+
+fn sql_string(value: &str) -> String {
+    format!("'{}'", value.replace("'", "''"))
+}
+
+fn quoted_list(values: &[String]) -> String {
+    values
+        .iter()
+        .map(|value| sql_string(value))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,185 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+use super::processor::{CHUNK_SIZE, Section};
+
+/// Entries further apart than this start a new chunk.
+const LOG_GAP_SECS: i64 = 60;
+const RANGE_SEPARATOR: &str = " .. ";
+const TIME_FILTER_PREFIX: &str = "time:";
+
+static TIMESTAMP: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\[?(\d{4})-(\d{2})-(\d{2})[T ](\d{2}):(\d{2})(?::(\d{2}))?")
+        .expect("timestamp pattern is valid")
+});
+
+/// Groups log lines into sections of entries close together in time. Lines without a
+/// timestamp, such as stack traces, stay with the entry above them. Each section is
+/// labelled with the time range it covers.
+pub fn sections(content: &str) -> Vec<Section> {
+    let mut sections = Vec::new();
+    let mut current: Option<(Section, String, String, i64)> = None;
+    let mut offset = 0;
+
+    for (line_index, line) in content.split_inclusive('\n').enumerate() {
+        let line_start = offset;
+        offset += line.len();
+
+        let Some((timestamp, seconds)) = parse_timestamp(line) else {
+            continue;
+        };
+
+        if let Some((section, first, last, last_seconds)) = current.as_mut() {
+            let fits = line_start - section.start_byte + line.len() <= CHUNK_SIZE;
+            if fits && seconds - *last_seconds <= LOG_GAP_SECS {
+                *last = timestamp;
+                *last_seconds = seconds;
+                continue;
+            }
+
+            section.end_byte = line_start;
+            section.label = Some(format_range(first, last));
+            sections.push(section.clone());
+        } else if line_start > 0 {
+            sections.push(Section {
+                start_byte: 0,
+                end_byte: line_start,
+                start_line: 1,
+                label: None,
+            });
+        }
+
+        current = Some((
+            Section {
+                start_byte: line_start,
+                end_byte: content.len(),
+                start_line: line_index + 1,
+                label: None,
+            },
+            timestamp.clone(),
+            timestamp,
+            seconds,
+        ));
+    }
+
+    match current {
+        Some((mut section, first, last, _)) => {
+            section.end_byte = content.len();
+            section.label = Some(format_range(&first, &last));
+            sections.push(section);
+        }
+        None => sections.push(Section {
+            start_byte: 0,
+            end_byte: content.len(),
+            start_line: 1,
+            label: None,
+        }),
+    }
+
+    sections
+}
+
+/// Returns the timestamp at the start of a line as `YYYY-MM-DD HH:MM:SS`, with its
+/// seconds since the epoch for measuring gaps.
+fn parse_timestamp(line: &str) -> Option<(String, i64)> {
+    let captures = TIMESTAMP.captures(line)?;
+    let field = |index: usize| -> i64 {
+        captures
+            .get(index)
+            .and_then(|m| m.as_str().parse().ok())
+            .unwrap_or(0)
+    };
+    let (year, month, day) = (field(1), field(2), field(3));
+    let (hour, minute, second) = (field(4), field(5), field(6));
+
+    let seconds = days_from_civil(year, month, day) * 86_400 + hour * 3_600 + minute * 60 + second;
+    let timestamp = format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year, month, day, hour, minute, second
+    );
+    Some((timestamp, seconds))
+}
+
+/// Days since 1970-01-01 in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn format_range(first: &str, last: &str) -> String {
+    if first == last {
+        first.to_string()
+    } else {
+        format!("{}{}{}", first, RANGE_SEPARATOR, last)
+    }
+}
+
+/// The last timestamp of a chunk's time range label, for ordering chunks by recency.
+pub fn latest(label: &str) -> Option<&str> {
+    parse_range(label).map(|(_, last)| last)
+}
+
+fn parse_range(label: &str) -> Option<(&str, &str)> {
+    let (first, last) = label.split_once(RANGE_SEPARATOR).unwrap_or((label, label));
+    TIMESTAMP.is_match(first).then_some((first, last))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimeFilter {
+    After(String),
+    Before(String),
+    Within(String),
+}
+
+impl TimeFilter {
+    /// Splits `time:>...`, `time:<...` and `time:...` terms out of a query, returning the
+    /// remaining query text and the filters.
+    pub fn extract(query: &str) -> (String, Vec<TimeFilter>) {
+        let mut filters = Vec::new();
+        let mut terms = Vec::new();
+
+        for term in query.split_whitespace() {
+            let Some(value) = term.strip_prefix(TIME_FILTER_PREFIX) else {
+                terms.push(term);
+                continue;
+            };
+
+            let filter = if let Some(value) = value.strip_prefix('>') {
+                TimeFilter::After(normalize(value))
+            } else if let Some(value) = value.strip_prefix('<') {
+                TimeFilter::Before(normalize(value))
+            } else {
+                TimeFilter::Within(normalize(value))
+            };
+            filters.push(filter);
+        }
+
+        (terms.join(" "), filters)
+    }
+
+    /// Checks a chunk's time range label. Timestamps compare as strings, so partial values
+    /// like `2024-03` match by prefix.
+    pub fn matches(&self, section: Option<&str>) -> bool {
+        let Some((first, last)) = section.and_then(parse_range) else {
+            return false;
+        };
+
+        match self {
+            TimeFilter::After(value) => last > value.as_str(),
+            TimeFilter::Before(value) => first < value.as_str(),
+            TimeFilter::Within(value) => {
+                let len = value.len().min(first.len());
+                &first[..len] <= value.as_str() && value.as_str() <= &last[..len.min(last.len())]
+            }
+        }
+    }
+}
+
+fn normalize(value: &str) -> String {
+    value.replace('T', " ")
+}
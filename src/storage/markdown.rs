@@ -0,0 +1,233 @@
+use std::path::Path;
+
+use super::processor::Section;
+
+const HEADING_SEPARATOR: &str = " > ";
+const RST_UNDERLINE_CHARS: &str = "=-~^\"'`*+#:._!$%&,/;<>?@[]{}|";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Markup {
+    Markdown,
+    AsciiDoc,
+    Rst,
+}
+
+impl Markup {
+    pub fn from_path(file_path: &Path) -> Self {
+        match file_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_lowercase)
+            .as_deref()
+        {
+            Some("adoc" | "asciidoc") => Markup::AsciiDoc,
+            Some("rst") => Markup::Rst,
+            _ => Markup::Markdown,
+        }
+    }
+}
+
+/// Splits a document at its headings. Each section is labelled with its heading path,
+/// e.g. "Install > Linux", and front matter becomes a section of its own.
+pub fn sections(content: &str, markup: Markup) -> Vec<Section> {
+    let lines: Vec<(usize, &str)> = content
+        .split_inclusive('\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, line))
+        })
+        .collect();
+
+    let mut sections = Vec::new();
+    let mut current = Section {
+        start_byte: 0,
+        end_byte: 0,
+        start_line: 1,
+        label: None,
+    };
+    let mut index = 0;
+
+    if markup == Markup::Markdown
+        && let Some((line_count, title)) = front_matter(&lines)
+    {
+        let end_byte = lines
+            .get(line_count)
+            .map_or(content.len(), |(offset, _)| *offset);
+        sections.push(Section {
+            start_byte: 0,
+            end_byte,
+            start_line: 1,
+            label: Some(title.unwrap_or_else(|| "Front matter".to_string())),
+        });
+        current.start_byte = end_byte;
+        current.start_line = line_count + 1;
+        index = line_count;
+    }
+
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let mut rst_levels: Vec<char> = Vec::new();
+    let mut in_fence = false;
+
+    while index < lines.len() {
+        let (offset, line) = lines[index];
+        let line = line.trim_end();
+        let next_line = lines.get(index + 1).map(|(_, next)| next.trim_end());
+
+        if markup == Markup::Markdown {
+            let fence_marker = line.trim_start();
+            if fence_marker.starts_with("```") || fence_marker.starts_with("~~~") {
+                in_fence = !in_fence;
+                index += 1;
+                continue;
+            }
+        }
+        if in_fence {
+            index += 1;
+            continue;
+        }
+
+        let heading = match markup {
+            Markup::Markdown => atx_heading(line).or_else(|| setext_heading(line, next_line)),
+            Markup::AsciiDoc => asciidoc_heading(line),
+            Markup::Rst => rst_heading(line, next_line, &mut rst_levels),
+        };
+
+        let Some((level, title, heading_lines)) = heading else {
+            index += 1;
+            continue;
+        };
+
+        if offset > current.start_byte {
+            current.end_byte = offset;
+            sections.push(current.clone());
+        }
+
+        while headings.last().is_some_and(|(last, _)| *last >= level) {
+            headings.pop();
+        }
+        headings.push((level, title));
+
+        current = Section {
+            start_byte: offset,
+            end_byte: content.len(),
+            start_line: index + 1,
+            label: Some(
+                headings
+                    .iter()
+                    .map(|(_, title)| title.as_str())
+                    .collect::<Vec<_>>()
+                    .join(HEADING_SEPARATOR),
+            ),
+        };
+        index += heading_lines;
+    }
+
+    if content.len() > current.start_byte {
+        current.end_byte = content.len();
+        sections.push(current);
+    }
+
+    sections
+}
+
+/// Returns the number of lines taken by YAML (`---`) or TOML (`+++`) front matter and
+/// its `title`, if any.
+fn front_matter(lines: &[(usize, &str)]) -> Option<(usize, Option<String>)> {
+    let delimiter = match lines.first()?.1.trim_end() {
+        "---" => "---",
+        "+++" => "+++",
+        _ => return None,
+    };
+
+    let closing = lines.iter().skip(1).position(|(_, line)| {
+        let line = line.trim_end();
+        line == delimiter || (delimiter == "---" && line == "...")
+    })? + 1;
+
+    let title = lines[1..closing].iter().find_map(|(_, line)| {
+        let rest = line.trim().strip_prefix("title")?.trim_start();
+        let value = rest.strip_prefix(':').or_else(|| rest.strip_prefix('='))?;
+        let value = value.trim().trim_matches(['"', '\'']);
+        (!value.is_empty()).then(|| value.to_string())
+    });
+
+    Some((closing + 1, title))
+}
+
+fn atx_heading(line: &str) -> Option<(usize, String, usize)> {
+    let trimmed = line.trim_start();
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+
+    let level = trimmed.chars().take_while(|&c| c == '#').count();
+    if level == 0 || level > 6 {
+        return None;
+    }
+
+    let rest = &trimmed[level..];
+    if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+
+    let title = rest.trim().trim_end_matches('#').trim_end();
+    (!title.is_empty()).then(|| (level, title.to_string(), 1))
+}
+
+fn setext_heading(line: &str, next_line: Option<&str>) -> Option<(usize, String, usize)> {
+    let title = line.trim();
+    let underline = next_line?.trim();
+    if title.is_empty() || underline.is_empty() || line.starts_with([' ', '\t']) {
+        return None;
+    }
+
+    if underline.chars().all(|c| c == '=') {
+        Some((1, title.to_string(), 2))
+    } else if underline.len() >= 2 && underline.chars().all(|c| c == '-') {
+        Some((2, title.to_string(), 2))
+    } else {
+        None
+    }
+}
+
+fn asciidoc_heading(line: &str) -> Option<(usize, String, usize)> {
+    let level = line.chars().take_while(|&c| c == '=').count();
+    if level == 0 || level > 6 {
+        return None;
+    }
+
+    let title = line[level..].strip_prefix(' ')?.trim();
+    (!title.is_empty()).then(|| (level, title.to_string(), 1))
+}
+
+/// RST titles are underlined with a punctuation character; levels follow the order in
+/// which underline characters first appear in the document.
+fn rst_heading(
+    line: &str,
+    next_line: Option<&str>,
+    levels: &mut Vec<char>,
+) -> Option<(usize, String, usize)> {
+    let title = line.trim();
+    let underline = next_line?;
+    let marker = underline.chars().next()?;
+
+    if title.is_empty()
+        || line.starts_with([' ', '\t'])
+        || !RST_UNDERLINE_CHARS.contains(marker)
+        || !underline.chars().all(|c| c == marker)
+        || underline.chars().count() < title.chars().count()
+    {
+        return None;
+    }
+
+    let level = match levels.iter().position(|&c| c == marker) {
+        Some(position) => position + 1,
+        None => {
+            levels.push(marker);
+            levels.len()
+        }
+    };
+
+    Some((level, title.to_string(), 2))
+}
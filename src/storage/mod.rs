@@ -1,72 +1,703 @@
+pub mod aliases;
+pub mod annotations;
+pub mod checkpoint;
+pub mod code;
+pub mod embedded_query;
+pub mod exclusions;
+pub mod explain;
+pub mod failures;
+pub mod filters;
 pub mod lance_indexer;
+pub mod logs;
+pub mod markdown;
+pub mod neighbors;
 pub mod processor;
+pub mod profile;
+pub mod progress;
+pub mod qdrant_indexer;
+pub mod scope;
+pub mod secrets;
+pub mod structured;
+pub mod text_index;
 pub mod text_indexer;
+pub mod tokens;
+pub mod vector_index;
 
 use anyhow::Result;
+use futures::{StreamExt, TryStreamExt};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, watch};
+use tokio_util::sync::CancellationToken;
 
-use crate::types::Chunk;
+use crate::config::{Config, QueryExpansion, SearchConfig, SemanticConfig, VectorBackend};
+use crate::crawler::SkippedFile;
+use crate::semantic::expansion::QueryExpander;
+use crate::semantic::sparse::{SparseEncoder, SparseModel, SparseVector};
+use crate::semantic::{embeddings, models};
+use crate::throttle;
+use crate::types::{Chunk, FileIndex, ProcessingConfig, QueryMode, SearchTiming};
+use aliases::QueryAliases;
+use annotations::Annotation;
+use checkpoint::IndexCheckpoint;
+use embedded_query::{EmbeddedQuery, QueryModels};
+use explain::{Explanation, SemanticMatch, SparseMatch};
+use failures::ReadFailures;
+use filters::QueryFilters;
 use lance_indexer::LanceIndexer;
-use processor::FileProcessor;
+use processor::{FileProcessor, ProcessedItem};
+use profile::IndexProfile;
+use progress::IndexProgress;
+use qdrant_indexer::QdrantIndexer;
+use text_index::TextIndex;
 use text_indexer::TextIndexer;
+use tokens::TokenBudget;
+use vector_index::VectorIndex;
+
+/// Storage shared between the UI, which searches it, and background indexing.
+pub type SharedStorage = Arc<Mutex<StorageManager>>;
 
 pub struct StorageManager {
-    lance_indexer: LanceIndexer,
-    text_indexer: TextIndexer,
+    data_dir: PathBuf,
+    /// Held for as long as this instance may write the indexes; None when read-only
+    index_lock: Option<std::fs::File>,
+    vector_index: Box<dyn VectorIndex>,
+    text_index: Box<dyn TextIndex>,
+    /// False in keyword-only mode, where nothing is embedded
+    semantic: bool,
+    /// Whether chunks that look like they hold credentials are embedded too
+    embed_secrets: bool,
+    /// Files with chunks the vector index failed to store, which are left out of the file
+    /// index so the next run embeds them again
+    unembedded: HashSet<PathBuf>,
+    /// Weighs chunk and query terms for the learned-sparse channel, when `[semantic] sparse`
+    /// is on
+    sparse: Option<SparseEncoder>,
+    /// Whether semantic and hybrid results are reordered by token vectors, for
+    /// `[semantic] late_interaction`
+    late_interaction: bool,
+    search_config: SearchConfig,
+    processing_config: ProcessingConfig,
+    commit_interval: usize,
+    query_expander: Arc<QueryExpander>,
+    aliases: QueryAliases,
+    /// Stage timings of the indexing run, kept when `[indexing] profile` is on
+    profile: Option<IndexProfile>,
+    /// Counts of the indexing run under way, for showing its rate and time left
+    progress: watch::Sender<IndexProgress>,
+    /// Set to hold indexing runs after their current batch until it is cleared
+    paused: watch::Sender<bool>,
+    /// Where the time of the last search went
+    search_timing: SearchTiming,
+}
+
+/// Bumped whenever the chunk schema of either index changes; indexes written with another
+/// version are discarded and rebuilt.
+const SCHEMA_VERSION: u32 = 14;
+const SCHEMA_VERSION_FILE: &str = "schema_version";
+const LOCK_FILE: &str = "sema.lock";
+const KEYWORD_ONLY_DIR: &str = "keyword-only";
+const PROFILES_DIR: &str = "profiles";
+const MAX_RELEVANCE_LINES: usize = 64;
+const FILTER_OVERFETCH: usize = 4;
+/// Share of a hybrid score taken from cosine similarity; the rest comes from BM25
+const HYBRID_SEMANTIC_WEIGHT: f32 = 0.7;
+/// Share of the BM25 part of a hybrid score given to the learned-sparse channel, when on
+const HYBRID_SPARSE_WEIGHT: f32 = 0.5;
+const CHUNK_CHANNEL_CAPACITY: usize = 1024;
+/// Files checked per lock acquisition, so searches interleave with the freshness check
+const FRESHNESS_CHECK_GROUP: usize = 256;
+const HASH_CONCURRENCY: usize = 16;
+
+enum Freshness {
+    /// Unchanged since it was indexed into this many chunks
+    Fresh(usize),
+    /// Same content with new metadata; the file index is refreshed without reindexing
+    Touched(FileIndex),
+    Changed,
+    New,
 }
 
 impl StorageManager {
-    pub async fn new(data_dir: &Path) -> Result<Self> {
+    pub async fn new(data_dir: &Path, config: &Config) -> Result<Self> {
+        let semantic = config.semantic.enabled;
+        throttle::configure(&config.indexing);
+        if semantic
+            && config.semantic.late_interaction
+            && config.vector_index.backend == VectorBackend::Qdrant
+        {
+            anyhow::bail!("[semantic] late_interaction needs the LanceDB backend, not Qdrant");
+        }
+
+        let data_dir = Self::index_dir(data_dir, config);
+        let data_dir = data_dir.as_path();
+
         std::fs::create_dir_all(data_dir)?;
+        // Only the instance holding the lock writes; others search what it has committed
+        let index_lock = Self::try_lock(data_dir)?;
+        let writable = index_lock.is_some();
+        if writable {
+            Self::reset_outdated_indexes(data_dir, &config.semantic)?;
+        }
 
-        let lance_indexer = LanceIndexer::new(data_dir).await?;
-        let text_indexer = TextIndexer::new(data_dir)?;
+        // Without embeddings the local index only tracks file hashes, so no server is needed
+        let vector_index: Box<dyn VectorIndex> = match config.vector_index.backend {
+            VectorBackend::Qdrant if semantic => Box::new(
+                QdrantIndexer::new(
+                    data_dir,
+                    &config.vector_index,
+                    &config.semantic,
+                    &config.resources,
+                )
+                .await?,
+            ),
+            _ => Box::new(LanceIndexer::new(data_dir, &config.semantic, &config.resources).await?),
+        };
+        let text_index: Box<dyn TextIndex> = Box::new(TextIndexer::new(
+            data_dir,
+            writable,
+            config.search.docs_boost,
+            &config.resources,
+        )?);
 
         Ok(Self {
-            lance_indexer,
-            text_indexer,
+            data_dir: data_dir.to_owned(),
+            index_lock,
+            vector_index,
+            text_index,
+            semantic,
+            embed_secrets: config.indexing.embed_secrets,
+            unembedded: HashSet::new(),
+            sparse: (semantic && config.semantic.sparse)
+                .then(|| SparseEncoder::new(&config.semantic, data_dir)),
+            late_interaction: semantic && config.semantic.late_interaction,
+            search_config: config.search.clone(),
+            processing_config: ProcessingConfig::from(config),
+            commit_interval: config.indexing.commit_interval.max(1),
+            query_expander: Arc::new(QueryExpander::new(&config.search, &config.llm)),
+            aliases: QueryAliases::new(&config.all_synonyms()),
+            profile: config.indexing.profile.then(IndexProfile::default),
+            progress: watch::Sender::new(IndexProgress::default()),
+            paused: watch::Sender::new(false),
+            search_timing: SearchTiming::default(),
         })
     }
-    pub async fn process_and_index_files(&mut self, files: Vec<PathBuf>) -> Result<usize> {
-        let mut files_to_process = Vec::new();
 
-        for file_path in &files {
-            if !file_path.exists() {
-                continue;
+    /// Where the indexes for `config` live under the data directory.
+    pub fn index_dir(data_dir: &Path, config: &Config) -> PathBuf {
+        // Each profile indexes with its own extensions, chunking and model, so switching
+        // between them doesn't rebuild the others
+        let data_dir = match &config.active_profile {
+            Some(profile) => data_dir.join(PROFILES_DIR).join(profile),
+            None => data_dir.to_owned(),
+        };
+        // Keyword-only indexes are kept apart, so files they mark as indexed still get
+        // embedded on the next semantic run
+        if config.semantic.enabled {
+            data_dir
+        } else {
+            data_dir.join(KEYWORD_ONLY_DIR)
+        }
+    }
+
+    /// Takes the search options, synonyms and query expansion settings of a reloaded
+    /// config. Settings baked into the indexes need a restart.
+    pub fn apply_search_settings(&mut self, config: &Config) {
+        self.search_config = config.search.clone();
+        self.query_expander = Arc::new(QueryExpander::new(&config.search, &config.llm));
+        self.aliases = QueryAliases::new(&config.all_synonyms());
+    }
+
+    /// Takes the advisory lock on the index directory without waiting. Returns None when
+    /// another instance holds it. Filesystems without locking are treated as unshared.
+    fn try_lock(data_dir: &Path) -> Result<Option<std::fs::File>> {
+        let lock_file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(data_dir.join(LOCK_FILE))?;
+
+        match lock_file.try_lock() {
+            Ok(()) => Ok(Some(lock_file)),
+            Err(std::fs::TryLockError::WouldBlock) => Ok(None),
+            Err(std::fs::TryLockError::Error(e)) if e.kind() == std::io::ErrorKind::Unsupported => {
+                Ok(Some(lock_file))
             }
+            Err(std::fs::TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+
+    /// Follows the progress of indexing runs on this storage.
+    pub fn progress(&self) -> watch::Receiver<IndexProgress> {
+        self.progress.subscribe()
+    }
+
+    /// Pauses indexing runs on this storage while set to true. A run commits the batch it
+    /// has gathered before it stops.
+    pub fn pause_switch(&self) -> watch::Sender<bool> {
+        self.paused.clone()
+    }
 
-            let current_hash = Self::calculate_file_hash_from_path(file_path).await?;
+    /// True when another instance owns the indexes, so this one only searches them.
+    pub fn is_read_only(&self) -> bool {
+        self.index_lock.is_none()
+    }
 
-            let needs_processing = match self.lance_indexer.get_file_index(file_path).await? {
-                Some(file_index) if file_index.hash == current_hash => false,
-                Some(_) => {
-                    self.lance_indexer.remove_file_chunks(file_path).await?;
-                    true
+    fn reset_outdated_indexes(data_dir: &Path, semantic: &SemanticConfig) -> Result<()> {
+        let version_file = data_dir.join(SCHEMA_VERSION_FILE);
+        // Vectors embedded by another model or with another document prompt don't compare
+        // with new ones either
+        let mut version = SCHEMA_VERSION.to_string();
+        if !semantic.document_prompt.is_empty() {
+            version.push_str(&format!("\n{}", semantic.document_prompt));
+        }
+        if semantic.model_dir.is_none()
+            && (semantic.model != embeddings::MODEL_ID || semantic.revision.is_some())
+        {
+            version.push_str(&format!(
+                "\nmodel {}@{}",
+                semantic.model,
+                semantic.revision.as_deref().unwrap_or("main")
+            ));
+        }
+        // Token vectors are written alongside the chunks, or dropped along with them
+        if semantic.late_interaction {
+            version.push_str("\nlate interaction");
+        }
+        if semantic.sparse {
+            match &semantic.sparse_model_dir {
+                Some(model_dir) => version.push_str(&format!("\nsparse {}", model_dir.display())),
+                None => version.push_str(&format!("\nsparse {}", semantic.sparse_model)),
+            }
+        }
+        let stored_version = std::fs::read_to_string(&version_file).ok();
+
+        // Trailing whitespace is ignored on both sides, since a document prompt such as
+        // "passage: " ends the version in it
+        if stored_version.as_deref().map(str::trim_end) == Some(version.trim_end()) {
+            return Ok(());
+        }
+
+        // A Qdrant collection may be shared, so it is left alone; its file hashes only
+        // count for files the keyword index has, so every file is embedded again
+        for index_dir in [lance_indexer::LANCE_DIR, text_indexer::TANTIVY_DIR] {
+            let path = data_dir.join(index_dir);
+            if path.exists() {
+                std::fs::remove_dir_all(&path)?;
+            }
+        }
+        // The rebuilt index may embed with other model files
+        let checksums = data_dir.join(models::INDEX_CHECKSUMS_FILE);
+        if checksums.exists() {
+            std::fs::remove_file(&checksums)?;
+        }
+
+        std::fs::write(&version_file, version)?;
+        Ok(())
+    }
+
+    /// Indexes new and changed files. When `cancel` fires, the chunks gathered so far are
+    /// committed and the remaining files are left for the next run. The lock is taken per
+    /// step rather than for the whole run, so searches against the existing index keep
+    /// working while this runs in the background. `size_limits` are the limits the crawl
+    /// found `.sema.toml` files setting for some of the files.
+    #[tracing::instrument(skip_all, fields(files = files.len()))]
+    pub async fn process_and_index_files(
+        storage: &SharedStorage,
+        files: Vec<PathBuf>,
+        size_limits: HashMap<PathBuf, u64>,
+        cancel: &CancellationToken,
+    ) -> Result<usize> {
+        let (data_dir, mut processing_config, commit_interval, progress, mut paused) = {
+            let mut storage = storage.lock().await;
+            // Indexing is left to the instance that owns the indexes
+            if storage.is_read_only() {
+                return Ok(0);
+            }
+            storage.recover_checkpoint().await?;
+            (
+                storage.data_dir.clone(),
+                storage.processing_config.clone(),
+                storage.commit_interval,
+                storage.progress.clone(),
+                storage.paused.subscribe(),
+            )
+        };
+
+        let hash_check_started = Instant::now();
+        let mut files_to_process = Vec::new();
+        for group in files.chunks(FRESHNESS_CHECK_GROUP) {
+            if cancel.is_cancelled() {
+                return Ok(0);
+            }
+            files_to_process.extend(storage.lock().await.stale_files(group).await?);
+        }
+        let file_count = files_to_process.len();
+        // Reprocessed files are dropped from the failures and added back if they fail again
+        let mut failures = ReadFailures::load(&data_dir);
+        for file_path in &files_to_process {
+            failures.files.remove(file_path);
+        }
+        storage.lock().await.record(|profile| {
+            profile.hash_check += hash_check_started.elapsed();
+            profile.files += file_count;
+        });
+        progress.send_modify(|progress| progress.start(file_count));
+        processing_config.size_limits = size_limits;
+        if file_count > 0 {
+            processing_config.token_budget = storage.lock().await.token_budget().await;
+        }
+
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(CHUNK_CHANNEL_CAPACITY);
+        let producer = tokio::task::spawn_blocking(move || {
+            FileProcessor::stream_files(files_to_process, &processing_config, sender)
+        });
+
+        let mut batch = Vec::with_capacity(commit_interval);
+        let mut batch_size = throttle::batch_size(commit_interval);
+        let mut checkpoint = IndexCheckpoint::default();
+        let mut completed_files = Vec::new();
+        let mut chunk_count = 0;
+
+        loop {
+            if *paused.borrow_and_update() && !cancel.is_cancelled() {
+                let committed = batch.len();
+                chunk_count += committed;
+                Self::commit_batch(
+                    storage,
+                    &mut batch,
+                    &mut completed_files,
+                    &mut checkpoint,
+                    cancel,
+                )
+                .await?;
+                progress.send_modify(|progress| progress.committed(committed));
+                tracing::info!("Indexing paused");
+                // The producer stops too once the channel fills up
+                tokio::select! {
+                    _ = paused.wait_for(|paused| !paused) => {}
+                    _ = cancel.cancelled() => {}
                 }
-                None => true,
+                progress.send_modify(IndexProgress::resumed);
+                tracing::info!("Indexing resumed");
+            }
+
+            let item = tokio::select! {
+                item = receiver.recv() => item,
+                Ok(()) = paused.changed() => continue,
+                _ = cancel.cancelled() => None,
+            };
+            let Some(item) = item else {
+                break;
             };
 
-            if needs_processing {
-                files_to_process.push(file_path.clone());
+            match item {
+                ProcessedItem::Chunk(chunk) => {
+                    if !checkpoint.in_progress.contains(&chunk.file_path) {
+                        checkpoint.in_progress.insert(chunk.file_path.clone());
+                    }
+                    batch.push(chunk);
+                    progress.send_modify(|progress| progress.chunks_seen += 1);
+                }
+                ProcessedItem::FileDone(file_path, chunk_count) => {
+                    completed_files.push((file_path, chunk_count));
+                    progress.send_modify(|progress| progress.files_chunked += 1);
+                }
+                ProcessedItem::Failed(file_path, error) => {
+                    failures.files.insert(file_path, error);
+                    progress.send_modify(|progress| progress.files_chunked += 1);
+                }
+            }
+
+            if batch.len() >= batch_size {
+                let committed = batch.len();
+                chunk_count += committed;
+                Self::commit_batch(
+                    storage,
+                    &mut batch,
+                    &mut completed_files,
+                    &mut checkpoint,
+                    cancel,
+                )
+                .await?;
+                progress.send_modify(|progress| progress.committed(committed));
+                batch_size = throttle::batch_size(commit_interval);
             }
         }
 
-        let files_clone = files_to_process.clone();
-        let chunks = tokio::task::spawn_blocking(move || FileProcessor::process_files(files_clone))
-            .await??;
-        let chunk_count = chunks.len();
+        // Closing the channel stops the producer if indexing was cancelled
+        drop(receiver);
+        let committed = batch.len();
+        chunk_count += committed;
+        Self::commit_batch(
+            storage,
+            &mut batch,
+            &mut completed_files,
+            &mut checkpoint,
+            cancel,
+        )
+        .await?;
+        progress.send_modify(|progress| progress.committed(committed));
+        let chunking = producer.await?;
 
-        if !chunks.is_empty() {
-            self.index_chunks(&chunks).await?;
+        if !cancel.is_cancelled() {
+            IndexCheckpoint::clear(&data_dir)?;
+        }
+        if let Err(e) = failures.save(&data_dir) {
+            tracing::warn!("Failed to save the read failures: {:#}", e);
+        }
 
-            for file_path in &files_to_process {
-                if let Ok(hash) = Self::calculate_file_hash_from_path(file_path).await {
-                    let _ = self.lance_indexer.update_file_index(file_path, &hash).await;
+        let mut storage = storage.lock().await;
+        storage.record(|profile| {
+            profile.chunking += chunking;
+            profile.chunks += chunk_count;
+        });
+        if let Some(profile) = &storage.profile
+            && let Err(e) = profile.save(&data_dir)
+        {
+            tracing::warn!("Failed to save the indexing profile: {:#}", e);
+        }
+        tracing::info!(
+            files = file_count,
+            chunks = chunk_count,
+            cancelled = cancel.is_cancelled(),
+            "Indexing finished"
+        );
+
+        Ok(chunk_count)
+    }
+
+    /// Adds the time a crawl took to the indexing profile, when profiling.
+    pub fn record_crawl(&mut self, elapsed: Duration) {
+        self.record(|profile| profile.crawl += elapsed);
+    }
+
+    /// The stage timings recorded so far, when profiling.
+    pub fn index_profile(&self) -> Option<&IndexProfile> {
+        self.profile.as_ref()
+    }
+
+    fn record(&mut self, record: impl FnOnce(&mut IndexProfile)) {
+        if let Some(profile) = &mut self.profile {
+            record(profile);
+        }
+    }
+
+    /// Drops the chunks of files whose indexing was interrupted, so they are indexed afresh.
+    async fn recover_checkpoint(&mut self) -> Result<()> {
+        if let Some(checkpoint) = IndexCheckpoint::load(&self.data_dir) {
+            for file_path in &checkpoint.in_progress {
+                self.remove_file_chunks(file_path).await?;
+            }
+            IndexCheckpoint::clear(&self.data_dir)?;
+        }
+        Ok(())
+    }
+
+    /// The files that are new or changed since they were indexed, as `stale_files` finds
+    /// them but without touching the indexes, checked a group at a time so searches
+    /// aren't held up. Used to size a crawl before deciding to index it.
+    pub async fn files_to_index(
+        storage: &SharedStorage,
+        files: &[PathBuf],
+        cancel: &CancellationToken,
+    ) -> Result<Vec<PathBuf>> {
+        let mut pending = Vec::new();
+        for group in files.chunks(FRESHNESS_CHECK_GROUP) {
+            if cancel.is_cancelled() {
+                break;
+            }
+            let checks = storage.lock().await.check_freshness(group).await?;
+            pending.extend(
+                checks
+                    .into_iter()
+                    .filter(|(_, freshness)| {
+                        matches!(freshness, Freshness::Changed | Freshness::New)
+                    })
+                    .map(|(file_path, _)| file_path),
+            );
+        }
+        Ok(pending)
+    }
+
+    /// Returns the files that are new or changed since they were indexed, removing the stale
+    /// chunks of changed ones.
+    async fn stale_files(&mut self, files: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        let mut stale = Vec::new();
+        for (file_path, freshness) in self.check_freshness(files).await? {
+            match freshness {
+                Freshness::Fresh(_) => {}
+                Freshness::Touched(file_index) => {
+                    let _ = self.vector_index.update_file_index(&file_index).await;
+                }
+                Freshness::Changed => {
+                    self.remove_file_chunks(&file_path).await?;
+                    stale.push(file_path);
+                }
+                Freshness::New => {
+                    // Chunks of a run that left the file unrecorded, as when embedding
+                    // failed, are replaced rather than indexed twice
+                    if self.text_index.contains_file(&file_path)? {
+                        self.remove_file_chunks(&file_path).await?;
+                    }
+                    stale.push(file_path);
                 }
             }
         }
 
-        Ok(chunk_count)
+        Ok(stale)
+    }
+
+    /// The freshness of each file. Files are checked concurrently.
+    async fn check_freshness(&mut self, files: &[PathBuf]) -> Result<Vec<(PathBuf, Freshness)>> {
+        let vector_index = &*self.vector_index;
+        let checks: Vec<(PathBuf, Freshness)> = futures::stream::iter(files)
+            .map(|file_path| async move {
+                let freshness = Self::freshness(vector_index, file_path).await?;
+                Ok::<_, anyhow::Error>((file_path.clone(), freshness))
+            })
+            .buffer_unordered(HASH_CONCURRENCY)
+            .try_collect()
+            .await?;
+
+        checks
+            .into_iter()
+            .map(|(file_path, freshness)| {
+                // Hashes in a Qdrant collection may come from a teammate's run, and ones
+                // from before a schema reset outlive the keyword index, so they only hold
+                // for files this keyword index has
+                let freshness = match freshness {
+                    Freshness::Fresh(chunks)
+                        if chunks > 0 && !self.text_index.contains_file(&file_path)? =>
+                    {
+                        Freshness::Changed
+                    }
+                    Freshness::Touched(file_index)
+                        if file_index.chunk_count > 0
+                            && !self.text_index.contains_file(&file_path)? =>
+                    {
+                        Freshness::Changed
+                    }
+                    freshness => freshness,
+                };
+                Ok((file_path, freshness))
+            })
+            .collect()
+    }
+
+    /// Compares a file with its file index entry. Matching size and modification time are
+    /// trusted without reading the file; otherwise the content hash decides.
+    async fn freshness(vector_index: &dyn VectorIndex, file_path: &Path) -> Result<Freshness> {
+        let Ok(metadata) = tokio::fs::metadata(file_path).await else {
+            return Ok(Freshness::Fresh(0));
+        };
+        let Some(file_index) = vector_index.get_file_index(file_path).await? else {
+            return Ok(Freshness::New);
+        };
+
+        let modified = modified_secs(&metadata);
+        if file_index.size == metadata.len() && file_index.modified == modified && modified > 0 {
+            return Ok(Freshness::Fresh(file_index.chunk_count));
+        }
+
+        let current_hash = Self::calculate_file_hash_from_path(file_path).await?;
+        if file_index.hash != current_hash {
+            return Ok(Freshness::Changed);
+        }
+
+        Ok(Freshness::Touched(FileIndex {
+            size: metadata.len(),
+            modified,
+            ..file_index
+        }))
+    }
+
+    /// Commits a batch to both indexes, records completed files in the file index, and
+    /// checkpoints the files that still have chunks outstanding.
+    async fn commit_batch(
+        storage: &SharedStorage,
+        batch: &mut Vec<Chunk>,
+        completed_files: &mut Vec<(PathBuf, usize)>,
+        checkpoint: &mut IndexCheckpoint,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        if batch.is_empty() {
+            // Still flush any queued deletions of removed or changed files
+            let mut storage = storage.lock().await;
+            let started = Instant::now();
+            storage.text_index.commit()?;
+            storage.record(|profile| profile.text_commit += started.elapsed());
+        } else {
+            Self::index_chunks(storage, batch, cancel).await?;
+            batch.clear();
+        }
+
+        let mut storage = storage.lock().await;
+        for (file_path, _) in completed_files.iter() {
+            checkpoint.in_progress.remove(file_path);
+        }
+        storage.mark_files_indexed(completed_files).await;
+
+        if checkpoint.in_progress.is_empty() {
+            IndexCheckpoint::clear(&storage.data_dir)
+        } else {
+            checkpoint.save(&storage.data_dir)
+        }
+    }
+
+    async fn remove_file_chunks(&mut self, file_path: &Path) -> Result<()> {
+        self.vector_index.remove_file_chunks(file_path).await?;
+        self.text_index.remove_file_chunks(file_path)?;
+        Ok(())
+    }
+
+    /// Records the hash and metadata of files whose chunks have all been indexed, so the UI
+    /// can describe a file without touching the disk. Files with chunks left unembedded,
+    /// as when the model failed to load, are not recorded, so they count as new next time.
+    async fn mark_files_indexed(&mut self, completed_files: &mut Vec<(PathBuf, usize)>) {
+        for (file_path, chunk_count) in completed_files.drain(..) {
+            if self.unembedded.remove(&file_path) {
+                continue;
+            }
+            let Ok(hash) = Self::calculate_file_hash_from_path(&file_path).await else {
+                continue;
+            };
+            let Ok(metadata) = tokio::fs::metadata(&file_path).await else {
+                continue;
+            };
+
+            let file_index = FileIndex {
+                file_path,
+                hash,
+                size: metadata.len(),
+                modified: modified_secs(&metadata),
+                chunk_count,
+            };
+            let _ = self.vector_index.update_file_index(&file_index).await;
+        }
+    }
+
+    /// Metadata recorded for a file when it was indexed.
+    pub async fn file_info(&self, file_path: &Path) -> Option<FileIndex> {
+        self.vector_index
+            .get_file_index(file_path)
+            .await
+            .ok()
+            .flatten()
+    }
+
+    /// The files under `root` that are in the index, before a crawl has listed them.
+    pub async fn indexed_files(&self, root: &Path) -> Vec<PathBuf> {
+        self.vector_index
+            .indexed_files(root)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("Failed to list the indexed files: {:#}", e);
+                Vec::new()
+            })
     }
 
     async fn calculate_file_hash_from_path(file_path: &Path) -> Result<String> {
@@ -93,40 +724,700 @@ impl StorageManager {
         }
     }
 
-    pub async fn index_chunks(&mut self, chunks: &[Chunk]) -> Result<()> {
+    /// Indexes a batch of chunks. The lock is taken to read and write the indexes but not
+    /// to embed, so searches go on against the index meanwhile. Once `cancel` fires the
+    /// embedding stops and the batch reaches only the text index, its files left to embed
+    /// on the next run.
+    async fn index_chunks(
+        storage: &SharedStorage,
+        chunks: &[Chunk],
+        cancel: &CancellationToken,
+    ) -> Result<()> {
         if chunks.is_empty() {
             return Ok(());
         }
 
-        if let Err(e) = self.lance_indexer.index_chunks(chunks).await {
-            eprintln!("Warning: Failed to index chunks in LanceDB: {}", e);
+        // Without a model, chunks still reach the text index for keyword search
+        let started = Instant::now();
+        let (embedded, pending, sparse_model) = {
+            let mut storage = storage.lock().await;
+            let embedded = storage.embeddable_chunks(chunks);
+            let pending = if storage.semantic && !embedded.is_empty() {
+                Some(storage.vector_index.prepare_chunks(&embedded).await)
+            } else {
+                None
+            };
+            let sparse_model = match &mut storage.sparse {
+                Some(sparse) => sparse.model().await,
+                None => None,
+            };
+            (embedded, pending, sparse_model)
+        };
+        let mut vector_append = started.elapsed();
+
+        let started = Instant::now();
+        let vectors = match pending {
+            Some(Ok(pending)) => Some(pending.embed(cancel).await),
+            Some(Err(e)) => Some(Err(e)),
+            None => None,
+        };
+        // Sparse terms are model output too, so chunks kept from the model go without
+        let sparse = match sparse_model {
+            Some(model) => Self::sparse_terms(model, chunks, &embedded).await,
+            None => Vec::new(),
+        };
+        let embedding = started.elapsed();
+
+        let mut storage = storage.lock().await;
+        let started = Instant::now();
+        if let Some(vectors) = vectors {
+            let stored = match vectors {
+                Ok(vectors) => storage.vector_index.store_chunks(vectors).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = stored {
+                if storage.vector_index.embedding_error().is_none() && !cancel.is_cancelled() {
+                    tracing::warn!("Failed to index chunks in the vector index: {:#}", e);
+                }
+                storage
+                    .unembedded
+                    .extend(embedded.iter().map(|chunk| chunk.file_path.clone()));
+            }
         }
+        vector_append += started.elapsed();
 
-        if let Err(e) = self.text_indexer.index_chunks(chunks) {
-            eprintln!("Warning: Failed to index chunks in Tantivy: {}", e);
+        let started = Instant::now();
+        if let Err(e) = storage.text_index.index_chunks(chunks, &sparse) {
+            tracing::warn!("Failed to index chunks in the text index: {:#}", e);
         }
+        let text_commit = started.elapsed();
+
+        storage.record(|profile| {
+            profile.embedding += embedding;
+            profile.vector_append += vector_append;
+            profile.text_commit += text_commit;
+        });
 
         Ok(())
     }
 
-    pub async fn search(&mut self, query: &str, limit: usize) -> Result<Vec<(Chunk, f32)>> {
+    /// The learned-sparse terms of each of `chunks` that is among `embedded`, or nothing
+    /// when encoding fails.
+    async fn sparse_terms(
+        model: Arc<SparseModel>,
+        chunks: &[Chunk],
+        embedded: &[Chunk],
+    ) -> Vec<Option<SparseVector>> {
+        let texts = embedded.iter().map(vector_index::embedding_text).collect();
+        let Some(terms) = model.encode_all(texts).await else {
+            return Vec::new();
+        };
+
+        let mut by_id: HashMap<&str, SparseVector> = embedded
+            .iter()
+            .zip(terms)
+            .filter_map(|(chunk, terms)| Some((chunk.id.as_str(), terms?)))
+            .collect();
+        chunks
+            .iter()
+            .map(|chunk| by_id.remove(chunk.id.as_str()))
+            .collect()
+    }
+
+    /// What chunks are split to fit, when they're embedded at all.
+    async fn token_budget(&mut self) -> Option<Arc<TokenBudget>> {
+        if !self.semantic {
+            return None;
+        }
+        self.vector_index.token_budget().await.map(Arc::new)
+    }
+
+    /// The chunks to embed: all of them, or those without anything that looks like a
+    /// credential unless `[indexing] embed_secrets` is on. Withheld chunks are still
+    /// found by keyword search.
+    fn embeddable_chunks<'a>(&self, chunks: &'a [Chunk]) -> Cow<'a, [Chunk]> {
+        if self.embed_secrets || !self.semantic {
+            return Cow::Borrowed(chunks);
+        }
+
+        let withheld: Vec<bool> = chunks
+            .iter()
+            .map(|chunk| secrets::contains_secret(&chunk.content))
+            .collect();
+        if !withheld.contains(&true) {
+            return Cow::Borrowed(chunks);
+        }
+
+        let embedded: Vec<Chunk> = chunks
+            .iter()
+            .zip(&withheld)
+            .filter(|(_, withheld)| !**withheld)
+            .map(|(chunk, _)| chunk.clone())
+            .collect();
+        tracing::info!(
+            withheld = chunks.len() - embedded.len(),
+            "Kept chunks that look like they hold credentials out of the vector index"
+        );
+        Cow::Owned(embedded)
+    }
+
+    /// Answers a query from the index its prefix picks. Firing `cancel` abandons the search
+    /// at its next await, including between query embeddings, and returns an error.
+    #[tracing::instrument(skip(self, cancel))]
+    pub async fn search(
+        &mut self,
+        query: &str,
+        limit: usize,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<(Chunk, f32)>> {
+        let started = Instant::now();
+        let embedded = self.query_models(query).await.run(cancel).await?;
+        self.search_embedded(query, embedded, limit, cancel, started)
+            .await
+    }
+
+    /// Like `search`, holding the lock on `storage` only to read the indexes. The query is
+    /// expanded and embedded in between, so searches run together, and indexing, take
+    /// turns on the indexes rather than waiting on each other's models. Returns where the
+    /// time went with the results.
+    pub async fn search_shared(
+        storage: &SharedStorage,
+        query: &str,
+        limit: usize,
+        cancel: &CancellationToken,
+    ) -> Result<(Vec<(Chunk, f32)>, SearchTiming)> {
+        let started = Instant::now();
+        let models = storage.lock().await.query_models(query).await;
+        let embedded = models.run(cancel).await?;
+
+        let mut storage = storage.lock().await;
+        let results = storage
+            .search_embedded(query, embedded, limit, cancel, started)
+            .await?;
+        Ok((results, storage.search_timing))
+    }
+
+    /// The models `query` is run through: the embedding model for semantic and hybrid
+    /// queries, loaded if it isn't yet, and the sparse model for hybrid ones.
+    async fn query_models(&mut self, query: &str) -> QueryModels {
+        let (query, filters) = QueryFilters::extract(query);
+        let (mode, query) = QueryMode::parse(&query);
+        let query = query.trim();
+        let synonym_variants = match self.search_config.query_expansion {
+            QueryExpansion::Synonyms => self.aliases.semantic_variants(query),
+            _ => Vec::new(),
+        };
+        let mut models = QueryModels {
+            query: self.aliases.semantic_query(query),
+            synonym_variants,
+            expander: self.query_expander.clone(),
+            embedder: None,
+            late_interaction: self.late_interaction,
+            sparse: None,
+        };
+        if query.is_empty() || self.effective_mode(mode, &filters) == QueryMode::Keyword {
+            return models;
+        }
+
+        // A model that fails to load leaves the query to keyword search
+        let embedder = self.vector_index.embedder();
+        if embedder.vector_store().await.is_err() {
+            return models;
+        }
+        models.embedder = Some(embedder.clone());
+        if mode == QueryMode::Hybrid
+            && let Some(sparse) = &mut self.sparse
+        {
+            models.sparse = sparse.model().await;
+        }
+        models
+    }
+
+    /// Searches with the query run through its models. `started` is when the search began,
+    /// before the query was embedded.
+    async fn search_embedded(
+        &mut self,
+        query: &str,
+        embedded: EmbeddedQuery,
+        limit: usize,
+        cancel: &CancellationToken,
+        started: Instant,
+    ) -> Result<Vec<(Chunk, f32)>> {
+        self.search_timing = embedded.timing;
+        let (query, filters) = QueryFilters::extract(query);
+        // Filters applied after retrieval need extra candidates to fill the limit
+        let fetch_limit = if filters.post_filtered() {
+            limit * FILTER_OVERFETCH
+        } else {
+            limit
+        };
+
+        let (mode, query) = QueryMode::parse(&query);
         let query = query.trim();
 
-        if let Some(stripped) = query.strip_prefix('\'') {
-            if !stripped.is_empty() {
-                self.text_indexer.search(stripped, limit)
+        let mut results = if query.is_empty() {
+            // A query of only time filters lists the log chunks in range, latest first
+            if filters.time.is_empty() {
+                Vec::new()
             } else {
-                Ok(Vec::new())
+                self.text_index.timed_chunks(&filters, fetch_limit)?
+            }
+        } else {
+            let mode = self.effective_mode(mode, &filters);
+            let search = async {
+                match mode {
+                    QueryMode::Keyword => self.timed_keyword_search(query, &filters, fetch_limit),
+                    QueryMode::Semantic => {
+                        let results = self
+                            .semantic_search(query, &embedded.variants, &filters, fetch_limit)
+                            .await?;
+                        self.rerank(&embedded.tokens, results).await
+                    }
+                    QueryMode::Hybrid => {
+                        let results = self
+                            .hybrid_search(query, &embedded, &filters, fetch_limit)
+                            .await?;
+                        self.rerank(&embedded.tokens, results).await
+                    }
+                }
+            };
+            tokio::select! {
+                results = search => results?,
+                _ = cancel.cancelled() => anyhow::bail!("Search cancelled"),
+            }
+        };
+
+        filters.apply(&mut results, limit);
+        if self.search_config.neighbor_chunks > 0 {
+            results = neighbors::expand(results, self.search_config.neighbor_chunks, |file| {
+                self.text_index.file_chunks(file).unwrap_or_default()
+            });
+        }
+        self.search_timing.total = started.elapsed();
+        tracing::debug!(
+            results = results.len(),
+            timing = ?self.search_timing,
+            "Search finished"
+        );
+        Ok(results)
+    }
+
+    /// TODO, FIXME and HACK lines across the index, found by keyword search and confirmed
+    /// line by line. Every matching chunk is scanned, however many there are.
+    pub fn annotations(&self) -> Result<Vec<Annotation>> {
+        let chunks = self
+            .text_index
+            .matching_chunks(annotations::ANNOTATION_QUERY, &QueryFilters::default())?;
+        Ok(annotations::find(&chunks))
+    }
+
+    /// Terms of the keyword index with their document frequencies, for autocompletion.
+    pub fn indexed_terms(&self) -> Vec<(String, u64)> {
+        self.text_index.terms().unwrap_or_default()
+    }
+
+    /// Files of `files` that failed to be read or chunked when they were last indexed.
+    pub fn read_failures(&self, files: &[PathBuf]) -> Vec<SkippedFile> {
+        ReadFailures::load(&self.data_dir).skipped_files(files)
+    }
+
+    /// Chunks of the project at `root`, counted in the keyword index, which holds every
+    /// indexed chunk of every project.
+    pub fn chunk_count(&self, root: &Path) -> Option<u64> {
+        self.text_index.chunk_count(root).ok()
+    }
+
+    /// Chunks of every project in the keyword index.
+    pub fn total_chunk_count(&self) -> Option<u64> {
+        self.text_index.total_chunk_count().ok()
+    }
+
+    /// Deletes the chunks of every file under `root` from both indexes, as when its
+    /// project is forgotten, except those under the directories in `kept`, such as
+    /// projects nested in it.
+    pub async fn remove_directory(&mut self, root: &Path, kept: &[PathBuf]) -> Result<()> {
+        if self.is_read_only() {
+            anyhow::bail!(
+                "Another sema instance is using the index at {}; close it first",
+                self.data_dir.display()
+            );
+        }
+        let mut files = self.text_index.files(root)?;
+        files.retain(|file| !kept.iter().any(|kept| file.starts_with(kept)));
+        self.vector_index
+            .remove_directory_chunks(root, kept, &files)
+            .await?;
+        self.text_index.remove_directory_chunks(root, kept)?;
+        self.text_index.commit()
+    }
+
+    /// A respelling of a query that found nothing, with each word missing from the keyword
+    /// index replaced by the closest indexed term. Prefixes, filters and exclusions are
+    /// kept as typed. None when no word could be corrected.
+    pub fn suggest_correction(&self, query: &str) -> Option<String> {
+        let mut corrected = false;
+        let words: Vec<String> = query
+            .split_whitespace()
+            .map(|word| {
+                let text = word.trim_start_matches(['\'', '~']);
+                let prefix = &word[..word.len() - text.len()];
+                if text.is_empty() || !text.chars().all(char::is_alphanumeric) {
+                    return word.to_string();
+                }
+                match self.text_index.closest_term(text) {
+                    Ok(Some(term)) => {
+                        corrected = true;
+                        format!("{}{}", prefix, term)
+                    }
+                    _ => word.to_string(),
+                }
+            })
+            .collect();
+
+        corrected.then(|| words.join(" "))
+    }
+
+    /// Keyword matches for a query that hybrid search will answer, cheap enough to show
+    /// while the embedding search runs. None for queries answered by one index alone,
+    /// where there is nothing to show early.
+    pub fn preliminary_search(&self, query: &str, limit: usize) -> Option<Vec<(Chunk, f32)>> {
+        let (query, filters) = QueryFilters::extract(query);
+        let (mode, query) = QueryMode::parse(&query);
+        let query = query.trim();
+        if query.is_empty() || self.effective_mode(mode, &filters) != QueryMode::Hybrid {
+            return None;
+        }
+
+        let fetch_limit = if filters.post_filtered() {
+            limit * FILTER_OVERFETCH
+        } else {
+            limit
+        };
+        let mut results = self.keyword_search(query, &filters, fetch_limit).ok()?;
+        filters.apply(&mut results, limit);
+        Some(results)
+    }
+
+    fn keyword_search(
+        &self,
+        query: &str,
+        filters: &QueryFilters,
+        limit: usize,
+    ) -> Result<Vec<(Chunk, f32)>> {
+        let query = self.aliases.keyword_query(query);
+        let results = self.text_index.search(&query, filters, limit)?;
+        Ok(Self::normalize_bm25_scores(results))
+    }
+
+    /// A keyword search counted towards the lexical time of the running search.
+    fn timed_keyword_search(
+        &mut self,
+        query: &str,
+        filters: &QueryFilters,
+        limit: usize,
+    ) -> Result<Vec<(Chunk, f32)>> {
+        let started = Instant::now();
+        let results = self.keyword_search(query, filters, limit);
+        *self.search_timing.lexical.get_or_insert_default() += started.elapsed();
+        results
+    }
+
+    /// Nearest neighbours of the query's embeddings above the similarity threshold, or
+    /// keyword matches when the embedding model is unavailable.
+    async fn semantic_search(
+        &mut self,
+        query: &str,
+        embeddings: &[Vec<f32>],
+        filters: &QueryFilters,
+        limit: usize,
+    ) -> Result<Vec<(Chunk, f32)>> {
+        if self.vector_index.embedding_error().is_some() {
+            return self.timed_keyword_search(query, filters, limit);
+        }
+
+        let min_similarity = self.search_config.min_similarity;
+        let started = Instant::now();
+        let results = self
+            .vector_index
+            .search_embeddings(embeddings, filters, limit)
+            .await;
+        *self.search_timing.vector.get_or_insert_default() += started.elapsed();
+
+        Ok(results?
+            .into_iter()
+            .filter(|(_, score)| *score >= min_similarity)
+            .collect())
+    }
+
+    /// The candidates reordered by late interaction with the query's token vectors, when
+    /// `[semantic] late_interaction` is on, counted towards the vector time of the running
+    /// search.
+    async fn rerank(
+        &mut self,
+        query_tokens: &[Vec<f32>],
+        results: Vec<(Chunk, f32)>,
+    ) -> Result<Vec<(Chunk, f32)>> {
+        let started = Instant::now();
+        let results = self.vector_index.rerank(query_tokens, results).await;
+        *self.search_timing.vector.get_or_insert_default() += started.elapsed();
+        results
+    }
+
+    /// Chunks holding the learned-sparse terms of the query, scored by the dot product of
+    /// their term weights with the query's, scaled like keyword scores and counted towards
+    /// the lexical time of the running search. None without terms, as while the channel is
+    /// off or its model unavailable.
+    fn sparse_search(
+        &mut self,
+        terms: Option<&SparseVector>,
+        filters: &QueryFilters,
+        limit: usize,
+    ) -> Option<Vec<(Chunk, f32)>> {
+        let started = Instant::now();
+        let results = self.text_index.sparse_search(terms?, filters, limit);
+        *self.search_timing.lexical.get_or_insert_default() += started.elapsed();
+        match results {
+            Ok(results) => Some(Self::normalize_bm25_scores(results)),
+            Err(e) => {
+                tracing::warn!("Sparse search failed: {:#}", e);
+                None
+            }
+        }
+    }
+
+    /// Semantic and keyword matches, and learned-sparse ones when that channel is on,
+    /// merged by a weighted sum of their scores, so chunks found by several rank above
+    /// chunks found by one alone.
+    async fn hybrid_search(
+        &mut self,
+        query: &str,
+        embedded: &EmbeddedQuery,
+        filters: &QueryFilters,
+        limit: usize,
+    ) -> Result<Vec<(Chunk, f32)>> {
+        let semantic = self
+            .semantic_search(query, &embedded.variants, filters, limit)
+            .await?;
+        if self.vector_index.embedding_error().is_some() {
+            return Ok(semantic);
+        }
+        // Natural-language queries are not always valid keyword query syntax
+        let keyword = self
+            .timed_keyword_search(query, filters, limit)
+            .unwrap_or_default();
+        let sparse = self.sparse_search(embedded.sparse.as_ref(), filters, limit);
+        let keyword_weight = if sparse.is_some() {
+            1.0 - HYBRID_SPARSE_WEIGHT
+        } else {
+            1.0
+        };
+
+        let mut merged: HashMap<String, (Chunk, f32)> = HashMap::new();
+        for (chunk, score) in semantic {
+            merged.insert(chunk.id.clone(), (chunk, score * HYBRID_SEMANTIC_WEIGHT));
+        }
+        for (chunk, score) in keyword {
+            merged.entry(chunk.id.clone()).or_insert((chunk, 0.0)).1 +=
+                score * (1.0 - HYBRID_SEMANTIC_WEIGHT) * keyword_weight;
+        }
+        for (chunk, score) in sparse.unwrap_or_default() {
+            merged.entry(chunk.id.clone()).or_insert((chunk, 0.0)).1 +=
+                score * (1.0 - HYBRID_SEMANTIC_WEIGHT) * HYBRID_SPARSE_WEIGHT;
+        }
+
+        let mut results: Vec<(Chunk, f32)> = merged.into_values().collect();
+        results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        results.truncate(limit);
+        Ok(results)
+    }
+
+    /// Reruns `query` to tell how it found `chunk`: its rank and score in each index the
+    /// query asked, the weight of each keyword, the late-interaction score it was reordered
+    /// by, and the file's index entry. The candidates are fetched as `search` fetches them
+    /// for `limit` results.
+    pub async fn explain(
+        &mut self,
+        query: &str,
+        chunk: &Chunk,
+        limit: usize,
+    ) -> Result<Explanation> {
+        let embedded = self
+            .query_models(query)
+            .await
+            .run(&CancellationToken::new())
+            .await?;
+        let (query, filters) = QueryFilters::extract(query);
+        let candidates = if filters.post_filtered() {
+            limit * FILTER_OVERFETCH
+        } else {
+            limit
+        };
+        let (mode, query) = QueryMode::parse(&query);
+        let query = query.trim();
+        let mode = self.effective_mode(mode, &filters);
+
+        let keyword = match mode {
+            QueryMode::Keyword | QueryMode::Hybrid => self.text_index.explain(
+                &self.aliases.keyword_query(query),
+                &filters,
+                &chunk.id,
+                candidates,
+            )?,
+            QueryMode::Semantic => None,
+        };
+
+        let semantic = match mode {
+            QueryMode::Semantic | QueryMode::Hybrid => {
+                let semantic_query = self.aliases.semantic_query(query);
+                let nearest = self
+                    .vector_index
+                    .search_embeddings(&embedded.variants, &filters, candidates)
+                    .await?;
+                let found = nearest
+                    .iter()
+                    .position(|(candidate, _)| candidate.id == chunk.id)
+                    .map(|rank| (rank + 1, nearest[rank].1));
+                let (rank, similarity) = match found {
+                    Some((rank, similarity)) => (Some(rank), similarity),
+                    None => {
+                        let similarity = self
+                            .vector_index
+                            .line_similarities(&semantic_query, &[chunk.content.clone()])
+                            .await?;
+                        (None, similarity.first().copied().unwrap_or(0.0))
+                    }
+                };
+                Some(SemanticMatch {
+                    rank,
+                    similarity,
+                    min_similarity: self.search_config.min_similarity,
+                })
             }
+            QueryMode::Keyword => None,
+        };
+
+        let sparse = match mode {
+            QueryMode::Hybrid => self
+                .sparse_search(embedded.sparse.as_ref(), &filters, candidates)
+                .map(|matches| {
+                    let found = matches
+                        .iter()
+                        .position(|(candidate, _)| candidate.id == chunk.id);
+                    SparseMatch {
+                        rank: found.map(|rank| rank + 1),
+                        score: found.map_or(0.0, |rank| matches[rank].1),
+                    }
+                }),
+            _ => None,
+        };
+        let keyword_weight = if sparse.is_some() {
+            1.0 - HYBRID_SPARSE_WEIGHT
         } else {
-            let chunks = self.lance_indexer.search(query, limit).await?;
-            Ok(chunks.into_iter().map(|c| (c, 1.0)).collect())
+            1.0
+        };
+
+        let mut explanation = Explanation {
+            mode,
+            candidates,
+            keyword,
+            semantic,
+            sparse,
+            semantic_weight: HYBRID_SEMANTIC_WEIGHT,
+            keyword_weight,
+            reranked: None,
+            file_index: self.file_info(&chunk.file_path).await,
+        };
+
+        if self.late_interaction && mode != QueryMode::Keyword {
+            let score = match mode {
+                QueryMode::Hybrid => explanation.fused_score(),
+                _ => explanation
+                    .semantic
+                    .as_ref()
+                    .map_or(0.0, |semantic| semantic.similarity),
+            };
+            let reranked = self
+                .vector_index
+                .rerank(&embedded.tokens, vec![(chunk.clone(), score)])
+                .await?;
+            explanation.reranked = reranked.first().map(|(_, score)| *score);
+        }
+        Ok(explanation)
+    }
+
+    /// Keyword search stands in for the other modes when nothing is embedded, and answers
+    /// `docs:` queries from its docs field, the only index that keeps comments and
+    /// docstrings apart from the code.
+    fn effective_mode(&self, mode: QueryMode, filters: &QueryFilters) -> QueryMode {
+        if !self.semantic || self.vector_index.embedding_error().is_some() || filters.docs_only {
+            QueryMode::Keyword
+        } else {
+            mode
+        }
+    }
+
+    /// The index that answered `query`, for showing next to the results.
+    pub fn answered_by(&self, query: &str) -> QueryMode {
+        let (query, filters) = QueryFilters::extract(query);
+        self.effective_mode(QueryMode::parse(&query).0, &filters)
+    }
+
+    /// Why semantic queries are answered by keyword search instead, if the embedding model
+    /// could not be loaded.
+    pub fn semantic_unavailable(&self) -> Option<&str> {
+        self.vector_index.embedding_error()
+    }
+
+    /// Per-line similarity of a chunk to a semantic query, used to show why it matched.
+    /// Keyword queries have no embedding and return nothing.
+    pub async fn line_relevance(&mut self, query: &str, chunk: &Chunk) -> Result<Vec<f32>> {
+        let (query, _) = QueryFilters::extract(query);
+        let (mode, query) = QueryMode::parse(&query);
+        let query = query.trim();
+        if !self.semantic || query.is_empty() || mode == QueryMode::Keyword {
+            return Ok(Vec::new());
         }
+
+        let lines: Vec<String> = chunk
+            .content
+            .lines()
+            .take(MAX_RELEVANCE_LINES)
+            .map(str::to_string)
+            .collect();
+        self.vector_index
+            .line_similarities(&self.aliases.semantic_query(query), &lines)
+            .await
+    }
+
+    /// BM25 scores are unbounded, so scale them against the best hit to land in `0.0..=1.0`
+    /// alongside cosine similarities.
+    fn normalize_bm25_scores(results: Vec<(Chunk, f32)>) -> Vec<(Chunk, f32)> {
+        let max_score = results
+            .iter()
+            .map(|(_, score)| *score)
+            .fold(0.0f32, f32::max);
+
+        if max_score <= 0.0 {
+            return results;
+        }
+
+        results
+            .into_iter()
+            .map(|(chunk, score)| (chunk, score / max_score))
+            .collect()
     }
 
     pub async fn close(mut self) {
-        if let Err(e) = self.text_indexer.commit() {
-            eprintln!("Warning: Failed to commit text index changes: {}", e);
+        if let Err(e) = self.text_index.commit() {
+            tracing::warn!("Failed to commit text index changes: {:#}", e);
         }
     }
 }
+
+fn modified_secs(metadata: &std::fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |age| age.as_secs())
+}
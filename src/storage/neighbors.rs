@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::types::Chunk;
+
+/// Widens each result to the chunks on either side of it in its file, up to `window`
+/// each way, so a match reads as a whole function or section rather than a slice of one.
+/// `file_chunks` lists a file's indexed chunks. A result already covered by a better one
+/// after widening is dropped.
+pub fn expand(
+    results: Vec<(Chunk, f32)>,
+    window: usize,
+    mut file_chunks: impl FnMut(&Path) -> Vec<Chunk>,
+) -> Vec<(Chunk, f32)> {
+    let mut chunks_by_file: HashMap<PathBuf, Vec<Chunk>> = HashMap::new();
+    let mut expanded: Vec<(Chunk, f32)> = Vec::with_capacity(results.len());
+
+    for (chunk, score) in results {
+        let covered = expanded.iter().any(|(kept, _)| {
+            kept.file_path == chunk.file_path
+                && kept.start_line <= chunk.start_line
+                && chunk.end_line <= kept.end_line
+        });
+        if covered {
+            continue;
+        }
+
+        let siblings = chunks_by_file
+            .entry(chunk.file_path.clone())
+            .or_insert_with(|| {
+                let mut chunks = file_chunks(&chunk.file_path);
+                chunks.sort_by_key(|chunk| (chunk.start_line, chunk.end_line));
+                chunks
+            });
+        let merged = match siblings.iter().position(|sibling| sibling.id == chunk.id) {
+            Some(position) => {
+                let first = position.saturating_sub(window);
+                let last = (position + window).min(siblings.len() - 1);
+                merge(&chunk, &siblings[first..=last])
+            }
+            None => chunk,
+        };
+        expanded.push((merged, score));
+    }
+
+    expanded
+}
+
+/// The matched chunk stretched over `run`, a run of consecutive chunks containing it.
+/// Lines that overlapping chunks share are kept once.
+fn merge(matched: &Chunk, run: &[Chunk]) -> Chunk {
+    let mut content = String::new();
+    let mut last_line = 0;
+    for chunk in run {
+        for (offset, line) in chunk.content.lines().enumerate() {
+            let line_number = chunk.start_line + offset;
+            if line_number <= last_line && !content.is_empty() {
+                continue;
+            }
+            if !content.is_empty() {
+                content.push('\n');
+            }
+            content.push_str(line);
+            last_line = line_number;
+        }
+    }
+
+    Chunk {
+        start_line: run
+            .iter()
+            .map(|chunk| chunk.start_line)
+            .min()
+            .unwrap_or(matched.start_line),
+        end_line: run
+            .iter()
+            .map(|chunk| chunk.end_line)
+            .max()
+            .unwrap_or(matched.end_line),
+        content,
+        partial: run.iter().any(|chunk| chunk.partial),
+        ..matched.clone()
+    }
+}
@@ -1,33 +1,338 @@
 use anyhow::Result;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
 
-use crate::types::Chunk;
+use super::code::Annotator;
+use super::logs;
+use super::markdown::{self, Markup};
+use super::structured::{self, DataFormat};
+use crate::config::{ChunkStrategy, OversizedFiles};
+use crate::crawler::images;
+use crate::types::{Chunk, ProcessingConfig};
 
-const CHUNK_SIZE: usize = 1000;
+pub(super) const CHUNK_SIZE: usize = 1000;
 const OVERLAP_SIZE: usize = 100;
 const MIN_CHUNK_SIZE: usize = 50;
+const SAMPLE_WINDOWS: u64 = 8;
+/// Files above this size are chunked from a buffered reader instead of being read whole.
+const STREAMING_THRESHOLD: u64 = 1_048_576;
+
+pub enum ProcessedItem {
+    Chunk(Chunk),
+    /// Sent after the last chunk of a file, once the file has been fully processed, with
+    /// the number of chunks it produced
+    FileDone(PathBuf, usize),
+    /// Sent before `FileDone` for a file that could not be read or chunked, with the error
+    Failed(PathBuf, String),
+}
+
+/// A span of a document that is chunked on its own, labelled with where it sits.
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub start_line: usize,
+    pub label: Option<String>,
+}
 
 pub struct FileProcessor;
 
 impl FileProcessor {
-    pub fn process_files(files: Vec<PathBuf>) -> Result<Vec<Chunk>> {
+    /// Chunks `files` in parallel and sends the chunks through `sender`. The bounded channel
+    /// provides backpressure, so only as many chunks as it holds are ever in memory. Returns
+    /// the time spent chunking, summed over threads and excluding waits on the channel.
+    pub fn stream_files(
+        files: Vec<PathBuf>,
+        config: &ProcessingConfig,
+        sender: mpsc::Sender<ProcessedItem>,
+    ) -> Duration {
         use rayon::prelude::*;
 
-        let all_chunks: Vec<Chunk> = files
-            .par_iter()
-            .filter_map(|file_path| Self::process_file_sync(file_path).ok())
-            .flatten()
-            .collect();
+        let chunking_nanos = AtomicU64::new(0);
+        files.par_iter().for_each(|file_path| {
+            if sender.is_closed() {
+                return;
+            }
+
+            let started = Instant::now();
+            let mut waiting = Duration::ZERO;
+            let mut pipeline_open = true;
+            let mut chunk_count = 0;
+            // Unreadable files still count as processed; they are retried once their hash changes
+            let processed = Self::for_each_chunk(file_path, config, |chunk| {
+                chunk_count += 1;
+                let send_started = Instant::now();
+                pipeline_open = sender.blocking_send(ProcessedItem::Chunk(chunk)).is_ok();
+                waiting += send_started.elapsed();
+                if pipeline_open {
+                    Ok(())
+                } else {
+                    Err(anyhow::anyhow!("Indexing pipeline closed"))
+                }
+            });
+            if let Err(e) = processed
+                && pipeline_open
+            {
+                tracing::warn!("Failed to read {}: {:#}", file_path.display(), e);
+                pipeline_open = sender
+                    .blocking_send(ProcessedItem::Failed(file_path.clone(), format!("{:#}", e)))
+                    .is_ok();
+            }
+            let busy = started.elapsed().saturating_sub(waiting);
+            chunking_nanos.fetch_add(busy.as_nanos() as u64, Ordering::Relaxed);
+
+            if pipeline_open {
+                let _ =
+                    sender.blocking_send(ProcessedItem::FileDone(file_path.clone(), chunk_count));
+            }
+        });
+
+        Duration::from_nanos(chunking_nanos.into_inner())
+    }
+
+    fn for_each_chunk(
+        file_path: &Path,
+        config: &ProcessingConfig,
+        mut emit: impl FnMut(Chunk) -> Result<()>,
+    ) -> Result<()> {
+        let mut annotator = Annotator::new(file_path);
+        // Chunks split to fit the model are numbered in file order with the rest
+        let mut chunk_id = 0;
+        let mut emit = |chunk: Chunk| -> Result<()> {
+            let pieces = match &config.token_budget {
+                Some(token_budget) => token_budget.fit(chunk),
+                None => vec![chunk],
+            };
+            for mut piece in pieces {
+                piece.id = format!("{}:{}", file_path.to_string_lossy(), chunk_id);
+                chunk_id += 1;
+                annotator.annotate(&mut piece);
+                emit(piece)?;
+            }
+            Ok(())
+        };
+
+        if images::is_image(file_path) {
+            return emit(Chunk {
+                file_path: file_path.to_owned(),
+                start_line: 1,
+                end_line: 1,
+                content: images::describe(file_path)?,
+                ..Chunk::default()
+            });
+        }
+
+        let file_size = std::fs::metadata(file_path)?.len();
+        let streaming = match Self::resolve_strategy(file_path, config) {
+            Some(ChunkStrategy::Lines) => true,
+            Some(ChunkStrategy::Bytes) => false,
+            Some(ChunkStrategy::Markdown | ChunkStrategy::Structured | ChunkStrategy::Log)
+            | None => file_size > STREAMING_THRESHOLD,
+        };
 
-        Ok(all_chunks)
+        if streaming && Self::within_size_limit(file_path, file_size, config) {
+            for chunk in LineChunker::open(file_path)? {
+                emit(chunk?)?;
+            }
+            return Ok(());
+        }
+
+        for chunk in Self::process_file_sync(file_path, file_size, config)? {
+            emit(chunk)?;
+        }
+        Ok(())
     }
 
-    fn process_file_sync(file_path: &Path) -> Result<Vec<Chunk>> {
-        let content = std::fs::read_to_string(file_path)?;
-        let chunks = Self::create_chunks(file_path, &content);
+    fn process_file_sync(
+        file_path: &Path,
+        file_size: u64,
+        config: &ProcessingConfig,
+    ) -> Result<Vec<Chunk>> {
+        if Self::within_size_limit(file_path, file_size, config) {
+            let content = std::fs::read_to_string(file_path)?;
+            return Ok(match Self::resolve_strategy(file_path, config) {
+                Some(ChunkStrategy::Markdown) => {
+                    let sections = markdown::sections(&content, Markup::from_path(file_path));
+                    Self::chunk_sections(file_path, &content, &sections)
+                }
+                Some(ChunkStrategy::Structured) => {
+                    let format = DataFormat::from_path(file_path).unwrap_or(DataFormat::Yaml);
+                    let sections = structured::sections(&content, format);
+                    Self::chunk_sections(file_path, &content, &sections)
+                }
+                Some(ChunkStrategy::Log) => {
+                    Self::chunk_sections(file_path, &content, &logs::sections(&content))
+                }
+                _ => Self::create_chunks(file_path, &content),
+            });
+        }
+
+        let mut chunks = match config.oversized_files {
+            OversizedFiles::Skip => return Ok(Vec::new()),
+            OversizedFiles::Head => Self::chunk_head(file_path, config.oversized_index_bytes)?,
+            OversizedFiles::Sample => {
+                Self::chunk_sampled_windows(file_path, file_size, config.oversized_index_bytes)?
+            }
+        };
+
+        for (chunk_id, chunk) in chunks.iter_mut().enumerate() {
+            chunk.id = format!("{}:{}", file_path.to_string_lossy(), chunk_id);
+            chunk.partial = true;
+        }
+
         Ok(chunks)
     }
 
+    /// Whether the whole file is indexed, against the size limit the crawl found in effect
+    /// for it. When oversized files are skipped, the crawler has already left them out.
+    fn within_size_limit(file_path: &Path, file_size: u64, config: &ProcessingConfig) -> bool {
+        file_size <= config.max_file_size(file_path)
+            || matches!(config.oversized_files, OversizedFiles::Skip)
+    }
+
+    /// The `[languages]` override for the file, or the strategy implied by its extension.
+    /// Sections need the whole file in memory, so a file streamed for its size is chunked
+    /// by lines, and an oversized one by `oversized_files`, whatever its strategy.
+    fn resolve_strategy(file_path: &Path, config: &ProcessingConfig) -> Option<ChunkStrategy> {
+        if let Some(strategy) = config.chunk_strategy(file_path) {
+            return Some(strategy);
+        }
+
+        if DataFormat::from_path(file_path).is_some() {
+            return Some(ChunkStrategy::Structured);
+        }
+        let extension = file_path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "md" | "markdown" | "mdx" | "adoc" | "asciidoc" | "rst" => {
+                Some(ChunkStrategy::Markdown)
+            }
+            "log" => Some(ChunkStrategy::Log),
+            _ => None,
+        }
+    }
+
+    /// Chunks each section separately so no chunk straddles a section boundary. Sections
+    /// that fit in one chunk are kept whole, however short.
+    fn chunk_sections(file_path: &Path, content: &str, sections: &[Section]) -> Vec<Chunk> {
+        let mut chunks = Vec::new();
+
+        for section in sections {
+            let text = &content[section.start_byte..section.end_byte];
+            if text.trim().is_empty() {
+                continue;
+            }
+
+            let section_chunks = if text.len() <= CHUNK_SIZE {
+                vec![Chunk {
+                    file_path: file_path.to_owned(),
+                    start_line: 1,
+                    end_line: 1 + text.matches('\n').count(),
+                    content: text.to_string(),
+                    ..Chunk::default()
+                }]
+            } else {
+                Self::create_chunks(file_path, text)
+            };
+
+            for mut chunk in section_chunks {
+                chunk.start_line += section.start_line - 1;
+                chunk.end_line += section.start_line - 1;
+                chunk.section = section.label.clone();
+                chunks.push(chunk);
+            }
+        }
+
+        for (chunk_id, chunk) in chunks.iter_mut().enumerate() {
+            chunk.id = format!("{}:{}", file_path.to_string_lossy(), chunk_id);
+        }
+
+        chunks
+    }
+
+    fn chunk_head(file_path: &Path, budget: u64) -> Result<Vec<Chunk>> {
+        let mut head = Vec::new();
+        File::open(file_path)?.take(budget).read_to_end(&mut head)?;
+
+        let end = Self::last_line_end(&head);
+        let content = String::from_utf8(head[..end].to_vec())?;
+        Ok(Self::create_chunks(file_path, &content))
+    }
+
+    /// Chunks `SAMPLE_WINDOWS` evenly spaced, line-aligned windows that together cover
+    /// `budget` bytes, keeping line numbers true to the whole file.
+    fn chunk_sampled_windows(file_path: &Path, file_size: u64, budget: u64) -> Result<Vec<Chunk>> {
+        let window_size = (budget / SAMPLE_WINDOWS).max(CHUNK_SIZE as u64);
+        let stride = file_size.saturating_sub(window_size) / (SAMPLE_WINDOWS - 1);
+
+        let mut reader = BufReader::new(File::open(file_path)?);
+        let mut position = 0u64;
+        let mut current_line = 1usize;
+        let mut chunks = Vec::new();
+
+        for window_index in 0..SAMPLE_WINDOWS {
+            let offset = window_index * stride;
+            if offset < position {
+                continue;
+            }
+
+            current_line += Self::count_newlines(&mut (&mut reader).take(offset - position))?;
+            position = offset;
+
+            let mut window = Vec::new();
+            (&mut reader).take(window_size).read_to_end(&mut window)?;
+            position += window.len() as u64;
+
+            let start = if offset == 0 {
+                0
+            } else {
+                window
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map_or(window.len(), |p| p + 1)
+            };
+            let end = Self::last_line_end(&window).max(start);
+            let window_start_line =
+                current_line + window[..start].iter().filter(|&&b| b == b'\n').count();
+            current_line += window.iter().filter(|&&b| b == b'\n').count();
+
+            let Ok(content) = std::str::from_utf8(&window[start..end]) else {
+                continue;
+            };
+
+            for mut chunk in Self::create_chunks(file_path, content) {
+                chunk.start_line += window_start_line - 1;
+                chunk.end_line += window_start_line - 1;
+                chunks.push(chunk);
+            }
+        }
+
+        Ok(chunks)
+    }
+
+    fn count_newlines(reader: &mut impl Read) -> Result<usize> {
+        let mut buffer = [0u8; 65_536];
+        let mut count = 0;
+        loop {
+            let bytes_read = reader.read(&mut buffer)?;
+            if bytes_read == 0 {
+                return Ok(count);
+            }
+            count += buffer[..bytes_read].iter().filter(|&&b| b == b'\n').count();
+        }
+    }
+
+    fn last_line_end(bytes: &[u8]) -> usize {
+        bytes
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(bytes.len(), |p| p + 1)
+    }
+
     fn create_chunks(file_path: &Path, content: &str) -> Vec<Chunk> {
         let mut chunks = Vec::new();
 
@@ -64,6 +369,7 @@ impl FileProcessor {
                     start_line,
                     end_line,
                     content: chunk_content.to_string(),
+                    ..Chunk::default()
                 });
 
                 chunk_id += 1;
@@ -84,3 +390,150 @@ impl FileProcessor {
         chunks
     }
 }
+
+/// Builds chunks line by line from a buffered reader, so memory use is bounded by the chunk
+/// size rather than the file size. Lines longer than a chunk are split at char boundaries.
+pub struct LineChunker<R: BufRead> {
+    reader: R,
+    file_path: PathBuf,
+    lines: VecDeque<String>,
+    lines_len: usize,
+    start_line: usize,
+    next_line: usize,
+    chunk_id: usize,
+    pending: VecDeque<Chunk>,
+    has_unflushed_lines: bool,
+    finished: bool,
+}
+
+impl LineChunker<BufReader<File>> {
+    pub fn open(file_path: &Path) -> Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(file_path)?), file_path))
+    }
+}
+
+impl<R: BufRead> LineChunker<R> {
+    pub fn new(reader: R, file_path: &Path) -> Self {
+        Self {
+            reader,
+            file_path: file_path.to_owned(),
+            lines: VecDeque::new(),
+            lines_len: 0,
+            start_line: 1,
+            next_line: 1,
+            chunk_id: 0,
+            pending: VecDeque::new(),
+            has_unflushed_lines: false,
+            finished: false,
+        }
+    }
+
+    fn push_chunk(&mut self, start_line: usize, content: String) {
+        let end_line = start_line + content.matches('\n').count();
+        self.pending.push_back(Chunk {
+            id: format!("{}:{}", self.file_path.to_string_lossy(), self.chunk_id),
+            file_path: self.file_path.clone(),
+            start_line,
+            end_line,
+            content,
+            ..Chunk::default()
+        });
+        self.chunk_id += 1;
+    }
+
+    /// Emits the buffered lines as a chunk and keeps a tail of whole lines as overlap.
+    /// The final flush keeps short chunks so the end of the file is never dropped.
+    fn flush_lines(&mut self, is_final: bool) {
+        if !self.has_unflushed_lines {
+            return;
+        }
+        self.has_unflushed_lines = false;
+
+        let content: String = self.lines.iter().map(String::as_str).collect();
+        if content.len() >= MIN_CHUNK_SIZE || self.chunk_id == 0 || is_final {
+            self.push_chunk(self.start_line, content);
+        }
+
+        while self.lines_len > OVERLAP_SIZE {
+            let Some(line) = self.lines.pop_front() else {
+                break;
+            };
+            self.lines_len -= line.len();
+            self.start_line += 1;
+        }
+    }
+
+    fn push_long_line(&mut self, line: &str, line_number: usize) {
+        let mut start = 0;
+        while start < line.len() {
+            let mut end = (start + CHUNK_SIZE).min(line.len());
+            while end > start && !line.is_char_boundary(end) {
+                end -= 1;
+            }
+            if end == start {
+                end = line.len();
+            }
+            self.push_chunk(line_number, line[start..end].to_string());
+            start = end;
+        }
+    }
+
+    fn read_next_line(&mut self) -> Result<bool> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Ok(false);
+        }
+
+        let line_number = self.next_line;
+        self.next_line += 1;
+
+        if line.len() > CHUNK_SIZE {
+            self.flush_lines(false);
+            self.lines.clear();
+            self.lines_len = 0;
+            self.push_long_line(&line, line_number);
+            self.start_line = self.next_line;
+            return Ok(true);
+        }
+
+        if self.lines_len + line.len() > CHUNK_SIZE {
+            self.flush_lines(false);
+        }
+
+        if self.lines.is_empty() {
+            self.start_line = line_number;
+        }
+        self.lines_len += line.len();
+        self.lines.push_back(line);
+        self.has_unflushed_lines = true;
+
+        Ok(true)
+    }
+}
+
+impl<R: BufRead> Iterator for LineChunker<R> {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(chunk) = self.pending.pop_front() {
+                return Some(Ok(chunk));
+            }
+            if self.finished {
+                return None;
+            }
+
+            match self.read_next_line() {
+                Ok(true) => {}
+                Ok(false) => {
+                    self.finished = true;
+                    self.flush_lines(true);
+                }
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
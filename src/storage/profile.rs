@@ -0,0 +1,67 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+const PROFILE_FILE: &str = "index_profile.json";
+
+/// Where the last indexing run spent its time, recorded with `--profile-index`. Chunking
+/// runs on several threads alongside the rest, so its time is summed over them and can
+/// exceed the wall-clock total.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IndexProfile {
+    pub crawl: Duration,
+    /// Comparing files against the file index, hashing those whose metadata changed
+    pub hash_check: Duration,
+    pub chunking: Duration,
+    pub embedding: Duration,
+    /// Adding chunks to the keyword index and committing it
+    pub text_commit: Duration,
+    /// Writing embedded chunks to the vector index
+    pub vector_append: Duration,
+    pub files: usize,
+    pub chunks: usize,
+}
+
+impl IndexProfile {
+    pub fn load(data_dir: &Path) -> Option<Self> {
+        let content = std::fs::read_to_string(data_dir.join(PROFILE_FILE)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn save(&self, data_dir: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(data_dir.join(PROFILE_FILE), content)?;
+        Ok(())
+    }
+
+    /// A table of the stages with their share of the time.
+    pub fn report(&self) -> String {
+        let stages = [
+            ("crawl", self.crawl),
+            ("hash check", self.hash_check),
+            ("chunking", self.chunking),
+            ("embedding", self.embedding),
+            ("text commit", self.text_commit),
+            ("vector append", self.vector_append),
+        ];
+        let total: Duration = stages.iter().map(|(_, time)| *time).sum();
+
+        let mut report = format!("Indexed {} chunks from {} files\n", self.chunks, self.files);
+        for (stage, time) in stages {
+            let share = if total.is_zero() {
+                0.0
+            } else {
+                time.as_secs_f64() / total.as_secs_f64() * 100.0
+            };
+            report.push_str(&format!(
+                "  {:<14} {:>9.2}s {:>5.1}%\n",
+                stage,
+                time.as_secs_f64(),
+                share
+            ));
+        }
+        report.push_str(&format!("  {:<14} {:>9.2}s", "total", total.as_secs_f64()));
+        report
+    }
+}
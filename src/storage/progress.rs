@@ -0,0 +1,76 @@
+use std::time::{Duration, Instant};
+
+/// Weight of the latest commit in the throughput, so the estimate follows a rate that
+/// changes with file types or throttling without jumping at every batch
+const RATE_SMOOTHING: f64 = 0.3;
+
+/// How far the indexing run has got, updated after each commit.
+#[derive(Debug, Clone, Default)]
+pub struct IndexProgress {
+    /// New and changed files this run indexes
+    pub files_total: usize,
+    /// Files chunked so far, including those that failed to read
+    pub files_chunked: usize,
+    /// Chunks produced so far, committed or waiting in the batch
+    pub chunks_seen: usize,
+    pub chunks_committed: usize,
+    /// Smoothed chunks embedded and committed per second
+    pub chunks_per_second: f64,
+    last_commit: Option<Instant>,
+}
+
+impl IndexProgress {
+    pub(crate) fn start(&mut self, files_total: usize) {
+        *self = Self {
+            files_total,
+            last_commit: Some(Instant::now()),
+            ..Self::default()
+        };
+    }
+
+    /// Counts `chunks` as committed and folds the rate since the last commit into the
+    /// throughput.
+    pub(crate) fn committed(&mut self, chunks: usize) {
+        let now = Instant::now();
+        if let Some(last_commit) = self.last_commit
+            && chunks > 0
+        {
+            let elapsed = now.duration_since(last_commit).as_secs_f64();
+            if elapsed > 0.0 {
+                let rate = chunks as f64 / elapsed;
+                self.chunks_per_second = if self.chunks_per_second == 0.0 {
+                    rate
+                } else {
+                    RATE_SMOOTHING * rate + (1.0 - RATE_SMOOTHING) * self.chunks_per_second
+                };
+            }
+        }
+        self.chunks_committed += chunks;
+        self.last_commit = Some(now);
+    }
+
+    /// Restarts the rate measurement after a pause, so the paused time doesn't count.
+    pub(crate) fn resumed(&mut self) {
+        self.last_commit = Some(Instant::now());
+    }
+
+    /// Chunks the whole run is expected to produce, going by the chunks per file so far.
+    pub fn chunks_expected(&self) -> usize {
+        if self.files_chunked == 0 {
+            return self.chunks_seen;
+        }
+        let per_file = self.chunks_seen as f64 / self.files_chunked as f64;
+        (per_file * self.files_total as f64).max(self.chunks_seen as f64) as usize
+    }
+
+    /// Time left at the current throughput, once there is one to go by.
+    pub fn remaining(&self) -> Option<Duration> {
+        if self.chunks_per_second <= 0.0 {
+            return None;
+        }
+        let left = self.chunks_expected().saturating_sub(self.chunks_committed);
+        Some(Duration::from_secs_f64(
+            left as f64 / self.chunks_per_second,
+        ))
+    }
+}
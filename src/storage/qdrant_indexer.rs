@@ -0,0 +1,344 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::{Method, RequestBuilder};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::filters::QueryFilters;
+use super::scope::PathScope;
+use super::tokens::TokenBudget;
+use super::vector_index::{EmbeddedChunks, PendingChunks, VectorIndex, embedding_text};
+use crate::config::{ResourcesConfig, SemanticConfig, VectorIndexConfig};
+use crate::semantic::embeddings::{EMBEDDING_DIM, Embedder};
+use crate::types::{Chunk, ChunkKind, FileIndex};
+
+const REQUEST_TIMEOUT_SECS: u64 = 30;
+
+/// Stores chunk embeddings in a Qdrant collection over its REST API. File hashes live in
+/// the payload of each file's points, so a file without chunks is re-read on every run.
+pub struct QdrantIndexer {
+    client: reqwest::Client,
+    collection_url: String,
+    api_key: Option<String>,
+    embedder: Embedder,
+    /// Chunks embedded and upserted per request
+    write_batch: usize,
+}
+
+impl QdrantIndexer {
+    pub async fn new(
+        data_dir: &Path,
+        config: &VectorIndexConfig,
+        semantic_config: &SemanticConfig,
+        resources: &ResourcesConfig,
+    ) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .build()?;
+
+        let indexer = Self {
+            client,
+            collection_url: format!(
+                "{}/collections/{}",
+                config.url.trim_end_matches('/'),
+                config.collection
+            ),
+            api_key: config.api_key.clone(),
+            embedder: Embedder::new(semantic_config, data_dir),
+            write_batch: resources.vector_write_batch.max(1),
+        };
+        indexer.ensure_collection().await?;
+
+        Ok(indexer)
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self
+            .client
+            .request(method, format!("{}{}", self.collection_url, path));
+        match &self.api_key {
+            Some(api_key) => request.header("api-key", api_key),
+            None => request,
+        }
+    }
+
+    async fn send(request: RequestBuilder) -> Result<Value> {
+        let response = request.send().await?.error_for_status()?.json().await?;
+        Ok(response)
+    }
+
+    async fn ensure_collection(&self) -> Result<()> {
+        let response = self
+            .request(Method::GET, "")
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach Qdrant at {}", self.collection_url))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        // Anything but a missing collection, such as a rejected API key, is left to the
+        // user rather than answered by creating one
+        if status != reqwest::StatusCode::NOT_FOUND {
+            anyhow::bail!(
+                "Qdrant at {} answered {} when asked for the collection",
+                self.collection_url,
+                status
+            );
+        }
+
+        Self::send(self.request(Method::PUT, "").json(&json!({
+            "vectors": { "size": EMBEDDING_DIM, "distance": "Cosine" },
+        })))
+        .await
+        .context("Failed to create Qdrant collection")?;
+
+        for field_name in ["file_path", "directories", "kind", "language"] {
+            Self::send(self.request(Method::PUT, "/index").json(&json!({
+                "field_name": field_name,
+                "field_schema": "keyword",
+            })))
+            .await
+            .context("Failed to index chunk fields in Qdrant")?;
+        }
+
+        Ok(())
+    }
+
+    async fn nearest_chunks(
+        &self,
+        vector: Vec<f32>,
+        filters: &QueryFilters,
+        limit: usize,
+    ) -> Result<Vec<(Chunk, f32)>> {
+        let mut request = json!({
+            "vector": vector,
+            "limit": limit,
+            "with_payload": true,
+        });
+        let mut must = Vec::new();
+        // Qdrant can't match a path by prefix, so each point lists every scope holding it
+        if let Some(scope) = &filters.scope {
+            must.push(json!({ "key": "directories", "match": { "value": scope.directory() } }));
+        }
+        let kinds: Vec<&str> = filters.kinds().into_iter().map(ChunkKind::label).collect();
+        for (key, values) in [("kind", kinds), ("language", filters.languages())] {
+            if !values.is_empty() {
+                must.push(json!({ "key": key, "match": { "any": values } }));
+            }
+        }
+        if !must.is_empty() {
+            request["filter"] = json!({ "must": must });
+        }
+        let response =
+            Self::send(self.request(Method::POST, "/points/search").json(&request)).await?;
+
+        Ok(response["result"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|point| {
+                let chunk = chunk_from_payload(&point["payload"])?;
+                let score = point["score"].as_f64()? as f32;
+                Some((chunk, score.clamp(0.0, 1.0)))
+            })
+            .collect())
+    }
+
+    /// Upserts embedded chunks in one request; those that failed to embed are left out.
+    async fn upsert_chunks(
+        &self,
+        chunks: &[Chunk],
+        embeddings: &[Option<(Vec<f32>, Vec<Vec<f32>>)>],
+    ) -> Result<()> {
+        let points: Vec<Value> = chunks
+            .iter()
+            .zip(embeddings)
+            .filter_map(|(chunk, embedding)| {
+                Some(json!({
+                    "id": point_id(&chunk.id),
+                    "vector": embedding.as_ref()?.0,
+                    "payload": {
+                        "id": chunk.id,
+                        "file_path": chunk.file_path.to_string_lossy(),
+                        "directories": PathScope::ancestors(&chunk.file_path),
+                        "start_line": chunk.start_line,
+                        "end_line": chunk.end_line,
+                        "content": chunk.content,
+                        "partial": chunk.partial,
+                        "section": chunk.section,
+                        "language": chunk.language,
+                        "symbol": chunk.symbol,
+                        "kind": chunk.kind.map(ChunkKind::label),
+                        "docs": chunk.docs,
+                    },
+                }))
+            })
+            .collect();
+        if points.is_empty() {
+            return Ok(());
+        }
+
+        Self::send(
+            self.request(Method::PUT, "/points?wait=true")
+                .json(&json!({ "points": points })),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl VectorIndex for QdrantIndexer {
+    async fn prepare_chunks(&mut self, chunks: &[Chunk]) -> Result<PendingChunks> {
+        let texts: Vec<String> = chunks.iter().map(embedding_text).collect();
+        let job = self.embedder.document_job(&texts, false).await?;
+        Ok(PendingChunks::new(chunks.to_vec(), job))
+    }
+
+    #[tracing::instrument(skip_all, fields(chunks = embedded.chunks.len()))]
+    async fn store_chunks(&mut self, embedded: EmbeddedChunks) -> Result<()> {
+        for (chunks, embeddings) in embedded
+            .chunks
+            .chunks(self.write_batch)
+            .zip(embedded.embeddings.chunks(self.write_batch))
+        {
+            self.upsert_chunks(chunks, embeddings).await?;
+        }
+        Ok(())
+    }
+
+    fn embedder(&mut self) -> &mut Embedder {
+        &mut self.embedder
+    }
+
+    #[tracing::instrument(skip_all, fields(variants = embeddings.len(), limit))]
+    async fn search_embeddings(
+        &mut self,
+        embeddings: &[Vec<f32>],
+        filters: &QueryFilters,
+        limit: usize,
+    ) -> Result<Vec<(Chunk, f32)>> {
+        let mut best_matches: HashMap<String, (Chunk, f32)> = HashMap::new();
+
+        for embedding in embeddings {
+            for (chunk, score) in self
+                .nearest_chunks(embedding.clone(), filters, limit)
+                .await?
+            {
+                match best_matches.get(&chunk.id) {
+                    Some((_, best_score)) if *best_score >= score => {}
+                    _ => {
+                        best_matches.insert(chunk.id.clone(), (chunk, score));
+                    }
+                }
+            }
+        }
+
+        let mut chunks: Vec<(Chunk, f32)> = best_matches.into_values().collect();
+        chunks.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        chunks.truncate(limit);
+        Ok(chunks)
+    }
+
+    fn embedding_error(&self) -> Option<&str> {
+        self.embedder.load_error()
+    }
+
+    async fn token_budget(&mut self) -> Option<TokenBudget> {
+        TokenBudget::load(&mut self.embedder).await
+    }
+
+    async fn line_similarities(&mut self, query: &str, lines: &[String]) -> Result<Vec<f32>> {
+        self.embedder.line_similarities(query, lines).await
+    }
+
+    async fn get_file_index(&self, file_path: &Path) -> Result<Option<FileIndex>> {
+        let response = Self::send(self.request(Method::POST, "/points/scroll").json(&json!({
+            "filter": file_filter(file_path),
+            "limit": 1,
+            "with_payload": ["file_hash", "file_size", "file_modified", "chunk_count"],
+            "with_vector": false,
+        })))
+        .await?;
+
+        let payload = &response["result"]["points"][0]["payload"];
+        Ok(payload["file_hash"].as_str().map(|hash| FileIndex {
+            file_path: file_path.to_owned(),
+            hash: hash.to_string(),
+            size: payload["file_size"].as_u64().unwrap_or(0),
+            modified: payload["file_modified"].as_u64().unwrap_or(0),
+            chunk_count: payload["chunk_count"].as_u64().unwrap_or(0) as usize,
+        }))
+    }
+
+    async fn update_file_index(&mut self, file_index: &FileIndex) -> Result<()> {
+        Self::send(
+            self.request(Method::POST, "/points/payload?wait=true")
+                .json(&json!({
+                    "payload": {
+                        "file_hash": file_index.hash,
+                        "file_size": file_index.size,
+                        "file_modified": file_index.modified,
+                        "chunk_count": file_index.chunk_count,
+                    },
+                    "filter": file_filter(&file_index.file_path),
+                })),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn remove_file_chunks(&mut self, file_path: &Path) -> Result<()> {
+        Self::send(
+            self.request(Method::POST, "/points/delete?wait=true")
+                .json(&json!({ "filter": file_filter(file_path) })),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Leaves the collection alone: it is shared, and a teammate may still index the
+    /// same directory. Hashes left behind only hold for files the local keyword index has,
+    /// so a project indexed again is embedded again.
+    async fn remove_directory_chunks(
+        &mut self,
+        _directory: &Path,
+        _kept: &[PathBuf],
+        _files: &[PathBuf],
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Qdrant point ids must be integers or UUIDs, so chunk ids are hashed.
+fn point_id(chunk_id: &str) -> u64 {
+    xxhash_rust::xxh3::xxh3_64(chunk_id.as_bytes())
+}
+
+fn file_filter(file_path: &Path) -> Value {
+    json!({
+        "must": [{ "key": "file_path", "match": { "value": file_path.to_string_lossy() } }],
+    })
+}
+
+fn chunk_from_payload(payload: &Value) -> Option<Chunk> {
+    Some(Chunk {
+        id: payload["id"].as_str()?.to_string(),
+        file_path: PathBuf::from(payload["file_path"].as_str()?),
+        start_line: payload["start_line"].as_u64()? as usize,
+        end_line: payload["end_line"].as_u64()? as usize,
+        content: payload["content"].as_str()?.to_string(),
+        partial: payload["partial"].as_bool().unwrap_or(false),
+        section: payload["section"].as_str().map(str::to_string),
+        language: payload["language"].as_str().map(str::to_string),
+        symbol: payload["symbol"].as_str().map(str::to_string),
+        kind: payload["kind"].as_str().and_then(ChunkKind::parse),
+        docs: payload["docs"].as_str().map(str::to_string),
+    })
+}
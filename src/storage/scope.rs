@@ -0,0 +1,80 @@
+use std::path::{Path, PathBuf};
+
+use super::exclusions::split_terms;
+use crate::types::Chunk;
+
+const SCOPE_PREFIX: &str = "in:";
+
+/// An `in:<directory>` query term limiting results to files under the directory. The
+/// indexes only retrieve chunks under it, so a project sharing the index with larger ones
+/// still fills the results.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathScope(PathBuf);
+
+impl PathScope {
+    /// Splits the scope out of a query, returning the remaining query text and the scope.
+    /// With several `in:` terms the last one wins.
+    pub fn extract(query: &str) -> (String, Option<PathScope>) {
+        let mut scope = None;
+        let mut terms = Vec::new();
+
+        for term in split_terms(query) {
+            match term.strip_prefix(SCOPE_PREFIX) {
+                Some(directory) => {
+                    let directory = directory.trim_matches('"');
+                    if !directory.is_empty() {
+                        scope = Some(PathScope(PathBuf::from(directory)));
+                    }
+                }
+                None => terms.push(term),
+            }
+        }
+
+        (terms.join(" "), scope)
+    }
+
+    /// The query term restricting a search to `directory`, quoted when the path has spaces.
+    pub fn term(directory: &Path) -> String {
+        let directory = directory.to_string_lossy();
+        if directory.contains(char::is_whitespace) {
+            format!("{}\"{}\"", SCOPE_PREFIX, directory)
+        } else {
+            format!("{}{}", SCOPE_PREFIX, directory)
+        }
+    }
+
+    /// Compares whole path components, so `src/tui` doesn't contain `src/tuition.rs`.
+    pub fn contains(&self, chunk: &Chunk) -> bool {
+        chunk.file_path.starts_with(&self.0)
+    }
+
+    /// A regular expression, without anchors, matching the paths `contains` accepts: the
+    /// directory itself and everything under it.
+    pub fn pattern(&self) -> String {
+        let directory = self.0.to_string_lossy();
+        let directory = directory.trim_end_matches(std::path::is_separator);
+        format!(
+            "{}({}.*)?",
+            regex::escape(directory),
+            regex::escape(std::path::MAIN_SEPARATOR_STR)
+        )
+    }
+
+    /// The path and each directory above it: every scope holding it, as `directory`
+    /// names them, for indexes that can't match a path by pattern.
+    pub fn ancestors(path: &Path) -> Vec<String> {
+        path.ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .map(|ancestor| ancestor.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    /// The directory as `ancestors` lists it.
+    pub fn directory(&self) -> String {
+        self.0
+            .components()
+            .collect::<PathBuf>()
+            .to_string_lossy()
+            .into_owned()
+    }
+}
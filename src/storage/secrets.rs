@@ -0,0 +1,135 @@
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// Well-known credential formats, checked before the entropy heuristics
+static TOKEN_PATTERNS: LazyLock<Vec<(&'static str, Regex)>> = LazyLock::new(|| {
+    [
+        ("AWS access key", r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b"),
+        (
+            "GitHub token",
+            r"\b(?:gh[pousr]_[A-Za-z0-9]{36,}|github_pat_[A-Za-z0-9_]{22,})",
+        ),
+        ("Slack token", r"\bxox[abprs]-[A-Za-z0-9-]{10,}"),
+        ("Google API key", r"\bAIza[0-9A-Za-z_-]{35}"),
+        ("Stripe key", r"\b[rs]k_live_[0-9A-Za-z]{20,}"),
+        ("Private key", r"-----BEGIN (?:[A-Z]+ )?PRIVATE KEY-----"),
+        (
+            "JSON web token",
+            r"\beyJ[A-Za-z0-9_-]{10,}\.[A-Za-z0-9_-]{10,}\.[A-Za-z0-9_-]{10,}",
+        ),
+    ]
+    .into_iter()
+    .map(|(kind, pattern)| (kind, Regex::new(pattern).expect("token pattern is valid")))
+    .collect()
+});
+
+/// A quoted value assigned to something named like a credential, as in `password = "..."`
+static ASSIGNMENT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(concat!(
+        r#"(?i)(?:secret|passw(?:or)?d|api[_-]?key|access[_-]?key|auth[_-]?token|token)s?"#,
+        r#"["']?\s*[:=]\s*["']([^"'\s]{8,})["']"#
+    ))
+    .expect("assignment pattern is valid")
+});
+
+/// Runs of characters a key or token might be encoded in
+static CANDIDATE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"[A-Za-z0-9+/_=-]{32,}").expect("candidate pattern is valid"));
+
+/// Bits per character above which an assigned credential looks random rather than a
+/// placeholder like `changeme`
+const ASSIGNED_ENTROPY: f64 = 3.0;
+/// Bits per character above which a long token looks random. Hex digests such as lockfile
+/// checksums top out at 4 and stay below it.
+const TOKEN_ENTROPY: f64 = 4.3;
+/// Digest names opening the integrity hashes of npm and yarn lockfiles
+const INTEGRITY_PREFIXES: [&str; 3] = ["sha256-", "sha384-", "sha512-"];
+/// Characters of a secret shown in reports; the rest is elided
+const PREVIEW_CHARS: usize = 4;
+
+/// A line that looks like it holds a credential.
+#[derive(Debug, Clone)]
+pub struct Secret {
+    /// 0-based line within the scanned text
+    pub line: usize,
+    pub kind: &'static str,
+    /// The start of the matched text, so reports don't repeat the secret itself
+    pub preview: String,
+}
+
+/// Every line of `text` that looks like it holds a credential, at most one finding each.
+pub fn find(text: &str) -> Vec<Secret> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(line, content)| {
+            let (kind, matched) = scan_line(content)?;
+            Some(Secret {
+                line,
+                kind,
+                preview: preview(matched),
+            })
+        })
+        .collect()
+}
+
+/// Whether any line of `text` looks like it holds a credential.
+pub fn contains_secret(text: &str) -> bool {
+    text.lines().any(|line| scan_line(line).is_some())
+}
+
+fn scan_line(line: &str) -> Option<(&'static str, &str)> {
+    for (kind, pattern) in TOKEN_PATTERNS.iter() {
+        if let Some(matched) = pattern.find(line) {
+            return Some((kind, matched.as_str()));
+        }
+    }
+
+    if let Some(captures) = ASSIGNMENT.captures(line)
+        && let Some(value) = captures.get(1)
+        && entropy(value.as_str()) >= ASSIGNED_ENTROPY
+    {
+        return Some(("Assigned credential", value.as_str()));
+    }
+
+    CANDIDATE
+        .find_iter(line)
+        .map(|candidate| candidate.as_str())
+        .find(|candidate| looks_random(candidate))
+        .map(|candidate| ("High-entropy string", candidate))
+}
+
+/// Mixed case and digits with high entropy; long identifiers and paths lack one or the other.
+/// Subresource integrity hashes, as in lockfiles, are public and skipped.
+fn looks_random(candidate: &str) -> bool {
+    !INTEGRITY_PREFIXES
+        .iter()
+        .any(|prefix| candidate.starts_with(prefix))
+        && candidate.chars().any(|c| c.is_ascii_uppercase())
+        && candidate.chars().any(|c| c.is_ascii_lowercase())
+        && candidate.chars().any(|c| c.is_ascii_digit())
+        && !candidate.contains("//")
+        && entropy(candidate) >= TOKEN_ENTROPY
+}
+
+/// Shannon entropy of the characters of `text`, in bits per character.
+fn entropy(text: &str) -> f64 {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in text.chars() {
+        *counts.entry(c).or_default() += 1;
+    }
+    let total = text.chars().count() as f64;
+
+    counts
+        .values()
+        .map(|&count| {
+            let share = count as f64 / total;
+            -share * share.log2()
+        })
+        .sum()
+}
+
+fn preview(matched: &str) -> String {
+    let shown: String = matched.chars().take(PREVIEW_CHARS).collect();
+    format!("{}…", shown)
+}
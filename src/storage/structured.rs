@@ -0,0 +1,313 @@
+use std::path::Path;
+
+use super::processor::{CHUNK_SIZE, Section};
+
+const LABEL_SEPARATOR: &str = ", ";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl DataFormat {
+    pub fn from_path(file_path: &Path) -> Option<Self> {
+        let extension = file_path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "json" => Some(DataFormat::Json),
+            "yaml" | "yml" => Some(DataFormat::Yaml),
+            "toml" => Some(DataFormat::Toml),
+            _ => None,
+        }
+    }
+}
+
+/// A key that starts a section, at `depth` 1 (top level) or 2 (nested one level).
+/// Keys without a name mark document boundaries.
+struct KeyEvent {
+    line: usize,
+    depth: usize,
+    key: Option<String>,
+}
+
+/// Splits a configuration file along its top-level keys (and document boundaries), falling
+/// back to second-level keys for oversized stanzas. Sections are labelled with the key path,
+/// e.g. "database.pool", and small neighbouring sections are merged.
+pub fn sections(content: &str, format: DataFormat) -> Vec<Section> {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+        .filter(|&offset| offset < content.len())
+        .collect();
+
+    let whole_file = vec![Section {
+        start_byte: 0,
+        end_byte: content.len(),
+        start_line: 1,
+        label: None,
+    }];
+    if line_starts.len() <= 1 {
+        return whole_file;
+    }
+
+    let mut events = match format {
+        DataFormat::Json => json_keys(content),
+        DataFormat::Yaml => yaml_keys(content),
+        DataFormat::Toml => toml_keys(content),
+    };
+    events.dedup_by_key(|event| event.line);
+
+    let top_level: Vec<&KeyEvent> = events.iter().filter(|event| event.depth == 1).collect();
+    if top_level.is_empty() {
+        return whole_file;
+    }
+
+    let byte_at = |line: usize| line_starts.get(line).copied().unwrap_or(content.len());
+    let mut sections = Vec::new();
+
+    if top_level[0].line > 0 {
+        sections.push(Section {
+            start_byte: 0,
+            end_byte: byte_at(top_level[0].line),
+            start_line: 1,
+            label: None,
+        });
+    }
+
+    for (index, event) in top_level.iter().enumerate() {
+        let end_line = top_level
+            .get(index + 1)
+            .map_or(line_starts.len(), |next| next.line);
+        let start_byte = byte_at(event.line);
+        let end_byte = byte_at(end_line);
+
+        let children: Vec<&KeyEvent> = events
+            .iter()
+            .filter(|child| child.depth == 2 && child.line > event.line && child.line < end_line)
+            .collect();
+
+        if end_byte - start_byte <= CHUNK_SIZE || children.is_empty() {
+            sections.push(Section {
+                start_byte,
+                end_byte,
+                start_line: event.line + 1,
+                label: event.key.clone(),
+            });
+            continue;
+        }
+
+        sections.push(Section {
+            start_byte,
+            end_byte: byte_at(children[0].line),
+            start_line: event.line + 1,
+            label: event.key.clone(),
+        });
+        for (child_index, child) in children.iter().enumerate() {
+            let child_end = children
+                .get(child_index + 1)
+                .map_or(end_line, |next| next.line);
+            let label = match (&event.key, &child.key) {
+                (Some(parent), Some(key)) => Some(format!("{}.{}", parent, key)),
+                (None, key) => key.clone(),
+                (parent, None) => parent.clone(),
+            };
+            sections.push(Section {
+                start_byte: byte_at(child.line),
+                end_byte: byte_at(child_end),
+                start_line: child.line + 1,
+                label,
+            });
+        }
+    }
+
+    merge_small_sections(sections)
+}
+
+/// Joins neighbouring sections while they fit in one chunk, so a file of many one-line
+/// keys doesn't turn into one tiny chunk per key.
+fn merge_small_sections(sections: Vec<Section>) -> Vec<Section> {
+    let mut merged: Vec<Section> = Vec::new();
+
+    for section in sections {
+        if let Some(last) = merged.last_mut()
+            && section.end_byte - last.start_byte <= CHUNK_SIZE
+        {
+            last.end_byte = section.end_byte;
+            last.label = match (last.label.take(), section.label) {
+                (Some(a), Some(b)) => Some(format!("{}{}{}", a, LABEL_SEPARATOR, b)),
+                (a, b) => a.or(b),
+            };
+            continue;
+        }
+        merged.push(section);
+    }
+
+    merged
+}
+
+fn json_keys(content: &str) -> Vec<KeyEvent> {
+    let mut events = Vec::new();
+    // One entry per open container: whether it is an object
+    let mut containers: Vec<bool> = Vec::new();
+    let mut line = 0;
+    let mut chars = content.char_indices().peekable();
+    let mut last_string: Option<(usize, String)> = None;
+
+    while let Some((_, c)) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            '{' => containers.push(true),
+            '[' => containers.push(false),
+            '}' | ']' => {
+                containers.pop();
+            }
+            '"' => {
+                let start_line = line;
+                let mut value = String::new();
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '\\' => {
+                            if let Some((_, escaped)) = chars.next() {
+                                value.push(escaped);
+                            }
+                        }
+                        '"' => break,
+                        '\n' => {
+                            line += 1;
+                            value.push(c);
+                        }
+                        _ => value.push(c),
+                    }
+                }
+                last_string = Some((start_line, value));
+                continue;
+            }
+            ':' => {
+                let depth = containers.len();
+                if let Some((key_line, key)) = last_string.take()
+                    && (depth == 1 || depth == 2)
+                    && containers.last() == Some(&true)
+                {
+                    events.push(KeyEvent {
+                        line: key_line,
+                        depth,
+                        key: Some(key),
+                    });
+                }
+            }
+            _ => {}
+        }
+
+        if !c.is_whitespace() {
+            last_string = None;
+        }
+    }
+
+    events
+}
+
+fn yaml_keys(content: &str) -> Vec<KeyEvent> {
+    let mut events = Vec::new();
+    let mut child_indent: Option<usize> = None;
+
+    for (line_index, line) in content.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        if line.trim_end() == "---" {
+            events.push(KeyEvent {
+                line: line_index,
+                depth: 1,
+                key: None,
+            });
+            child_indent = None;
+            continue;
+        }
+
+        let indent = line.len() - trimmed.len();
+        let Some(key) = yaml_key(trimmed) else {
+            continue;
+        };
+
+        if indent == 0 {
+            events.push(KeyEvent {
+                line: line_index,
+                depth: 1,
+                key: Some(key),
+            });
+            child_indent = None;
+        } else if *child_indent.get_or_insert(indent) == indent {
+            events.push(KeyEvent {
+                line: line_index,
+                depth: 2,
+                key: Some(key),
+            });
+        }
+    }
+
+    events
+}
+
+fn yaml_key(trimmed: &str) -> Option<String> {
+    if trimmed.starts_with(['-', '[', '{', '|', '>']) {
+        return None;
+    }
+
+    let (key, rest) = trimmed.split_once(':')?;
+    if !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+
+    let key = key.trim().trim_matches(['"', '\'']);
+    (!key.is_empty()).then(|| key.to_string())
+}
+
+fn toml_keys(content: &str) -> Vec<KeyEvent> {
+    let mut events = Vec::new();
+    let mut in_table = false;
+
+    for (line_index, line) in content.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        if trimmed.starts_with('[') {
+            let name = trimmed
+                .trim_start_matches('[')
+                .split(']')
+                .next()
+                .unwrap_or("")
+                .trim();
+            events.push(KeyEvent {
+                line: line_index,
+                depth: 1,
+                key: (!name.is_empty()).then(|| name.to_string()),
+            });
+            in_table = true;
+            continue;
+        }
+
+        // Continuation lines of multi-line values are indented or lack an `=`
+        if line.starts_with([' ', '\t']) {
+            continue;
+        }
+        let Some((key, _)) = trimmed.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().trim_matches(['"', '\'']);
+        if key.is_empty() {
+            continue;
+        }
+
+        events.push(KeyEvent {
+            line: line_index,
+            depth: if in_table { 2 } else { 1 },
+            key: Some(key.to_string()),
+        });
+    }
+
+    events
+}
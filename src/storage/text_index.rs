@@ -0,0 +1,106 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+use super::filters::QueryFilters;
+use crate::semantic::sparse::SparseVector;
+use crate::types::Chunk;
+
+/// How the keyword index scored a chunk for a query.
+#[derive(Debug, Clone)]
+pub struct KeywordMatch {
+    /// 1-based position among the keyword matches
+    pub rank: usize,
+    /// Raw BM25 score
+    pub score: f32,
+    /// BM25 score of the best match, which keyword scores are scaled against
+    pub top_score: f32,
+    /// Heaviest first
+    pub terms: Vec<TermWeight>,
+}
+
+/// What one query word added to a chunk's score through one field.
+#[derive(Debug, Clone)]
+pub struct TermWeight {
+    pub term: String,
+    pub field: &'static str,
+    pub weight: f32,
+}
+
+/// A keyword index over chunk text, searched by `'` and hybrid queries. Writes may be
+/// buffered until `commit`, so backends can batch them.
+pub trait TextIndex: Send {
+    /// Indexes the chunks, each with its learned-sparse terms from `sparse` where it has
+    /// them; `sparse` is empty while that channel is off.
+    fn index_chunks(&mut self, chunks: &[Chunk], sparse: &[Option<SparseVector>]) -> Result<()>;
+
+    /// Returns matching chunks paired with their raw relevance score, best first. Only
+    /// chunks in the scope are retrieved, chunks hit by an exclusion are left out where the
+    /// backend can express it, and a `docs:` query matches comments and docstrings only.
+    fn search(
+        &self,
+        query: &str,
+        filters: &QueryFilters,
+        limit: usize,
+    ) -> Result<Vec<(Chunk, f32)>>;
+
+    /// Every chunk matching `query`, in no particular order, for scans that have to see all
+    /// of them rather than the best few. Filters apply as in `search`.
+    fn matching_chunks(&self, query: &str, filters: &QueryFilters) -> Result<Vec<Chunk>>;
+
+    /// Chunks holding any of the learned-sparse `terms` of a query, scored by the dot
+    /// product of the term weights of the query and the chunk, best first. The scope and
+    /// exclusions apply as in `search`.
+    fn sparse_search(
+        &self,
+        terms: &SparseVector,
+        filters: &QueryFilters,
+        limit: usize,
+    ) -> Result<Vec<(Chunk, f32)>>;
+
+    /// Where a chunk ranks among the first `limit` matches of `query`, and what each query
+    /// word contributed to its score. None when it is not among them.
+    fn explain(
+        &self,
+        query: &str,
+        filters: &QueryFilters,
+        chunk_id: &str,
+        limit: usize,
+    ) -> Result<Option<KeywordMatch>>;
+
+    /// The indexed content term closest to `word` by edit distance, for suggesting a
+    /// respelling. None when `word` is indexed as is or nothing is close enough.
+    fn closest_term(&self, word: &str) -> Result<Option<String>>;
+
+    /// Chunks labelled with a time range that every time filter of `filters` admits,
+    /// latest first, for a query of nothing but `time:` terms. The scope and exclusions
+    /// apply as in `search`.
+    fn timed_chunks(&self, filters: &QueryFilters, limit: usize) -> Result<Vec<(Chunk, f32)>>;
+
+    /// Every indexed chunk of `file_path`, in no particular order.
+    fn file_chunks(&self, file_path: &Path) -> Result<Vec<Chunk>>;
+
+    /// Whether any chunk of `file_path` is indexed, as of the last commit.
+    fn contains_file(&self, file_path: &Path) -> Result<bool>;
+
+    /// Every indexed content term with the number of chunks containing it.
+    fn terms(&self) -> Result<Vec<(String, u64)>>;
+
+    /// How many chunks of files under `root` are indexed, as of the last commit.
+    fn chunk_count(&self, root: &Path) -> Result<u64>;
+
+    /// How many chunks are indexed across every project, as of the last commit.
+    fn total_chunk_count(&self) -> Result<u64>;
+
+    /// Every file under `root` with indexed chunks. Files deleted since the index last
+    /// merged its segments may still be listed.
+    fn files(&self, root: &Path) -> Result<Vec<PathBuf>>;
+
+    /// Queues deletion of every chunk of `file_path`; applied on the next commit.
+    fn remove_file_chunks(&mut self, file_path: &Path) -> Result<()>;
+
+    /// Queues deletion of every chunk of the files under `root` but outside the
+    /// directories in `kept`; applied on the next commit.
+    fn remove_directory_chunks(&mut self, root: &Path, kept: &[PathBuf]) -> Result<()>;
+
+    fn commit(&mut self) -> Result<()>;
+}
@@ -1,46 +1,113 @@
-use anyhow::Result;
-use std::path::Path;
+use anyhow::{Context, Result};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
 use tantivy::{
-    Index, IndexReader, IndexWriter, ReloadPolicy,
-    collector::TopDocs,
+    Index, IndexReader, IndexWriter, ReloadPolicy, Term,
+    collector::{Count, DocSetCollector, TopDocs},
     directory::MmapDirectory,
     doc,
-    query::QueryParser,
-    schema::{Field, OwnedValue, STORED, Schema, TEXT},
+    query::{
+        BooleanQuery, BoostQuery, Occur, PhraseQuery, Query, QueryParser, RegexQuery, TermQuery,
+    },
+    schema::{
+        Field, IndexRecordOption, OwnedValue, STORED, STRING, Schema, TEXT, TextFieldIndexing,
+        TextOptions,
+    },
+    tokenizer::TokenStream,
 };
 
-use crate::types::Chunk;
+use super::exclusions::Exclusion;
+use super::filters::QueryFilters;
+use super::logs;
+use super::text_index::{KeywordMatch, TermWeight, TextIndex};
+use crate::config::ResourcesConfig;
+use crate::semantic::sparse::SparseVector;
+use crate::types::{Chunk, ChunkKind};
+
+pub const TANTIVY_DIR: &str = "index";
+const PATH_FIELD_BOOST: f32 = 0.5;
+/// Words up to this many characters may be one edit away from a suggestion, longer ones two
+const SHORT_WORD_CHARS: usize = 4;
+/// The smallest heap tantivy gives a writer thread
+const MIN_THREAD_HEAP_BYTES: usize = 15_000_000;
+/// Chunks holding a sparse query term retrieved per result, to be rescored by their
+/// stored weights. Tantivy has no per-term weights, so retrieval only sees which terms a
+/// chunk holds.
+const SPARSE_CANDIDATES_PER_RESULT: usize = 4;
 
 pub struct TextIndexer {
     index: Index,
-    writer: IndexWriter,
+    /// None when another instance holds the index; searches still see its commits
+    writer: Option<IndexWriter>,
     reader: IndexReader,
     content_field: Field,
     path_field: Field,
+    path_exact_field: Field,
     start_line_field: Field,
     end_line_field: Field,
     id_field: Field,
+    partial_field: Field,
+    section_field: Field,
+    language_field: Field,
+    symbol_field: Field,
+    kind_field: Field,
+    docs_field: Field,
+    /// Learned-sparse terms, each once, for retrieval
+    sparse_field: Field,
+    /// The learned-sparse terms with their weights, as JSON, for rescoring
+    sparse_weights_field: Field,
+    docs_boost: f32,
 }
 
 impl TextIndexer {
-    pub fn new(data_dir: &Path) -> Result<Self> {
-        let index_path = data_dir.join("index");
+    pub fn new(
+        data_dir: &Path,
+        writable: bool,
+        docs_boost: f32,
+        resources: &ResourcesConfig,
+    ) -> Result<Self> {
+        let index_path = data_dir.join(TANTIVY_DIR);
         std::fs::create_dir_all(&index_path)?;
 
         let mut schema_builder = Schema::builder();
         let content_field = schema_builder.add_text_field("content", TEXT | STORED);
         let path_field = schema_builder.add_text_field("path", TEXT | STORED);
+        let path_exact_field = schema_builder.add_text_field("path_exact", STRING);
         let start_line_field = schema_builder.add_u64_field("start_line", STORED);
         let end_line_field = schema_builder.add_u64_field("end_line", STORED);
         let id_field = schema_builder.add_text_field("id", STORED);
+        let partial_field = schema_builder.add_bool_field("partial", STORED);
+        let section_field = schema_builder.add_text_field("section", TEXT | STORED);
+        let language_field = schema_builder.add_text_field("language", STRING | STORED);
+        let symbol_field = schema_builder.add_text_field("symbol", TEXT | STORED);
+        let kind_field = schema_builder.add_text_field("kind", STRING | STORED);
+        let docs_field = schema_builder.add_text_field("docs", TEXT | STORED);
+        // Model vocabulary as is; the weights are stored apart and scored after retrieval
+        let sparse_field = schema_builder.add_text_field(
+            "sparse",
+            TextOptions::default().set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer("whitespace")
+                    .set_fieldnorms(false)
+                    .set_index_option(IndexRecordOption::Basic),
+            ),
+        );
+        let sparse_weights_field = schema_builder.add_bytes_field("sparse_weights", STORED);
         let schema = schema_builder.build();
 
         let index_dir = MmapDirectory::open(&index_path)?;
         let index = Index::open_or_create(index_dir, schema)?;
-        let writer = index.writer(200_000_000)?;
+        let (writer, reload_policy) = if writable {
+            (
+                Some(Self::open_writer(&index, resources)?),
+                ReloadPolicy::Manual,
+            )
+        } else {
+            (None, ReloadPolicy::OnCommitWithDelay)
+        };
         let reader = index
             .reader_builder()
-            .reload_policy(ReloadPolicy::Manual)
+            .reload_policy(reload_policy)
             .try_into()?;
 
         Ok(Self {
@@ -49,115 +116,644 @@ impl TextIndexer {
             reader,
             content_field,
             path_field,
+            path_exact_field,
             start_line_field,
             end_line_field,
             id_field,
+            partial_field,
+            section_field,
+            language_field,
+            symbol_field,
+            kind_field,
+            docs_field,
+            sparse_field,
+            sparse_weights_field,
+            docs_boost,
         })
     }
 
-    pub fn index_chunks(&mut self, chunks: &[Chunk]) -> Result<()> {
-        for chunk in chunks {
-            let doc = doc!(
+    /// A writer within the `[resources]` heap. Tantivy fails on a heap below its minimum
+    /// per thread, so a smaller one is raised to it rather than refused.
+    fn open_writer(index: &Index, resources: &ResourcesConfig) -> Result<IndexWriter> {
+        let heap = (resources.text_writer_heap_mb * 1_000_000).max(MIN_THREAD_HEAP_BYTES);
+        let writer = match resources.text_writer_threads {
+            0 => index.writer(heap)?,
+            threads => {
+                index.writer_with_num_threads(threads, heap.max(threads * MIN_THREAD_HEAP_BYTES))?
+            }
+        };
+        Ok(writer)
+    }
+
+    fn writer(&mut self) -> Result<&mut IndexWriter> {
+        self.writer
+            .as_mut()
+            .context("The keyword index is open read-only")
+    }
+
+    /// Chunks of the files under `root` as a whole directory, so `/src/api` doesn't take
+    /// in `/src/api2`.
+    fn directory_query(&self, root: &Path) -> Result<RegexQuery> {
+        let prefix = root.join("");
+        Ok(RegexQuery::from_pattern(
+            &format!("{}.*", regex::escape(&prefix.to_string_lossy())),
+            self.path_exact_field,
+        )?)
+    }
+
+    /// Paths and extensions match the untokenized path exactly; phrases match the tokenized
+    /// content, so case and punctuation are ignored.
+    fn exclusion_query(&self, exclusion: &Exclusion) -> Result<Option<Box<dyn Query>>> {
+        let query: Box<dyn Query> = match exclusion {
+            Exclusion::Path(path) => Box::new(RegexQuery::from_pattern(
+                &format!(".*{}.*", regex::escape(path)),
+                self.path_exact_field,
+            )?),
+            Exclusion::Extension(extension) => Box::new(RegexQuery::from_pattern(
+                &format!(".*\\.{}", regex::escape(extension)),
+                self.path_exact_field,
+            )?),
+            Exclusion::Phrase(phrase) => {
+                let mut tokenizer = self.index.tokenizer_for_field(self.content_field)?;
+                let mut stream = tokenizer.token_stream(phrase);
+                let mut terms = Vec::new();
+                stream.process(&mut |token| {
+                    terms.push(Term::from_field_text(self.content_field, &token.text))
+                });
+
+                match terms.len() {
+                    0 => return Ok(None),
+                    1 => Box::new(TermQuery::new(terms.remove(0), IndexRecordOption::Basic)),
+                    _ => Box::new(PhraseQuery::new(terms)),
+                }
+            }
+        };
+
+        Ok(Some(query))
+    }
+}
+
+impl TextIndexer {
+    /// The fields a query searches, named for explanations, with their score boosts.
+    fn search_fields(&self, docs_only: bool) -> Vec<(Field, &'static str, f32)> {
+        let docs = (self.docs_field, "docs", self.docs_boost);
+        if docs_only {
+            return vec![docs];
+        }
+        vec![
+            (self.content_field, "content", 1.0),
+            (self.section_field, "section", 1.0),
+            (self.symbol_field, "symbol", 1.0),
+            // Path tokens help rank files in relevant directories without outweighing the
+            // content
+            (self.path_field, "path", PATH_FIELD_BOOST),
+            docs,
+        ]
+    }
+
+    fn parse_query(&self, query: &str, filters: &QueryFilters) -> Result<BooleanQuery> {
+        let fields = self.search_fields(filters.docs_only);
+        let mut query_parser =
+            QueryParser::for_index(&self.index, fields.iter().map(|field| field.0).collect());
+        for (field, _, boost) in fields {
+            query_parser.set_field_boost(field, boost);
+        }
+        self.filtered_query(query_parser.parse_query(query)?, filters)
+    }
+
+    /// `query` limited to the scope and without the exclusions of `filters`.
+    fn filtered_query(
+        &self,
+        query: Box<dyn Query>,
+        filters: &QueryFilters,
+    ) -> Result<BooleanQuery> {
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![(Occur::Must, query)];
+        if let Some(scope) = &filters.scope {
+            clauses.push((
+                Occur::Must,
+                Box::new(RegexQuery::from_pattern(
+                    &scope.pattern(),
+                    self.path_exact_field,
+                )?),
+            ));
+        }
+        let kinds: Vec<&str> = filters.kinds().into_iter().map(ChunkKind::label).collect();
+        for (field, values) in [
+            (self.kind_field, kinds),
+            (self.language_field, filters.languages()),
+        ] {
+            if !values.is_empty() {
+                clauses.push((Occur::Must, Self::any_term(field, &values)));
+            }
+        }
+        for exclusion in &filters.exclusions {
+            if let Some(excluded) = self.exclusion_query(exclusion)? {
+                clauses.push((Occur::MustNot, excluded));
+            }
+        }
+        Ok(BooleanQuery::new(clauses))
+    }
+
+    /// Matches documents whose untokenized `field` is any of `values`.
+    fn any_term(field: Field, values: &[&str]) -> Box<dyn Query> {
+        let clauses: Vec<(Occur, Box<dyn Query>)> = values
+            .iter()
+            .map(|value| {
+                let query: Box<dyn Query> = Box::new(TermQuery::new(
+                    Term::from_field_text(field, value),
+                    IndexRecordOption::Basic,
+                ));
+                (Occur::Should, query)
+            })
+            .collect();
+        Box::new(BooleanQuery::new(clauses))
+    }
+
+    /// Rebuilds a chunk from the fields stored with it.
+    fn stored_chunk(&self, doc: &tantivy::TantivyDocument) -> Chunk {
+        let id = match doc.get_first(self.id_field) {
+            Some(field_value) => {
+                let value = OwnedValue::from(field_value);
+                match value {
+                    OwnedValue::Str(s) => s,
+                    _ => String::new(),
+                }
+            }
+            None => String::new(),
+        };
+
+        let path_str = match doc.get_first(self.path_field) {
+            Some(field_value) => {
+                let value = OwnedValue::from(field_value);
+                match value {
+                    OwnedValue::Str(s) => s,
+                    _ => String::new(),
+                }
+            }
+            None => String::new(),
+        };
+
+        let content = match doc.get_first(self.content_field) {
+            Some(field_value) => {
+                let value = OwnedValue::from(field_value);
+                match value {
+                    OwnedValue::Str(s) => s,
+                    _ => String::new(),
+                }
+            }
+            None => String::new(),
+        };
+
+        let start_line = match doc.get_first(self.start_line_field) {
+            Some(field_value) => {
+                let value = OwnedValue::from(field_value);
+                match value {
+                    OwnedValue::U64(n) => n as usize,
+                    _ => 0,
+                }
+            }
+            None => 0,
+        };
+
+        let end_line = match doc.get_first(self.end_line_field) {
+            Some(field_value) => {
+                let value = OwnedValue::from(field_value);
+                match value {
+                    OwnedValue::U64(n) => n as usize,
+                    _ => 0,
+                }
+            }
+            None => 0,
+        };
+
+        let partial = matches!(
+            doc.get_first(self.partial_field).map(OwnedValue::from),
+            Some(OwnedValue::Bool(true))
+        );
+
+        let stored_text = |field| match doc.get_first(field).map(OwnedValue::from) {
+            Some(OwnedValue::Str(s)) => Some(s),
+            _ => None,
+        };
+
+        Chunk {
+            id,
+            file_path: std::path::PathBuf::from(path_str),
+            start_line,
+            end_line,
+            content,
+            partial,
+            section: stored_text(self.section_field),
+            language: stored_text(self.language_field),
+            symbol: stored_text(self.symbol_field),
+            kind: stored_text(self.kind_field).and_then(|kind| ChunkKind::parse(&kind)),
+            docs: stored_text(self.docs_field),
+        }
+    }
+
+    /// The learned-sparse terms stored with a chunk; none for chunks indexed without them.
+    fn stored_sparse_weights(&self, doc: &tantivy::TantivyDocument) -> SparseVector {
+        match doc
+            .get_first(self.sparse_weights_field)
+            .map(OwnedValue::from)
+        {
+            Some(OwnedValue::Bytes(bytes)) => serde_json::from_slice(&bytes).unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// What each word of the query adds to a document's score, per field, boosts included.
+    fn term_weights(
+        &self,
+        searcher: &tantivy::Searcher,
+        query: &str,
+        docs_only: bool,
+        doc_address: tantivy::DocAddress,
+    ) -> Result<Vec<TermWeight>> {
+        let mut tokenizer = self.index.tokenizer_for_field(self.content_field)?;
+        let mut stream = tokenizer.token_stream(query);
+        let mut words: Vec<String> = Vec::new();
+        stream.process(&mut |token| {
+            if !words.contains(&token.text) {
+                words.push(token.text.clone());
+            }
+        });
+
+        let mut weights = Vec::new();
+        for word in &words {
+            for (field, name, boost) in self.search_fields(docs_only) {
+                let term_query = TermQuery::new(
+                    Term::from_field_text(field, word),
+                    IndexRecordOption::WithFreqs,
+                );
+                // Fails for documents the term doesn't match
+                if let Ok(explanation) = term_query.explain(searcher, doc_address)
+                    && explanation.value() > 0.0
+                {
+                    weights.push(TermWeight {
+                        term: word.clone(),
+                        field: name,
+                        weight: explanation.value() * boost,
+                    });
+                }
+            }
+        }
+        weights.sort_by(|a, b| b.weight.total_cmp(&a.weight));
+        Ok(weights)
+    }
+}
+
+impl TextIndex for TextIndexer {
+    #[tracing::instrument(skip_all, fields(chunks = chunks.len()))]
+    fn index_chunks(&mut self, chunks: &[Chunk], sparse: &[Option<SparseVector>]) -> Result<()> {
+        for (index, chunk) in chunks.iter().enumerate() {
+            let mut doc = doc!(
                 self.content_field => chunk.content.clone(),
                 self.path_field => chunk.file_path.to_string_lossy().to_string(),
+                self.path_exact_field => chunk.file_path.to_string_lossy().to_string(),
                 self.start_line_field => chunk.start_line as u64,
                 self.end_line_field => chunk.end_line as u64,
                 self.id_field => chunk.id.clone(),
+                self.partial_field => chunk.partial,
             );
-            self.writer.add_document(doc)?;
+            if let Some(section) = &chunk.section {
+                doc.add_text(self.section_field, section);
+            }
+            if let Some(language) = &chunk.language {
+                doc.add_text(self.language_field, language);
+            }
+            if let Some(symbol) = &chunk.symbol {
+                doc.add_text(self.symbol_field, symbol);
+            }
+            if let Some(kind) = chunk.kind {
+                doc.add_text(self.kind_field, kind.label());
+            }
+            if let Some(docs) = &chunk.docs {
+                doc.add_text(self.docs_field, docs);
+            }
+            if let Some(Some(terms)) = sparse.get(index) {
+                let text: Vec<&str> = terms.iter().map(|(term, _)| term.as_str()).collect();
+                doc.add_text(self.sparse_field, text.join(" "));
+                doc.add_bytes(self.sparse_weights_field, &serde_json::to_vec(terms)?);
+            }
+            self.writer()?.add_document(doc)?;
         }
 
-        self.writer.commit()?;
+        self.writer()?.commit()?;
         self.reader.reload()?;
         Ok(())
     }
 
-    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<(Chunk, f32)>> {
+    #[tracing::instrument(skip(self, filters))]
+    fn search(
+        &self,
+        query: &str,
+        filters: &QueryFilters,
+        limit: usize,
+    ) -> Result<Vec<(Chunk, f32)>> {
         if query.is_empty() {
             return Ok(Vec::new());
         }
 
         let searcher = self.reader.searcher();
-        let query_parser = QueryParser::for_index(&self.index, vec![self.content_field]);
-        let parsed_query = query_parser.parse_query(query)?;
+        let parsed_query = self.parse_query(query, filters)?;
         let top_docs = searcher.search(&parsed_query, &TopDocs::with_limit(limit))?;
 
         let mut results = Vec::new();
         for (score, doc_address) in top_docs {
             let doc = searcher.doc::<tantivy::TantivyDocument>(doc_address)?;
+            results.push((self.stored_chunk(&doc), score));
+        }
 
-            let id = match doc.get_first(self.id_field) {
-                Some(field_value) => {
-                    let value = OwnedValue::from(field_value);
-                    match value {
-                        OwnedValue::Str(s) => s,
-                        _ => String::new(),
-                    }
+        Ok(results)
+    }
+
+    fn matching_chunks(&self, query: &str, filters: &QueryFilters) -> Result<Vec<Chunk>> {
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let searcher = self.reader.searcher();
+        let parsed_query = self.parse_query(query, filters)?;
+        let mut chunks = Vec::new();
+        for doc_address in searcher.search(&parsed_query, &DocSetCollector)? {
+            let doc = searcher.doc::<tantivy::TantivyDocument>(doc_address)?;
+            chunks.push(self.stored_chunk(&doc));
+        }
+        Ok(chunks)
+    }
+
+    fn sparse_search(
+        &self,
+        terms: &SparseVector,
+        filters: &QueryFilters,
+        limit: usize,
+    ) -> Result<Vec<(Chunk, f32)>> {
+        // Comments and docstrings are a channel of their own, which sparse terms don't cover
+        if terms.is_empty() || filters.docs_only {
+            return Ok(Vec::new());
+        }
+
+        let clauses: Vec<(Occur, Box<dyn Query>)> = terms
+            .iter()
+            .map(|(term, weight)| {
+                let term_query = TermQuery::new(
+                    Term::from_field_text(self.sparse_field, term),
+                    IndexRecordOption::Basic,
+                );
+                // Candidates holding the query's heavier terms come first
+                let query: Box<dyn Query> =
+                    Box::new(BoostQuery::new(Box::new(term_query), *weight));
+                (Occur::Should, query)
+            })
+            .collect();
+        let query = self.filtered_query(Box::new(BooleanQuery::new(clauses)), filters)?;
+
+        let searcher = self.reader.searcher();
+        let candidates = limit * SPARSE_CANDIDATES_PER_RESULT;
+        let mut results = Vec::new();
+        for (_, doc_address) in searcher.search(&query, &TopDocs::with_limit(candidates))? {
+            let doc = searcher.doc::<tantivy::TantivyDocument>(doc_address)?;
+            let score = sparse_dot(terms, &self.stored_sparse_weights(&doc));
+            results.push((self.stored_chunk(&doc), score));
+        }
+        results.sort_by(|a, b| b.1.total_cmp(&a.1));
+        results.truncate(limit);
+        Ok(results)
+    }
+
+    fn explain(
+        &self,
+        query: &str,
+        filters: &QueryFilters,
+        chunk_id: &str,
+        limit: usize,
+    ) -> Result<Option<KeywordMatch>> {
+        if query.is_empty() {
+            return Ok(None);
+        }
+
+        let searcher = self.reader.searcher();
+        let parsed_query = self.parse_query(query, filters)?;
+        let top_docs = searcher.search(&parsed_query, &TopDocs::with_limit(limit))?;
+        let top_score = top_docs.first().map_or(0.0, |(score, _)| *score);
+
+        for (rank, (score, doc_address)) in top_docs.into_iter().enumerate() {
+            let doc = searcher.doc::<tantivy::TantivyDocument>(doc_address)?;
+            let is_chunk = matches!(
+                doc.get_first(self.id_field).map(OwnedValue::from),
+                Some(OwnedValue::Str(id)) if id == chunk_id
+            );
+            if is_chunk {
+                return Ok(Some(KeywordMatch {
+                    rank: rank + 1,
+                    score,
+                    top_score,
+                    terms: self.term_weights(&searcher, query, filters.docs_only, doc_address)?,
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn timed_chunks(&self, filters: &QueryFilters, limit: usize) -> Result<Vec<(Chunk, f32)>> {
+        // Time range labels start with a year, so only sections holding one are read
+        let timed = RegexQuery::from_pattern("[12][0-9]{3}", self.section_field)?;
+        let query = self.filtered_query(Box::new(timed), filters)?;
+
+        let searcher = self.reader.searcher();
+        let mut chunks = Vec::new();
+        for doc_address in searcher.search(&query, &DocSetCollector)? {
+            let chunk = self.stored_chunk(&searcher.doc::<tantivy::TantivyDocument>(doc_address)?);
+            let section = chunk.section.as_deref();
+            if let Some(latest) = section.and_then(logs::latest)
+                && filters.time.iter().all(|filter| filter.matches(section))
+            {
+                chunks.push((latest.to_string(), chunk));
+            }
+        }
+        chunks.sort_by(|a, b| b.0.cmp(&a.0));
+        Ok(chunks
+            .into_iter()
+            .take(limit)
+            .map(|(_, chunk)| (chunk, 1.0))
+            .collect())
+    }
+
+    fn file_chunks(&self, file_path: &Path) -> Result<Vec<Chunk>> {
+        let searcher = self.reader.searcher();
+        let term = Term::from_field_text(self.path_exact_field, &file_path.to_string_lossy());
+        // Deleted chunks still count until merged away, so this is enough to hold them all
+        let limit = (searcher.doc_freq(&term)? as usize).max(1);
+        let query = TermQuery::new(term, IndexRecordOption::Basic);
+
+        let mut chunks = Vec::new();
+        for (_, doc_address) in searcher.search(&query, &TopDocs::with_limit(limit))? {
+            let doc = searcher.doc::<tantivy::TantivyDocument>(doc_address)?;
+            chunks.push(self.stored_chunk(&doc));
+        }
+        Ok(chunks)
+    }
+
+    fn contains_file(&self, file_path: &Path) -> Result<bool> {
+        let term = Term::from_field_text(self.path_exact_field, &file_path.to_string_lossy());
+        let query = TermQuery::new(term, IndexRecordOption::Basic);
+        Ok(self.reader.searcher().search(&query, &Count)? > 0)
+    }
+
+    fn closest_term(&self, word: &str) -> Result<Option<String>> {
+        let searcher = self.reader.searcher();
+        let word = word.to_lowercase();
+        if searcher.doc_freq(&Term::from_field_text(self.content_field, &word))? > 0 {
+            return Ok(None);
+        }
+        let Some(first) = word.chars().next() else {
+            return Ok(None);
+        };
+        let max_distance = if word.chars().count() <= SHORT_WORD_CHARS {
+            1
+        } else {
+            2
+        };
+
+        // Misspellings rarely get the first letter wrong, so only that part of each
+        // segment's dictionary is scanned
+        let prefix = first.to_string();
+        let mut best: Option<(usize, u32, String)> = None;
+        for segment in searcher.segment_readers() {
+            let inverted_index = segment.inverted_index(self.content_field)?;
+            let mut terms = inverted_index.terms().range().ge(&prefix).into_stream()?;
+            while terms.advance() {
+                let Ok(candidate) = std::str::from_utf8(terms.key()) else {
+                    continue;
+                };
+                if !candidate.starts_with(&prefix) {
+                    break;
                 }
-                None => String::new(),
-            };
-
-            let path_str = match doc.get_first(self.path_field) {
-                Some(field_value) => {
-                    let value = OwnedValue::from(field_value);
-                    match value {
-                        OwnedValue::Str(s) => s,
-                        _ => String::new(),
-                    }
+                if candidate.len().abs_diff(word.len()) > max_distance {
+                    continue;
                 }
-                None => String::new(),
-            };
-
-            let content = match doc.get_first(self.content_field) {
-                Some(field_value) => {
-                    let value = OwnedValue::from(field_value);
-                    match value {
-                        OwnedValue::Str(s) => s,
-                        _ => String::new(),
+
+                let distance = edit_distance(&word, candidate);
+                let doc_freq = terms.value().doc_freq;
+                let better = match &best {
+                    None => true,
+                    Some((best_distance, best_freq, _)) => {
+                        (distance, std::cmp::Reverse(doc_freq))
+                            < (*best_distance, std::cmp::Reverse(*best_freq))
                     }
+                };
+                if distance <= max_distance && better {
+                    best = Some((distance, doc_freq, candidate.to_string()));
                 }
-                None => String::new(),
-            };
-
-            let start_line = match doc.get_first(self.start_line_field) {
-                Some(field_value) => {
-                    let value = OwnedValue::from(field_value);
-                    match value {
-                        OwnedValue::U64(n) => n as usize,
-                        _ => 0,
-                    }
+            }
+        }
+
+        Ok(best.map(|(_, _, term)| term))
+    }
+
+    fn terms(&self) -> Result<Vec<(String, u64)>> {
+        let mut doc_freqs: HashMap<String, u64> = HashMap::new();
+        for segment in self.reader.searcher().segment_readers() {
+            let inverted_index = segment.inverted_index(self.content_field)?;
+            let mut terms = inverted_index.terms().stream()?;
+            while terms.advance() {
+                if let Ok(term) = std::str::from_utf8(terms.key()) {
+                    *doc_freqs.entry(term.to_string()).or_default() +=
+                        u64::from(terms.value().doc_freq);
                 }
-                None => 0,
-            };
-
-            let end_line = match doc.get_first(self.end_line_field) {
-                Some(field_value) => {
-                    let value = OwnedValue::from(field_value);
-                    match value {
-                        OwnedValue::U64(n) => n as usize,
-                        _ => 0,
-                    }
+            }
+        }
+
+        Ok(doc_freqs.into_iter().collect())
+    }
+
+    fn chunk_count(&self, root: &Path) -> Result<u64> {
+        let query = self.directory_query(root)?;
+        Ok(self.reader.searcher().search(&query, &Count)? as u64)
+    }
+
+    fn total_chunk_count(&self) -> Result<u64> {
+        Ok(self.reader.searcher().num_docs())
+    }
+
+    fn files(&self, root: &Path) -> Result<Vec<PathBuf>> {
+        let prefix = root.join("");
+        let prefix = prefix.to_string_lossy();
+        let mut files = BTreeSet::new();
+        for segment in self.reader.searcher().segment_readers() {
+            let inverted_index = segment.inverted_index(self.path_exact_field)?;
+            let mut terms = inverted_index
+                .terms()
+                .range()
+                .ge(prefix.as_bytes())
+                .into_stream()?;
+            // Terms are sorted, so the paths under `root` come in one run
+            while terms.advance() {
+                let Ok(path) = std::str::from_utf8(terms.key()) else {
+                    continue;
+                };
+                if !path.starts_with(prefix.as_ref()) {
+                    break;
                 }
-                None => 0,
-            };
-
-            results.push((
-                Chunk {
-                    id,
-                    file_path: std::path::PathBuf::from(path_str),
-                    start_line,
-                    end_line,
-                    content,
-                },
-                score,
-            ));
+                files.insert(PathBuf::from(path));
+            }
         }
+        Ok(files.into_iter().collect())
+    }
 
-        Ok(results)
+    fn remove_file_chunks(&mut self, file_path: &Path) -> Result<()> {
+        let term = Term::from_field_text(self.path_exact_field, &file_path.to_string_lossy());
+        self.writer()?.delete_term(term);
+        Ok(())
     }
 
-    pub fn commit(&mut self) -> Result<()> {
-        self.writer.commit()?;
+    fn remove_directory_chunks(&mut self, root: &Path, kept: &[PathBuf]) -> Result<()> {
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> =
+            vec![(Occur::Must, Box::new(self.directory_query(root)?))];
+        for kept in kept {
+            clauses.push((Occur::MustNot, Box::new(self.directory_query(kept)?)));
+        }
+        self.writer()?
+            .delete_query(Box::new(BooleanQuery::new(clauses)))?;
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        if let Some(writer) = &mut self.writer {
+            writer.commit()?;
+        }
+        self.reader.reload()?;
         Ok(())
     }
 }
+
+/// The dot product of a query's sparse terms with a chunk's, term by term.
+fn sparse_dot(query: &SparseVector, chunk: &SparseVector) -> f32 {
+    let chunk: HashMap<&str, f32> = chunk
+        .iter()
+        .map(|(term, weight)| (term.as_str(), *weight))
+        .collect();
+    query
+        .iter()
+        .filter_map(|(term, weight)| Some(weight * chunk.get(term.as_str())?))
+        .sum()
+}
+
+/// Levenshtein distance between two words, by character.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, &b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
@@ -0,0 +1,147 @@
+use tokenizers::Tokenizer;
+
+use super::vector_index::embedding_text;
+use crate::semantic::embeddings::Embedder;
+use crate::types::Chunk;
+
+/// Tokens repeated at the start of a piece from the end of the one before, so text cut
+/// between two pieces is still seen whole in one of them
+const OVERLAP_TOKENS: usize = 32;
+/// Content tokens each piece gets however long the file path and section above it are
+const MIN_WINDOW_TOKENS: usize = 64;
+
+/// Splits chunks too long for the embedding model into pieces it reads whole. A longer
+/// chunk is embedded as the average of its windows, which blurs what each part is about
+/// and points results at more lines than match.
+#[derive(Debug, Clone)]
+pub struct TokenBudget {
+    tokenizer: Tokenizer,
+    /// Tokens the model reads, special tokens included
+    max_tokens: usize,
+    document_prompt: String,
+}
+
+impl TokenBudget {
+    /// The budget of the embedder's model, loading it if it isn't yet; `None` when it
+    /// can't be loaded.
+    pub async fn load(embedder: &mut Embedder) -> Option<Self> {
+        let vector_store = embedder.vector_store().await.ok()?;
+        Some(Self {
+            tokenizer: vector_store.tokenizer().clone(),
+            max_tokens: vector_store.max_tokens(),
+            document_prompt: embedder.document_text(""),
+        })
+    }
+
+    /// The chunk whole if the model reads all of it, or else pieces that each fit, split
+    /// at line ends where possible and overlapping by `OVERLAP_TOKENS`.
+    pub fn fit(&self, chunk: Chunk) -> Vec<Chunk> {
+        let text = embedding_text(&chunk);
+        // The content comes last, after the file path and section
+        let header = format!(
+            "{}{}",
+            self.document_prompt,
+            &text[..text.len() - chunk.content.len()]
+        );
+        // Every token covers at least a byte, so short chunks fit without being counted
+        if header.len() + chunk.content.len() + 2 <= self.max_tokens
+            || self.count(&header, &chunk.content) <= self.max_tokens
+        {
+            return vec![chunk];
+        }
+
+        let Ok(encoding) = self.tokenizer.encode(chunk.content.as_str(), false) else {
+            return vec![chunk];
+        };
+        let offsets = encoding.get_offsets();
+        let window = self
+            .max_tokens
+            .saturating_sub(self.count(&header, ""))
+            .max(MIN_WINDOW_TOKENS);
+        let content = chunk.content.as_str();
+
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        while start < content.len() {
+            let first = offsets.partition_point(|(token_start, _)| *token_start < start);
+
+            // Text tokenized on its own can split slightly differently at its edges, so
+            // the piece shrinks until it's counted within the budget
+            let mut tokens = window;
+            let mut end = piece_end(content, offsets, start, first, tokens);
+            loop {
+                let excess = self
+                    .count(&header, &content[start..end])
+                    .saturating_sub(self.max_tokens);
+                if excess == 0 || tokens <= MIN_WINDOW_TOKENS {
+                    break;
+                }
+                tokens = tokens.saturating_sub(excess).max(MIN_WINDOW_TOKENS);
+                end = piece_end(content, offsets, start, first, tokens);
+            }
+
+            let start_line = chunk.start_line + content[..start].matches('\n').count();
+            let piece = &content[start..end];
+            pieces.push(Chunk {
+                start_line,
+                end_line: start_line + piece.matches('\n').count(),
+                content: piece.to_string(),
+                ..chunk.clone()
+            });
+
+            if end >= content.len() {
+                break;
+            }
+            start = next_start(content, offsets, start, end);
+        }
+        pieces
+    }
+
+    fn count(&self, header: &str, content: &str) -> usize {
+        self.tokenizer
+            .encode(format!("{}{}", header, content), true)
+            .map_or(0, |encoding| encoding.len())
+    }
+}
+
+/// Where a piece starting at byte `start`, whose first token is `first`, ends to hold at
+/// most `tokens` of them: after the last line end in the second half of the piece, or
+/// mid-line when a single line is longer than that.
+fn piece_end(
+    content: &str,
+    offsets: &[(usize, usize)],
+    start: usize,
+    first: usize,
+    tokens: usize,
+) -> usize {
+    let Some(&(cut, _)) = offsets.get(first + tokens) else {
+        return content.len();
+    };
+    if cut <= start {
+        // Tokens of a single character share its offsets
+        return offsets
+            .get(first)
+            .map(|&(_, token_end)| token_end)
+            .filter(|token_end| *token_end > start)
+            .unwrap_or(content.len());
+    }
+
+    match content[start..cut].rfind('\n') {
+        Some(newline) if newline + 1 > (cut - start) / 2 => start + newline + 1,
+        _ => cut,
+    }
+}
+
+/// Where the piece after one spanning `start..end` begins: `OVERLAP_TOKENS` before `end`,
+/// moved on to the next line start within the overlap if there is one.
+fn next_start(content: &str, offsets: &[(usize, usize)], start: usize, end: usize) -> usize {
+    let last = offsets.partition_point(|(token_start, _)| *token_start < end);
+    let overlap_start = offsets
+        .get(last.saturating_sub(OVERLAP_TOKENS))
+        .map_or(end, |&(token_start, _)| token_start.min(end));
+    let next = match content[overlap_start..end].find('\n') {
+        Some(newline) if overlap_start + newline + 1 < end => overlap_start + newline + 1,
+        _ => overlap_start,
+    };
+    if next > start { next } else { end }
+}
@@ -0,0 +1,138 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+use tokio_util::sync::CancellationToken;
+
+use super::filters::QueryFilters;
+use super::tokens::TokenBudget;
+use crate::semantic::embeddings::{Embedder, EmbeddingJob};
+use crate::types::{Chunk, FileIndex};
+
+const EMBEDDED_PATH_COMPONENTS: usize = 3;
+
+/// A store of chunk embeddings. LanceDB keeps the index on local disk; Qdrant lets a team
+/// share one server-hosted index.
+#[async_trait]
+pub trait VectorIndex: Send + Sync {
+    /// Gets the chunks ready to embed, loading the model if it isn't yet. What the index
+    /// can store without embedding, such as chunks whose content it already holds, is
+    /// stored now and left out of the pending chunks.
+    async fn prepare_chunks(&mut self, chunks: &[Chunk]) -> Result<PendingChunks>;
+
+    /// Stores chunks embedded from `prepare_chunks`.
+    async fn store_chunks(&mut self, embedded: EmbeddedChunks) -> Result<()>;
+
+    /// The embedding model, loaded under the storage lock and cloned to embed queries
+    /// without it.
+    fn embedder(&mut self) -> &mut Embedder;
+
+    /// Merges the nearest neighbours of each query embedding, returning chunks paired with
+    /// their cosine similarity in `0.0..=1.0`. Only chunks in the scope of `filters` are
+    /// searched.
+    async fn search_embeddings(
+        &mut self,
+        embeddings: &[Vec<f32>],
+        filters: &QueryFilters,
+        limit: usize,
+    ) -> Result<Vec<(Chunk, f32)>>;
+
+    /// Why the embedding model is unavailable, once loading it has failed.
+    fn embedding_error(&self) -> Option<&str>;
+
+    /// What chunks must fit in to be embedded whole, loading the model if it isn't yet;
+    /// `None` when it can't be loaded.
+    async fn token_budget(&mut self) -> Option<TokenBudget>;
+
+    /// Reorders the final candidates of a semantic or hybrid search by late interaction
+    /// with the query's token vectors, when the index stores them; the rest return the
+    /// candidates as they are.
+    async fn rerank(
+        &mut self,
+        _query_tokens: &[Vec<f32>],
+        results: Vec<(Chunk, f32)>,
+    ) -> Result<Vec<(Chunk, f32)>> {
+        Ok(results)
+    }
+
+    /// Cosine similarity between the query and each line; blank lines score zero.
+    async fn line_similarities(&mut self, query: &str, lines: &[String]) -> Result<Vec<f32>>;
+
+    async fn get_file_index(&self, file_path: &Path) -> Result<Option<FileIndex>>;
+
+    async fn update_file_index(&mut self, file_index: &FileIndex) -> Result<()>;
+
+    /// The files under `root` in the file index, for listing a project before its crawl
+    /// finishes. Indexes that can't list them without reading every chunk list none.
+    async fn indexed_files(&self, _root: &Path) -> Result<Vec<PathBuf>> {
+        Ok(Vec::new())
+    }
+
+    async fn remove_file_chunks(&mut self, file_path: &Path) -> Result<()>;
+
+    /// Removes the chunks of every file under `directory` but outside the directories in
+    /// `kept`, which the keyword index lists as `files`. Indexes able to select by path
+    /// prefix needn't go file by file.
+    async fn remove_directory_chunks(
+        &mut self,
+        _directory: &Path,
+        _kept: &[PathBuf],
+        files: &[PathBuf],
+    ) -> Result<()> {
+        for file in files {
+            self.remove_file_chunks(file).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Chunks waiting to be embedded for a vector index.
+pub struct PendingChunks {
+    chunks: Vec<Chunk>,
+    job: EmbeddingJob,
+}
+
+impl PendingChunks {
+    pub fn new(chunks: Vec<Chunk>, job: EmbeddingJob) -> Self {
+        Self { chunks, job }
+    }
+
+    /// Embeds the chunks. This needs nothing from the index, so it runs without the storage
+    /// lock; it gives up once `cancel` fires.
+    pub async fn embed(self, cancel: &CancellationToken) -> Result<EmbeddedChunks> {
+        Ok(EmbeddedChunks {
+            embeddings: self.job.run(cancel).await?,
+            chunks: self.chunks,
+        })
+    }
+}
+
+pub struct EmbeddedChunks {
+    pub chunks: Vec<Chunk>,
+    /// The embedding of each chunk, `None` where it failed, with its token vectors when the
+    /// index keeps them
+    pub embeddings: Vec<Option<(Vec<f32>, Vec<Vec<f32>>)>>,
+}
+
+/// The text embedded for a chunk. The file's location and section path are embedded with the
+/// content so directory names, the filename, headings and keys all inform the match.
+pub fn embedding_text(chunk: &Chunk) -> String {
+    let mut text = format!("file: {}\n", path_tail(&chunk.file_path));
+    if let Some(section) = &chunk.section {
+        text.push_str(section);
+        text.push('\n');
+    }
+    text.push_str(&chunk.content);
+    text
+}
+
+/// The last few components of a path, e.g. `src/tui/engine.rs`. Leading directories such as
+/// the home directory are the same for every file and would only dilute the embedding.
+fn path_tail(path: &Path) -> String {
+    let components: Vec<_> = path.components().collect();
+    let start = components.len().saturating_sub(EMBEDDED_PATH_COMPONENTS);
+    components[start..]
+        .iter()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
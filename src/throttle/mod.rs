@@ -0,0 +1,176 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::IndexingConfig;
+
+/// How long a reading of the power source and system load is trusted.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Why indexing runs with fewer embedding workers and smaller batches than configured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThrottleReason {
+    Battery,
+    /// One-minute load average per core, not counting the embedding workers
+    Load(f64),
+}
+
+impl ThrottleReason {
+    pub fn label(self) -> String {
+        match self {
+            Self::Battery => "on battery".to_string(),
+            Self::Load(load) => format!("load {:.1}/core", load),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Settings {
+    max_load: f64,
+    jobs: usize,
+    batch: usize,
+}
+
+struct Throttle {
+    /// None while throttling is off
+    settings: Option<Settings>,
+    sampled: Option<(Instant, Option<ThrottleReason>)>,
+    /// Embedding workers last started, whose own load is discounted
+    workers: usize,
+}
+
+static THROTTLE: Mutex<Throttle> = Mutex::new(Throttle {
+    settings: None,
+    sampled: None,
+    workers: 0,
+});
+
+/// Takes the throttling settings of `[indexing]`.
+pub fn configure(config: &IndexingConfig) {
+    if let Ok(mut throttle) = THROTTLE.lock() {
+        throttle.settings = config.throttle.then_some(Settings {
+            max_load: config.throttle_load,
+            jobs: config.throttled_jobs.max(1),
+            batch: config.throttled_batch.max(1),
+        });
+        throttle.sampled = None;
+    }
+}
+
+/// Why indexing is throttled as of the last reading, without taking a new one.
+pub fn current() -> Option<ThrottleReason> {
+    let throttle = THROTTLE.lock().ok()?;
+    throttle.settings?;
+    throttle.sampled?.1
+}
+
+/// How many of `workers` embedding workers to start now.
+pub fn workers(workers: usize) -> usize {
+    let Ok(mut throttle) = THROTTLE.lock() else {
+        return workers;
+    };
+    let workers = match (throttle.reason(), throttle.settings) {
+        (Some(_), Some(settings)) => workers.min(settings.jobs),
+        _ => workers,
+    };
+    throttle.workers = workers;
+    workers
+}
+
+/// How many chunks to embed and commit per batch now, given the configured `batch`.
+pub fn batch_size(batch: usize) -> usize {
+    let Ok(mut throttle) = THROTTLE.lock() else {
+        return batch;
+    };
+    match (throttle.reason(), throttle.settings) {
+        (Some(_), Some(settings)) => batch.min(settings.batch),
+        _ => batch,
+    }
+}
+
+impl Throttle {
+    fn reason(&mut self) -> Option<ThrottleReason> {
+        let settings = self.settings?;
+        if let Some((sampled_at, reason)) = self.sampled
+            && sampled_at.elapsed() < SAMPLE_INTERVAL
+        {
+            return reason;
+        }
+
+        let reason = if on_battery() {
+            Some(ThrottleReason::Battery)
+        } else {
+            load_average().and_then(|load| {
+                // The embedding workers are busy themselves and would throttle their own pool
+                let cores = num_cpus::get() as f64;
+                let load = (load - self.workers as f64).max(0.0) / cores;
+                (load > settings.max_load).then_some(ThrottleReason::Load(load))
+            })
+        };
+        if reason != self.sampled.and_then(|(_, reason)| reason) {
+            tracing::info!(?reason, "Indexing throttle changed");
+        }
+        self.sampled = Some((Instant::now(), reason));
+        reason
+    }
+}
+
+/// Whether the machine runs on battery, as far as the platform tells.
+fn on_battery() -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        let Ok(supplies) = std::fs::read_dir("/sys/class/power_supply") else {
+            return false;
+        };
+        let mut discharging = false;
+        let mut plugged_in = false;
+        for supply in supplies.flatten() {
+            let path = supply.path();
+            let read = |name: &str| {
+                std::fs::read_to_string(path.join(name))
+                    .map(|value| value.trim().to_string())
+                    .unwrap_or_default()
+            };
+            match read("type").as_str() {
+                "Battery" => discharging |= read("status") == "Discharging",
+                "Mains" | "USB" => plugged_in |= read("online") == "1",
+                _ => {}
+            }
+        }
+        discharging && !plugged_in
+    }
+    #[cfg(target_os = "macos")]
+    {
+        std::process::Command::new("pmset")
+            .args(["-g", "batt"])
+            .output()
+            .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains("'Battery Power'"))
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        false
+    }
+}
+
+/// The one-minute load average, where the platform reports one.
+fn load_average() -> Option<f64> {
+    #[cfg(target_os = "linux")]
+    {
+        let loadavg = std::fs::read_to_string("/proc/loadavg").ok()?;
+        loadavg.split_whitespace().next()?.parse().ok()
+    }
+    #[cfg(target_os = "macos")]
+    {
+        // Prints "{ 1.52 1.61 1.70 }"
+        let output = std::process::Command::new("sysctl")
+            .args(["-n", "vm.loadavg"])
+            .output()
+            .ok()?;
+        String::from_utf8_lossy(&output.stdout)
+            .split_whitespace()
+            .find_map(|field| field.parse().ok())
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        None
+    }
+}
@@ -1,48 +1,113 @@
 use anyhow::Result;
 use ratatui::crossterm::{
     event::{
-        self, DisableMouseCapture, EnableMouseCapture, Event, KeyEventKind, MouseButton,
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, MouseButton,
         MouseEventKind,
     },
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
 use ratatui::{Terminal, backend::CrosstermBackend};
-use std::io;
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 
-use crate::config::Config;
-use crate::crawler::FileCrawler;
-use crate::storage::StorageManager;
+use crate::config::{Config, ConfigManager};
+use crate::daemon::DaemonClient;
+use crate::export;
+use crate::semantic::embeddings;
+use crate::storage::annotations::ANNOTATION_QUERY;
+use crate::types::{Chunk, SearchResult};
 
-use super::engine::Engine;
+use super::config_reload::{ConfigChange, ConfigLoader, ConfigWatcher};
+use super::engine::{ASK_PREFIX, Engine, QUERY_SEPARATOR, unix_now};
 use super::events::{EventHandler, EventResult};
+use super::indexing::{IndexChoice, IndexingTask};
+use super::notifications::Level;
 use super::ui::UI;
 
+const EXPORT_FILE_NAME: &str = "sema-results.md";
+const DEFAULT_RESULTS_FILE_NAME: &str = "sema-results.csv";
+const QUICKFIX_FILE_NAME: &str = "sema-quickfix.txt";
+/// Typed as a query, lists TODO, FIXME and HACK comments instead of searching
+const ANNOTATIONS_SHORTCUT: &str = ":todo";
 const POLL_INTERVAL_MS: u64 = 100;
 const SPINNER_UPDATE_INTERVAL_MS: u64 = 100;
 
 pub struct App {
     engine: Engine,
+    print_on_exit: bool,
+    output: Option<PathBuf>,
+    /// Searched as soon as the index can answer, e.g. from `-q`
+    initial_query: Option<String>,
+    config_watcher: Option<ConfigWatcher>,
 }
 
 impl App {
     pub fn new_with_directory(directory: PathBuf, config: Config) -> Result<Self> {
         let engine = Engine::new(directory, config);
 
-        Ok(Self { engine })
+        Ok(Self {
+            engine,
+            print_on_exit: false,
+            output: None,
+            initial_query: None,
+            config_watcher: None,
+        })
+    }
+
+    pub fn with_print_on_exit(mut self, enabled: bool) -> Self {
+        self.print_on_exit = enabled;
+        self
+    }
+
+    pub fn with_output(mut self, output: Option<PathBuf>) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Searches these queries together once the index opens, as if typed separated by `;`.
+    pub fn with_initial_queries(mut self, queries: &[String]) -> Self {
+        let separator = format!("{} ", QUERY_SEPARATOR);
+        self.initial_query = Some(queries.join(&separator)).filter(|query| !query.is_empty());
+        self
+    }
+
+    /// Applies edits to `config_file` while running, reading it again with `load`.
+    pub fn with_config_reload(mut self, config_file: PathBuf, load: ConfigLoader) -> Self {
+        self.config_watcher = Some(ConfigWatcher::new(
+            config_file,
+            &self.engine.root_path,
+            load,
+        ));
+        self
+    }
+
+    /// Searches every indexed project rather than just this one, as `--all-projects` does.
+    pub fn with_all_projects(mut self, enabled: bool) -> Self {
+        self.engine.all_projects = enabled;
+        self
+    }
+
+    /// Indexes these files along with the crawled directory, e.g. captured stdin.
+    pub fn with_extra_files(mut self, files: Vec<PathBuf>) -> Self {
+        self.engine.crawler_config.extra_files = files;
+        self
     }
 
     pub async fn run(&mut self) -> Result<()> {
         enable_raw_mode()?;
-        let mut stdout = io::stdout();
-        execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-        let backend = CrosstermBackend::new(stdout);
+        // Draw on stderr so stdout stays free for output printed on exit
+        let mut stderr = io::stderr();
+        execute!(stderr, EnterAlternateScreen, EnableMouseCapture)?;
+        let backend = CrosstermBackend::new(stderr);
         let mut terminal = Terminal::new(backend)?;
 
         let result = self.run_main_loop(&mut terminal).await;
 
+        let _ = self.engine.inline_images.clear(terminal.backend_mut());
         disable_raw_mode()?;
         execute!(
             terminal.backend_mut(),
@@ -51,55 +116,194 @@ impl App {
         )?;
         terminal.show_cursor()?;
 
+        // Printed once the alternate screen is gone, on stderr like the interface itself
+        if let Some(service) = &self.engine.processing_service
+            && let Some(profile) = service.lock().await.index_profile()
+        {
+            eprintln!("{}", profile.report());
+        }
+
+        let output = match self.engine.exit_output.take() {
+            Some(output) => Some(output),
+            None if self.print_on_exit => self.selected_location(),
+            None => None,
+        };
+        if let Some(output) = output {
+            println!("{}", output);
+        }
+
+        if let Some(path) = &self.output
+            && !self.engine.search_results.is_empty()
+        {
+            let results: Vec<&SearchResult> = self.engine.search_results.iter().collect();
+            export::write_results(
+                path,
+                &self.engine.current_search_query,
+                &results,
+                &self.engine.root_path,
+            )?;
+        }
+
+        if let Some(mut command) = self.engine.exit_command.take() {
+            command.status()?;
+        }
+
         result
     }
 
-    async fn run_main_loop<B: ratatui::backend::Backend>(
+    async fn run_main_loop<B: ratatui::backend::Backend + Write>(
         &mut self,
         terminal: &mut Terminal<B>,
     ) -> Result<()> {
         let mut last_tick = Instant::now();
 
-        let config_dir = match dirs::config_dir() {
-            Some(dir) => dir,
-            None => match std::env::current_dir() {
-                Ok(dir) => dir,
-                Err(_) => PathBuf::from("."),
-            },
-        }
-        .join("sema");
+        let shutdown = CancellationToken::new();
+        let signal_shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                signal_shutdown.cancel();
+            }
+        });
 
         self.engine.state = crate::types::AppState::Crawling;
-        terminal.draw(|f| UI::render(f, &mut self.engine))?;
+        self.draw(terminal)?;
 
-        let crawler = FileCrawler::new(self.engine.crawler_config.clone());
-        let files = crawler.crawl_directory(&self.engine.root_path).await?;
+        // A running daemon already has the indexes open, so only crawl for the file finder
+        self.engine.daemon = DaemonClient::connect(&self.engine.root_path).await;
+        let mut indexing = Some(IndexingTask::spawn(
+            self.engine.root_path.clone(),
+            self.engine.crawler_config.clone(),
+            self.engine.config.clone(),
+            ConfigManager::get_data_dir(),
+            self.engine.daemon.is_none(),
+            shutdown.child_token(),
+        ));
 
-        self.engine.state = crate::types::AppState::Chunking;
-        terminal.draw(|f| UI::render(f, &mut self.engine))?;
+        loop {
+            if let Some(task) = indexing.as_mut() {
+                if let Some(files) = task.take_crawled() {
+                    self.engine.indexed_files = files;
+                }
+                if let Some(choice) = self.engine.index_choice.take() {
+                    task.choose(choice);
+                }
+                if let Some(service) = task.take_storage() {
+                    {
+                        let storage = service.lock().await;
+                        self.engine.index_read_only = storage.is_read_only();
+                        self.engine.index_progress = Some(storage.progress());
+                        self.engine.index_pause = Some(storage.pause_switch());
+                    }
+                    if self.engine.index_read_only {
+                        self.engine.notifications.push(
+                            Level::Warning,
+                            "The index is locked by another sema; searching without indexing"
+                                .to_string(),
+                        );
+                    }
+                    self.engine.processing_service = Some(service);
+                }
+                self.engine.state = match embeddings::download_progress() {
+                    Some((downloaded, total)) => {
+                        crate::types::AppState::DownloadingModel { downloaded, total }
+                    }
+                    None => task.state(),
+                };
 
-        let mut service = StorageManager::new(&config_dir).await?;
-        service.process_and_index_files(files).await?;
+                if task.is_finished()
+                    && let Some(task) = indexing.take()
+                {
+                    let (files, stats) = task.join().await?;
+                    self.engine.summarize_corpus(&files, stats).await;
+                    self.engine.indexed_files = files;
+                    self.engine.indexed_at = Some(unix_now());
+                    self.engine.state = crate::types::AppState::Ready;
+                    // Rebuilt on the next keystroke with the newly indexed terms
+                    self.engine.reset_term_completions();
+                    self.draw(terminal)?;
+                }
+            }
 
-        self.engine.processing_service = Some(service);
-        self.engine.state = crate::types::AppState::Ready;
-        terminal.draw(|f| UI::render(f, &mut self.engine))?;
+            if self.engine.can_search()
+                && let Some(query) = self.initial_query.take()
+            {
+                self.engine.search_input = tui_input::Input::new(query.clone());
+                self.execute_search(&query).await;
+                self.draw(terminal)?;
+            }
 
-        loop {
             if ratatui::crossterm::event::poll(Duration::from_millis(POLL_INTERVAL_MS))?
                 && let Ok(event) = event::read()
             {
                 let terminal_size = terminal.size()?;
+                let resized = matches!(event, Event::Resize(..));
                 let _ = self.handle_event(event, terminal_size.height).await;
+                self.engine
+                    .load_visible_blame(terminal_size.height as usize)
+                    .await;
+                self.engine
+                    .load_visible_annotation_blame(terminal_size.height as usize)
+                    .await;
+                if resized {
+                    // Laid out for the new size now rather than on the next tick
+                    self.draw(terminal)?;
+                }
+            }
+
+            match self.engine.poll_question_rewrite().await {
+                Ok(true) => {
+                    // A daemon answers at once; an in-process search streams in below
+                    if !self.engine.search_in_progress() {
+                        self.preview_streamed_results(true).await;
+                    }
+                    self.draw(terminal)?;
+                }
+                Ok(false) => {}
+                Err(_) => {
+                    self.engine.search_error = Some("Search failed".to_string());
+                    self.engine.clear_search();
+                    self.draw(terminal)?;
+                }
+            }
+            if let Some(finished) = self.engine.poll_search_updates() {
+                self.preview_streamed_results(finished).await;
+                self.draw(terminal)?;
+            }
+            if self.engine.poll_answer_updates() {
+                self.draw(terminal)?;
+            }
+            if self.engine.poll_relevant_lines() {
+                self.draw(terminal)?;
+            }
+            if self.engine.poll_term_completions() {
+                self.draw(terminal)?;
+            }
+
+            if let Some(change) = self.config_watcher.as_mut().and_then(ConfigWatcher::poll) {
+                self.apply_config_change(change).await;
             }
 
             if last_tick.elapsed() >= Duration::from_millis(SPINNER_UPDATE_INTERVAL_MS) {
+                // Drawn on this tick, and toasts expire on later ones
+                self.engine.notifications.collect();
                 self.engine.spinner_frame = (self.engine.spinner_frame + 1) % 8;
-                terminal.draw(|f| UI::render(f, &mut self.engine))?;
+                self.draw(terminal)?;
                 last_tick = Instant::now();
             }
 
+            if shutdown.is_cancelled() {
+                self.engine.should_quit = true;
+            }
+
             if self.engine.should_quit {
+                if let Some(task) = indexing.take() {
+                    task.cancel();
+                    self.engine.state = crate::types::AppState::Stopping;
+                    self.draw(terminal)?;
+                    // Partial work is committed before the task returns; dropping the
+                    // storage afterwards releases the index locks.
+                    let _ = task.join().await;
+                }
                 break;
             }
         }
@@ -107,13 +311,91 @@ impl App {
         Ok(())
     }
 
+    /// Draws a frame, then the preview's image over it.
+    fn draw<B: ratatui::backend::Backend + Write>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+    ) -> Result<()> {
+        terminal.draw(|f| UI::render(f, &mut self.engine))?;
+        if self.engine.inline_images.needs_repaint() {
+            terminal.clear()?;
+            terminal.draw(|f| UI::render(f, &mut self.engine))?;
+        }
+        self.engine.inline_images.flush(terminal.backend_mut())?;
+        Ok(())
+    }
+
     async fn handle_event(&mut self, event: Event, terminal_height: u16) -> bool {
         match event {
             Event::Key(key) if key.kind == KeyEventKind::Press => {
                 self.engine.search_error = None;
+                // Any key dismisses the explanation popup
+                if self.engine.result_explanation.take().is_some() {
+                    return true;
+                }
                 let prev_selected = self.engine.selected_search_result;
+                let prev_query = self.engine.search_input.value().to_string();
 
-                let result = if matches!(self.engine.state, crate::types::AppState::Ready) {
+                if key.code == KeyCode::Tab
+                    && matches!(self.engine.ui_mode, crate::types::UIMode::SearchInput)
+                    && let Some(completion) = self.engine.completion.take()
+                {
+                    let query = format!("{}{}", prev_query, completion);
+                    self.engine.search_input = tui_input::Input::new(query);
+                    return true;
+                }
+
+                let result = if matches!(self.engine.state, crate::types::AppState::Confirming(_)) {
+                    EventHandler::handle_confirm_input(&key, &mut self.engine.exclude_input)
+                } else if matches!(self.engine.ui_mode, crate::types::UIMode::FilePreview)
+                    && self.engine.file_preview_find_input.is_some()
+                {
+                    EventHandler::handle_find_input(&key, &mut self.engine.file_preview_find_input)
+                } else if matches!(self.engine.ui_mode, crate::types::UIMode::FilePreview)
+                    && self.engine.file_preview_goto_input.is_some()
+                {
+                    EventHandler::handle_goto_input(&key, &mut self.engine.file_preview_goto_input)
+                } else if matches!(self.engine.ui_mode, crate::types::UIMode::SearchResults)
+                    && self.engine.results_filter_input.is_some()
+                {
+                    EventHandler::handle_filter_input(&key, &mut self.engine.results_filter_input)
+                } else if matches!(self.engine.ui_mode, crate::types::UIMode::Bookmarks) {
+                    let bookmarks_len = self.engine.bookmarks.as_ref().map_or(0, |b| b.len());
+                    EventHandler::handle_bookmarks_input(
+                        &key,
+                        &mut self.engine.ui_mode,
+                        &mut self.engine.selected_bookmark,
+                        bookmarks_len,
+                    )
+                } else if matches!(self.engine.ui_mode, crate::types::UIMode::Notifications) {
+                    EventHandler::handle_notifications_input(
+                        &key,
+                        &mut self.engine.ui_mode,
+                        &mut self.engine.selected_notification,
+                        self.engine.notifications.len(),
+                    )
+                } else if matches!(self.engine.ui_mode, crate::types::UIMode::SkippedFiles) {
+                    EventHandler::handle_skipped_files_input(
+                        &key,
+                        &mut self.engine.ui_mode,
+                        &mut self.engine.selected_skipped_file,
+                        self.engine.skipped_files.len(),
+                    )
+                } else if matches!(self.engine.ui_mode, crate::types::UIMode::Projects) {
+                    EventHandler::handle_projects_input(
+                        &key,
+                        &mut self.engine.ui_mode,
+                        &mut self.engine.selected_project,
+                        self.engine.projects.projects().len() + 1,
+                    )
+                } else if matches!(self.engine.ui_mode, crate::types::UIMode::Annotations) {
+                    EventHandler::handle_annotations_input(
+                        &key,
+                        &mut self.engine.ui_mode,
+                        &mut self.engine.selected_annotation,
+                        self.engine.annotations.len(),
+                    )
+                } else if self.engine.can_search() {
                     let current_result = self
                         .engine
                         .search_results
@@ -125,6 +407,8 @@ impl App {
                         &mut self.engine.selected_search_result,
                         &mut self.engine.search_results_scroll_offset,
                         &mut self.engine.file_preview_scroll_offset,
+                        &mut self.engine.file_preview_horizontal_offset,
+                        &mut self.engine.file_preview_wrap,
                         self.engine.search_results.len(),
                         current_result,
                         terminal_height,
@@ -137,22 +421,139 @@ impl App {
                 match result {
                     EventResult::ExecuteSearch(query) => self.execute_search(&query).await,
                     EventResult::OpenFile => self.open_file().await,
+                    EventResult::RevealFile => self.reveal_file(),
+                    EventResult::PrintDirectory => self.print_directory(),
+                    EventResult::ToggleBookmark => self.toggle_bookmark(),
+                    EventResult::SaveQuery => self.save_query(),
+                    EventResult::ShowBookmarks => self.show_bookmarks(),
+                    EventResult::ShowNotifications => {
+                        self.engine.selected_notification = 0;
+                        self.engine.ui_mode = crate::types::UIMode::Notifications;
+                    }
+                    EventResult::ShowSkippedFiles => {
+                        self.engine.selected_skipped_file = 0;
+                        self.engine.ui_mode = crate::types::UIMode::SkippedFiles;
+                    }
+                    EventResult::ShowProjects => self.engine.show_projects(),
+                    EventResult::ChooseProject(index) => {
+                        self.engine.choose_project(index);
+                        let query = self.engine.current_search_query.clone();
+                        if !query.is_empty() {
+                            self.execute_search(&query).await;
+                        }
+                    }
+                    EventResult::OpenBookmark(index) => self.open_bookmark(index).await,
+                    EventResult::RemoveBookmark(index) => self.remove_bookmark(index),
+                    EventResult::OpenAnnotation(index) => self.open_annotation(index).await,
+                    EventResult::StartGoToLine => {
+                        self.engine.file_preview_goto_input = Some(String::new())
+                    }
+                    EventResult::GoToLine(line) => self.go_to_line(line),
+                    EventResult::GoToEnd => self.go_to_end(terminal_height),
+                    EventResult::StartFind => {
+                        self.engine.file_preview_find_input = Some(String::new())
+                    }
+                    EventResult::Find(pattern) => {
+                        self.engine.file_preview_find_pattern =
+                            Some(pattern).filter(|p| !p.is_empty());
+                        self.find_in_preview(true);
+                    }
+                    EventResult::FindNext => self.find_in_preview(true),
+                    EventResult::FindPrevious => self.find_in_preview(false),
+                    EventResult::ToggleMark => self.toggle_mark(),
+                    EventResult::CopyPaths => self.copy_paths(),
+                    EventResult::ExportMarkdown => self.export_markdown(),
+                    EventResult::ExportResults => self.export_results(),
+                    EventResult::ToggleChunkPreview => {
+                        self.engine.file_preview_chunk_only = !self.engine.file_preview_chunk_only
+                    }
+                    EventResult::AdjustContextLines(delta) => {
+                        self.engine.file_preview_context_lines = self
+                            .engine
+                            .file_preview_context_lines
+                            .saturating_add_signed(delta)
+                    }
+                    EventResult::OpenQuickfix => self.open_quickfix(),
+                    EventResult::ToggleFileFinder => self.toggle_file_finder().await,
+                    EventResult::ToggleDiff => self.engine.toggle_diff().await,
+                    EventResult::AcceptSuggestion => {
+                        if let Some(suggestion) = self.engine.query_suggestion.take() {
+                            self.engine.search_input = tui_input::Input::new(suggestion.clone());
+                            self.execute_search(&suggestion).await;
+                        }
+                    }
+                    EventResult::ScopeToDirectory => self.scope_to_directory().await,
+                    EventResult::ExplainResult => {
+                        if let Err(e) = self.engine.explain_selected().await {
+                            self.engine
+                                .notifications
+                                .push(Level::Warning, format!("Can't explain the result: {}", e));
+                        }
+                    }
+                    EventResult::ClearScope => {
+                        if self.engine.search_scope.take().is_some() {
+                            let query = self.engine.current_search_query.clone();
+                            self.execute_search(&query).await;
+                        }
+                    }
+                    EventResult::ToggleBlame => {
+                        self.engine.file_preview_blame = !self.engine.file_preview_blame;
+                    }
+                    EventResult::StartResultsFilter => {
+                        self.engine.results_filter_input = Some(self.engine.results_filter.clone())
+                    }
+                    EventResult::FilterResults => {
+                        if let Some(filter) = &self.engine.results_filter_input {
+                            self.engine.results_filter = filter.clone();
+                        }
+                        self.engine.apply_results_filter();
+                        self.sync_file_preview().await;
+                    }
+                    EventResult::ClearResultsFilter => {
+                        self.engine.results_filter.clear();
+                        self.engine.apply_results_filter();
+                        self.sync_file_preview().await;
+                    }
+                    EventResult::JumpToSource(number) => {
+                        // Scrolled to the source itself, not the start of its result
+                        self.jump_to_source(number).await;
+                        return true;
+                    }
+                    EventResult::ChooseIndexing(choice) => self.choose_indexing(choice),
+                    EventResult::TogglePause => {
+                        if self.engine.can_pause_indexing() {
+                            self.toggle_indexing_pause();
+                        }
+                    }
                     EventResult::Quit => self.engine.should_quit = true,
                     EventResult::Continue => {}
                 }
 
-                if self.engine.selected_search_result != prev_selected {
+                // The file finder filters as you type rather than waiting for Enter
+                if self.engine.file_finder
+                    && matches!(self.engine.ui_mode, crate::types::UIMode::SearchInput)
+                    && self.engine.search_input.value() != prev_query
+                {
+                    let query = self.engine.search_input.value().to_string();
+                    self.engine.find_files(&query);
+                    self.sync_file_preview().await;
+                } else if self.engine.selected_search_result != prev_selected {
                     self.sync_file_preview().await;
                 }
+                if self.engine.search_input.value() != prev_query {
+                    self.engine.update_completion();
+                }
 
                 true
             }
+            Event::Resize(_, height) => {
+                self.fit_to_height(height);
+                true
+            }
             Event::Mouse(mouse)
                 if matches!(mouse.kind, MouseEventKind::Down(MouseButton::Left)) =>
             {
-                if matches!(self.engine.state, crate::types::AppState::Ready)
-                    && !self.engine.search_results.is_empty()
-                {
+                if self.engine.can_search() && !self.engine.search_results.is_empty() {
                     self.engine.ui_mode = crate::types::UIMode::SearchInput;
                 }
                 true
@@ -161,23 +562,105 @@ impl App {
         }
     }
 
+    async fn apply_config_change(&mut self, change: ConfigChange) {
+        let (level, message) = match change {
+            ConfigChange::Reloaded(config) => {
+                let restart_needed = self.engine.apply_config(config).await;
+                let message = if restart_needed.is_empty() {
+                    "Reloaded config.toml".to_string()
+                } else {
+                    format!(
+                        "Reloaded config.toml; changes to {} apply after a restart",
+                        restart_needed.join(", ")
+                    )
+                };
+                (Level::Info, message)
+            }
+            ConfigChange::Rejected(e) => {
+                // Parse errors quote the offending line below the first
+                let error = format!("{:#}", e);
+                let summary = error.lines().next().unwrap_or_default();
+                (
+                    Level::Warning,
+                    format!("Kept the previous settings: {}", summary),
+                )
+            }
+            ConfigChange::ProjectChanged => (
+                Level::Info,
+                ".sema.toml changed; it applies the next time sema indexes".to_string(),
+            ),
+        };
+        self.engine.notifications.push(level, message);
+    }
+
     async fn execute_search(&mut self, query: &str) {
-        if query.trim().len() <= 2 {
-            self.engine.clear_search();
+        if self.engine.file_finder {
+            self.engine.find_files(query);
+            if !self.engine.search_results.is_empty() {
+                self.engine.ui_mode = crate::types::UIMode::SearchResults;
+            }
+        } else if query.trim().eq_ignore_ascii_case(ANNOTATIONS_SHORTCUT) {
+            self.engine.show_annotations().await;
             return;
-        }
-
-        if self.engine.execute_search(query).await.is_err() {
-            self.engine.search_error = Some("Search failed".to_string());
+        } else if query.trim().strip_prefix(ASK_PREFIX) == Some("") {
+            // A bare `?` starts a new conversation
             self.engine.clear_search();
+            let (level, message) = match self.engine.forget_conversation() {
+                Ok(()) => (Level::Info, "Started a new conversation".to_string()),
+                Err(e) => (
+                    Level::Warning,
+                    format!("Can't forget the conversation: {}", e),
+                ),
+            };
+            self.engine.notifications.push(level, message);
             return;
+        } else {
+            if query.trim().len() <= 2 {
+                self.engine.clear_search();
+                return;
+            }
+
+            if self.engine.execute_search(query).await.is_err() {
+                self.engine.search_error = Some("Search failed".to_string());
+                self.engine.clear_search();
+                return;
+            }
+            // The main loop previews results as they stream in
+            if self.engine.search_in_progress() {
+                return;
+            }
         }
 
         if let Some(first) = self.engine.search_results.first().cloned() {
             self.engine
                 .update_current_file_content(&first.chunk.file_path)
                 .await;
+            self.engine.update_relevant_lines();
             self.engine.file_preview_scroll_offset = first.chunk.start_line.saturating_sub(1);
+            self.engine.file_preview_horizontal_offset = 0;
+        }
+    }
+
+    /// Follows a streamed search: previews the selected result once its file changes, and
+    /// adds what needs the index once the search has finished.
+    async fn preview_streamed_results(&mut self, finished: bool) {
+        let Some(result) = self
+            .engine
+            .search_results
+            .get(self.engine.selected_search_result)
+            .cloned()
+        else {
+            return;
+        };
+
+        if self.engine.current_file_path.as_ref() != Some(&result.chunk.file_path) {
+            self.engine.preview_file(&result.chunk.file_path).await;
+            self.engine.file_preview_scroll_offset = result.chunk.start_line.saturating_sub(1);
+            self.engine.file_preview_horizontal_offset = 0;
+        }
+        if finished {
+            self.engine.load_file_info().await;
+            self.engine.update_relevant_lines();
         }
     }
 
@@ -195,27 +678,546 @@ impl App {
         self.engine
             .update_current_file_content(&result.chunk.file_path)
             .await;
+        self.engine.update_relevant_lines();
         self.engine.file_preview_scroll_offset = result.chunk.start_line.saturating_sub(1);
+        self.engine.file_preview_horizontal_offset = 0;
         self.engine.ui_mode = crate::types::UIMode::FilePreview;
     }
 
-    async fn sync_file_preview(&mut self) {
+    fn go_to_line(&mut self, line: usize) {
+        let total_lines = self.engine.current_file_line_count();
+        self.engine.file_preview_scroll_offset = line.min(total_lines).saturating_sub(1);
+    }
+
+    fn go_to_end(&mut self, terminal_height: u16) {
+        let visible_lines = UI::preview_lines(terminal_height);
+        let total_lines = self.engine.current_file_line_count();
+        self.engine.file_preview_scroll_offset = total_lines.saturating_sub(visible_lines);
+    }
+
+    /// Keeps the selected result on screen and the preview within the file once the
+    /// terminal is `height` rows tall.
+    fn fit_to_height(&mut self, height: u16) {
+        let results_per_page = UI::results_per_page(height);
+        let results_len = self.engine.search_results.len();
+        // Growing the terminal fills the list instead of leaving rows empty below the end
+        self.engine.search_results_scroll_offset = self
+            .engine
+            .search_results_scroll_offset
+            .min(results_len.saturating_sub(results_per_page));
+        EventHandler::update_scroll_offset(
+            self.engine.selected_search_result,
+            &mut self.engine.search_results_scroll_offset,
+            results_per_page,
+        );
+
+        // Chunk-only and diff previews scroll their own line lists
+        if !self.engine.file_preview_chunk_only
+            && self.engine.current_file_diff.is_none()
+            && self.engine.current_file_content.is_some()
+        {
+            let last_top_line = self
+                .engine
+                .current_file_line_count()
+                .saturating_sub(UI::preview_lines(height));
+            self.engine.file_preview_scroll_offset =
+                self.engine.file_preview_scroll_offset.min(last_top_line);
+        }
+    }
+
+    /// Scrolls the preview to the next (or previous) line containing the find pattern,
+    /// wrapping around at the end of the file.
+    fn find_in_preview(&mut self, forward: bool) {
+        let Some(pattern) = self.engine.file_preview_find_pattern.as_ref() else {
+            return;
+        };
+        let Some(content) = self.engine.current_file_content.as_ref() else {
+            return;
+        };
+
+        let pattern = pattern.to_lowercase();
+        let lines: Vec<&str> = content.lines().collect();
+        if lines.is_empty() {
+            return;
+        }
+
+        let current = self.engine.file_preview_scroll_offset.min(lines.len() - 1);
+        let found = (1..=lines.len())
+            .map(|step| {
+                if forward {
+                    (current + step) % lines.len()
+                } else {
+                    (current + lines.len() - step % lines.len()) % lines.len()
+                }
+            })
+            .find(|&index| lines[index].to_lowercase().contains(&pattern));
+
+        match found {
+            Some(index) => self.engine.file_preview_scroll_offset = index,
+            None => self.engine.search_error = Some(format!("Pattern not found: {}", pattern)),
+        }
+    }
+
+    fn reveal_file(&mut self) {
+        let Some(result) = self
+            .engine
+            .search_results
+            .get(self.engine.selected_search_result)
+        else {
+            return;
+        };
+
+        let spawned = reveal_command(&result.chunk.file_path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+
+        if spawned.is_err() {
+            self.engine.search_error = Some("Failed to open file manager".to_string());
+        }
+    }
+
+    fn print_directory(&mut self) {
         let Some(result) = self
             .engine
             .search_results
             .get(self.engine.selected_search_result)
+        else {
+            return;
+        };
+
+        if let Some(parent) = result.chunk.file_path.parent() {
+            self.engine.exit_output = Some(parent.to_string_lossy().to_string());
+            self.engine.should_quit = true;
+        }
+    }
+
+    /// Re-runs the current query limited to the selected result's directory.
+    async fn scope_to_directory(&mut self) {
+        if self.engine.file_finder {
+            return;
+        }
+        let Some(directory) = self
+            .engine
+            .search_results
+            .get(self.engine.selected_search_result)
+            .and_then(|result| result.chunk.file_path.parent())
+            .map(std::path::Path::to_path_buf)
+        else {
+            return;
+        };
+
+        self.engine.search_scope = Some(directory);
+        let query = self.engine.current_search_query.clone();
+        self.execute_search(&query).await;
+    }
+
+    fn toggle_bookmark(&mut self) {
+        let Some(result) = self
+            .engine
+            .search_results
+            .get(self.engine.selected_search_result)
+        else {
+            return;
+        };
+        let Some(bookmarks) = self.engine.bookmarks.as_mut() else {
+            self.engine.search_error = Some("Bookmarks unavailable".to_string());
+            return;
+        };
+
+        self.engine.search_error = Some(
+            match bookmarks.toggle_result(&result.chunk, &self.engine.current_search_query) {
+                Ok(true) => "Bookmarked".to_string(),
+                Ok(false) => "Bookmark removed".to_string(),
+                Err(e) => format!("Failed to save bookmark: {}", e),
+            },
+        );
+    }
+
+    fn save_query(&mut self) {
+        let query = self.engine.search_input.value().trim().to_string();
+        if query.is_empty() {
+            return;
+        }
+        let Some(bookmarks) = self.engine.bookmarks.as_mut() else {
+            self.engine.search_error = Some("Bookmarks unavailable".to_string());
+            return;
+        };
+
+        self.engine.search_error = Some(match bookmarks.save_query(&query, &query) {
+            Ok(()) => "Query saved".to_string(),
+            Err(e) => format!("Failed to save query: {}", e),
+        });
+    }
+
+    /// Hands the answer to the large project confirmation to the indexing task.
+    fn choose_indexing(&mut self, choice: IndexChoice) {
+        let message = match &choice {
+            IndexChoice::Index => None,
+            IndexChoice::KeywordOnly => {
+                self.engine.keyword_only = true;
+                Some("Building only the keyword index for this run".to_string())
+            }
+            IndexChoice::Exclude(patterns) => Some(format!(
+                "Crawling again without {}; add them to exclude_patterns to keep them out",
+                patterns.join(", ")
+            )),
+            IndexChoice::Skip => Some("Not indexing; searching the existing index".to_string()),
+        };
+        if let Some(message) = message {
+            self.engine.notifications.push(Level::Info, message);
+        }
+        self.engine.index_choice = Some(choice);
+    }
+
+    /// Pauses the background indexing after its current batch, or resumes it.
+    fn toggle_indexing_pause(&mut self) {
+        let Some(pause) = &self.engine.index_pause else {
+            return;
+        };
+        let paused = !*pause.borrow();
+        pause.send_replace(paused);
+        let message = if paused {
+            "Indexing paused after the current batch; press p in the results to resume"
+        } else {
+            "Indexing resumed"
+        };
+        self.engine
+            .notifications
+            .push(Level::Info, message.to_string());
+    }
+
+    fn show_bookmarks(&mut self) {
+        let bookmarks_len = self.engine.bookmarks.as_ref().map_or(0, |b| b.len());
+        self.engine.selected_bookmark = self
+            .engine
+            .selected_bookmark
+            .min(bookmarks_len.saturating_sub(1));
+        self.engine.ui_mode = crate::types::UIMode::Bookmarks;
+    }
+
+    async fn open_bookmark(&mut self, index: usize) {
+        let Some(bookmarks) = self.engine.bookmarks.as_ref() else {
+            return;
+        };
+
+        let query_count = bookmarks.bookmarks().queries.len();
+        if let Some(saved) = bookmarks.bookmarks().queries.get(index) {
+            let query = saved.query.clone();
+            self.engine.search_input = tui_input::Input::new(query.clone());
+            self.engine.ui_mode = crate::types::UIMode::SearchInput;
+            self.execute_search(&query).await;
+            return;
+        }
+
+        let results: Vec<SearchResult> = bookmarks
+            .bookmarks()
+            .results
+            .iter()
+            .enumerate()
+            .map(|(i, bookmark)| SearchResult {
+                chunk: Chunk {
+                    id: format!("bookmark:{}", i),
+                    file_path: bookmark.file_path.clone(),
+                    start_line: bookmark.start_line,
+                    end_line: bookmark.end_line,
+                    content: bookmark.content.clone(),
+                    ..Chunk::default()
+                },
+                score: 1.0,
+                total_matches_in_file: 1,
+                matched_queries: Vec::new(),
+            })
+            .collect();
+
+        let selected = index - query_count;
+        let Some(query) = bookmarks
+            .bookmarks()
+            .results
+            .get(selected)
+            .map(|b| b.query.clone())
+        else {
+            return;
+        };
+
+        self.engine.set_results(results);
+        self.engine.selected_search_result = selected;
+        self.engine.search_results_scroll_offset = selected;
+        self.engine.current_search_query = query;
+        self.open_file().await;
+    }
+
+    /// Opens an annotation in the preview, with every annotation as the results to browse.
+    async fn open_annotation(&mut self, index: usize) {
+        let results: Vec<SearchResult> = self
+            .engine
+            .annotations
+            .iter()
+            .enumerate()
+            .map(|(i, annotation)| SearchResult {
+                chunk: Chunk {
+                    id: format!("annotation:{}", i),
+                    file_path: annotation.file_path.clone(),
+                    start_line: annotation.line,
+                    end_line: annotation.line,
+                    content: format!("{}: {}", annotation.tag, annotation.text),
+                    ..Chunk::default()
+                },
+                score: 1.0,
+                total_matches_in_file: 1,
+                matched_queries: Vec::new(),
+            })
+            .collect();
+
+        self.engine.set_results(results);
+        self.engine.selected_search_result = index;
+        self.engine.search_results_scroll_offset = index;
+        self.engine.current_search_query = ANNOTATION_QUERY.to_string();
+        self.open_file().await;
+    }
+
+    fn remove_bookmark(&mut self, index: usize) {
+        let Some(bookmarks) = self.engine.bookmarks.as_mut() else {
+            return;
+        };
+
+        if let Err(e) = bookmarks.remove(index) {
+            self.engine.search_error = Some(format!("Failed to remove bookmark: {}", e));
+        }
+        self.engine.selected_bookmark = self
+            .engine
+            .selected_bookmark
+            .min(bookmarks.len().saturating_sub(1));
+    }
+
+    fn toggle_mark(&mut self) {
+        let index = self.engine.selected_search_result;
+        if !self.engine.marked_results.remove(&index) {
+            self.engine.marked_results.insert(index);
+        }
+    }
+
+    fn copy_paths(&mut self) {
+        let results = self.engine.batch_results();
+        let paths: Vec<String> = results
+            .iter()
+            .map(|result| result.chunk.file_path.to_string_lossy().to_string())
+            .collect();
+        let count = paths.len();
+
+        let copied = clipboard_command()
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .and_then(|mut child| {
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(paths.join("\n").as_bytes())?;
+                }
+                child.wait()
+            });
+
+        self.engine.search_error = Some(match copied {
+            Ok(status) if status.success() => format!("Copied {} path(s)", count),
+            _ => "Failed to copy to clipboard".to_string(),
+        });
+    }
+
+    fn export_markdown(&mut self) {
+        let results = self.engine.batch_results();
+        let count = results.len();
+        let markdown = export::to_markdown(
+            &self.engine.current_search_query,
+            &results,
+            &self.engine.root_path,
+        );
+
+        self.engine.search_error = Some(match std::fs::write(EXPORT_FILE_NAME, markdown) {
+            Ok(()) => format!("Exported {} result(s) to {}", count, EXPORT_FILE_NAME),
+            Err(e) => format!("Failed to export results: {}", e),
+        });
+    }
+
+    /// Writes the whole result set to the `--output` path, or a CSV in the working directory.
+    fn export_results(&mut self) {
+        let path = self
+            .output
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_RESULTS_FILE_NAME));
+        let results: Vec<&SearchResult> = self.engine.search_results.iter().collect();
+
+        self.engine.search_error = Some(
+            match export::write_results(
+                &path,
+                &self.engine.current_search_query,
+                &results,
+                &self.engine.root_path,
+            ) {
+                Ok(()) => format!("Exported {} result(s) to {}", results.len(), path.display()),
+                Err(e) => format!("Failed to export results: {}", e),
+            },
+        );
+    }
+
+    /// Writes the results as a quickfix list and, when `$EDITOR` is a vi, quits, opening
+    /// `$EDITOR -q` on exit. Other editors don't read `-q`, so only the path is shown.
+    fn open_quickfix(&mut self) {
+        let quickfix = export::to_quickfix(&self.engine.batch_results());
+        let path = std::env::temp_dir().join(QUICKFIX_FILE_NAME);
+
+        if let Err(e) = std::fs::write(&path, quickfix) {
+            self.engine.search_error = Some(format!("Failed to write quickfix list: {}", e));
+            return;
+        }
+
+        let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+        let mut words = editor.split_whitespace();
+        let program = words.next().unwrap_or("vi");
+        let vi_like = Path::new(program)
+            .file_stem()
+            .is_some_and(|stem| matches!(stem.to_str(), Some("vi" | "vim" | "nvim")));
+        if !vi_like {
+            self.engine.search_error = Some(format!("Wrote quickfix list to {}", path.display()));
+            return;
+        }
+
+        let mut command = Command::new(program);
+        command.args(words).arg("-q").arg(path);
+        self.engine.exit_command = Some(command);
+        self.engine.should_quit = true;
+    }
+
+    fn selected_location(&self) -> Option<String> {
+        if matches!(self.engine.ui_mode, crate::types::UIMode::SearchInput) {
+            return None;
+        }
+
+        let result = self
+            .engine
+            .search_results
+            .get(self.engine.selected_search_result)?;
+
+        Some(format!(
+            "{}:{}",
+            result.chunk.file_path.display(),
+            result.chunk.start_line
+        ))
+    }
+
+    /// Switches between semantic search and fuzzy-matching file paths.
+    async fn toggle_file_finder(&mut self) {
+        self.engine.file_finder = !self.engine.file_finder;
+        self.engine.clear_search();
+
+        let query = self.engine.search_input.value().to_string();
+        if self.engine.file_finder && !query.trim().is_empty() {
+            self.engine.find_files(&query);
+            self.sync_file_preview().await;
+        }
+    }
+
+    /// Previews source `number` of the answer, as it is cited there.
+    async fn jump_to_source(&mut self, number: usize) {
+        let Some(source) = self
+            .engine
+            .answer
+            .as_ref()
+            .and_then(|answer| answer.sources.get(number - 1))
             .cloned()
         else {
             return;
         };
 
-        let needs_load = self.engine.current_file_path.as_ref() != Some(&result.chunk.file_path);
-        if needs_load {
-            self.engine
-                .update_current_file_content(&result.chunk.file_path)
-                .await;
+        if let Some(index) = self
+            .engine
+            .search_results
+            .iter()
+            .position(|result| result.chunk.file_path == source.file_path)
+        {
+            self.engine.selected_search_result = index;
+            self.engine.search_results_scroll_offset =
+                self.engine.search_results_scroll_offset.min(index);
         }
+        self.engine
+            .update_current_file_content(&source.file_path)
+            .await;
+        // Highlight the lines behind the sentences citing the source, or failing that the
+        // lines closest to the question
+        let cited: Vec<String> = self
+            .engine
+            .answer
+            .as_ref()
+            .map(|answer| {
+                crate::ask::citations(&answer.text)
+                    .into_iter()
+                    .filter(|citation| citation.sources.contains(&number))
+                    .map(|citation| citation.sentence)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let text = if cited.is_empty() {
+            self.engine
+                .answer
+                .as_ref()
+                .map(|answer| answer.question.clone())
+                .unwrap_or_default()
+        } else {
+            cited.join(" ")
+        };
+        self.engine.highlight_relevant_lines(&text, &source);
+        self.engine.file_preview_scroll_offset = source.start_line.saturating_sub(1);
+        self.engine.file_preview_horizontal_offset = 0;
+        self.engine.ui_mode = crate::types::UIMode::FilePreview;
+    }
+
+    async fn sync_file_preview(&mut self) {
+        let Some(result) = self
+            .engine
+            .search_results
+            .get(self.engine.selected_search_result)
+            .cloned()
+        else {
+            return;
+        };
+
+        self.engine
+            .update_current_file_content(&result.chunk.file_path)
+            .await;
+        self.engine.update_relevant_lines();
 
         self.engine.file_preview_scroll_offset = result.chunk.start_line.saturating_sub(1);
+        self.engine.file_preview_horizontal_offset = 0;
+    }
+}
+
+fn reveal_command(file_path: &Path) -> Command {
+    if cfg!(target_os = "macos") {
+        let mut command = Command::new("open");
+        command.arg("-R").arg(file_path);
+        command
+    } else if cfg!(target_os = "windows") {
+        let mut command = Command::new("explorer");
+        command.arg(format!("/select,{}", file_path.display()));
+        command
+    } else {
+        let directory = file_path.parent().unwrap_or(file_path);
+        let mut command = Command::new("xdg-open");
+        command.arg(directory);
+        command
+    }
+}
+
+fn clipboard_command() -> Command {
+    if cfg!(target_os = "macos") {
+        Command::new("pbcopy")
+    } else if cfg!(target_os = "windows") {
+        Command::new("clip")
+    } else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        Command::new("wl-copy")
+    } else {
+        let mut command = Command::new("xclip");
+        command.args(["-selection", "clipboard"]);
+        command
     }
 }
@@ -0,0 +1,37 @@
+use std::collections::BTreeMap;
+
+/// Terms shorter than this aren't worth completing
+const MIN_TERM_CHARS: usize = 4;
+/// Characters typed before a completion is offered
+const MIN_PREFIX_CHARS: usize = 2;
+
+/// Indexed terms with how many chunks contain them, for completing the word being typed.
+pub struct TermCompletions {
+    terms: BTreeMap<String, u64>,
+}
+
+impl TermCompletions {
+    pub fn new(terms: Vec<(String, u64)>) -> Self {
+        Self {
+            terms: terms
+                .into_iter()
+                .filter(|(term, _)| term.chars().count() >= MIN_TERM_CHARS)
+                .collect(),
+        }
+    }
+
+    /// The rest of the most frequent term starting with `prefix`, ignoring case.
+    pub fn complete(&self, prefix: &str) -> Option<&str> {
+        if prefix.chars().count() < MIN_PREFIX_CHARS {
+            return None;
+        }
+        let prefix = prefix.to_lowercase();
+
+        self.terms
+            .range::<str, _>(prefix.as_str()..)
+            .take_while(|(term, _)| term.starts_with(&prefix))
+            .filter(|(term, _)| term.len() > prefix.len())
+            .max_by_key(|(_, doc_freq)| **doc_freq)
+            .map(|(term, _)| &term[prefix.len()..])
+    }
+}
@@ -0,0 +1,77 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::config::Config;
+use crate::crawler::overrides::OVERRIDE_FILE;
+
+/// How often the watched files' modification times are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reads the config as sema started with it, command-line overrides included.
+pub type ConfigLoader = Box<dyn Fn() -> Result<Config> + Send>;
+
+pub enum ConfigChange {
+    Reloaded(Config),
+    /// The edited config.toml didn't load; the previous settings stay
+    Rejected(anyhow::Error),
+    /// The project's `.sema.toml` changed, which only a new crawl picks up
+    ProjectChanged,
+}
+
+/// Notices edits to config.toml and the project's `.sema.toml` by polling their
+/// modification times.
+pub struct ConfigWatcher {
+    load: ConfigLoader,
+    config_file: PathBuf,
+    project_file: PathBuf,
+    config_modified: Option<SystemTime>,
+    project_modified: Option<SystemTime>,
+    last_check: Instant,
+}
+
+impl ConfigWatcher {
+    pub fn new(config_file: PathBuf, project_root: &Path, load: ConfigLoader) -> Self {
+        let project_file = project_root.join(OVERRIDE_FILE);
+        Self {
+            load,
+            config_modified: modified(&config_file),
+            project_modified: modified(&project_file),
+            config_file,
+            project_file,
+            last_check: Instant::now(),
+        }
+    }
+
+    /// Reports a change to either file since the last call, at most once per
+    /// `CHECK_INTERVAL`. A config.toml that fails to load is reported once per edit.
+    pub fn poll(&mut self) -> Option<ConfigChange> {
+        if self.last_check.elapsed() < CHECK_INTERVAL {
+            return None;
+        }
+        self.last_check = Instant::now();
+
+        let config_modified = modified(&self.config_file);
+        if config_modified != self.config_modified {
+            self.config_modified = config_modified;
+            return Some(match (self.load)() {
+                Ok(config) => ConfigChange::Reloaded(config),
+                Err(e) => ConfigChange::Rejected(e),
+            });
+        }
+
+        let project_modified = modified(&self.project_file);
+        if project_modified != self.project_modified {
+            self.project_modified = project_modified;
+            return Some(ConfigChange::ProjectChanged);
+        }
+
+        None
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
@@ -1,74 +1,331 @@
-use anyhow::Result;
-use std::collections::HashMap;
-use std::path::PathBuf;
+use anyhow::{Context, Result};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{Mutex, mpsc, oneshot, watch};
+use tokio_util::sync::CancellationToken;
 use tui_input::Input;
 
-use crate::config::Config;
-use crate::crawler::FileCrawler;
-use crate::storage::StorageManager;
-use crate::types::{AppState as AppStateEnum, CrawlerConfig, SearchResult, UIMode};
+use crate::ask::{
+    self,
+    conversation::{self, Conversation, Turn},
+};
+use crate::bookmarks::BookmarkStore;
+use crate::config::{Config, ConfigManager};
+use crate::crawler::{CrawlStats, FileCrawler, SkippedFile, images};
+use crate::daemon::{DaemonClient, SearchReply};
+use crate::projects::{Project, ProjectRegistry};
+use crate::storage::annotations::Annotation;
+use crate::storage::explain::Explanation;
+use crate::storage::progress::IndexProgress;
+use crate::storage::scope::PathScope;
+use crate::storage::{SharedStorage, StorageManager};
+use crate::tui::completion::TermCompletions;
+use crate::tui::file_cache::FileCache;
+use crate::tui::fuzzy;
+use crate::tui::git::{self, BlameLine, DiffLine};
+use crate::tui::highlight::PreviewHighlighter;
+use crate::tui::image::{InlineImages, PreviewImage};
+use crate::tui::indexing::IndexChoice;
+use crate::tui::notifications::{Level, Notifications};
+use crate::tui::summary::CorpusSummary;
+use crate::types::{
+    Answer, AppState as AppStateEnum, Chunk, CrawlerConfig, FileIndex, QueryMode, SearchResult,
+    SearchTiming, UIMode,
+};
 
 const SEARCH_RESULTS_LIMIT: usize = 50;
+const SEMANTIC_HIGHLIGHT_LINES: usize = 3;
+/// Separates queries searched together, as in `retry backoff; circuit breaker`
+pub const QUERY_SEPARATOR: char = ';';
+/// Starts a question answered by the `[llm]` model from the best matches, as in
+/// `?how are retries scheduled`
+pub const ASK_PREFIX: char = '?';
+
+/// Results of an in-process search, sent as they become available.
+enum SearchUpdate {
+    /// Keyword matches, shown while the embedding search runs
+    Preliminary(Vec<(Chunk, f32)>),
+    Done {
+        results: Vec<(Chunk, f32)>,
+        /// Which of several queries found each chunk, by chunk id
+        provenance: HashMap<String, Vec<usize>>,
+        answered_by: QueryMode,
+        semantic_unavailable: Option<String>,
+        suggestion: Option<String>,
+        timing: SearchTiming,
+    },
+    Failed(String),
+}
+
+/// Pieces of an answer as they stream in from the model.
+enum AnswerUpdate {
+    Text(String),
+    Failed(String),
+}
 
 pub struct Engine {
     pub should_quit: bool,
+    pub exit_output: Option<String>,
+    pub exit_command: Option<std::process::Command>,
     pub state: AppStateEnum,
     pub ui_mode: UIMode,
     pub spinner_frame: usize,
 
     pub search_input: Input,
     pub search_results: Vec<SearchResult>,
+    /// Results of the last search before the results filter narrowed them
+    pub unfiltered_results: Vec<SearchResult>,
+    pub results_filter: String,
+    /// Set while the results filter is being typed
+    pub results_filter_input: Option<String>,
+    /// The index that answered the last search
+    pub query_mode: Option<QueryMode>,
+    /// Where the time of the last finished search went
+    pub search_timing: Option<SearchTiming>,
+    /// How the selected result was found, shown over the results until the next key
+    pub result_explanation: Option<Explanation>,
+    /// How to index a large crawl, until the indexing task takes it
+    pub index_choice: Option<IndexChoice>,
+    /// Patterns being typed on the confirmation screen, to crawl again without
+    pub exclude_input: Option<String>,
+    /// Set when this run builds only the keyword index although embeddings are configured
+    pub keyword_only: bool,
     pub selected_search_result: usize,
+    pub marked_results: BTreeSet<usize>,
     pub search_results_scroll_offset: usize,
     pub file_preview_scroll_offset: usize,
+    pub file_preview_horizontal_offset: usize,
+    pub file_preview_wrap: bool,
+    pub file_preview_goto_input: Option<String>,
+    pub file_preview_chunk_only: bool,
+    pub file_preview_context_lines: usize,
+    pub file_preview_find_input: Option<String>,
+    pub file_preview_find_pattern: Option<String>,
     pub current_search_query: String,
+    /// The queries of the last search when it ran several at once, otherwise empty
+    pub search_queries: Vec<String>,
+    /// Directory searches are limited to, picked from a result's location
+    pub search_scope: Option<PathBuf>,
+    /// Set while searches cover every indexed project instead of this one
+    pub all_projects: bool,
     pub search_error: Option<String>,
 
-    pub current_file_content: Option<String>,
+    pub current_file_content: Option<Arc<str>>,
     pub current_file_path: Option<PathBuf>,
+    pub current_file_hash: Option<u64>,
+    /// What the index recorded about the previewed file
+    pub current_file_info: Option<FileIndex>,
+    /// Set when the previewed file is an image, drawn inline where the terminal can
+    pub current_file_image: Option<PreviewImage>,
+    /// Uncommitted changes of the previewed file, shown instead of its content when set
+    pub current_file_diff: Option<Vec<DiffLine>>,
+    /// Whether the preview shows a blame gutter
+    pub file_preview_blame: bool,
+    /// Blame of the previewed file by 0-based line, filled in a screenful at a time
+    pub current_file_blame: HashMap<usize, BlameLine>,
+    /// Zero-based file lines of the selected chunk that best match a semantic query
+    pub relevant_lines: Vec<usize>,
+    /// The relevant lines still being found in the background, if any
+    relevance_updates: Option<oneshot::Receiver<Vec<usize>>>,
+    pub file_cache: FileCache,
+    pub highlighter: PreviewHighlighter,
+    pub inline_images: InlineImages,
 
-    pub processing_service: Option<StorageManager>,
+    pub processing_service: Option<SharedStorage>,
+    /// Set when a daemon serves this project; searches go to it instead of the local index
+    pub daemon: Option<DaemonClient>,
+    /// Results of the in-process search still running, if any
+    search_updates: Option<mpsc::UnboundedReceiver<SearchUpdate>>,
+    /// The question of the search under way and what was searched for, answered once its
+    /// results are in
+    pending_question: Option<(String, String)>,
+    /// Shown above the results of a `?` question
+    pub answer: Option<Answer>,
+    answer_updates: Option<mpsc::UnboundedReceiver<AnswerUpdate>>,
+    /// Earlier questions, which follow-ups are read in the light of
+    conversation: Option<Conversation>,
+    /// A follow-up still being rewritten to stand on its own: the question and, unless
+    /// the model failed, its rewrite
+    question_rewrite: Option<oneshot::Receiver<(String, Option<String>)>>,
+    /// Fired when a newer query or Esc supersedes the running search
+    search_cancel: CancellationToken,
+    /// "Did you mean" respelling of a query that found nothing, accepted with Tab
+    pub query_suggestion: Option<String>,
+    /// Loaded from the keyword index on first use and dropped when indexing finishes
+    term_completions: Option<TermCompletions>,
+    /// The term list still being loaded in the background, if any
+    term_updates: Option<oneshot::Receiver<TermCompletions>>,
+    /// Rest of the word being typed, shown as ghost text and accepted with Tab
+    pub completion: Option<String>,
+    /// Another instance owns the indexes; this one searches them but leaves indexing to it
+    pub index_read_only: bool,
+    /// Counts of the indexing run in the background, for its throughput and time left
+    pub index_progress: Option<watch::Receiver<IndexProgress>>,
+    /// Holds the background indexing after its current batch while set
+    pub index_pause: Option<watch::Sender<bool>>,
+    /// Files found by the last crawl, searched by the file finder
+    pub indexed_files: Vec<PathBuf>,
+    /// When the last indexing run finished, in seconds since the Unix epoch
+    pub indexed_at: Option<u64>,
+    /// Shown in place of the status message until the first search
+    pub corpus_summary: Option<CorpusSummary>,
+    pub file_finder: bool,
+
+    pub bookmarks: Option<BookmarkStore>,
+    pub selected_bookmark: usize,
+
+    /// TODO, FIXME and HACK lines listed by the `:todo` view
+    pub annotations: Vec<Annotation>,
+    pub selected_annotation: usize,
+    /// Blame of annotated lines by file and 0-based line, loaded as they come on screen
+    pub annotation_blame: HashMap<PathBuf, HashMap<usize, BlameLine>>,
+
+    /// Non-fatal warnings, shown as toasts and listed in the log panel
+    pub notifications: Notifications,
+    pub selected_notification: usize,
+    /// Files left out of the index and why, listed with Ctrl+K
+    pub skipped_files: Vec<SkippedFile>,
+    pub selected_skipped_file: usize,
+    /// Every indexed project, for labelling results and the Ctrl+O project switcher
+    pub projects: ProjectRegistry,
+    pub selected_project: usize,
 
     pub crawler_config: CrawlerConfig,
+    pub config: Config,
     pub root_path: PathBuf,
 }
 
 impl Engine {
     pub fn new(directory: PathBuf, config: Config) -> Self {
-        let crawler_config = CrawlerConfig::from(&config.general);
+        let crawler_config = CrawlerConfig {
+            max_files: config.general.max_files,
+            ..CrawlerConfig::from(&config.general)
+        };
 
         Self {
             should_quit: false,
+            exit_output: None,
+            exit_command: None,
             state: AppStateEnum::Crawling,
             ui_mode: UIMode::SearchInput,
             spinner_frame: 0,
 
             search_input: Input::default(),
             search_results: Vec::new(),
+            unfiltered_results: Vec::new(),
+            results_filter: String::new(),
+            results_filter_input: None,
+            query_mode: None,
+            search_timing: None,
+            result_explanation: None,
+            index_choice: None,
+            exclude_input: None,
+            keyword_only: false,
             selected_search_result: 0,
+            marked_results: BTreeSet::new(),
             search_results_scroll_offset: 0,
             file_preview_scroll_offset: 0,
+            file_preview_horizontal_offset: 0,
+            file_preview_wrap: true,
+            file_preview_goto_input: None,
+            file_preview_chunk_only: false,
+            file_preview_context_lines: config.preview.context_lines,
+            file_preview_find_input: None,
+            file_preview_find_pattern: None,
             current_search_query: String::new(),
+            search_queries: Vec::new(),
+            search_scope: None,
+            all_projects: false,
             search_error: None,
 
             current_file_content: None,
             current_file_path: None,
+            current_file_hash: None,
+            current_file_info: None,
+            current_file_image: None,
+            current_file_diff: None,
+            file_preview_blame: false,
+            current_file_blame: HashMap::new(),
+            relevant_lines: Vec::new(),
+            relevance_updates: None,
+            file_cache: FileCache::default(),
+            highlighter: PreviewHighlighter::new(&config.languages),
+            inline_images: InlineImages::new(config.preview.images),
 
             processing_service: None,
+            daemon: None,
+            search_updates: None,
+            pending_question: None,
+            answer: None,
+            answer_updates: None,
+            conversation: Self::open_conversation(&directory),
+            question_rewrite: None,
+            search_cancel: CancellationToken::new(),
+            query_suggestion: None,
+            term_completions: None,
+            term_updates: None,
+            completion: None,
+            index_read_only: false,
+            index_progress: None,
+            index_pause: None,
+            indexed_files: Vec::new(),
+            indexed_at: None,
+            corpus_summary: None,
+            file_finder: false,
+
+            bookmarks: BookmarkStore::open(&directory).ok(),
+            selected_bookmark: 0,
+
+            annotations: Vec::new(),
+            selected_annotation: 0,
+            annotation_blame: HashMap::new(),
+
+            notifications: Notifications::default(),
+            selected_notification: 0,
+            skipped_files: Vec::new(),
+            selected_skipped_file: 0,
+            projects: ProjectRegistry::load().unwrap_or_default(),
+            selected_project: 0,
 
             crawler_config,
+            config,
             root_path: directory,
         }
     }
 
     pub fn clear_search(&mut self) {
+        self.search_cancel.cancel();
+        self.search_updates = None;
+        self.question_rewrite = None;
+        self.pending_question = None;
+        self.answer = None;
+        self.answer_updates = None;
+        self.query_suggestion = None;
+        self.completion = None;
         self.search_results.clear();
+        self.unfiltered_results.clear();
+        self.results_filter.clear();
+        self.results_filter_input = None;
+        self.query_mode = None;
+        self.search_timing = None;
         self.selected_search_result = 0;
+        self.marked_results.clear();
         self.search_results_scroll_offset = 0;
         self.current_search_query.clear();
+        self.search_queries.clear();
+        self.search_scope = None;
         self.search_error = None;
         self.current_file_content = None;
         self.current_file_path = None;
+        self.current_file_hash = None;
+        self.current_file_info = None;
+        self.current_file_image = None;
+        self.current_file_diff = None;
+        self.current_file_blame.clear();
+        self.relevant_lines.clear();
+        self.relevance_updates = None;
+        self.file_preview_find_pattern = None;
         self.ui_mode = UIMode::SearchInput;
     }
 
@@ -76,45 +333,244 @@ impl Engine {
         self.state = AppStateEnum::Crawling;
 
         let crawler = FileCrawler::new(self.crawler_config.clone());
-        let files = crawler.crawl_directory(&self.root_path).await?;
+        let cancel = CancellationToken::new();
+        let (files, stats) = crawler
+            .crawl_directory_with_stats(&self.root_path, cancel.clone())
+            .await?;
 
         self.state = AppStateEnum::Chunking;
 
-        let config_dir = match dirs::config_dir() {
-            Some(dir) => dir,
-            None => match std::env::current_dir() {
-                Ok(dir) => dir,
-                Err(_) => PathBuf::from("."),
-            },
-        }
-        .join("sema");
+        let config_dir = ConfigManager::get_data_dir();
 
-        let mut service = StorageManager::new(&config_dir).await?;
-        service.process_and_index_files(files).await?;
-        service.close().await;
+        let service = Arc::new(Mutex::new(
+            StorageManager::new(&config_dir, &self.config).await?,
+        ));
+        StorageManager::process_and_index_files(
+            &service,
+            files.clone(),
+            stats.size_limits.clone(),
+            &cancel,
+        )
+        .await?;
 
-        self.processing_service = Some(StorageManager::new(&config_dir).await?);
+        self.processing_service = Some(service);
+        self.summarize_corpus(&files, stats).await;
+        self.indexed_files = files;
+        self.indexed_at = Some(unix_now());
         self.state = AppStateEnum::Ready;
 
         Ok(())
     }
 
+    /// Breaks down the crawled files for the status screen and keeps the list of skipped
+    /// ones, adding those that failed to index. Chunks and failures are only known when
+    /// this instance has the index open.
+    pub async fn summarize_corpus(&mut self, files: &[PathBuf], mut stats: CrawlStats) {
+        let chunks = match &self.processing_service {
+            Some(service) => {
+                let storage = service.lock().await;
+                stats.skipped.extend(storage.read_failures(files));
+                storage.chunk_count(&self.root_path)
+            }
+            None => None,
+        };
+        stats.sort();
+        self.corpus_summary = Some(CorpusSummary::new(&self.root_path, files, &stats, chunks));
+        self.skipped_files = stats.skipped;
+        self.selected_skipped_file = 0;
+    }
+
+    /// Reruns the query that found the selected result to tell how it was found, for the
+    /// explanation popup. Needs the index open in this instance rather than in a daemon.
+    pub async fn explain_selected(&mut self) -> Result<()> {
+        let Some(result) = self.search_results.get(self.selected_search_result) else {
+            return Ok(());
+        };
+        let Some(service) = &self.processing_service else {
+            anyhow::bail!("Results can't be explained while a daemon answers the searches");
+        };
+
+        let query = result
+            .matched_queries
+            .first()
+            .and_then(|&index| self.search_queries.get(index))
+            .unwrap_or(&self.current_search_query);
+        let query = self.scoped_query(query);
+
+        let explanation = service
+            .lock()
+            .await
+            .explain(&query, &result.chunk, SEARCH_RESULTS_LIMIT)
+            .await?;
+        self.result_explanation = Some(explanation);
+        Ok(())
+    }
+
+    /// Searches run against the existing index as soon as it is open, while new and
+    /// changed files are still being indexed.
+    pub fn indexing_paused(&self) -> bool {
+        matches!(self.state, AppStateEnum::Chunking)
+            && self
+                .index_pause
+                .as_ref()
+                .is_some_and(|pause| *pause.borrow())
+    }
+
+    /// Whether indexing runs here for `p` in the results or preview to pause or resume.
+    pub fn can_pause_indexing(&self) -> bool {
+        matches!(self.state, AppStateEnum::Chunking)
+            && self.index_pause.is_some()
+            && !self.index_read_only
+    }
+
+    pub fn can_search(&self) -> bool {
+        matches!(self.state, AppStateEnum::Ready)
+            || self.processing_service.is_some()
+            || self.daemon.is_some()
+    }
+
     pub async fn execute_search(&mut self, query: &str) -> Result<()> {
+        // Stale results must not arrive after the new ones, and the storage is freed sooner
+        self.search_cancel.cancel();
+        self.search_cancel = CancellationToken::new();
         self.search_error = None;
+        self.search_timing = None;
+        self.query_suggestion = None;
+        self.completion = None;
+        self.answer = None;
+        self.answer_updates = None;
+        self.question_rewrite = None;
+        // The question itself is what gets searched for, made standalone if it follows up
+        // on earlier ones
+        let question = query
+            .strip_prefix(ASK_PREFIX)
+            .map(str::trim)
+            .filter(|question| !question.is_empty());
+        if let (Some(question), Some(conversation)) = (question, &self.conversation) {
+            self.rewrite_question(question, conversation.turns().to_vec());
+            return Ok(());
+        }
+        let query = question.unwrap_or(query.trim_start_matches(ASK_PREFIX));
+        self.search_for(question, query).await
+    }
+
+    /// Has the model rewrite a follow-up in the background, under the search's
+    /// cancellation, and shows the question as being answered meanwhile. The search starts
+    /// from `poll_question_rewrite`.
+    fn rewrite_question(&mut self, question: &str, turns: Vec<Turn>) {
+        let (sender, receiver) = oneshot::channel();
+        let llm = self.config.llm.clone();
+        let cancel = self.search_cancel.clone();
+        let task_question = question.to_string();
+        tokio::spawn(async move {
+            let rewritten = tokio::select! {
+                rewritten = conversation::standalone_query(&llm, &turns, &task_question) => {
+                    rewritten.ok()
+                }
+                _ = cancel.cancelled() => return,
+            };
+            let _ = sender.send((task_question, rewritten));
+        });
+
+        self.current_search_query = question.to_string();
+        self.answer = Some(Answer {
+            question: question.to_string(),
+            query: question.to_string(),
+            ..Answer::default()
+        });
+        self.question_rewrite = Some(receiver);
+    }
+
+    /// Searches for a follow-up once it has been rewritten, or as asked if the model
+    /// failed. Returns whether the search started.
+    pub async fn poll_question_rewrite(&mut self) -> Result<bool> {
+        let Some(receiver) = self.question_rewrite.as_mut() else {
+            return Ok(false);
+        };
+        let (question, rewritten) = match receiver.try_recv() {
+            Ok(rewrite) => rewrite,
+            Err(oneshot::error::TryRecvError::Empty) => return Ok(false),
+            Err(oneshot::error::TryRecvError::Closed) => {
+                self.question_rewrite = None;
+                return Ok(false);
+            }
+        };
+        self.question_rewrite = None;
+        // Replaced once the results are in and the answer starts
+        self.answer = None;
+        let query = rewritten.unwrap_or_else(|| question.clone());
+        self.search_for(Some(&question), &query).await?;
+        Ok(true)
+    }
+
+    /// Runs the search for `query`, answering `question` from its results if one was asked.
+    async fn search_for(&mut self, question: Option<&str>, query: &str) -> Result<()> {
+        self.pending_question = question.map(|question| (question.to_string(), query.to_string()));
         self.current_search_query = query.to_string();
+        self.search_queries = Self::split_queries(query);
+        let scoped_query = self.scoped_query(query);
+        let scoped_queries: Vec<String> = self
+            .search_queries
+            .iter()
+            .map(|query| self.scoped_query(query))
+            .collect();
 
-        if self.processing_service.is_none() {
-            let config_dir = match dirs::config_dir() {
-                Some(dir) => dir,
-                None => match std::env::current_dir() {
-                    Ok(dir) => dir,
-                    Err(_) => PathBuf::from("."),
-                },
+        if self.daemon.is_some() && !scoped_queries.is_empty() {
+            match Self::search_daemon_queries(&self.root_path, &scoped_queries).await {
+                Ok(replies) => {
+                    let answered_by = replies[0].answered_by;
+                    let semantic_unavailable = replies[0].semantic_unavailable.clone();
+                    // Older daemons don't report where the time went
+                    let timing =
+                        replies
+                            .iter()
+                            .try_fold(SearchTiming::default(), |mut timing, reply| {
+                                timing.add(reply.timing.as_ref()?);
+                                Some(timing)
+                            });
+                    let (results, provenance) = Self::merge_query_results(
+                        replies.into_iter().map(|reply| reply.results).collect(),
+                    );
+                    self.start_answer(&results);
+                    self.show_results(
+                        query,
+                        results,
+                        &provenance,
+                        answered_by,
+                        semantic_unavailable.as_deref(),
+                    );
+                    self.search_timing = timing;
+                    return Ok(());
+                }
+                Err(_) => self.daemon = None,
             }
-            .join("sema");
+        }
 
-            self.processing_service = match StorageManager::new(&config_dir).await {
-                Ok(service) => Some(service),
+        if let Some(daemon) = &mut self.daemon {
+            match daemon.search(&scoped_query, SEARCH_RESULTS_LIMIT).await {
+                Ok(reply) => {
+                    self.start_answer(&reply.results);
+                    self.show_results(
+                        query,
+                        reply.results,
+                        &HashMap::new(),
+                        reply.answered_by,
+                        reply.semantic_unavailable.as_deref(),
+                    );
+                    self.query_suggestion = reply.suggestion.map(Self::unscoped);
+                    self.search_timing = reply.timing;
+                    return Ok(());
+                }
+                // The daemon went away; search in-process from now on
+                Err(_) => self.daemon = None,
+            }
+        }
+
+        if self.processing_service.is_none() {
+            let config_dir = ConfigManager::get_data_dir();
+
+            self.processing_service = match StorageManager::new(&config_dir, &self.config).await {
+                Ok(service) => Some(Arc::new(Mutex::new(service))),
                 Err(_) => {
                     self.search_error = Some("Failed to initialize search".to_string());
                     return Ok(());
@@ -122,35 +578,529 @@ impl Engine {
             };
         }
 
-        if let Some(ref mut service) = self.processing_service {
-            match service.search(query, SEARCH_RESULTS_LIMIT).await {
-                Ok(results) => {
-                    let search_results: Vec<SearchResult> = results
-                        .into_iter()
-                        .map(|(chunk, score)| SearchResult {
-                            chunk,
-                            score,
-                            total_matches_in_file: 1,
-                        })
-                        .collect();
-
-                    self.search_results = Self::group_results_by_file(search_results);
-                    self.selected_search_result = 0;
-                    self.search_results_scroll_offset = 0;
-
-                    if !self.search_results.is_empty()
-                        && matches!(self.ui_mode, UIMode::SearchInput)
+        if let Some(service) = self.processing_service.clone() {
+            // Cleared so the first update of this search replaces the previous results
+            self.query_mode = None;
+            let (sender, receiver) = mpsc::unbounded_channel();
+            if scoped_queries.is_empty() {
+                tokio::spawn(stream_search(
+                    service,
+                    scoped_query,
+                    sender,
+                    self.search_cancel.clone(),
+                ));
+            } else {
+                tokio::spawn(stream_queries(
+                    service,
+                    scoped_queries,
+                    sender,
+                    self.search_cancel.clone(),
+                ));
+            }
+            self.search_updates = Some(receiver);
+        }
+
+        Ok(())
+    }
+
+    /// The query as searched: limited to the directory picked from a result, or else to
+    /// this project unless searching them all, since the index holds every project. Sent
+    /// as an `in:` term, so the daemon and the preliminary results honour it too.
+    fn scoped_query(&self, query: &str) -> String {
+        match &self.search_scope {
+            Some(scope) => format!("{} {}", query, PathScope::term(scope)),
+            None if self.all_projects => query.to_string(),
+            // Before the query, so an `in:` typed into it wins
+            None => format!("{} {}", PathScope::term(&self.root_path), query),
+        }
+    }
+
+    /// The project to label a result with: its own while searching every project, and
+    /// otherwise only one other than this.
+    pub fn result_project(&self, file_path: &Path) -> Option<Project> {
+        let project = self.projects.project_of(file_path)?;
+        (self.all_projects || project.root != self.root_path).then_some(project)
+    }
+
+    /// Opens the project switcher, listing the projects indexed since it was last open.
+    pub fn show_projects(&mut self) {
+        if let Ok(projects) = ProjectRegistry::load() {
+            self.projects = projects;
+        }
+        self.selected_project = self.selected_project.min(self.projects.projects().len());
+        self.ui_mode = UIMode::Projects;
+    }
+
+    /// Searches every project for the first entry of the switcher, and otherwise the
+    /// project listed at `index`.
+    pub fn choose_project(&mut self, index: usize) {
+        let root = index
+            .checked_sub(1)
+            .and_then(|index| self.projects.projects().into_iter().nth(index))
+            .map(|project| project.root);
+        self.all_projects = root.is_none();
+        self.search_scope = root.filter(|root| *root != self.root_path);
+        self.ui_mode = UIMode::SearchInput;
+    }
+
+    /// Offers the rest of the last word of the query when the cursor is at its end. The
+    /// term list is loaded from the index in the background on first use, so nothing is
+    /// offered until it arrives.
+    pub fn update_completion(&mut self) {
+        self.completion = None;
+        let value = self.search_input.value();
+        if self.file_finder
+            || !matches!(self.ui_mode, UIMode::SearchInput)
+            || self.search_input.cursor() != value.chars().count()
+        {
+            return;
+        }
+
+        let Some(completions) = &self.term_completions else {
+            self.load_term_completions();
+            return;
+        };
+
+        let word = value
+            .rsplit(char::is_whitespace)
+            .next()
+            .unwrap_or_default()
+            .trim_start_matches(['\'', '~']);
+        if word.chars().all(char::is_alphanumeric) {
+            self.completion = completions.complete(word).map(str::to_string);
+        }
+    }
+
+    /// Walks the term dictionary of the keyword index off the UI thread, once until the
+    /// list is dropped.
+    fn load_term_completions(&mut self) {
+        if self.term_updates.is_some() {
+            return;
+        }
+        let Some(service) = self.processing_service.clone() else {
+            return;
+        };
+        let (sender, receiver) = oneshot::channel();
+        tokio::task::spawn_blocking(move || {
+            let terms = service.blocking_lock().indexed_terms();
+            let _ = sender.send(TermCompletions::new(terms));
+        });
+        self.term_updates = Some(receiver);
+    }
+
+    /// Takes the term list once it has loaded and offers a completion of the word typed
+    /// meanwhile. Returns whether it arrived.
+    pub fn poll_term_completions(&mut self) -> bool {
+        let Some(receiver) = self.term_updates.as_mut() else {
+            return false;
+        };
+        match receiver.try_recv() {
+            Ok(completions) => {
+                self.term_completions = Some(completions);
+                self.term_updates = None;
+                self.update_completion();
+                true
+            }
+            Err(oneshot::error::TryRecvError::Empty) => false,
+            Err(oneshot::error::TryRecvError::Closed) => {
+                self.term_updates = None;
+                false
+            }
+        }
+    }
+
+    /// Drops the term list, so it is loaded again with the newly indexed terms.
+    pub fn reset_term_completions(&mut self) {
+        self.term_completions = None;
+        self.term_updates = None;
+    }
+
+    /// The conversation `?` questions follow up on, warning when an unreadable one had to
+    /// be moved aside.
+    fn open_conversation(root: &Path) -> Option<Conversation> {
+        let conversation = Conversation::open(root).ok()?;
+        if let Some(aside) = conversation.set_aside() {
+            tracing::warn!(
+                "The earlier conversation couldn't be read and was moved to {}",
+                aside.display()
+            );
+        }
+        Some(conversation)
+    }
+
+    /// The queries of a `;`-separated search, or nothing for a single query.
+    fn split_queries(query: &str) -> Vec<String> {
+        let queries: Vec<String> = query
+            .split(QUERY_SEPARATOR)
+            .map(str::trim)
+            .filter(|query| !query.is_empty())
+            .map(str::to_string)
+            .collect();
+        if queries.len() > 1 {
+            queries
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Sends each query on a connection of its own, so the daemon works on them together.
+    async fn search_daemon_queries(root: &Path, queries: &[String]) -> Result<Vec<SearchReply>> {
+        futures::future::try_join_all(queries.iter().map(|query| async move {
+            let mut daemon = DaemonClient::connect(root)
+                .await
+                .context("The daemon is no longer running")?;
+            daemon.search(query, SEARCH_RESULTS_LIMIT).await
+        }))
+        .await
+    }
+
+    /// Merges the results of several queries, in query order, keeping each chunk's best
+    /// score and recording which queries found it.
+    fn merge_query_results(
+        per_query: Vec<Vec<(Chunk, f32)>>,
+    ) -> (Vec<(Chunk, f32)>, HashMap<String, Vec<usize>>) {
+        let mut merged: HashMap<String, (Chunk, f32)> = HashMap::new();
+        let mut provenance: HashMap<String, Vec<usize>> = HashMap::new();
+
+        for (index, results) in per_query.into_iter().enumerate() {
+            for (chunk, score) in results {
+                provenance.entry(chunk.id.clone()).or_default().push(index);
+                let entry = merged.entry(chunk.id.clone()).or_insert((chunk, score));
+                entry.1 = entry.1.max(score);
+            }
+        }
+
+        let mut results: Vec<(Chunk, f32)> = merged.into_values().collect();
+        results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        results.truncate(SEARCH_RESULTS_LIMIT);
+        (results, provenance)
+    }
+
+    /// A suggested respelling without the scope term, which the search adds back itself.
+    fn unscoped(suggestion: String) -> String {
+        PathScope::extract(&suggestion).0
+    }
+
+    /// Whether results of the last search are still on their way.
+    pub fn search_in_progress(&self) -> bool {
+        self.search_updates.is_some() || self.question_rewrite.is_some()
+    }
+
+    /// Shows results that arrived since the last call. Returns None when nothing arrived,
+    /// otherwise whether the search has finished.
+    pub fn poll_search_updates(&mut self) -> Option<bool> {
+        let receiver = self.search_updates.as_mut()?;
+        let mut updates = Vec::new();
+        let finished = loop {
+            match receiver.try_recv() {
+                Ok(update) => updates.push(update),
+                Err(mpsc::error::TryRecvError::Empty) => break false,
+                Err(mpsc::error::TryRecvError::Disconnected) => break true,
+            }
+        };
+        let arrived = !updates.is_empty();
+
+        let query = self.current_search_query.clone();
+        for update in updates {
+            match update {
+                SearchUpdate::Preliminary(results) => {
+                    self.show_results(&query, results, &HashMap::new(), QueryMode::Keyword, None)
+                }
+                SearchUpdate::Done {
+                    results,
+                    provenance,
+                    answered_by,
+                    semantic_unavailable,
+                    suggestion,
+                    timing,
+                } => {
+                    self.start_answer(&results);
+                    self.show_results(
+                        &query,
+                        results,
+                        &provenance,
+                        answered_by,
+                        semantic_unavailable.as_deref(),
+                    );
+                    self.query_suggestion = suggestion.map(Self::unscoped);
+                    self.search_timing = Some(timing);
+                }
+                SearchUpdate::Failed(e) => {
+                    self.search_error = Some(format!("Search failed: {}", e))
+                }
+            }
+        }
+        // Cleared only now, so the final results count as an update of the same search
+        if finished {
+            self.search_updates = None;
+        }
+        (arrived || finished).then_some(finished)
+    }
+
+    /// Asks the model the pending question with the best of `results` as its sources,
+    /// streaming the answer in as `poll_answer_updates` is called.
+    fn start_answer(&mut self, results: &[(Chunk, f32)]) {
+        let Some((question, query)) = self.pending_question.take() else {
+            return;
+        };
+        let (sources, withheld) = ask::select_sources(
+            results.iter().map(|(chunk, _)| chunk.clone()),
+            self.config.llm.answer_sources.max(1),
+            self.config.indexing.embed_secrets,
+        );
+        if withheld > 0 {
+            self.notifications.push(
+                Level::Info,
+                format!(
+                    "Withheld {} matching source(s) that look like they hold credentials; set \
+                     [indexing] embed_secrets to send them",
+                    withheld
+                ),
+            );
+        }
+        if sources.is_empty() {
+            return;
+        }
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let llm = self.config.llm.clone();
+        let root = self.root_path.clone();
+        let cancel = self.search_cancel.clone();
+        let task_question = question.clone();
+        let task_sources = sources.clone();
+        let history = self
+            .conversation
+            .as_ref()
+            .map(|conversation| conversation.turns().to_vec())
+            .unwrap_or_default();
+        tokio::spawn(async move {
+            let answered = ask::stream_answer(
+                &llm,
+                &history,
+                &task_question,
+                &task_sources,
+                &root,
+                &cancel,
+                |text| {
+                    let _ = sender.send(AnswerUpdate::Text(text.to_string()));
+                },
+            )
+            .await;
+            if let Err(e) = answered {
+                let _ = sender.send(AnswerUpdate::Failed(format!("{:#}", e)));
+            }
+        });
+
+        self.answer = Some(Answer {
+            question,
+            query,
+            sources,
+            ..Answer::default()
+        });
+        self.answer_updates = Some(receiver);
+    }
+
+    /// Adds what arrived of the answer since the last call. Returns whether anything did.
+    pub fn poll_answer_updates(&mut self) -> bool {
+        let (Some(receiver), Some(answer)) = (self.answer_updates.as_mut(), self.answer.as_mut())
+        else {
+            return false;
+        };
+        let mut changed = false;
+        loop {
+            match receiver.try_recv() {
+                Ok(AnswerUpdate::Text(text)) => answer.text.push_str(&text),
+                Ok(AnswerUpdate::Failed(e)) => answer.error = Some(e),
+                Err(mpsc::error::TryRecvError::Empty) => break,
+                Err(mpsc::error::TryRecvError::Disconnected) => {
+                    answer.finished = true;
+                    self.answer_updates = None;
+                    if answer.error.is_none()
+                        && !answer.text.is_empty()
+                        && let Some(conversation) = &mut self.conversation
                     {
-                        self.ui_mode = UIMode::SearchResults;
+                        let _ = conversation.record(Turn {
+                            question: answer.question.clone(),
+                            query: answer.query.clone(),
+                            answer: answer.text.clone(),
+                        });
                     }
-                }
-                Err(e) => {
-                    self.search_error = Some(format!("Search failed: {}", e));
+                    return true;
                 }
             }
+            changed = true;
         }
+        changed
+    }
 
-        Ok(())
+    /// Forgets the questions asked so far, so the next one is read on its own.
+    pub fn forget_conversation(&mut self) -> Result<()> {
+        match &mut self.conversation {
+            Some(conversation) => conversation.clear(),
+            None => Ok(()),
+        }
+    }
+
+    fn show_results(
+        &mut self,
+        query: &str,
+        results: Vec<(Chunk, f32)>,
+        provenance: &HashMap<String, Vec<usize>>,
+        answered_by: QueryMode,
+        semantic_unavailable: Option<&str>,
+    ) {
+        let search_results: Vec<SearchResult> = results
+            .into_iter()
+            .map(|(chunk, score)| SearchResult {
+                matched_queries: provenance.get(&chunk.id).cloned().unwrap_or_default(),
+                chunk,
+                score,
+                total_matches_in_file: 1,
+            })
+            .collect();
+
+        // Later updates of a streamed search keep the filter and the selected result; the
+        // first one, like any other search, starts over
+        let results = Self::group_results_by_file(search_results);
+        if self.query_mode.is_some() && self.search_in_progress() {
+            self.replace_results(results);
+        } else {
+            self.set_results(results);
+        }
+
+        self.query_mode = Some(answered_by);
+        if let Some(reason) = semantic_unavailable
+            && QueryMode::parse(query).0 != QueryMode::Keyword
+        {
+            self.search_error = Some(format!(
+                "Embedding model unavailable, showing keyword matches: {}",
+                reason
+            ));
+        }
+
+        if !self.search_results.is_empty() && matches!(self.ui_mode, UIMode::SearchInput) {
+            self.ui_mode = UIMode::SearchResults;
+        }
+    }
+
+    /// Ranks the crawled files by how well their paths fuzzy-match the query, without
+    /// touching the index. Each file becomes a result pointing at its first line.
+    pub fn find_files(&mut self, query: &str) {
+        self.current_search_query = query.to_string();
+        self.query_mode = None;
+        self.search_timing = None;
+
+        let mut matches: Vec<(i64, &PathBuf, usize)> = self
+            .indexed_files
+            .iter()
+            .filter_map(|file_path| {
+                let relative = self.relative_path(file_path);
+                let score = fuzzy::score(query, &relative)?;
+                Some((score, file_path, relative.len()))
+            })
+            .collect();
+        // Among equal scores, shorter paths are usually the ones being looked for
+        matches.sort_by(|a, b| b.0.cmp(&a.0).then(a.2.cmp(&b.2)));
+        matches.truncate(SEARCH_RESULTS_LIMIT);
+
+        let best_score = matches.first().map_or(1, |(score, _, _)| (*score).max(1)) as f32;
+        let results = matches
+            .into_iter()
+            .map(|(score, file_path, _)| SearchResult {
+                chunk: Chunk {
+                    id: file_path.to_string_lossy().to_string(),
+                    file_path: file_path.clone(),
+                    start_line: 1,
+                    end_line: 1,
+                    ..Chunk::default()
+                },
+                score: score as f32 / best_score,
+                total_matches_in_file: 1,
+                matched_queries: Vec::new(),
+            })
+            .collect();
+        self.set_results(results);
+    }
+
+    /// Shows a new result set, dropping any results filter from the previous one.
+    pub fn set_results(&mut self, results: Vec<SearchResult>) {
+        self.unfiltered_results = results;
+        self.results_filter.clear();
+        self.results_filter_input = None;
+        self.apply_results_filter();
+    }
+
+    fn replace_results(&mut self, results: Vec<SearchResult>) {
+        let selected = self
+            .search_results
+            .get(self.selected_search_result)
+            .map(|result| result.chunk.id.clone());
+        let scroll_offset = self.search_results_scroll_offset;
+
+        self.unfiltered_results = results;
+        self.apply_results_filter();
+
+        if let Some(index) = selected.and_then(|id| {
+            self.search_results
+                .iter()
+                .position(|result| result.chunk.id == id)
+        }) {
+            self.selected_search_result = index;
+            self.search_results_scroll_offset = scroll_offset.min(index);
+        }
+    }
+
+    /// Narrows the last result set to the results matching `results_filter`, without
+    /// querying the index again.
+    pub fn apply_results_filter(&mut self) {
+        let terms: Vec<String> = self
+            .results_filter
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .collect();
+
+        self.search_results = self
+            .unfiltered_results
+            .iter()
+            .filter(|result| {
+                let path = self.relative_path(&result.chunk.file_path).to_lowercase();
+                terms
+                    .iter()
+                    .all(|term| Self::matches_filter_term(result, &path, term))
+            })
+            .cloned()
+            .collect();
+        self.selected_search_result = 0;
+        self.marked_results.clear();
+        self.search_results_scroll_offset = 0;
+    }
+
+    /// `ext:rs` or `.rs` match the extension, `dir:src` or `src/` a directory in the path,
+    /// and any other term a substring of the path or the chunk, ignoring case.
+    fn matches_filter_term(result: &SearchResult, path: &str, term: &str) -> bool {
+        if let Some(extension) = term
+            .strip_prefix("ext:")
+            .or_else(|| term.strip_prefix('.').filter(|rest| !rest.contains('/')))
+        {
+            return path.ends_with(&format!(".{}", extension.trim_start_matches('.')));
+        }
+
+        let directory = term.strip_prefix("dir:").or_else(|| term.strip_suffix('/'));
+        if let Some(directory) = directory {
+            let directory = directory.trim_matches('/');
+            return format!("/{}", path).contains(&format!("/{}/", directory));
+        }
+
+        path.contains(term) || result.chunk.content.to_lowercase().contains(term)
+    }
+
+    /// A file's path relative to the project root, with archive and stdin files shown under
+    /// their virtual names.
+    fn relative_path(&self, file_path: &std::path::Path) -> String {
+        let shown = crate::crawler::virtual_path(file_path, &self.root_path)
+            .unwrap_or_else(|| file_path.to_path_buf());
+        let relative = shown.strip_prefix(&self.root_path).unwrap_or(&shown);
+        relative.to_string_lossy().to_string()
     }
 
     fn group_results_by_file(results: Vec<SearchResult>) -> Vec<SearchResult> {
@@ -167,8 +1117,17 @@ impl Engine {
         for mut group in file_groups.into_values() {
             group.sort_by_key(|r| r.chunk.start_line);
             let total_count = group.len();
+            let best_score = group.iter().map(|r| r.score).fold(0.0f32, f32::max);
+            let mut matched_queries: Vec<usize> = group
+                .iter()
+                .flat_map(|r| r.matched_queries.iter().copied())
+                .collect();
+            matched_queries.sort_unstable();
+            matched_queries.dedup();
             if let Some(mut first) = group.into_iter().next() {
                 first.total_matches_in_file = total_count;
+                first.score = best_score;
+                first.matched_queries = matched_queries;
                 grouped_results.push(first);
             }
         }
@@ -181,26 +1140,423 @@ impl Engine {
         grouped_results
     }
 
-    pub async fn load_file_content(&self, file_path: &std::path::Path) -> Result<String> {
-        let metadata = tokio::fs::metadata(file_path).await?;
-        const MAX_SIZE: u64 = 1_048_576;
+    /// The results a batch action applies to: the marked ones, or the selected one if
+    /// nothing is marked.
+    pub fn batch_results(&self) -> Vec<&SearchResult> {
+        if self.marked_results.is_empty() {
+            return self
+                .search_results
+                .get(self.selected_search_result)
+                .into_iter()
+                .collect();
+        }
+
+        self.marked_results
+            .iter()
+            .filter_map(|&index| self.search_results.get(index))
+            .collect()
+    }
 
-        if metadata.len() > MAX_SIZE {
-            let size_mb = metadata.len() as f64 / 1_048_576.0;
-            return Ok(format!("File too large to display ({:.1} MB)", size_mb));
+    /// Takes the settings that can change while running from a reloaded config: search
+    /// options, synonyms, query expansion, previews and highlighting. Returns the settings
+    /// whose changes only apply after a restart.
+    pub async fn apply_config(&mut self, config: Config) -> Vec<&'static str> {
+        let mut restart_needed = Vec::new();
+        if !same_settings(&self.config.general, &config.general) {
+            restart_needed.push("[general]");
+        }
+        if !same_settings(&self.config.indexing, &config.indexing) {
+            restart_needed.push("[indexing]");
+        }
+        if !same_settings(&self.config.resources, &config.resources) {
+            restart_needed.push("[resources]");
+        }
+        if !same_settings(&self.config.vector_index, &config.vector_index) {
+            restart_needed.push("[vector_index]");
+        }
+        if !same_settings(&self.config.semantic, &config.semantic) {
+            restart_needed.push("[semantic]");
+        }
+        // Weighted into the keyword index when it opens
+        if self.config.search.docs_boost != config.search.docs_boost {
+            restart_needed.push("docs_boost");
         }
 
-        tokio::fs::read_to_string(file_path)
-            .await
-            .or_else(|e| Ok(format!("Failed to read file: {}", e)))
+        if let Some(service) = &self.processing_service {
+            service.lock().await.apply_search_settings(&config);
+        }
+        if self.config.preview.context_lines != config.preview.context_lines {
+            self.file_preview_context_lines = config.preview.context_lines;
+        }
+        self.highlighter = PreviewHighlighter::new(&config.languages);
+        self.inline_images.configure(config.preview.images);
+
+        self.config.search = config.search;
+        self.config.llm = config.llm;
+        self.config.preview = config.preview;
+        self.config.languages = config.languages;
+        self.config.synonyms = config.synonyms;
+        restart_needed
+    }
+
+    /// Finds the lines of the selected chunk most similar to the current semantic query.
+    pub fn update_relevant_lines(&mut self) {
+        self.relevant_lines.clear();
+        let Some(result) = self
+            .search_results
+            .get(self.selected_search_result)
+            .cloned()
+        else {
+            return;
+        };
+        let query = self.current_search_query.clone();
+        self.highlight_relevant_lines(&query, &result.chunk);
+    }
+
+    /// Marks the lines of `chunk` closest in meaning to `text`, the query or the answer
+    /// sentences citing the chunk. Embedding the lines takes a while, so they are found in
+    /// the background and marked by `poll_relevant_lines`; a later call supersedes them.
+    pub fn highlight_relevant_lines(&mut self, text: &str, chunk: &Chunk) {
+        self.relevant_lines.clear();
+        self.relevance_updates = None;
+        if !self.config.preview.semantic_highlights {
+            return;
+        }
+        // The daemon's connection stays with the UI, so the task opens one of its own
+        let daemon_root = self.daemon.is_some().then(|| self.root_path.clone());
+        let service = self.processing_service.clone();
+        if daemon_root.is_none() && service.is_none() {
+            return;
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        let text = text.to_string();
+        let chunk = chunk.clone();
+        let min_similarity = self.config.search.min_similarity;
+        tokio::spawn(async move {
+            let similarities = match (daemon_root, service) {
+                (Some(root), _) => match DaemonClient::connect(&root).await {
+                    Some(mut daemon) => daemon.line_relevance(&text, &chunk).await,
+                    None => return,
+                },
+                (None, Some(service)) => service.lock().await.line_relevance(&text, &chunk).await,
+                (None, None) => return,
+            };
+            let Ok(similarities) = similarities else {
+                return;
+            };
+
+            let mut ranked: Vec<(usize, f32)> = similarities
+                .into_iter()
+                .enumerate()
+                .filter(|(_, similarity)| *similarity >= min_similarity)
+                .collect();
+            ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+            let first_line = chunk.start_line.saturating_sub(1);
+            let _ = sender.send(
+                ranked
+                    .into_iter()
+                    .take(SEMANTIC_HIGHLIGHT_LINES)
+                    .map(|(offset, _)| first_line + offset)
+                    .collect(),
+            );
+        });
+        self.relevance_updates = Some(receiver);
+    }
+
+    /// Marks the relevant lines once they have been found. Returns whether they arrived.
+    pub fn poll_relevant_lines(&mut self) -> bool {
+        let Some(receiver) = self.relevance_updates.as_mut() else {
+            return false;
+        };
+        match receiver.try_recv() {
+            Ok(lines) => {
+                self.relevant_lines = lines;
+                self.relevance_updates = None;
+                true
+            }
+            Err(oneshot::error::TryRecvError::Empty) => false,
+            Err(oneshot::error::TryRecvError::Closed) => {
+                self.relevance_updates = None;
+                false
+            }
+        }
+    }
+
+    pub fn current_file_line_count(&self) -> usize {
+        self.current_file_content
+            .as_ref()
+            .map_or(0, |content| content.lines().count())
     }
 
     pub async fn update_current_file_content(&mut self, file_path: &std::path::Path) {
-        let content = self
-            .load_file_content(file_path)
-            .await
-            .unwrap_or_else(|_| "Failed to load file".to_string());
-        self.current_file_content = Some(content);
+        self.preview_file(file_path).await;
+        self.load_file_info().await;
+    }
+
+    /// Loads a file into the preview without touching the index, so it works while a
+    /// search holds the storage. `load_file_info` fills in the summary afterwards.
+    pub async fn preview_file(&mut self, file_path: &std::path::Path) {
+        if images::is_image(file_path) {
+            // Described in text, which the image covers where the terminal can draw it
+            let (image, description) = match PreviewImage::load(file_path).await {
+                Ok(image) => {
+                    let description = image.describe();
+                    (Some(image), description)
+                }
+                Err(e) => (None, format!("Failed to read image: {:#}", e)),
+            };
+            self.current_file_hash = Some(xxhash_rust::xxh3::xxh3_64(description.as_bytes()));
+            self.current_file_content = Some(Arc::from(description));
+            self.current_file_image = image;
+        } else {
+            let cached = self.file_cache.get(file_path).await;
+            self.highlighter
+                .request(file_path, cached.hash, cached.content.clone());
+            self.current_file_content = Some(cached.content);
+            self.current_file_hash = Some(cached.hash);
+            self.current_file_image = None;
+        }
         self.current_file_path = Some(file_path.to_path_buf());
+        self.current_file_info = None;
+        self.current_file_blame.clear();
+
+        // The diff view follows the selection until it is toggled off
+        if self.current_file_diff.is_some() {
+            self.current_file_diff = git::diff_against_head(file_path).await.ok();
+        }
     }
+
+    pub async fn load_file_info(&mut self) {
+        let Some(file_path) = self.current_file_path.clone() else {
+            return;
+        };
+        self.current_file_info = if let Some(daemon) = &mut self.daemon {
+            daemon.file_info(&file_path).await.ok().flatten()
+        } else if let Some(service) = &self.processing_service {
+            service.lock().await.file_info(&file_path).await
+        } else {
+            None
+        };
+    }
+
+    /// Scans the index for annotations and switches to the view listing them.
+    pub async fn show_annotations(&mut self) {
+        let annotations = if let Some(daemon) = &mut self.daemon {
+            daemon.annotations().await
+        } else if let Some(service) = &self.processing_service {
+            service.lock().await.annotations()
+        } else {
+            Ok(Vec::new())
+        };
+
+        match annotations {
+            Ok(annotations) => {
+                self.annotations = annotations;
+                self.selected_annotation = 0;
+                self.annotation_blame.clear();
+                self.ui_mode = UIMode::Annotations;
+            }
+            Err(e) => self.search_error = Some(format!("Failed to list TODOs: {}", e)),
+        }
+    }
+
+    /// Blames the annotated lines of files around the selection that have not been blamed
+    /// yet, one git call per file.
+    pub async fn load_visible_annotation_blame(&mut self, visible_rows: usize) {
+        if !matches!(self.ui_mode, UIMode::Annotations) {
+            return;
+        }
+
+        let start = self.selected_annotation.saturating_sub(visible_rows);
+        let end = (self.selected_annotation + visible_rows).min(self.annotations.len());
+        let mut files: Vec<PathBuf> = self
+            .annotations
+            .get(start..end)
+            .unwrap_or_default()
+            .iter()
+            .map(|annotation| annotation.file_path.clone())
+            .filter(|file_path| !self.annotation_blame.contains_key(file_path))
+            .collect();
+        files.dedup();
+
+        for file_path in files {
+            let lines: Vec<usize> = self
+                .annotations
+                .iter()
+                .filter(|annotation| annotation.file_path == file_path)
+                .map(|annotation| annotation.line)
+                .collect();
+            // Files outside git, or changed since they were indexed, show no ages rather than
+            // being retried on every keystroke
+            let blame = git::blame_lines(&file_path, &lines)
+                .await
+                .unwrap_or_default();
+            self.annotation_blame.insert(file_path, blame);
+        }
+    }
+
+    /// Switches the preview between the file and its diff against HEAD.
+    pub async fn toggle_diff(&mut self) {
+        if self.current_file_diff.take().is_some() {
+            return;
+        }
+        let Some(file_path) = self.current_file_path.clone() else {
+            return;
+        };
+
+        match git::diff_against_head(&file_path).await {
+            Ok(diff) => {
+                self.current_file_diff = Some(diff);
+                self.file_preview_scroll_offset = 0;
+            }
+            Err(e) => self.search_error = Some(format!("No diff: {}", e)),
+        }
+    }
+
+    /// Blames the lines on screen that have not been blamed yet. Runs after every event,
+    /// so scrolling fetches one screenful at a time rather than the whole file up front.
+    pub async fn load_visible_blame(&mut self, visible_lines: usize) {
+        if !self.file_preview_blame {
+            return;
+        }
+        let Some(file_path) = self.current_file_path.clone() else {
+            return;
+        };
+
+        let total_lines = self.current_file_line_count();
+        let (start, visible_lines) = match self.search_results.get(self.selected_search_result) {
+            Some(result) if self.file_preview_chunk_only => {
+                let start = result
+                    .chunk
+                    .start_line
+                    .saturating_sub(1)
+                    .saturating_sub(self.file_preview_context_lines);
+                let end = result.chunk.end_line + self.file_preview_context_lines;
+                (start, end.saturating_sub(start))
+            }
+            _ => (self.file_preview_scroll_offset, visible_lines),
+        };
+        let start = start.min(total_lines);
+        let end = (start + visible_lines).min(total_lines);
+        if (start..end).all(|line| self.current_file_blame.contains_key(&line)) {
+            return;
+        }
+
+        match git::blame(&file_path, start + 1, end).await {
+            Ok(blame) => self.current_file_blame.extend(blame),
+            Err(e) => {
+                self.file_preview_blame = false;
+                self.search_error = Some(format!("No blame: {}", e));
+            }
+        }
+    }
+}
+
+/// Runs a search in the background, sending keyword matches first when the query is also
+/// answered semantically. Nothing is sent once `cancel` fires. The storage is released in
+/// between, and while the query is embedded, so the preview can load the first results
+/// meanwhile.
+async fn stream_search(
+    service: SharedStorage,
+    query: String,
+    updates: mpsc::UnboundedSender<SearchUpdate>,
+    cancel: CancellationToken,
+) {
+    // A superseded search may still be queued behind the one before it
+    let storage = service.lock().await;
+    if cancel.is_cancelled() {
+        return;
+    }
+    if let Some(results) = storage.preliminary_search(&query, SEARCH_RESULTS_LIMIT) {
+        let _ = updates.send(SearchUpdate::Preliminary(results));
+    }
+    drop(storage);
+
+    let searched =
+        StorageManager::search_shared(&service, &query, SEARCH_RESULTS_LIMIT, &cancel).await;
+    let update = match searched {
+        Err(_) if cancel.is_cancelled() => return,
+        Ok((results, timing)) => {
+            let storage = service.lock().await;
+            SearchUpdate::Done {
+                provenance: HashMap::new(),
+                suggestion: results
+                    .is_empty()
+                    .then(|| storage.suggest_correction(&query))
+                    .flatten(),
+                results,
+                answered_by: storage.answered_by(&query),
+                semantic_unavailable: storage.semantic_unavailable().map(str::to_string),
+                timing,
+            }
+        }
+        Err(e) => SearchUpdate::Failed(e.to_string()),
+    };
+    let _ = updates.send(update);
+}
+
+/// Runs several queries together and sends their merged results once all have finished.
+/// Their queries are embedded at the same time, each taking the storage only to read the
+/// indexes.
+async fn stream_queries(
+    service: SharedStorage,
+    queries: Vec<String>,
+    updates: mpsc::UnboundedSender<SearchUpdate>,
+    cancel: CancellationToken,
+) {
+    let searches = queries.iter().map(|query| {
+        let service = service.clone();
+        let cancel = cancel.clone();
+        async move {
+            let (results, timing) =
+                StorageManager::search_shared(&service, query, SEARCH_RESULTS_LIMIT, &cancel)
+                    .await?;
+            let answered_by = service.lock().await.answered_by(query);
+            anyhow::Ok((results, answered_by, timing))
+        }
+    });
+
+    let update = match futures::future::try_join_all(searches).await {
+        Err(_) if cancel.is_cancelled() => return,
+        Ok(outcomes) => {
+            let answered_by = outcomes[0].1;
+            let mut timing = SearchTiming::default();
+            for (_, _, query_timing) in &outcomes {
+                timing.add(query_timing);
+            }
+            let (results, provenance) = Engine::merge_query_results(
+                outcomes
+                    .into_iter()
+                    .map(|(results, _, _)| results)
+                    .collect(),
+            );
+            SearchUpdate::Done {
+                results,
+                provenance,
+                answered_by,
+                semantic_unavailable: service
+                    .lock()
+                    .await
+                    .semantic_unavailable()
+                    .map(str::to_string),
+                suggestion: None,
+                timing,
+            }
+        }
+        Err(e) => SearchUpdate::Failed(e.to_string()),
+    };
+    let _ = updates.send(update);
+}
+
+/// Compares two config sections by their serialized form, as they don't implement `PartialEq`.
+fn same_settings<T: serde::Serialize>(current: &T, reloaded: &T) -> bool {
+    serde_json::to_value(current).ok() == serde_json::to_value(reloaded).ok()
+}
+
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
 }
@@ -1,10 +1,56 @@
+use super::indexing::IndexChoice;
+use super::ui::UI;
 use crate::types::{SearchResult, UIMode};
 use ratatui::crossterm::event::{KeyCode, KeyEvent};
 use tui_input::{Input, backend::crossterm::EventHandler as InputEventHandler};
 
+const HORIZONTAL_SCROLL_STEP: usize = 8;
+
 pub enum EventResult {
     ExecuteSearch(String),
     OpenFile,
+    RevealFile,
+    PrintDirectory,
+    ToggleBookmark,
+    SaveQuery,
+    ShowBookmarks,
+    ShowNotifications,
+    ShowSkippedFiles,
+    ShowProjects,
+    ExplainResult,
+    /// Opens the numbered source of the answer in the preview
+    JumpToSource(usize),
+    ChooseIndexing(IndexChoice),
+    /// Pauses or resumes the background indexing
+    TogglePause,
+    OpenBookmark(usize),
+    RemoveBookmark(usize),
+    OpenAnnotation(usize),
+    /// Searches the project at this entry of the switcher, or every project for the first
+    ChooseProject(usize),
+    StartGoToLine,
+    GoToLine(usize),
+    GoToEnd,
+    StartFind,
+    Find(String),
+    FindNext,
+    FindPrevious,
+    ToggleMark,
+    CopyPaths,
+    ExportMarkdown,
+    ExportResults,
+    ToggleChunkPreview,
+    AdjustContextLines(isize),
+    OpenQuickfix,
+    ToggleFileFinder,
+    ToggleDiff,
+    ToggleBlame,
+    AcceptSuggestion,
+    ScopeToDirectory,
+    ClearScope,
+    StartResultsFilter,
+    FilterResults,
+    ClearResultsFilter,
     Continue,
     Quit,
 }
@@ -19,11 +65,13 @@ impl EventHandler {
         selected_search_result: &mut usize,
         search_results_scroll_offset: &mut usize,
         file_preview_scroll_offset: &mut usize,
+        file_preview_horizontal_offset: &mut usize,
+        file_preview_wrap: &mut bool,
         search_results_len: usize,
         current_search_result: Option<&SearchResult>,
         terminal_height: u16,
     ) -> EventResult {
-        let results_per_page = ((terminal_height.saturating_sub(2)) / 3).max(1) as usize;
+        let results_per_page = UI::results_per_page(terminal_height);
 
         match key.code {
             KeyCode::Char('q') => EventResult::Quit,
@@ -42,13 +90,23 @@ impl EventHandler {
                         EventResult::Continue
                     }
                 }
+                UIMode::Bookmarks
+                | UIMode::Annotations
+                | UIMode::Notifications
+                | UIMode::SkippedFiles
+                | UIMode::Projects => EventResult::Continue,
             },
             KeyCode::Esc => match *ui_mode {
                 UIMode::FilePreview => {
                     *ui_mode = UIMode::SearchResults;
                     EventResult::Continue
                 }
-                UIMode::SearchResults => {
+                UIMode::SearchResults
+                | UIMode::Bookmarks
+                | UIMode::Annotations
+                | UIMode::Notifications
+                | UIMode::SkippedFiles
+                | UIMode::Projects => {
                     *ui_mode = UIMode::SearchInput;
                     EventResult::Continue
                 }
@@ -57,12 +115,20 @@ impl EventHandler {
                     EventResult::ExecuteSearch(String::new())
                 }
             },
+            KeyCode::Tab if search_results_len == 0 && matches!(*ui_mode, UIMode::SearchInput) => {
+                EventResult::AcceptSuggestion
+            }
             KeyCode::Tab => {
                 if search_results_len > 0 {
                     match *ui_mode {
                         UIMode::SearchInput => *ui_mode = UIMode::SearchResults,
                         UIMode::SearchResults => *ui_mode = UIMode::FilePreview,
-                        UIMode::FilePreview => *ui_mode = UIMode::SearchInput,
+                        UIMode::FilePreview
+                        | UIMode::Bookmarks
+                        | UIMode::Annotations
+                        | UIMode::Notifications
+                        | UIMode::SkippedFiles
+                        | UIMode::Projects => *ui_mode = UIMode::SearchInput,
                     }
                 }
                 EventResult::Continue
@@ -152,11 +218,40 @@ impl EventHandler {
                 EventResult::Continue
             }
             KeyCode::Left | KeyCode::Right | KeyCode::Home | KeyCode::End => {
-                if matches!(*ui_mode, UIMode::SearchInput) {
-                    search_input.handle_event(&ratatui::crossterm::event::Event::Key(*key));
+                match *ui_mode {
+                    UIMode::SearchInput => {
+                        search_input.handle_event(&ratatui::crossterm::event::Event::Key(*key));
+                    }
+                    UIMode::FilePreview if !*file_preview_wrap => match key.code {
+                        KeyCode::Left => {
+                            *file_preview_horizontal_offset = file_preview_horizontal_offset
+                                .saturating_sub(HORIZONTAL_SCROLL_STEP);
+                        }
+                        KeyCode::Right => {
+                            *file_preview_horizontal_offset += HORIZONTAL_SCROLL_STEP;
+                        }
+                        KeyCode::Home => *file_preview_horizontal_offset = 0,
+                        _ => {}
+                    },
+                    _ => {}
                 }
                 EventResult::Continue
             }
+            KeyCode::Char(c)
+                if key
+                    .modifiers
+                    .contains(ratatui::crossterm::event::KeyModifiers::CONTROL)
+                    && matches!(c, 'b' | 's' | 'p' | 'n' | 'k' | 'o') =>
+            {
+                match c {
+                    'b' => EventResult::ShowBookmarks,
+                    's' => EventResult::SaveQuery,
+                    'n' => EventResult::ShowNotifications,
+                    'k' => EventResult::ShowSkippedFiles,
+                    'o' => EventResult::ShowProjects,
+                    _ => EventResult::ToggleFileFinder,
+                }
+            }
             KeyCode::Char(c) => {
                 if matches!(*ui_mode, UIMode::SearchInput) {
                     search_input.handle_event(&ratatui::crossterm::event::Event::Key(*key));
@@ -166,6 +261,57 @@ impl EventHandler {
                         .contains(ratatui::crossterm::event::KeyModifiers::CONTROL)
                 {
                     return EventResult::Quit;
+                } else if c == 'p'
+                    && key.modifiers.is_empty()
+                    && matches!(*ui_mode, UIMode::SearchResults | UIMode::FilePreview)
+                {
+                    return EventResult::TogglePause;
+                } else if matches!(*ui_mode, UIMode::FilePreview)
+                    && matches!(
+                        c,
+                        'w' | ':' | 'g' | 'G' | '/' | 'n' | 'N' | 'c' | '+' | '-' | 'D' | 'B'
+                    )
+                {
+                    match c {
+                        'w' => {
+                            *file_preview_wrap = !*file_preview_wrap;
+                            *file_preview_horizontal_offset = 0;
+                        }
+                        ':' => return EventResult::StartGoToLine,
+                        'g' => return EventResult::GoToLine(1),
+                        'G' => return EventResult::GoToEnd,
+                        '/' => return EventResult::StartFind,
+                        'n' => return EventResult::FindNext,
+                        'c' => return EventResult::ToggleChunkPreview,
+                        '+' => return EventResult::AdjustContextLines(1),
+                        '-' => return EventResult::AdjustContextLines(-1),
+                        'D' => return EventResult::ToggleDiff,
+                        'B' => return EventResult::ToggleBlame,
+                        _ => return EventResult::FindPrevious,
+                    }
+                } else if matches!(*ui_mode, UIMode::SearchResults) && c == '/' {
+                    return EventResult::StartResultsFilter;
+                } else if matches!(*ui_mode, UIMode::SearchResults)
+                    && let Some(number) = c.to_digit(10).filter(|number| *number > 0)
+                {
+                    return EventResult::JumpToSource(number as usize);
+                } else if c == 'L' {
+                    // Also when the scope left nothing to select
+                    return EventResult::ClearScope;
+                } else if current_search_result.is_some() {
+                    match c {
+                        'o' => return EventResult::RevealFile,
+                        'd' => return EventResult::PrintDirectory,
+                        'b' => return EventResult::ToggleBookmark,
+                        ' ' => return EventResult::ToggleMark,
+                        'y' => return EventResult::CopyPaths,
+                        'e' => return EventResult::ExportMarkdown,
+                        'E' => return EventResult::ExportResults,
+                        'f' => return EventResult::OpenQuickfix,
+                        'l' => return EventResult::ScopeToDirectory,
+                        'x' => return EventResult::ExplainResult,
+                        _ => {}
+                    }
                 }
                 EventResult::Continue
             }
@@ -183,6 +329,13 @@ impl EventHandler {
             {
                 EventResult::Quit
             }
+            KeyCode::Char('n')
+                if key
+                    .modifiers
+                    .contains(ratatui::crossterm::event::KeyModifiers::CONTROL) =>
+            {
+                EventResult::ShowNotifications
+            }
             KeyCode::Backspace | KeyCode::Delete => {
                 search_input.handle_event(&ratatui::crossterm::event::Event::Key(*key));
                 EventResult::Continue
@@ -199,7 +352,360 @@ impl EventHandler {
         }
     }
 
-    fn update_scroll_offset(
+    pub fn handle_bookmarks_input(
+        key: &KeyEvent,
+        ui_mode: &mut UIMode,
+        selected_bookmark: &mut usize,
+        bookmarks_len: usize,
+    ) -> EventResult {
+        match key.code {
+            KeyCode::Char('c')
+                if key
+                    .modifiers
+                    .contains(ratatui::crossterm::event::KeyModifiers::CONTROL) =>
+            {
+                EventResult::Quit
+            }
+            KeyCode::Char('q') => EventResult::Quit,
+            KeyCode::Esc | KeyCode::Tab => {
+                *ui_mode = UIMode::SearchInput;
+                EventResult::Continue
+            }
+            KeyCode::Char('b')
+                if key
+                    .modifiers
+                    .contains(ratatui::crossterm::event::KeyModifiers::CONTROL) =>
+            {
+                *ui_mode = UIMode::SearchInput;
+                EventResult::Continue
+            }
+            KeyCode::Up => {
+                *selected_bookmark = selected_bookmark.saturating_sub(1);
+                EventResult::Continue
+            }
+            KeyCode::Down => {
+                if *selected_bookmark < bookmarks_len.saturating_sub(1) {
+                    *selected_bookmark += 1;
+                }
+                EventResult::Continue
+            }
+            KeyCode::Enter if *selected_bookmark < bookmarks_len => {
+                EventResult::OpenBookmark(*selected_bookmark)
+            }
+            KeyCode::Delete | KeyCode::Char('x') if *selected_bookmark < bookmarks_len => {
+                EventResult::RemoveBookmark(*selected_bookmark)
+            }
+            _ => EventResult::Continue,
+        }
+    }
+
+    pub fn handle_annotations_input(
+        key: &KeyEvent,
+        ui_mode: &mut UIMode,
+        selected_annotation: &mut usize,
+        annotations_len: usize,
+    ) -> EventResult {
+        match key.code {
+            KeyCode::Char('c')
+                if key
+                    .modifiers
+                    .contains(ratatui::crossterm::event::KeyModifiers::CONTROL) =>
+            {
+                EventResult::Quit
+            }
+            KeyCode::Char('q') => EventResult::Quit,
+            KeyCode::Esc | KeyCode::Tab => {
+                *ui_mode = UIMode::SearchInput;
+                EventResult::Continue
+            }
+            KeyCode::Up => {
+                *selected_annotation = selected_annotation.saturating_sub(1);
+                EventResult::Continue
+            }
+            KeyCode::Down => {
+                if *selected_annotation < annotations_len.saturating_sub(1) {
+                    *selected_annotation += 1;
+                }
+                EventResult::Continue
+            }
+            KeyCode::Enter if *selected_annotation < annotations_len => {
+                EventResult::OpenAnnotation(*selected_annotation)
+            }
+            _ => EventResult::Continue,
+        }
+    }
+
+    /// Picks a project to search from the switcher: every project first, then each indexed
+    /// project. Esc, Tab or Ctrl+O close it without changing anything.
+    pub fn handle_projects_input(
+        key: &KeyEvent,
+        ui_mode: &mut UIMode,
+        selected_project: &mut usize,
+        entries_len: usize,
+    ) -> EventResult {
+        let control = key
+            .modifiers
+            .contains(ratatui::crossterm::event::KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('c') if control => EventResult::Quit,
+            KeyCode::Char('q') => EventResult::Quit,
+            KeyCode::Esc | KeyCode::Tab => {
+                *ui_mode = UIMode::SearchInput;
+                EventResult::Continue
+            }
+            KeyCode::Char('o') if control => {
+                *ui_mode = UIMode::SearchInput;
+                EventResult::Continue
+            }
+            KeyCode::Up => {
+                *selected_project = selected_project.saturating_sub(1);
+                EventResult::Continue
+            }
+            KeyCode::Down => {
+                if *selected_project < entries_len.saturating_sub(1) {
+                    *selected_project += 1;
+                }
+                EventResult::Continue
+            }
+            KeyCode::Enter if *selected_project < entries_len => {
+                EventResult::ChooseProject(*selected_project)
+            }
+            _ => EventResult::Continue,
+        }
+    }
+
+    /// Scrolls the log panel, newest notification first; Esc, Tab or Ctrl+N close it.
+    pub fn handle_notifications_input(
+        key: &KeyEvent,
+        ui_mode: &mut UIMode,
+        selected_notification: &mut usize,
+        notifications_len: usize,
+    ) -> EventResult {
+        let control = key
+            .modifiers
+            .contains(ratatui::crossterm::event::KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('c') if control => EventResult::Quit,
+            KeyCode::Char('q') => EventResult::Quit,
+            KeyCode::Esc | KeyCode::Tab => {
+                *ui_mode = UIMode::SearchInput;
+                EventResult::Continue
+            }
+            KeyCode::Char('n') if control => {
+                *ui_mode = UIMode::SearchInput;
+                EventResult::Continue
+            }
+            KeyCode::Up => {
+                *selected_notification = selected_notification.saturating_sub(1);
+                EventResult::Continue
+            }
+            KeyCode::Down => {
+                if *selected_notification < notifications_len.saturating_sub(1) {
+                    *selected_notification += 1;
+                }
+                EventResult::Continue
+            }
+            _ => EventResult::Continue,
+        }
+    }
+
+    /// Keys of the large project confirmation. While `exclude_input` is open, keys type the
+    /// comma-separated patterns to crawl again without.
+    pub fn handle_confirm_input(key: &KeyEvent, exclude_input: &mut Option<String>) -> EventResult {
+        let control = key
+            .modifiers
+            .contains(ratatui::crossterm::event::KeyModifiers::CONTROL);
+        if key.code == KeyCode::Char('c') && control {
+            return EventResult::Quit;
+        }
+
+        if let Some(input) = exclude_input.as_mut() {
+            match key.code {
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Enter => {
+                    let patterns: Vec<String> = input
+                        .split(',')
+                        .map(|pattern| pattern.trim().to_string())
+                        .filter(|pattern| !pattern.is_empty())
+                        .collect();
+                    *exclude_input = None;
+                    if !patterns.is_empty() {
+                        return EventResult::ChooseIndexing(IndexChoice::Exclude(patterns));
+                    }
+                }
+                KeyCode::Esc => *exclude_input = None,
+                _ => {}
+            }
+            return EventResult::Continue;
+        }
+
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Enter => EventResult::ChooseIndexing(IndexChoice::Index),
+            KeyCode::Char('k') => EventResult::ChooseIndexing(IndexChoice::KeywordOnly),
+            KeyCode::Char('e') => {
+                *exclude_input = Some(String::new());
+                EventResult::Continue
+            }
+            KeyCode::Char('n') | KeyCode::Esc => EventResult::ChooseIndexing(IndexChoice::Skip),
+            KeyCode::Char('q') => EventResult::Quit,
+            _ => EventResult::Continue,
+        }
+    }
+
+    pub fn handle_skipped_files_input(
+        key: &KeyEvent,
+        ui_mode: &mut UIMode,
+        selected_skipped_file: &mut usize,
+        skipped_files_len: usize,
+    ) -> EventResult {
+        let control = key
+            .modifiers
+            .contains(ratatui::crossterm::event::KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('c') if control => EventResult::Quit,
+            KeyCode::Char('q') => EventResult::Quit,
+            KeyCode::Esc | KeyCode::Tab => {
+                *ui_mode = UIMode::SearchInput;
+                EventResult::Continue
+            }
+            KeyCode::Char('k') if control => {
+                *ui_mode = UIMode::SearchInput;
+                EventResult::Continue
+            }
+            KeyCode::Up => {
+                *selected_skipped_file = selected_skipped_file.saturating_sub(1);
+                EventResult::Continue
+            }
+            KeyCode::Down => {
+                if *selected_skipped_file < skipped_files_len.saturating_sub(1) {
+                    *selected_skipped_file += 1;
+                }
+                EventResult::Continue
+            }
+            KeyCode::PageUp => {
+                *selected_skipped_file = selected_skipped_file.saturating_sub(10);
+                EventResult::Continue
+            }
+            KeyCode::PageDown => {
+                *selected_skipped_file =
+                    (*selected_skipped_file + 10).min(skipped_files_len.saturating_sub(1));
+                EventResult::Continue
+            }
+            _ => EventResult::Continue,
+        }
+    }
+
+    /// Handles keys while a `:<line>` jump is being typed in the file preview.
+    pub fn handle_goto_input(key: &KeyEvent, goto_input: &mut Option<String>) -> EventResult {
+        let Some(input) = goto_input.as_mut() else {
+            return EventResult::Continue;
+        };
+
+        match key.code {
+            KeyCode::Char(c) if c.is_ascii_digit() => {
+                input.push(c);
+                EventResult::Continue
+            }
+            KeyCode::Backspace => {
+                if input.pop().is_none() {
+                    *goto_input = None;
+                }
+                EventResult::Continue
+            }
+            KeyCode::Enter => {
+                let line = input.parse::<usize>().ok();
+                *goto_input = None;
+                line.map_or(EventResult::Continue, EventResult::GoToLine)
+            }
+            KeyCode::Char('c')
+                if key
+                    .modifiers
+                    .contains(ratatui::crossterm::event::KeyModifiers::CONTROL) =>
+            {
+                EventResult::Quit
+            }
+            _ => {
+                *goto_input = None;
+                EventResult::Continue
+            }
+        }
+    }
+
+    /// Handles keys while a `/pattern` search is being typed in the file preview.
+    pub fn handle_find_input(key: &KeyEvent, find_input: &mut Option<String>) -> EventResult {
+        let Some(input) = find_input.as_mut() else {
+            return EventResult::Continue;
+        };
+
+        match key.code {
+            KeyCode::Char('c')
+                if key
+                    .modifiers
+                    .contains(ratatui::crossterm::event::KeyModifiers::CONTROL) =>
+            {
+                EventResult::Quit
+            }
+            KeyCode::Char(c) => {
+                input.push(c);
+                EventResult::Continue
+            }
+            KeyCode::Backspace => {
+                if input.pop().is_none() {
+                    *find_input = None;
+                }
+                EventResult::Continue
+            }
+            KeyCode::Enter => {
+                let pattern = std::mem::take(input);
+                *find_input = None;
+                EventResult::Find(pattern)
+            }
+            _ => {
+                *find_input = None;
+                EventResult::Continue
+            }
+        }
+    }
+
+    /// Handles keys while the results filter is being typed; every edit refilters the
+    /// results. Enter keeps the filter and Esc drops it.
+    pub fn handle_filter_input(key: &KeyEvent, filter_input: &mut Option<String>) -> EventResult {
+        let Some(input) = filter_input.as_mut() else {
+            return EventResult::Continue;
+        };
+
+        match key.code {
+            KeyCode::Char('c')
+                if key
+                    .modifiers
+                    .contains(ratatui::crossterm::event::KeyModifiers::CONTROL) =>
+            {
+                EventResult::Quit
+            }
+            KeyCode::Char(c) => {
+                input.push(c);
+                EventResult::FilterResults
+            }
+            KeyCode::Backspace => {
+                input.pop();
+                EventResult::FilterResults
+            }
+            KeyCode::Enter => {
+                *filter_input = None;
+                EventResult::Continue
+            }
+            KeyCode::Esc => {
+                *filter_input = None;
+                EventResult::ClearResultsFilter
+            }
+            _ => EventResult::Continue,
+        }
+    }
+
+    pub fn update_scroll_offset(
         selected_index: usize,
         scroll_offset: &mut usize,
         visible_height: usize,
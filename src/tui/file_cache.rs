@@ -0,0 +1,87 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+const CACHE_CAPACITY: usize = 32;
+const MAX_PREVIEW_SIZE: u64 = 1_048_576;
+
+/// File contents loaded for previews, with the hash they were read at.
+#[derive(Debug, Clone)]
+pub struct CachedFile {
+    pub content: Arc<str>,
+    pub hash: u64,
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+/// Least-recently-used cache of previewed files. Nothing invalidates entries from
+/// outside; they stay fresh only by being revalidated against the file's modification
+/// time and size on every lookup, which is how edits on disk are picked up.
+#[derive(Default)]
+pub struct FileCache {
+    entries: HashMap<PathBuf, CachedFile>,
+    order: VecDeque<PathBuf>,
+}
+
+impl FileCache {
+    pub async fn get(&mut self, path: &Path) -> CachedFile {
+        let metadata = tokio::fs::metadata(path).await;
+        let (modified, len) = match &metadata {
+            Ok(metadata) => (metadata.modified().ok(), metadata.len()),
+            Err(_) => (None, 0),
+        };
+
+        if let Some(entry) = self.entries.get(path)
+            && metadata.is_ok()
+            && entry.modified == modified
+            && entry.len == len
+        {
+            let entry = entry.clone();
+            self.touch(path);
+            return entry;
+        }
+
+        let content = match metadata {
+            Ok(_) if len > MAX_PREVIEW_SIZE => {
+                let size_mb = len as f64 / 1_048_576.0;
+                format!("File too large to display ({:.1} MB)", size_mb)
+            }
+            Ok(_) => tokio::fs::read_to_string(path)
+                .await
+                .unwrap_or_else(|e| format!("Failed to read file: {}", e)),
+            Err(e) => format!("Failed to read file: {}", e),
+        };
+
+        let entry = CachedFile {
+            hash: xxhash_rust::xxh3::xxh3_64(content.as_bytes()),
+            content: Arc::from(content),
+            modified,
+            len,
+        };
+        self.insert(path.to_path_buf(), entry.clone());
+        entry
+    }
+
+    fn insert(&mut self, path: PathBuf, entry: CachedFile) {
+        if self.entries.insert(path.clone(), entry).is_some() {
+            self.touch(&path);
+            return;
+        }
+
+        self.order.push_back(path);
+        while self.order.len() > CACHE_CAPACITY {
+            if let Some(evicted) = self.order.pop_front() {
+                self.entries.remove(&evicted);
+            }
+        }
+    }
+
+    fn touch(&mut self, path: &Path) {
+        if let Some(index) = self.order.iter().position(|p| p == path)
+            && let Some(path) = self.order.remove(index)
+        {
+            self.order.push_back(path);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+const SCORE_MATCH: i64 = 16;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_FILENAME: i64 = 4;
+const BONUS_CONSECUTIVE: i64 = 6;
+const PENALTY_GAP: i64 = 1;
+
+/// Scores a path against a fuzzy pattern, fzf-style. Each whitespace-separated term must
+/// appear in order within the path; matches at word boundaries, in the filename and in
+/// consecutive runs score higher, and gaps between matched characters cost a little.
+/// Lowercase pattern characters match either case.
+pub fn score(pattern: &str, path: &str) -> Option<i64> {
+    let candidate: Vec<char> = path.chars().collect();
+    let filename_start = candidate
+        .iter()
+        .rposition(|&c| c == '/' || c == '\\')
+        .map_or(0, |i| i + 1);
+
+    pattern.split_whitespace().try_fold(0, |total, term| {
+        let term: Vec<char> = term.chars().collect();
+        Some(total + score_term(&term, &candidate, filename_start)?)
+    })
+}
+
+/// Best alignment of `term` within `candidate`, found by dynamic programming over the
+/// positions each term character could match.
+fn score_term(term: &[char], candidate: &[char], filename_start: usize) -> Option<i64> {
+    if !is_subsequence(term, candidate) {
+        return None;
+    }
+
+    let bonus = |j: usize| {
+        let mut bonus = 0;
+        let previous = j.checked_sub(1).map(|i| candidate[i]);
+        let at_boundary = match previous {
+            None => true,
+            Some(previous) => {
+                matches!(previous, '/' | '\\' | '_' | '-' | '.' | ' ')
+                    || (previous.is_lowercase() && candidate[j].is_uppercase())
+            }
+        };
+        if at_boundary {
+            bonus += BONUS_BOUNDARY;
+        }
+        if j >= filename_start {
+            bonus += BONUS_FILENAME;
+        }
+        bonus
+    };
+
+    let mut previous_row: Vec<Option<i64>> = Vec::new();
+    for (i, &p) in term.iter().enumerate() {
+        let mut row = vec![None; candidate.len()];
+        // Best score of the previous term character matched before `j - 1`, less the gap
+        let mut best_gapped: Option<i64> = None;
+
+        for j in 0..candidate.len() {
+            if chars_match(p, candidate[j]) {
+                let base = SCORE_MATCH + bonus(j);
+                row[j] = if i == 0 {
+                    Some(base)
+                } else {
+                    let consecutive = j
+                        .checked_sub(1)
+                        .and_then(|k| previous_row[k])
+                        .map(|score| score + BONUS_CONSECUTIVE);
+                    consecutive.max(best_gapped).map(|score| score + base)
+                };
+            }
+
+            if i > 0 {
+                let adjacent = j.checked_sub(1).and_then(|k| previous_row[k]);
+                best_gapped = best_gapped.max(adjacent).map(|score| score - PENALTY_GAP);
+            }
+        }
+
+        previous_row = row;
+    }
+
+    previous_row.into_iter().flatten().max()
+}
+
+fn is_subsequence(term: &[char], candidate: &[char]) -> bool {
+    let mut remaining = candidate.iter();
+    term.iter().all(|&p| remaining.any(|&c| chars_match(p, c)))
+}
+
+fn chars_match(pattern: char, candidate: char) -> bool {
+    pattern == candidate
+        || (!pattern.is_uppercase() && candidate.to_lowercase().eq(pattern.to_lowercase()))
+}
@@ -0,0 +1,135 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::process::Command;
+
+/// A line of a unified diff against HEAD.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffLine {
+    Hunk(String),
+    Context(String),
+    Added(String),
+    Removed(String),
+}
+
+/// The file's uncommitted changes against HEAD; empty when it has none. Fails for files
+/// outside a git work tree or not yet committed.
+pub async fn diff_against_head(file_path: &Path) -> Result<Vec<DiffLine>> {
+    git(file_path, &["ls-files", "--error-unmatch", "--"])
+        .await
+        .context("Not tracked by git")?;
+    let diff = git(
+        file_path,
+        &["diff", "--no-color", "--no-ext-diff", "HEAD", "--"],
+    )
+    .await?;
+    Ok(parse_diff(&diff))
+}
+
+/// Who last touched a line, and when (seconds since the epoch).
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlameLine {
+    pub author: String,
+    pub time: u64,
+}
+
+/// Blame for the 1-based, inclusive line range, keyed by 0-based line index. Lines with
+/// uncommitted changes are attributed to "Not Committed Yet", as git reports them.
+pub async fn blame(
+    file_path: &Path,
+    first_line: usize,
+    last_line: usize,
+) -> Result<HashMap<usize, BlameLine>> {
+    let range = format!("{},{}", first_line, last_line);
+    let output = git(file_path, &["blame", "--porcelain", "-L", &range, "--"]).await?;
+    Ok(parse_blame(&output))
+}
+
+/// Blame for the given 1-based lines only, keyed by 0-based line index.
+pub async fn blame_lines(file_path: &Path, lines: &[usize]) -> Result<HashMap<usize, BlameLine>> {
+    let ranges: Vec<String> = lines
+        .iter()
+        .map(|line| format!("-L{},{}", line, line))
+        .collect();
+    let mut args = vec!["blame", "--porcelain"];
+    args.extend(ranges.iter().map(String::as_str));
+    args.push("--");
+    let output = git(file_path, &args).await?;
+    Ok(parse_blame(&output))
+}
+
+/// Runs git from the file's directory with the file as the last argument.
+async fn git(file_path: &Path, args: &[&str]) -> Result<String> {
+    let directory = file_path.parent().context("File has no parent directory")?;
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(args)
+        .arg(file_path)
+        .output()
+        .await
+        .context("Failed to run git")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("{}", stderr.lines().next().unwrap_or("git failed"));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn parse_diff(diff: &str) -> Vec<DiffLine> {
+    diff.lines()
+        .skip_while(|line| !line.starts_with("@@"))
+        .filter_map(|line| {
+            let line = if line.starts_with("@@") {
+                DiffLine::Hunk(line.to_string())
+            } else if let Some(added) = line.strip_prefix('+') {
+                DiffLine::Added(added.to_string())
+            } else if let Some(removed) = line.strip_prefix('-') {
+                DiffLine::Removed(removed.to_string())
+            } else if let Some(context) = line.strip_prefix(' ') {
+                DiffLine::Context(context.to_string())
+            } else {
+                // "\ No newline at end of file" and similar markers
+                return None;
+            };
+            Some(line)
+        })
+        .collect()
+}
+
+/// Porcelain output gives each line a header naming its commit and final line number,
+/// followed by the commit's details only the first time that commit appears.
+fn parse_blame(output: &str) -> HashMap<usize, BlameLine> {
+    let mut commits: HashMap<&str, BlameLine> = HashMap::new();
+    let mut lines = HashMap::new();
+    let mut current: Option<(&str, usize)> = None;
+
+    for line in output.lines() {
+        if line.starts_with('\t') {
+            if let Some((commit, line_number)) = current.take()
+                && let Some(blame) = commits.get(commit)
+            {
+                lines.insert(line_number.saturating_sub(1), blame.clone());
+            }
+        } else if let Some((commit, _)) = current {
+            let entry = commits.entry(commit).or_insert(BlameLine {
+                author: String::new(),
+                time: 0,
+            });
+            if let Some(author) = line.strip_prefix("author ") {
+                entry.author = author.to_string();
+            } else if let Some(time) = line.strip_prefix("author-time ") {
+                entry.time = time.parse().unwrap_or(0);
+            }
+        } else {
+            let mut fields = line.split_whitespace();
+            if let (Some(commit), Some(line_number)) = (fields.next(), fields.nth(1)) {
+                current = line_number.parse().ok().map(|number| (commit, number));
+            }
+        }
+    }
+
+    lines
+}
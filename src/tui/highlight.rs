@@ -0,0 +1,150 @@
+use ratatui::{
+    style::{Color, Style},
+    text::Span,
+};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+use syntect::{
+    easy::HighlightLines, highlighting::ThemeSet, parsing::SyntaxReference, util::LinesWithEndings,
+};
+
+use crate::config::{LanguageOverride, normalize_extension};
+use crate::storage::code::SYNTAX_SET;
+
+const HIGHLIGHT_CACHE_CAPACITY: usize = 8;
+const THEME_NAME: &str = "base16-ocean.dark";
+
+static THEME_SET: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
+
+/// Styled spans for every line of a file, without line numbers.
+pub type StyledLines = Arc<Vec<Vec<Span<'static>>>>;
+
+type HighlightKey = (PathBuf, u64);
+
+#[derive(Default)]
+struct HighlightState {
+    ready: HashMap<HighlightKey, StyledLines>,
+    order: VecDeque<HighlightKey>,
+    pending: HashSet<HighlightKey>,
+}
+
+/// Highlights previewed files on the blocking thread pool and keeps the styled lines of
+/// the most recently previewed files, so rendering a frame only slices cached lines.
+#[derive(Clone, Default)]
+pub struct PreviewHighlighter {
+    state: Arc<Mutex<HighlightState>>,
+    /// Syntax names from `[languages]`, keyed by lowercase extension
+    syntax_overrides: Arc<HashMap<String, String>>,
+}
+
+impl PreviewHighlighter {
+    pub fn new(languages: &BTreeMap<String, LanguageOverride>) -> Self {
+        let syntax_overrides = languages
+            .iter()
+            .filter_map(|(extension, language)| {
+                Some((normalize_extension(extension), language.syntax.clone()?))
+            })
+            .collect();
+
+        Self {
+            state: Arc::default(),
+            syntax_overrides: Arc::new(syntax_overrides),
+        }
+    }
+
+    /// Name of the syntax the file is highlighted with, e.g. "Rust".
+    pub fn language_name(&self, path: &Path, content: &str) -> &'static str {
+        &find_syntax(content, path, &self.syntax_overrides).name
+    }
+
+    pub fn get(&self, path: &Path, hash: u64) -> Option<StyledLines> {
+        let state = self.state.lock().ok()?;
+        state.ready.get(&(path.to_path_buf(), hash)).cloned()
+    }
+
+    /// Starts highlighting the file unless it is already cached or in progress.
+    pub fn request(&self, path: &Path, hash: u64, content: Arc<str>) {
+        let key = (path.to_path_buf(), hash);
+        {
+            let Ok(mut state) = self.state.lock() else {
+                return;
+            };
+            if state.ready.contains_key(&key) || !state.pending.insert(key.clone()) {
+                return;
+            }
+        }
+
+        let state = Arc::clone(&self.state);
+        let syntax_overrides = Arc::clone(&self.syntax_overrides);
+        tokio::task::spawn_blocking(move || {
+            let lines = highlight_lines(&content, &key.0, &syntax_overrides);
+
+            if let Ok(mut state) = state.lock() {
+                state.pending.remove(&key);
+                state.order.push_back(key.clone());
+                state.ready.insert(key, Arc::new(lines));
+
+                while state.order.len() > HIGHLIGHT_CACHE_CAPACITY {
+                    if let Some(evicted) = state.order.pop_front() {
+                        state.ready.remove(&evicted);
+                    }
+                }
+            }
+        });
+    }
+}
+
+pub fn highlight_lines(
+    content: &str,
+    file_path: &Path,
+    syntax_overrides: &HashMap<String, String>,
+) -> Vec<Vec<Span<'static>>> {
+    let syntax = find_syntax(content, file_path, syntax_overrides);
+    let mut highlighter = HighlightLines::new(syntax, &THEME_SET.themes[THEME_NAME]);
+
+    LinesWithEndings::from(content)
+        .map(|line| match highlighter.highlight_line(line, &SYNTAX_SET) {
+            Ok(ranges) => ranges
+                .into_iter()
+                .map(|(style, text)| {
+                    let fg_color =
+                        Color::Rgb(style.foreground.r, style.foreground.g, style.foreground.b);
+                    Span::styled(
+                        strip_line_ending(text).to_string(),
+                        Style::default().fg(fg_color),
+                    )
+                })
+                .collect(),
+            Err(_) => vec![Span::raw(strip_line_ending(line).to_string())],
+        })
+        .collect()
+}
+
+fn find_syntax(
+    content: &str,
+    file_path: &Path,
+    syntax_overrides: &HashMap<String, String>,
+) -> &'static SyntaxReference {
+    let extension = file_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("");
+
+    let overridden = syntax_overrides
+        .get(&normalize_extension(extension))
+        .and_then(|syntax| {
+            SYNTAX_SET
+                .find_syntax_by_name(syntax)
+                .or_else(|| SYNTAX_SET.find_syntax_by_token(syntax))
+        });
+
+    overridden
+        .or_else(|| SYNTAX_SET.find_syntax_by_extension(extension))
+        .or_else(|| SYNTAX_SET.find_syntax_by_first_line(content))
+        .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text())
+}
+
+fn strip_line_ending(text: &str) -> &str {
+    text.trim_end_matches(['\n', '\r'])
+}
@@ -0,0 +1,256 @@
+use anyhow::Result;
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD;
+use ratatui::crossterm::{
+    cursor::{MoveTo, RestorePosition, SavePosition},
+    queue,
+};
+use ratatui::layout::Rect;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::config::ImageProtocol;
+use crate::crawler::images::ImageInfo;
+
+/// Larger images are described instead of sent to the terminal
+const MAX_IMAGE_SIZE: u64 = 8 * 1_048_576;
+/// Base64 bytes per kitty graphics escape, the most the protocol accepts
+const KITTY_CHUNK_SIZE: usize = 4096;
+/// Width to height of a terminal cell when the terminal doesn't report its pixel size
+const DEFAULT_CELL_ASPECT: f64 = 0.5;
+
+/// An image file loaded for the preview pane.
+#[derive(Debug, Clone)]
+pub struct PreviewImage {
+    pub path: PathBuf,
+    pub data: Arc<[u8]>,
+    pub info: Option<ImageInfo>,
+}
+
+impl PreviewImage {
+    /// Reads `path`, leaving out images too large to send to the terminal.
+    pub async fn load(path: &Path) -> Result<Self> {
+        let size = tokio::fs::metadata(path).await?.len();
+        if size > MAX_IMAGE_SIZE {
+            anyhow::bail!(
+                "Image too large to display ({:.1} MB)",
+                size as f64 / 1_048_576.0
+            );
+        }
+        let data: Arc<[u8]> = tokio::fs::read(path).await?.into();
+        Ok(Self {
+            path: path.to_owned(),
+            info: ImageInfo::probe(&data),
+            data,
+        })
+    }
+
+    /// What the preview shows when the image can't be drawn inline.
+    pub fn describe(&self) -> String {
+        let size = format!("{:.1} KB", self.data.len() as f64 / 1024.0);
+        match self.info {
+            Some(info) => format!(
+                "{} image, {} × {} pixels, {}",
+                info.format, info.width, info.height, size
+            ),
+            None => format!("Image, {}", size),
+        }
+    }
+}
+
+/// Terminal graphics protocols images can be drawn with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    /// kitty graphics protocol, also spoken by Ghostty and WezTerm; PNG only, as other
+    /// formats would have to be decoded first
+    Kitty,
+    /// iTerm2 inline images, also spoken by WezTerm
+    Iterm,
+}
+
+impl Protocol {
+    /// The protocol `setting` picks, guessing from the environment when it is `auto`.
+    pub fn detect(setting: ImageProtocol) -> Option<Self> {
+        match setting {
+            ImageProtocol::Off => None,
+            ImageProtocol::Kitty => Some(Self::Kitty),
+            ImageProtocol::Iterm => Some(Self::Iterm),
+            ImageProtocol::Auto => {
+                let var = |name: &str| std::env::var(name).unwrap_or_default();
+                // Multiplexers swallow the escapes unless configured to pass them through
+                if !var("TMUX").is_empty() || var("TERM").starts_with("screen") {
+                    None
+                } else if !var("KITTY_WINDOW_ID").is_empty()
+                    || var("TERM") == "xterm-kitty"
+                    || var("TERM_PROGRAM") == "ghostty"
+                {
+                    Some(Self::Kitty)
+                } else if matches!(var("TERM_PROGRAM").as_str(), "iTerm.app" | "WezTerm")
+                    || var("LC_TERMINAL") == "iTerm2"
+                {
+                    Some(Self::Iterm)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn supports(self, image: &PreviewImage) -> bool {
+        match self {
+            Self::Kitty => image.info.is_some_and(|info| info.format == "PNG"),
+            Self::Iterm => true,
+        }
+    }
+}
+
+struct Shown {
+    path: PathBuf,
+    area: Rect,
+    protocol: Protocol,
+}
+
+/// Images drawn over the preview pane, outside ratatui's buffer. Rendering asks for an
+/// image each frame; `flush` then brings the terminal in line once the frame is drawn.
+pub struct InlineImages {
+    protocol: Option<Protocol>,
+    wanted: Option<(PreviewImage, Rect)>,
+    shown: Option<Shown>,
+}
+
+impl InlineImages {
+    pub fn new(setting: ImageProtocol) -> Self {
+        Self {
+            protocol: Protocol::detect(setting),
+            wanted: None,
+            shown: None,
+        }
+    }
+
+    /// Switches protocol after the config changed; the image on screen goes at the next flush.
+    pub fn configure(&mut self, setting: ImageProtocol) {
+        self.protocol = Protocol::detect(setting);
+    }
+
+    /// Whether `image` can be drawn inline in this terminal.
+    pub fn supports(&self, image: &PreviewImage) -> bool {
+        self.protocol
+            .is_some_and(|protocol| protocol.supports(image))
+    }
+
+    /// Called as a frame starts rendering, so only images that frame asks for stay.
+    pub fn begin_frame(&mut self) {
+        self.wanted = None;
+    }
+
+    /// Asks for `image` to cover `area` once the frame is drawn.
+    pub fn request(&mut self, image: &PreviewImage, area: Rect) {
+        self.wanted = Some((image.clone(), area));
+    }
+
+    /// Whether the screen must be cleared and redrawn before the next flush. ratatui
+    /// doesn't know what the terminal shows in the cells an image covered, and iTerm2
+    /// images live in those cells until they are written again.
+    pub fn needs_repaint(&self) -> bool {
+        self.changed()
+    }
+
+    /// Removes the image the last frame no longer asked for and draws the one it did.
+    pub fn flush(&mut self, out: &mut impl Write) -> io::Result<()> {
+        if !self.changed() {
+            return Ok(());
+        }
+
+        if let Some(shown) = self.shown.take()
+            && shown.protocol == Protocol::Kitty
+        {
+            write!(out, "\x1b_Ga=d,d=a,q=2\x1b\\")?;
+        }
+        if let (Some((image, area)), Some(protocol)) = (&self.wanted, self.protocol) {
+            let (columns, rows) = fit(image.info, *area);
+            queue!(out, SavePosition, MoveTo(area.x, area.y))?;
+            match protocol {
+                Protocol::Kitty => write_kitty(out, &image.data, columns, rows)?,
+                Protocol::Iterm => write!(
+                    out,
+                    "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1:{}\x07",
+                    image.data.len(),
+                    columns,
+                    rows,
+                    STANDARD.encode(&image.data)
+                )?,
+            }
+            queue!(out, RestorePosition)?;
+            self.shown = Some(Shown {
+                path: image.path.clone(),
+                area: *area,
+                protocol,
+            });
+        }
+        out.flush()
+    }
+
+    /// Removes any image before the interface exits.
+    pub fn clear(&mut self, out: &mut impl Write) -> io::Result<()> {
+        self.wanted = None;
+        self.flush(out)
+    }
+
+    fn changed(&self) -> bool {
+        let wanted = self
+            .wanted
+            .as_ref()
+            .map(|(image, area)| (&image.path, *area));
+        let shown = self.shown.as_ref().map(|shown| (&shown.path, shown.area));
+        wanted != shown
+    }
+}
+
+/// The cells an image takes inside `area`, keeping its aspect ratio and never scaled up.
+fn fit(info: Option<ImageInfo>, area: Rect) -> (u16, u16) {
+    let Some(info) = info.filter(|info| info.width > 0 && info.height > 0) else {
+        return (area.width, area.height);
+    };
+    let (cell_width, cell_height) = ratatui::crossterm::terminal::window_size()
+        .ok()
+        .filter(|size| size.width > 0 && size.columns > 0 && size.rows > 0)
+        .map(|size| {
+            (
+                f64::from(size.width) / f64::from(size.columns),
+                f64::from(size.height) / f64::from(size.rows),
+            )
+        })
+        .unwrap_or((DEFAULT_CELL_ASPECT * 16.0, 16.0));
+
+    let columns = f64::from(info.width) / cell_width;
+    let rows = f64::from(info.height) / cell_height;
+    let scale = (f64::from(area.width) / columns)
+        .min(f64::from(area.height) / rows)
+        .min(1.0);
+    (
+        ((columns * scale).round() as u16).clamp(1, area.width.max(1)),
+        ((rows * scale).round() as u16).clamp(1, area.height.max(1)),
+    )
+}
+
+/// Sends a PNG in base64 chunks, each flagged with whether more follow.
+fn write_kitty(out: &mut impl Write, png: &[u8], columns: u16, rows: u16) -> io::Result<()> {
+    let encoded = STANDARD.encode(png);
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK_SIZE).collect();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(index + 1 < chunks.len());
+        if index == 0 {
+            write!(
+                out,
+                "\x1b_Ga=T,f=100,t=d,c={},r={},C=1,q=2,m={};",
+                columns, rows, more
+            )?;
+        } else {
+            write!(out, "\x1b_Gm={};", more)?;
+        }
+        out.write_all(chunk)?;
+        write!(out, "\x1b\\")?;
+    }
+    Ok(())
+}
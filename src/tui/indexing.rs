@@ -0,0 +1,268 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::Config;
+use crate::crawler::{CrawlStats, FileCrawler};
+use crate::projects::ProjectRegistry;
+use crate::storage::profile::IndexProfile;
+use crate::storage::{SharedStorage, StorageManager};
+use crate::types::{AppState, CrawlerConfig, LargeCorpus};
+
+/// Bytes of text per embedded chunk, allowing for the overlap between chunks
+const ESTIMATED_CHUNK_BYTES: u64 = 900;
+/// Chunks one worker embeds per second on a typical laptop core, until a profiled run
+/// (`--profile-index`) has measured this machine
+const ESTIMATED_CHUNKS_PER_SECOND: f64 = 40.0;
+
+/// How to go on after a crawl too large to index without asking.
+#[derive(Debug, Clone)]
+pub enum IndexChoice {
+    Index,
+    /// Build only the keyword index for this run, in the index kept for keyword-only runs
+    KeywordOnly,
+    /// Crawl again with these patterns excluded as well
+    Exclude(Vec<String>),
+    /// Leave the index as it is
+    Skip,
+}
+
+/// Crawls and indexes the project in the background so the UI keeps handling input.
+/// The storage is opened first and shared right away, so the existing index can be
+/// searched while files are crawled and reconciled, and the files already indexed are
+/// listed until the crawl finishes and replaces them. Cancelling stops the pipeline after
+/// committing the work already done. With `index` off the project is only crawled, for
+/// when a daemon owns the indexes and answers searches. A crawl leaving more than
+/// `[indexing] confirm_files` or `confirm_mb` to index, or stopped at `max_files`, waits
+/// in `AppState::Confirming` for a `choose`.
+pub struct IndexingTask {
+    handle: JoinHandle<Result<(Vec<PathBuf>, CrawlStats)>>,
+    state: watch::Receiver<AppState>,
+    storage: watch::Receiver<Option<SharedStorage>>,
+    crawled: watch::Receiver<Option<Vec<PathBuf>>>,
+    choices: mpsc::UnboundedSender<IndexChoice>,
+    cancel: CancellationToken,
+}
+
+impl IndexingTask {
+    pub fn spawn(
+        root_path: PathBuf,
+        crawler_config: CrawlerConfig,
+        config: Config,
+        data_dir: PathBuf,
+        index: bool,
+        cancel: CancellationToken,
+    ) -> Self {
+        let (state_sender, state) = watch::channel(AppState::Crawling);
+        let (storage_sender, storage) = watch::channel(None);
+        let (crawled_sender, crawled) = watch::channel(None);
+        let (choices, mut choice_receiver) = mpsc::unbounded_channel();
+        let task_cancel = cancel.clone();
+
+        let handle = tokio::spawn(async move {
+            let mut service = if index {
+                let service = Arc::new(Mutex::new(StorageManager::new(&data_dir, &config).await?));
+                let _ = storage_sender.send(Some(service.clone()));
+                // The files already indexed stand in until this crawl lists them
+                let indexed = service.lock().await.indexed_files(&root_path).await;
+                if !indexed.is_empty() {
+                    let _ = crawled_sender.send(Some(indexed));
+                }
+                Some(service)
+            } else {
+                None
+            };
+
+            let mut crawler_config = crawler_config;
+            let (files, stats, crawl_time) = loop {
+                let crawl_started = Instant::now();
+                let crawler = FileCrawler::new(crawler_config.clone());
+                let (files, stats) = crawler
+                    .crawl_directory_with_stats(&root_path, task_cancel.clone())
+                    .await?;
+                let crawl_time = crawl_started.elapsed();
+
+                let Some(storage) = service.as_ref().filter(|_| !task_cancel.is_cancelled()) else {
+                    break (files, stats, crawl_time);
+                };
+                let Some(corpus) = large_corpus(
+                    &files,
+                    &stats,
+                    &root_path,
+                    storage,
+                    &config,
+                    &data_dir,
+                    &task_cancel,
+                )
+                .await?
+                else {
+                    break (files, stats, crawl_time);
+                };
+
+                // Keys pressed twice on an earlier confirmation must not answer this one
+                while choice_receiver.try_recv().is_ok() {}
+                let _ = state_sender.send(AppState::Confirming(corpus));
+                let choice = tokio::select! {
+                    choice = choice_receiver.recv() => choice.unwrap_or(IndexChoice::Skip),
+                    _ = task_cancel.cancelled() => IndexChoice::Skip,
+                };
+                match choice {
+                    IndexChoice::Index => {}
+                    IndexChoice::KeywordOnly => {
+                        let mut keyword_config = config.clone();
+                        keyword_config.semantic.enabled = false;
+                        let keyword_service = Arc::new(Mutex::new(
+                            StorageManager::new(&data_dir, &keyword_config).await?,
+                        ));
+                        let _ = storage_sender.send(Some(keyword_service.clone()));
+                        service = Some(keyword_service);
+                    }
+                    IndexChoice::Exclude(patterns) => {
+                        crawler_config.exclude_patterns.extend(patterns);
+                        let _ = state_sender.send(AppState::Crawling);
+                        continue;
+                    }
+                    IndexChoice::Skip => service = None,
+                }
+                break (files, stats, crawl_time);
+            };
+
+            if service.is_some()
+                && !task_cancel.is_cancelled()
+                && let Err(e) = ProjectRegistry::register(&root_path)
+            {
+                tracing::warn!("Failed to record the project: {:#}", e);
+            }
+            if !task_cancel.is_cancelled() {
+                let _ = crawled_sender.send(Some(files.clone()));
+            }
+
+            if let Some(service) = service
+                && !task_cancel.is_cancelled()
+            {
+                let _ = state_sender.send(AppState::Chunking);
+                service.lock().await.record_crawl(crawl_time);
+                StorageManager::process_and_index_files(
+                    &service,
+                    files.clone(),
+                    stats.size_limits.clone(),
+                    &task_cancel,
+                )
+                .await?;
+            }
+
+            Ok((files, stats))
+        });
+
+        Self {
+            handle,
+            state,
+            storage,
+            crawled,
+            choices,
+            cancel,
+        }
+    }
+
+    pub fn state(&self) -> AppState {
+        self.state.borrow().clone()
+    }
+
+    /// The opened storage, shared with the indexing still in progress, once each time it
+    /// is opened.
+    pub fn take_storage(&mut self) -> Option<SharedStorage> {
+        if !self.storage.has_changed().unwrap_or(false) {
+            return None;
+        }
+        self.storage.borrow_and_update().clone()
+    }
+
+    /// The files of the project, once each time they change: those already indexed, then
+    /// the crawled ones when the crawl has finished and indexing goes on.
+    pub fn take_crawled(&mut self) -> Option<Vec<PathBuf>> {
+        if !self.crawled.has_changed().unwrap_or(false) {
+            return None;
+        }
+        self.crawled.borrow_and_update().clone()
+    }
+
+    /// Answers `AppState::Confirming`.
+    pub fn choose(&self, choice: IndexChoice) {
+        let _ = self.choices.send(choice);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Returns the crawled files with the counts of those skipped.
+    pub async fn join(self) -> Result<(Vec<PathBuf>, CrawlStats)> {
+        self.handle.await?
+    }
+}
+
+/// The size of what the crawl leaves to index when it is over the limits worth asking
+/// about, with a guess at how long embedding it would take. For a project already in the
+/// index only the files changed since are counted, so reopening a large project doesn't
+/// ask again.
+async fn large_corpus(
+    files: &[PathBuf],
+    stats: &CrawlStats,
+    root: &Path,
+    storage: &SharedStorage,
+    config: &Config,
+    data_dir: &Path,
+    cancel: &CancellationToken,
+) -> Result<Option<LargeCorpus>> {
+    let indexing = &config.indexing;
+    if indexing.confirm_files == 0 && indexing.confirm_mb == 0 && stats.file_limit.is_none() {
+        return Ok(None);
+    }
+
+    let indexed = storage.lock().await.chunk_count(root).unwrap_or(0) > 0;
+    let files = if indexed {
+        StorageManager::files_to_index(storage, files, cancel).await?
+    } else {
+        files.to_vec()
+    };
+    let sized_files = files.clone();
+    let bytes: u64 = tokio::task::spawn_blocking(move || {
+        sized_files
+            .iter()
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .sum()
+    })
+    .await?;
+
+    let too_many = indexing.confirm_files > 0 && files.len() > indexing.confirm_files;
+    let too_big = indexing.confirm_mb > 0 && bytes > indexing.confirm_mb * 1024 * 1024;
+    if !too_many && !too_big && stats.file_limit.is_none() {
+        return Ok(None);
+    }
+
+    let embedding_estimate = config.semantic.enabled.then(|| {
+        let chunks = (bytes / ESTIMATED_CHUNK_BYTES) as f64;
+        let index_dir = StorageManager::index_dir(data_dir, config);
+        let seconds_per_chunk = IndexProfile::load(&index_dir)
+            .filter(|profile| profile.chunks > 0 && !profile.embedding.is_zero())
+            .map(|profile| profile.embedding.as_secs_f64() / profile.chunks as f64)
+            .unwrap_or(1.0 / (ESTIMATED_CHUNKS_PER_SECOND * config.semantic.workers() as f64));
+        Duration::from_secs_f64(chunks * seconds_per_chunk)
+    });
+
+    Ok(Some(LargeCorpus {
+        files: files.len(),
+        bytes,
+        file_limit: stats.file_limit,
+        embedding_estimate,
+    }))
+}
@@ -1,6 +1,16 @@
 pub mod app;
+pub mod completion;
+pub mod config_reload;
 pub mod engine;
 pub mod events;
+pub mod file_cache;
+pub mod fuzzy;
+pub mod git;
+pub mod highlight;
+pub mod image;
+pub mod indexing;
+pub mod notifications;
+pub mod summary;
 pub mod ui;
 
 pub use app::*;
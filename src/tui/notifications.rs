@@ -0,0 +1,100 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::logging;
+
+/// How long a toast stays on screen
+const TOAST_DURATION: Duration = Duration::from_secs(6);
+/// Toasts shown at once, newest first
+const MAX_TOASTS: usize = 3;
+/// Older notifications drop out of the log panel
+const MAX_HISTORY: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    /// Something the user did took effect, such as a config reload
+    Info,
+    Warning,
+}
+
+impl Level {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Info => "Info",
+            Self::Warning => "Warning",
+        }
+    }
+}
+
+/// Something worth telling the user about without interrupting them, such as a file that
+/// couldn't be read.
+pub struct Notification {
+    pub level: Level,
+    pub message: String,
+    /// Times the same message arrived in a row
+    pub count: usize,
+    /// Seconds since the epoch of the latest arrival
+    pub time: u64,
+    shown_at: Instant,
+}
+
+/// Notices shown briefly as toasts and kept, newest first, for the log panel.
+#[derive(Default)]
+pub struct Notifications {
+    history: VecDeque<Notification>,
+}
+
+impl Notifications {
+    pub fn push(&mut self, level: Level, message: String) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+
+        // A failing batch repeats the same warning; count it rather than filling the log
+        if let Some(latest) = self.history.front_mut()
+            && latest.level == level
+            && latest.message == message
+        {
+            latest.count += 1;
+            latest.time = time;
+            latest.shown_at = Instant::now();
+            return;
+        }
+
+        self.history.push_front(Notification {
+            level,
+            message,
+            count: 1,
+            time,
+            shown_at: Instant::now(),
+        });
+        self.history.truncate(MAX_HISTORY);
+    }
+
+    /// Takes the warnings logged since the last call.
+    pub fn collect(&mut self) {
+        for warning in logging::take_warnings() {
+            self.push(Level::Warning, warning);
+        }
+    }
+
+    /// Notifications recent enough to show as toasts, newest first.
+    pub fn toasts(&self) -> impl Iterator<Item = &Notification> {
+        self.history
+            .iter()
+            .take(MAX_TOASTS)
+            .filter(|notification| notification.shown_at.elapsed() < TOAST_DURATION)
+    }
+
+    pub fn history(&self) -> &VecDeque<Notification> {
+        &self.history
+    }
+
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
+}
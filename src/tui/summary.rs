@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use crate::crawler::{CrawlStats, SkipReason};
+use crate::storage::code;
+
+/// Languages and directories listed on the summary, largest first.
+const TOP_ENTRIES: usize = 6;
+
+/// A breakdown of the indexed corpus, shown on the status screen before the first search.
+#[derive(Debug, Clone, Default)]
+pub struct CorpusSummary {
+    pub files: usize,
+    /// Chunks in the index, unknown when a daemon owns it
+    pub chunks: Option<u64>,
+    /// Files per language, by the names `lang:` filters match
+    pub languages: Vec<(&'static str, usize)>,
+    /// Files under each top-level directory of the project, counting nested ones
+    pub directories: Vec<(PathBuf, usize)>,
+    pub skipped: Vec<(SkipReason, usize)>,
+    /// The `max_files` the crawl stopped at, if it did
+    pub file_limit: Option<usize>,
+}
+
+impl CorpusSummary {
+    pub fn new(root: &Path, files: &[PathBuf], stats: &CrawlStats, chunks: Option<u64>) -> Self {
+        let mut languages: HashMap<&'static str, usize> = HashMap::new();
+        let mut directories: HashMap<PathBuf, usize> = HashMap::new();
+        for file in files {
+            *languages
+                .entry(code::language_name(file).unwrap_or("Other"))
+                .or_default() += 1;
+            // Files of the root itself and those from outside it, such as archive members,
+            // have no directory to count towards
+            if let Ok(relative) = file.strip_prefix(root)
+                && let Some(Component::Normal(dir)) = relative.components().next()
+                && relative.components().nth(1).is_some()
+            {
+                *directories.entry(PathBuf::from(dir)).or_default() += 1;
+            }
+        }
+
+        Self {
+            files: files.len(),
+            chunks,
+            languages: largest(languages),
+            directories: largest(directories),
+            skipped: stats.counts().into_iter().collect(),
+            file_limit: stats.file_limit,
+        }
+    }
+
+    /// The summary as lines of text, one section after another.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![match self.chunks {
+            Some(chunks) => format!("{} files indexed in {} chunks", self.files, chunks),
+            None => format!("{} files crawled", self.files),
+        }];
+        if let Some(limit) = self.file_limit {
+            lines.push(format!(
+                "Crawl stopped at the limit of {} files; raise max_files or pass --max-files to see the rest",
+                limit
+            ));
+        }
+
+        if !self.languages.is_empty() {
+            lines.push(String::new());
+            lines.push(format!("Languages: {}", join_counts(&self.languages)));
+        }
+        if !self.directories.is_empty() {
+            let directories: Vec<(String, usize)> = self
+                .directories
+                .iter()
+                .map(|(dir, count)| (format!("{}/", dir.display()), *count))
+                .collect();
+            lines.push(format!(
+                "Largest directories: {}",
+                join_counts(&directories)
+            ));
+        }
+        if !self.skipped.is_empty() {
+            let skipped: Vec<(&str, usize)> = self
+                .skipped
+                .iter()
+                .map(|(reason, count)| (reason.label(), *count))
+                .collect();
+            let total: usize = skipped.iter().map(|(_, count)| count).sum();
+            lines.push(format!("Skipped {}: {}", total, join_counts(&skipped)));
+        }
+
+        lines
+    }
+}
+
+/// The entries with the most files, ties broken by name so the order is stable.
+fn largest<K: Ord>(counts: HashMap<K, usize>) -> Vec<(K, usize)> {
+    let mut counts: Vec<(K, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(TOP_ENTRIES);
+    counts
+}
+
+fn join_counts<K: std::fmt::Display>(counts: &[(K, usize)]) -> String {
+    counts
+        .iter()
+        .map(|(name, count)| format!("{} {}", name, count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -1,34 +1,546 @@
+use super::engine::Engine;
+use super::git::{BlameLine, DiffLine};
+use super::highlight::StyledLines;
+use super::notifications::Level;
+use crate::bookmarks::BookmarkEntry;
+use crate::crawler;
+use crate::storage::explain::Explanation;
+use crate::storage::filters::QueryFilters;
+use crate::throttle;
+use crate::types::{
+    Answer, AppState as AppStateEnum, Chunk, ChunkKind, LargeCorpus, QueryMode, SearchTiming,
+    UIMode,
+};
 use ratatui::{
     Frame,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, BorderType, Borders, List, ListItem, Paragraph, Wrap},
+    widgets::{Block, BorderType, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
 };
-use syntect::{easy::HighlightLines, highlighting::ThemeSet, parsing::SyntaxSet};
-
-use super::engine::Engine;
-use crate::types::{AppState as AppStateEnum, UIMode};
+use std::collections::HashMap;
+use std::time::Duration;
 
 const LAYOUT_SPLIT_PERCENTAGE: u16 = 30;
+const BLAME_AUTHOR_WIDTH: usize = 14;
+/// Longest query text shown in the tag of a result found by several queries
+const QUERY_TAG_WIDTH: usize = 16;
+/// Widest a toast gets, in columns including its border
+const TOAST_WIDTH: u16 = 60;
+/// Widest the explanation popup gets, in columns including its border
+const EXPLANATION_WIDTH: u16 = 90;
+/// Rows under the results and preview: the search input with its border, and the status bar
+const BOTTOM_ROWS: u16 = 4;
+/// Rows each entry of the results list takes
+const RESULT_ROWS: u16 = 3;
 
 pub struct UI;
 
 impl UI {
+    /// Results the list shows at once in a terminal `height` rows tall.
+    pub fn results_per_page(height: u16) -> usize {
+        (height.saturating_sub(BOTTOM_ROWS + 2) / RESULT_ROWS).max(1) as usize
+    }
+
+    /// File lines the preview shows at once in a terminal `height` rows tall.
+    pub fn preview_lines(height: u16) -> usize {
+        height.saturating_sub(BOTTOM_ROWS + 2) as usize
+    }
+
     pub fn render(f: &mut Frame, engine: &mut Engine) {
         let area = f.area();
         let background = Block::default().style(Style::default().bg(Color::Reset));
         f.render_widget(background, area);
+        engine.inline_images.begin_frame();
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(area);
+
+        match &engine.state {
+            AppStateEnum::Confirming(corpus) => {
+                Self::render_confirm_screen(f, chunks[0], engine, corpus);
+            }
+            AppStateEnum::Crawling
+            | AppStateEnum::DownloadingModel { .. }
+            | AppStateEnum::Chunking
+            | AppStateEnum::Ready
+            | AppStateEnum::Stopping => {
+                Self::render_main_interface(f, chunks[0], engine);
+            }
+        }
+        Self::render_status_bar(f, chunks[1], engine);
+        Self::render_toasts(f, chunks[0], engine);
+        if let Some(explanation) = &engine.result_explanation {
+            Self::render_explanation(f, chunks[0], engine, explanation);
+        }
+    }
+
+    /// How the selected result was found, in a box over the middle of the screen.
+    fn render_explanation(f: &mut Frame, area: Rect, engine: &Engine, explanation: &Explanation) {
+        let Some(result) = engine.search_results.get(engine.selected_search_result) else {
+            return;
+        };
+        let chunk = &result.chunk;
+        let label = Style::default().fg(Color::DarkGray);
+        let path = chunk
+            .file_path
+            .strip_prefix(&engine.root_path)
+            .unwrap_or(&chunk.file_path);
+
+        let mut lines = vec![
+            Line::from(format!(
+                "{}:{}-{}",
+                path.display(),
+                chunk.start_line,
+                chunk.end_line
+            )),
+            Line::from(vec![
+                Span::styled("Chunk ", label),
+                Span::raw(chunk.id.clone()),
+            ]),
+            Line::from(""),
+        ];
+
+        let lexical_weight = 1.0 - explanation.semantic_weight;
+        let answered = match explanation.mode {
+            QueryMode::Hybrid if explanation.sparse.is_some() => format!(
+                "{:.2} × similarity + {:.2} × keyword score + {:.2} × sparse score = {:.3}",
+                explanation.semantic_weight,
+                lexical_weight * explanation.keyword_weight,
+                lexical_weight * (1.0 - explanation.keyword_weight),
+                explanation.fused_score()
+            ),
+            QueryMode::Hybrid => format!(
+                "{:.2} × similarity + {:.2} × keyword score = {:.3}",
+                explanation.semantic_weight,
+                lexical_weight,
+                explanation.fused_score()
+            ),
+            QueryMode::Semantic => "nearest embeddings".to_string(),
+            QueryMode::Keyword => {
+                "BM25 over content, symbols, sections, paths and docs".to_string()
+            }
+        };
+        lines.push(Line::from(vec![
+            Span::styled(format!("{} search: ", explanation.mode.label()), label),
+            Span::raw(answered),
+        ]));
+        if let Some(reranked) = explanation.reranked {
+            lines.push(Line::from(vec![
+                Span::styled("Late interaction: ", label),
+                Span::raw(format!("{:.3} with token similarity blended in", reranked)),
+            ]));
+        }
+
+        if let Some(semantic) = &explanation.semantic {
+            let found = match semantic.rank {
+                Some(rank) => format!(
+                    "rank {} of {}, similarity {:.3} (at least {:.3} needed)",
+                    rank, explanation.candidates, semantic.similarity, semantic.min_similarity
+                ),
+                None => format!(
+                    "not among the {} nearest chunks; its text scores {:.3}",
+                    explanation.candidates, semantic.similarity
+                ),
+            };
+            lines.push(Line::from(vec![
+                Span::styled("Vector: ", label),
+                Span::raw(found),
+            ]));
+        }
 
-        match engine.state {
-            AppStateEnum::Crawling | AppStateEnum::Chunking | AppStateEnum::Ready => {
-                Self::render_main_interface(f, area, engine);
+        if matches!(explanation.mode, QueryMode::Keyword | QueryMode::Hybrid) {
+            match &explanation.keyword {
+                Some(keyword) => {
+                    let relative = if keyword.top_score > 0.0 {
+                        keyword.score / keyword.top_score
+                    } else {
+                        0.0
+                    };
+                    lines.push(Line::from(vec![
+                        Span::styled("Lexical: ", label),
+                        Span::raw(format!(
+                            "rank {} of {}, BM25 {:.2} ({:.2} of the best match)",
+                            keyword.rank, explanation.candidates, keyword.score, relative
+                        )),
+                    ]));
+                    for term in &keyword.terms {
+                        lines.push(Line::from(vec![
+                            Span::raw("  "),
+                            Span::styled(
+                                format!("{:<20}", term.term),
+                                Style::default().fg(Color::Yellow),
+                            ),
+                            Span::styled(format!("{:<8}", term.field), label),
+                            Span::raw(format!("{:.2}", term.weight)),
+                        ]));
+                    }
+                }
+                None => lines.push(Line::from(vec![
+                    Span::styled("Lexical: ", label),
+                    Span::raw(format!(
+                        "not among the {} keyword matches",
+                        explanation.candidates
+                    )),
+                ])),
             }
         }
+
+        if let Some(sparse) = &explanation.sparse {
+            let found = match sparse.rank {
+                Some(rank) => format!(
+                    "rank {} of {}, {:.2} of the best term-weight match",
+                    rank, explanation.candidates, sparse.score
+                ),
+                None => format!(
+                    "not among the {} learned-sparse matches",
+                    explanation.candidates
+                ),
+            };
+            lines.push(Line::from(vec![
+                Span::styled("Sparse: ", label),
+                Span::raw(found),
+            ]));
+        }
+
+        lines.push(Line::from(""));
+        match &explanation.file_index {
+            Some(file_index) => lines.push(Line::from(vec![
+                Span::styled("Indexed: ", label),
+                Span::raw(format!(
+                    "{} chunks, hash {}, file modified {} when indexed",
+                    file_index.chunk_count,
+                    file_index.hash.get(..12).unwrap_or(&file_index.hash),
+                    Self::format_age(file_index.modified)
+                )),
+            ])),
+            None => lines.push(Line::from(Span::styled(
+                "Not in the file index; it will be indexed on the next run",
+                label,
+            ))),
+        }
+        if let Some(indexed_at) = engine.indexed_at {
+            lines.push(Line::from(vec![
+                Span::styled("Last indexing run: ", label),
+                Span::raw(Self::format_age(indexed_at)),
+            ]));
+        }
+
+        let width = area.width.saturating_sub(4).min(EXPLANATION_WIDTH);
+        let height = (lines.len() as u16 + 2).min(area.height);
+        let popup = Rect::new(
+            area.x + (area.width.saturating_sub(width)) / 2,
+            area.y + (area.height.saturating_sub(height)) / 2,
+            width,
+            height,
+        );
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Cyan))
+            .title(" Why this result ")
+            .title_bottom(Line::from(" any key: close ").right_aligned())
+            .style(Style::default().bg(Color::Reset));
+
+        f.render_widget(Clear, popup);
+        f.render_widget(
+            Paragraph::new(lines)
+                .wrap(Wrap { trim: false })
+                .block(block),
+            popup,
+        );
+    }
+
+    /// One line along the bottom: the mode, which index the next search asks, filters in
+    /// effect, how fresh the index is, and as many of the mode's keys as fit.
+    fn render_status_bar(f: &mut Frame, area: Rect, engine: &Engine) {
+        let mut spans = vec![Span::styled(
+            format!(" {} ", Self::mode_label(engine)),
+            Style::default()
+                .fg(Color::Black)
+                .bg(Color::Red)
+                .add_modifier(Modifier::BOLD),
+        )];
+        if !engine.file_finder {
+            spans.push(Span::styled(
+                format!(" {} ", Self::search_mode(engine).label()),
+                Style::default().fg(Color::Cyan),
+            ));
+        }
+        let filters = Self::active_filters(engine);
+        if !filters.is_empty() {
+            spans.push(Span::styled(
+                format!(" {} ", filters.join(" ")),
+                Style::default().fg(Color::Yellow),
+            ));
+        }
+        spans.push(Span::styled(
+            format!(" {} ", Self::index_freshness(engine)),
+            Style::default().fg(Color::DarkGray),
+        ));
+
+        let used: usize = spans.iter().map(Span::width).sum();
+        let mut room = (area.width as usize).saturating_sub(used);
+        let mut hints = Vec::new();
+        for (key, action) in Self::key_hints(engine) {
+            let width = key.chars().count() + action.chars().count() + 3;
+            if width > room {
+                break;
+            }
+            room -= width;
+            hints.push(Span::styled(
+                format!(" {}", key),
+                Style::default().add_modifier(Modifier::BOLD),
+            ));
+            hints.push(Span::styled(
+                format!(" {} ", action),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        spans.push(Span::raw(" ".repeat(room)));
+        spans.extend(hints);
+
+        f.render_widget(Paragraph::new(Line::from(spans)), area);
+    }
+
+    fn mode_label(engine: &Engine) -> &'static str {
+        match engine.ui_mode {
+            UIMode::SearchInput if engine.file_finder => "FIND FILES",
+            UIMode::SearchInput => "SEARCH",
+            UIMode::SearchResults => "RESULTS",
+            UIMode::FilePreview => "PREVIEW",
+            UIMode::Bookmarks => "BOOKMARKS",
+            UIMode::Annotations => "TODOS",
+            UIMode::Notifications => "LOG",
+            UIMode::SkippedFiles => "SKIPPED",
+            UIMode::Projects => "PROJECTS",
+        }
+    }
+
+    /// The index that answered the last search, or that the query being typed would ask.
+    fn search_mode(engine: &Engine) -> QueryMode {
+        if let Some(mode) = engine.query_mode {
+            return mode;
+        }
+        if !engine.config.semantic.enabled || engine.keyword_only {
+            return QueryMode::Keyword;
+        }
+        QueryMode::parse(engine.search_input.value()).0
+    }
+
+    /// The directory scope, the filter terms of the last query and the results filter.
+    fn active_filters(engine: &Engine) -> Vec<String> {
+        let mut filters: Vec<String> = QueryFilters::terms(&engine.current_search_query)
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        if let Some(scope) = &engine.search_scope {
+            filters.push(format!(
+                "in:{}",
+                Self::get_display_path(scope, &engine.root_path)
+            ));
+        }
+        if !engine.results_filter.is_empty() {
+            filters.push(format!("/{}", engine.results_filter));
+        }
+        filters
+    }
+
+    fn index_freshness(engine: &Engine) -> String {
+        if engine.daemon.is_some() {
+            return "index kept fresh by daemon".to_string();
+        }
+        if engine.index_read_only {
+            return "index read-only".to_string();
+        }
+        match (&engine.state, engine.indexed_at) {
+            (AppStateEnum::Ready, Some(indexed_at)) => {
+                format!("indexed {}", Self::format_age(indexed_at))
+            }
+            (AppStateEnum::Stopping, _) => "stopping indexing".to_string(),
+            (AppStateEnum::Confirming(_), _) => "waiting to index".to_string(),
+            _ if engine.indexing_paused() => "indexing paused".to_string(),
+            (state, _) => {
+                let mut freshness = match throttle::current() {
+                    Some(reason) => format!("indexing, throttled ({})", reason.label()),
+                    None => "indexing".to_string(),
+                };
+                if matches!(state, AppStateEnum::Chunking)
+                    && let Some(progress) = &engine.index_progress
+                    && let Some(remaining) = progress.borrow().remaining()
+                {
+                    freshness.push_str(&format!(", {} left", Self::format_duration(remaining)));
+                }
+                freshness
+            }
+        }
+    }
+
+    fn pause_hint(engine: &Engine) -> (&'static str, &'static str) {
+        if engine.indexing_paused() {
+            ("p", "resume indexing")
+        } else {
+            ("p", "pause indexing")
+        }
+    }
+
+    /// Files done, throughput and time left of the indexing run, once chunks have been
+    /// committed to measure the rate by.
+    fn index_progress_line(engine: &Engine) -> Option<String> {
+        let progress = engine.index_progress.as_ref()?.borrow().clone();
+        let remaining = progress.remaining()?;
+        let rate = if engine.keyword_only {
+            "chunks indexed/s"
+        } else {
+            "chunks embedded/s"
+        };
+        Some(format!(
+            "{} / {} files · {:.1} {} · about {} left",
+            progress.files_chunked,
+            progress.files_total,
+            progress.chunks_per_second,
+            rate,
+            Self::format_duration(remaining)
+        ))
+    }
+
+    /// The keys worth knowing in the current mode, most useful first.
+    fn key_hints(engine: &Engine) -> Vec<(&'static str, &'static str)> {
+        if matches!(engine.state, AppStateEnum::Confirming(_)) {
+            if engine.exclude_input.is_some() {
+                return vec![("Enter", "crawl again"), ("Esc", "cancel")];
+            }
+            return vec![
+                ("y", "index"),
+                ("k", "keyword only"),
+                ("e", "exclude"),
+                ("n", "don't index"),
+            ];
+        }
+        if engine.file_preview_goto_input.is_some() {
+            return vec![("Enter", "jump"), ("Esc", "cancel")];
+        }
+        if engine.file_preview_find_input.is_some() {
+            return vec![("Enter", "find"), ("Esc", "cancel")];
+        }
+        if engine.results_filter_input.is_some() {
+            return vec![("Enter", "apply"), ("Esc", "cancel")];
+        }
+
+        match engine.ui_mode {
+            UIMode::SearchInput => {
+                let mut hints = vec![("Enter", "search")];
+                if !engine.search_results.is_empty() {
+                    hints.push(("Tab", "results"));
+                }
+                hints.extend([
+                    ("~ '", "semantic/keyword"),
+                    ("Ctrl+P", "files"),
+                    ("Ctrl+B", "bookmarks"),
+                    ("Ctrl+N", "log"),
+                    ("Ctrl+K", "skipped"),
+                    ("Ctrl+O", "projects"),
+                    ("Esc", "clear"),
+                ]);
+                hints
+            }
+            UIMode::SearchResults => {
+                let mut hints = vec![
+                    ("Enter", "open"),
+                    ("Tab", "preview"),
+                    ("/", "filter"),
+                    ("Space", "mark"),
+                    ("b", "bookmark"),
+                    ("l", "scope"),
+                    ("x", "explain"),
+                    ("y", "copy"),
+                ];
+                if engine.answer.is_some() {
+                    hints.insert(1, ("1-9", "source"));
+                }
+                if engine.can_pause_indexing() {
+                    hints.push(Self::pause_hint(engine));
+                }
+                hints.push(("Esc", "back"));
+                hints
+            }
+            UIMode::FilePreview => {
+                let mut hints = vec![
+                    ("Enter", "open"),
+                    ("/", "find"),
+                    (":", "line"),
+                    ("c", "chunk"),
+                    ("w", "wrap"),
+                    ("D", "diff"),
+                    ("B", "blame"),
+                ];
+                if engine.can_pause_indexing() {
+                    hints.push(Self::pause_hint(engine));
+                }
+                hints.push(("Esc", "back"));
+                hints
+            }
+            UIMode::Bookmarks => vec![("Enter", "open"), ("x", "remove"), ("Esc", "back")],
+            UIMode::Annotations => vec![("Enter", "open"), ("Esc", "back")],
+            UIMode::Notifications | UIMode::SkippedFiles => {
+                vec![("↑↓", "scroll"), ("Esc", "back")]
+            }
+            UIMode::Projects => vec![("Enter", "search"), ("Esc", "back")],
+        }
+    }
+
+    /// Recent warnings stacked in the top-right corner, over whatever is beneath.
+    fn render_toasts(f: &mut Frame, area: Rect, engine: &Engine) {
+        if matches!(engine.ui_mode, UIMode::Notifications) {
+            return;
+        }
+
+        let width = TOAST_WIDTH.min(area.width.saturating_sub(2));
+        let mut y = area.y + 1;
+        for notification in engine.notifications.toasts() {
+            if y + 3 > area.bottom() || width < 10 {
+                break;
+            }
+            let title = if notification.count > 1 {
+                format!(" {} ×{} ", notification.level.label(), notification.count)
+            } else {
+                format!(" {} ", notification.level.label())
+            };
+            let toast = Paragraph::new(Self::truncate_label(
+                &notification.message,
+                width.saturating_sub(4) as usize,
+            ))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(BorderType::Rounded)
+                    .border_style(Style::default().fg(Self::level_color(notification.level)))
+                    .title(title)
+                    .title_bottom(Line::from(" Ctrl+N: log ").right_aligned())
+                    .style(Style::default().bg(Color::Reset)),
+            );
+
+            let toast_area = Rect::new(area.right() - width - 1, y, width, 3);
+            f.render_widget(Clear, toast_area);
+            f.render_widget(toast, toast_area);
+            y += 3;
+        }
     }
 
     fn render_main_interface(f: &mut Frame, area: Rect, engine: &mut Engine) {
-        if !engine.search_results.is_empty() && matches!(engine.state, AppStateEnum::Ready) {
+        if matches!(engine.ui_mode, UIMode::Bookmarks) {
+            Self::render_bookmarks_screen(f, area, engine);
+        } else if matches!(engine.ui_mode, UIMode::Annotations) {
+            Self::render_annotations_screen(f, area, engine);
+        } else if matches!(engine.ui_mode, UIMode::Notifications) {
+            Self::render_notifications_screen(f, area, engine);
+        } else if matches!(engine.ui_mode, UIMode::SkippedFiles) {
+            Self::render_skipped_files_screen(f, area, engine);
+        } else if matches!(engine.ui_mode, UIMode::Projects) {
+            Self::render_projects_screen(f, area, engine);
+        } else if !engine.search_results.is_empty() && engine.can_search() {
             Self::render_search_interface(f, area, engine);
         } else {
             Self::render_status_screen(f, area, engine);
@@ -37,7 +549,12 @@ impl UI {
 
     fn render_search_interface(f: &mut Frame, area: Rect, engine: &mut Engine) {
         match engine.ui_mode {
-            UIMode::SearchInput => {
+            UIMode::SearchInput
+            | UIMode::Bookmarks
+            | UIMode::Annotations
+            | UIMode::Notifications
+            | UIMode::SkippedFiles
+            | UIMode::Projects => {
                 Self::render_status_screen(f, area, engine);
             }
             UIMode::SearchResults | UIMode::FilePreview => {
@@ -46,45 +563,551 @@ impl UI {
         }
     }
 
-    fn render_status_screen(f: &mut Frame, area: Rect, engine: &mut Engine) {
+    fn render_status_screen(f: &mut Frame, area: Rect, engine: &mut Engine) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(3)])
+            .split(area);
+
+        let (title, message) = Self::get_status_message(
+            &engine.state,
+            engine.spinner_frame,
+            engine.search_input.value(),
+        );
+
+        let status_block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Blue))
+            .title(title)
+            .title_style(
+                Style::default()
+                    .fg(Color::Reset)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .style(Style::default().bg(Color::Reset));
+
+        let mut lines = vec![Line::from("")];
+        for line in message.lines() {
+            lines.push(Line::from(vec![Span::styled(
+                line,
+                Style::default().fg(Color::DarkGray),
+            )]));
+        }
+        if matches!(engine.state, AppStateEnum::Chunking) {
+            let progress = if engine.indexing_paused() {
+                Some("Paused; press p to resume".to_string())
+            } else {
+                Self::index_progress_line(engine)
+            };
+            if let Some(progress) = progress {
+                lines.push(Line::from(""));
+                lines.push(Line::from(Span::styled(
+                    progress,
+                    Style::default().fg(Color::Reset),
+                )));
+            }
+        }
+        // The corpus summary replaces the blank screen until the first search
+        if let Some(summary) = &engine.corpus_summary
+            && matches!(engine.state, AppStateEnum::Ready)
+            && engine.search_results.is_empty()
+            && engine.current_search_query.is_empty()
+        {
+            lines.push(Line::from(""));
+            for line in summary.lines() {
+                lines.push(Line::from(Span::styled(
+                    line,
+                    Style::default().fg(Color::Reset),
+                )));
+            }
+        }
+
+        let status_para = Paragraph::new(lines)
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true })
+            .block(status_block);
+
+        f.render_widget(status_para, chunks[0]);
+
+        Self::render_search_input(f, chunks[1], engine);
+    }
+
+    /// Counts for a crawl too large to index without asking, and the ways to go on.
+    fn render_confirm_screen(f: &mut Frame, area: Rect, engine: &Engine, corpus: &LargeCorpus) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(3)])
+            .split(area);
+        let label = Style::default().fg(Color::DarkGray);
+        let key = Style::default().fg(Color::Yellow);
+
+        let found = match corpus.file_limit {
+            Some(limit) => format!(
+                "The crawl stopped at max_files after {} files ({}); there are more.",
+                limit,
+                Self::format_size(corpus.bytes)
+            ),
+            None => format!(
+                "{} files to index ({}).",
+                corpus.files,
+                Self::format_size(corpus.bytes)
+            ),
+        };
+        let estimate = match corpus.embedding_estimate {
+            Some(estimate) => format!(
+                "Embedding them takes about {}.",
+                Self::format_duration(estimate)
+            ),
+            None => "Only the keyword index is built, which is quick.".to_string(),
+        };
+        let mut lines = vec![
+            Line::from(""),
+            Line::from(engine.root_path.display().to_string()),
+            Line::from(found),
+            Line::from(Span::styled(estimate, label)),
+            Line::from(""),
+        ];
+        for (keys, action) in [
+            ("y / Enter", "index all of it"),
+            ("k", "build only the keyword index this time"),
+            ("e", "exclude more patterns and crawl again"),
+            ("n / Esc", "don't index; search the existing index"),
+        ] {
+            lines.push(Line::from(vec![
+                Span::styled(format!("{:>10}  ", keys), key),
+                Span::raw(format!("{:<40}", action)),
+            ]));
+        }
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Yellow))
+            .title(" Large project ")
+            .title_style(
+                Style::default()
+                    .fg(Color::Reset)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .style(Style::default().bg(Color::Reset));
+        f.render_widget(
+            Paragraph::new(lines)
+                .alignment(Alignment::Center)
+                .wrap(Wrap { trim: false })
+                .block(block),
+            chunks[0],
+        );
+
+        let input = match &engine.exclude_input {
+            Some(input) => format!("{}█", input),
+            None => String::new(),
+        };
+        let input_block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::DarkGray))
+            .title(" Exclude (comma-separated .gitignore patterns) ");
+        f.render_widget(Paragraph::new(input).block(input_block), chunks[1]);
+    }
+
+    /// "2 h 10 min", "14 min" or "under a minute".
+    fn format_duration(duration: Duration) -> String {
+        let minutes = duration.as_secs() / 60;
+        match minutes {
+            0 => "under a minute".to_string(),
+            1..60 => format!("{} min", minutes),
+            _ => format!("{} h {} min", minutes / 60, minutes % 60),
+        }
+    }
+
+    fn render_bookmarks_screen(f: &mut Frame, area: Rect, engine: &mut Engine) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(3)])
+            .split(area);
+
+        let entries = engine
+            .bookmarks
+            .as_ref()
+            .map(|b| b.entries())
+            .unwrap_or_default();
+
+        let bookmarks_block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Red))
+            .title(format!(" Bookmarks ({}) ", entries.len()))
+            .title_style(
+                Style::default()
+                    .fg(Color::Reset)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .style(Style::default().bg(Color::Reset));
+
+        if entries.is_empty() {
+            let empty_para = Paragraph::new(
+                "No bookmarks yet\nPress 'b' on a result to bookmark it, or Ctrl+S to save a query",
+            )
+            .alignment(Alignment::Center)
+            .style(Style::default().fg(Color::DarkGray))
+            .block(bookmarks_block);
+            f.render_widget(empty_para, chunks[0]);
+            Self::render_search_input(f, chunks[1], engine);
+            return;
+        }
+
+        let items: Vec<ListItem> = entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let is_selected = i == engine.selected_bookmark;
+                let name_style = if is_selected {
+                    Style::default()
+                        .bg(Color::Blue)
+                        .fg(Color::White)
+                        .add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().add_modifier(Modifier::BOLD)
+                };
+
+                let line = match entry {
+                    BookmarkEntry::Query(saved) => Line::from(vec![
+                        Span::styled("? ", Style::default().fg(Color::Yellow)),
+                        Span::styled(saved.name.clone(), name_style),
+                    ]),
+                    BookmarkEntry::Result(bookmark) => Line::from(vec![
+                        Span::styled("★ ", Style::default().fg(Color::Yellow)),
+                        Span::styled(
+                            Self::get_display_path(&bookmark.file_path, &engine.root_path),
+                            name_style,
+                        ),
+                        Span::styled(
+                            format!(" L{}-{}", bookmark.start_line, bookmark.end_line),
+                            Style::default().fg(Color::DarkGray),
+                        ),
+                        Span::styled(
+                            format!("  {}", bookmark.query),
+                            Style::default().fg(Color::DarkGray),
+                        ),
+                    ]),
+                };
+
+                ListItem::new(line)
+            })
+            .collect();
+
+        let mut list_state = ListState::default().with_selected(Some(engine.selected_bookmark));
+        f.render_stateful_widget(
+            List::new(items).block(bookmarks_block),
+            chunks[0],
+            &mut list_state,
+        );
+        Self::render_search_input(f, chunks[1], engine);
+    }
+
+    /// TODO, FIXME and HACK comments under a header for each file, with who left them and
+    /// when once blame has loaded.
+    fn render_annotations_screen(f: &mut Frame, area: Rect, engine: &mut Engine) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(3)])
+            .split(area);
+
+        let annotations_block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Yellow))
+            .title(format!(" TODOs ({}) ", engine.annotations.len()))
+            .title_style(
+                Style::default()
+                    .fg(Color::Reset)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .style(Style::default().bg(Color::Reset));
+
+        if engine.annotations.is_empty() {
+            let empty_para = Paragraph::new("No TODO, FIXME or HACK comments in the index")
+                .alignment(Alignment::Center)
+                .style(Style::default().fg(Color::DarkGray))
+                .block(annotations_block);
+            f.render_widget(empty_para, chunks[0]);
+            Self::render_search_input(f, chunks[1], engine);
+            return;
+        }
+
+        let mut items = Vec::new();
+        let mut selected_row = 0;
+        let mut current_file = None;
+        for (i, annotation) in engine.annotations.iter().enumerate() {
+            if current_file != Some(&annotation.file_path) {
+                current_file = Some(&annotation.file_path);
+                items.push(ListItem::new(Line::from(Span::styled(
+                    Self::get_display_path(&annotation.file_path, &engine.root_path),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ))));
+            }
+            if i == engine.selected_annotation {
+                selected_row = items.len();
+            }
+
+            let tag_color = match annotation.tag.as_str() {
+                "FIXME" => Color::Red,
+                "HACK" => Color::Magenta,
+                _ => Color::Yellow,
+            };
+            let text_style = if i == engine.selected_annotation {
+                Style::default().bg(Color::Blue).fg(Color::White)
+            } else {
+                Style::default()
+            };
+            let blame = engine
+                .annotation_blame
+                .get(&annotation.file_path)
+                .and_then(|blame| blame.get(&(annotation.line - 1)))
+                .map(|blame| format!("  {}, {}", blame.author, Self::format_age(blame.time)))
+                .unwrap_or_default();
+
+            items.push(ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!("  {:>5} ", annotation.line),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::styled(
+                    format!("{:<5} ", annotation.tag),
+                    Style::default().fg(tag_color),
+                ),
+                Span::styled(annotation.text.clone(), text_style),
+                Span::styled(blame, Style::default().fg(Color::DarkGray)),
+            ])));
+        }
+
+        let mut list_state = ListState::default().with_selected(Some(selected_row));
+        f.render_stateful_widget(
+            List::new(items).block(annotations_block),
+            chunks[0],
+            &mut list_state,
+        );
+        Self::render_search_input(f, chunks[1], engine);
+    }
+
+    /// Every notification this session, newest first, with how long ago it last arrived.
+    fn render_notifications_screen(f: &mut Frame, area: Rect, engine: &mut Engine) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(3)])
+            .split(area);
+
+        let notifications_block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Yellow))
+            .title(format!(" Log ({}) ", engine.notifications.len()))
+            .title_style(
+                Style::default()
+                    .fg(Color::Reset)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .style(Style::default().bg(Color::Reset));
+
+        if engine.notifications.is_empty() {
+            let empty_para = Paragraph::new("No notifications so far")
+                .alignment(Alignment::Center)
+                .style(Style::default().fg(Color::DarkGray))
+                .block(notifications_block);
+            f.render_widget(empty_para, chunks[0]);
+            Self::render_search_input(f, chunks[1], engine);
+            return;
+        }
+
+        let items: Vec<ListItem> = engine
+            .notifications
+            .history()
+            .iter()
+            .enumerate()
+            .map(|(i, notification)| {
+                let message_style = if i == engine.selected_notification {
+                    Style::default().bg(Color::Blue).fg(Color::White)
+                } else {
+                    Style::default()
+                };
+                let count = if notification.count > 1 {
+                    format!(" ×{}", notification.count)
+                } else {
+                    String::new()
+                };
+
+                ListItem::new(Line::from(vec![
+                    Span::styled(
+                        format!("{:>9} ", Self::format_age(notification.time)),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                    Span::styled(
+                        format!("{:<8}", notification.level.label()),
+                        Style::default().fg(Self::level_color(notification.level)),
+                    ),
+                    Span::styled(notification.message.clone(), message_style),
+                    Span::styled(count, Style::default().fg(Color::Yellow)),
+                ]))
+            })
+            .collect();
+
+        let mut list_state = ListState::default().with_selected(Some(engine.selected_notification));
+        f.render_stateful_widget(
+            List::new(items).block(notifications_block),
+            chunks[0],
+            &mut list_state,
+        );
+        Self::render_search_input(f, chunks[1], engine);
+    }
+
+    fn render_skipped_files_screen(f: &mut Frame, area: Rect, engine: &mut Engine) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(3)])
+            .split(area);
+
+        let skipped_block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Yellow))
+            .title(format!(" Skipped files ({}) ", engine.skipped_files.len()))
+            .title_style(
+                Style::default()
+                    .fg(Color::Reset)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .style(Style::default().bg(Color::Reset));
+
+        if engine.skipped_files.is_empty() {
+            let message = if engine.corpus_summary.is_some() {
+                "No files were skipped"
+            } else {
+                "Skipped files are listed once the crawl finishes"
+            };
+            let empty_para = Paragraph::new(message)
+                .alignment(Alignment::Center)
+                .style(Style::default().fg(Color::DarkGray))
+                .block(skipped_block);
+            f.render_widget(empty_para, chunks[0]);
+            Self::render_search_input(f, chunks[1], engine);
+            return;
+        }
+
+        let items: Vec<ListItem> = engine
+            .skipped_files
+            .iter()
+            .enumerate()
+            .map(|(i, file)| {
+                let path_style = if i == engine.selected_skipped_file {
+                    Style::default().bg(Color::Blue).fg(Color::White)
+                } else {
+                    Style::default()
+                };
+                let path = file
+                    .path
+                    .strip_prefix(&engine.root_path)
+                    .unwrap_or(&file.path);
+                let mut spans = vec![
+                    Span::styled(
+                        format!("{:<22}", file.reason.label()),
+                        Style::default().fg(Color::Yellow),
+                    ),
+                    Span::styled(path.display().to_string(), path_style),
+                ];
+                if let Some(detail) = &file.detail {
+                    spans.push(Span::styled(
+                        format!("  {}", detail),
+                        Style::default().fg(Color::DarkGray),
+                    ));
+                }
+                ListItem::new(Line::from(spans))
+            })
+            .collect();
+
+        let mut list_state = ListState::default().with_selected(Some(engine.selected_skipped_file));
+        f.render_stateful_widget(
+            List::new(items).block(skipped_block),
+            chunks[0],
+            &mut list_state,
+        );
+        Self::render_search_input(f, chunks[1], engine);
+    }
+
+    /// Every project first, then each indexed project with its root; the one searched is
+    /// marked.
+    fn render_projects_screen(f: &mut Frame, area: Rect, engine: &mut Engine) {
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints([Constraint::Min(1), Constraint::Length(3)])
             .split(area);
 
-        let (title, message) = Self::get_status_message(
-            &engine.state,
-            engine.spinner_frame,
-            engine.search_input.value(),
-        );
+        let projects = engine.projects.projects();
+        let searched = engine.search_scope.as_deref().unwrap_or(&engine.root_path);
+        let name_width = projects
+            .iter()
+            .map(|project| project.name.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max("All projects".len());
+
+        let mut entries = vec![(
+            engine.all_projects,
+            format!("{:<name_width$}", "All projects"),
+            format!("{} indexed", projects.len()),
+        )];
+        for project in &projects {
+            let mut detail = project.root.display().to_string();
+            if project.root == engine.root_path {
+                detail.push_str("  (this project)");
+            }
+            entries.push((
+                !engine.all_projects && project.root == searched,
+                format!("{:<name_width$}", project.name),
+                detail,
+            ));
+        }
 
-        let status_block = Block::default()
+        let items: Vec<ListItem> = entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, (searched, name, detail))| {
+                let name_style = if i == engine.selected_project {
+                    Style::default().bg(Color::Blue).fg(Color::White)
+                } else {
+                    Style::default()
+                };
+                ListItem::new(Line::from(vec![
+                    Span::styled(
+                        if searched { "● " } else { "  " },
+                        Style::default().fg(Color::Green),
+                    ),
+                    Span::styled(name, name_style),
+                    Span::styled(
+                        format!("  {}", detail),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                ]))
+            })
+            .collect();
+
+        let projects_block = Block::default()
             .borders(Borders::ALL)
             .border_type(BorderType::Rounded)
-            .border_style(Style::default().fg(Color::Blue))
-            .title(title)
+            .border_style(Style::default().fg(Color::Cyan))
+            .title(" Search in ")
             .title_style(
                 Style::default()
                     .fg(Color::Reset)
                     .add_modifier(Modifier::BOLD),
             )
             .style(Style::default().bg(Color::Reset));
-
-        let mut lines = vec![Line::from("")];
-        for line in message.lines() {
-            lines.push(Line::from(vec![Span::styled(
-                line,
-                Style::default().fg(Color::DarkGray),
-            )]));
-        }
-
-        let status_para = Paragraph::new(lines)
-            .alignment(Alignment::Center)
-            .wrap(Wrap { trim: true })
-            .block(status_block);
-
-        f.render_widget(status_para, chunks[0]);
-
+        let mut list_state = ListState::default().with_selected(Some(engine.selected_project));
+        f.render_stateful_widget(
+            List::new(items).block(projects_block),
+            chunks[0],
+            &mut list_state,
+        );
         Self::render_search_input(f, chunks[1], engine);
     }
 
@@ -103,15 +1126,174 @@ impl UI {
             .split(main_chunks[0]);
 
         Self::render_search_results(f, chunks[0], engine);
-        Self::render_file_preview(f, chunks[1], engine);
+        // The answer to a question takes the preview's place until a source is opened
+        match &engine.answer {
+            Some(answer) if matches!(engine.ui_mode, UIMode::SearchResults) => {
+                Self::render_answer(f, chunks[1], engine, answer)
+            }
+            _ => Self::render_file_preview(f, chunks[1], engine),
+        }
         Self::render_search_input(f, main_chunks[1], engine);
     }
 
+    /// The answer as far as it has streamed in, followed by the sources it cites. The end
+    /// is kept in view while it grows.
+    fn render_answer(f: &mut Frame, area: Rect, engine: &Engine, answer: &Answer) {
+        let title = if answer.error.is_some() {
+            " Answer (failed) ".to_string()
+        } else if answer.finished {
+            " Answer ".to_string()
+        } else {
+            format!(
+                " {} Answering... ",
+                Self::get_spinner_char(engine.spinner_frame)
+            )
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Black))
+            .title(title)
+            .title_style(
+                Style::default()
+                    .fg(Color::Reset)
+                    .add_modifier(Modifier::BOLD),
+            );
+
+        let label = Style::default().fg(Color::DarkGray);
+        let mut lines = vec![Line::from(Span::styled(answer.question.clone(), label))];
+        if answer.query != answer.question {
+            lines.push(Line::from(Span::styled(
+                format!("Searched for: {}", answer.query),
+                label,
+            )));
+        }
+        lines.push(Line::from(""));
+        let marker = Style::default().fg(Color::Yellow);
+        lines.extend(
+            answer
+                .text
+                .lines()
+                .map(|line| Self::cited_line(line, marker)),
+        );
+        if let Some(error) = &answer.error {
+            lines.push(Line::from(Span::styled(
+                error.clone(),
+                Style::default().fg(Color::Red),
+            )));
+        }
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(
+            "Sources (press the number to open)",
+            label,
+        )));
+        let citations = crate::ask::citations(&answer.text);
+        for (index, source) in answer.sources.iter().enumerate() {
+            let number = index + 1;
+            let citing: Vec<&crate::ask::Citation> = citations
+                .iter()
+                .filter(|citation| citation.sources.contains(&number))
+                .collect();
+            let cited = match citing.len() {
+                0 if answer.finished => " not cited".to_string(),
+                0 => String::new(),
+                count => format!(" cited {}×", count),
+            };
+            lines.push(Line::from(vec![
+                Span::styled(format!("[{}] ", number), marker),
+                Span::raw(crate::ask::source_label(source, &engine.root_path)),
+                Span::styled(cited, label),
+            ]));
+            if let Some(first) = citing.first() {
+                lines.push(Line::from(Span::styled(
+                    format!("    \"{}\"", first.sentence),
+                    label,
+                )));
+            }
+        }
+
+        // Rows the lines take once wrapped, to scroll the end into view
+        let width = area.width.saturating_sub(2).max(1) as usize;
+        let rows: usize = lines
+            .iter()
+            .map(|line| line.width().div_ceil(width).max(1))
+            .sum();
+        let scroll = rows.saturating_sub(area.height.saturating_sub(2) as usize);
+
+        let answer_para = Paragraph::new(lines)
+            .block(block)
+            .wrap(Wrap { trim: false })
+            .scroll((scroll.min(u16::MAX as usize) as u16, 0));
+        f.render_widget(answer_para, area);
+    }
+
+    /// A line of the answer with its `[n]` citation markers picked out.
+    fn cited_line(line: &str, marker: Style) -> Line<'static> {
+        let mut spans = Vec::new();
+        let mut rest = line;
+        while let Some(start) = rest.find('[') {
+            let Some(length) = rest[start..].find(']').map(|end| end + 1) else {
+                break;
+            };
+            let inner = &rest[start + 1..start + length - 1];
+            let is_marker = !inner.is_empty()
+                && inner
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || c == ',' || c == ' ');
+            if !is_marker {
+                spans.push(Span::raw(rest[..start + 1].to_string()));
+                rest = &rest[start + 1..];
+                continue;
+            }
+            if start > 0 {
+                spans.push(Span::raw(rest[..start].to_string()));
+            }
+            spans.push(Span::styled(
+                rest[start..start + length].to_string(),
+                marker,
+            ));
+            rest = &rest[start + length..];
+        }
+        if !rest.is_empty() {
+            spans.push(Span::raw(rest.to_string()));
+        }
+        Line::from(spans)
+    }
+
     fn render_search_results(f: &mut Frame, area: Rect, engine: &mut Engine) {
         let is_focused = matches!(engine.ui_mode, UIMode::SearchResults);
         let border_color = if is_focused { Color::Red } else { Color::Black };
 
-        let title = format!(" Search Results ({}) ", engine.search_results.len());
+        let count = if engine.results_filter.is_empty() {
+            engine.search_results.len().to_string()
+        } else {
+            format!(
+                "{} of {}",
+                engine.search_results.len(),
+                engine.unfiltered_results.len()
+            )
+        };
+        let mut title = if engine.marked_results.is_empty() {
+            format!(" Search Results ({}) ", count)
+        } else {
+            format!(
+                " Search Results ({}, {} marked) ",
+                count,
+                engine.marked_results.len()
+            )
+        };
+        if let Some(scope) = &engine.search_scope {
+            let scope = Self::get_display_path(scope, &engine.root_path);
+            let scope = if scope.is_empty() { "." } else { &scope };
+            title.push_str(&format!("[in {}/] ", scope));
+        } else if engine.all_projects {
+            title.push_str("[all projects] ");
+        }
+        if let Some(ref filter_input) = engine.results_filter_input {
+            title.push_str(&format!("/{} ", filter_input));
+        } else if !engine.results_filter.is_empty() {
+            title.push_str(&format!("[filter: {}] ", engine.results_filter));
+        }
 
         let results_block = Block::default()
             .borders(Borders::ALL)
@@ -135,7 +1317,7 @@ impl UI {
         }
 
         let visible_height = area.height.saturating_sub(2) as usize;
-        let results_per_page = (visible_height / 3).max(1);
+        let results_per_page = (visible_height / RESULT_ROWS as usize).max(1);
         let start_index = engine.search_results_scroll_offset;
         let end_index = (start_index + results_per_page).min(engine.search_results.len());
 
@@ -145,9 +1327,16 @@ impl UI {
             .map(|(i, result)| {
                 let actual_index = start_index + i;
                 let is_selected = actual_index == engine.selected_search_result;
-
-                let file_display_path =
-                    Self::get_display_path(&result.chunk.file_path, &engine.root_path);
+                let is_marked = engine.marked_results.contains(&actual_index);
+
+                let file_display_path = match engine.result_project(&result.chunk.file_path) {
+                    Some(project) => format!(
+                        "[{}] {}",
+                        project.name,
+                        Self::get_display_path(&result.chunk.file_path, &project.root)
+                    ),
+                    None => Self::get_display_path(&result.chunk.file_path, &engine.root_path),
+                };
 
                 let (results_count, line_range) = if result.total_matches_in_file > 1 {
                     (
@@ -162,10 +1351,16 @@ impl UI {
                 };
 
                 let available_width = area.width.saturating_sub(4) as usize;
-                let results_count_len = results_count.len();
+                let relevance = Self::format_relevance(result.score);
+                let results_count_len = if results_count.is_empty() {
+                    0
+                } else {
+                    results_count.len() + 1
+                };
+                let relevance_len = relevance.chars().count();
                 let line_range_len = line_range.len();
-                let middle_padding =
-                    available_width.saturating_sub(results_count_len + line_range_len);
+                let middle_padding = available_width
+                    .saturating_sub(results_count_len + relevance_len + line_range_len);
 
                 let filename_style = if is_selected {
                     Style::default()
@@ -176,24 +1371,63 @@ impl UI {
                     Style::default().add_modifier(Modifier::BOLD)
                 };
 
-                let info_line = if !results_count.is_empty() {
-                    Line::from(vec![
-                        Span::styled(results_count, Style::default().fg(Color::Yellow)),
-                        Span::styled(" ".repeat(middle_padding), Style::default()),
-                        Span::styled(line_range, Style::default().fg(Color::DarkGray)),
-                    ])
-                } else {
-                    Line::from(vec![
-                        Span::styled(" ".repeat(middle_padding), Style::default()),
-                        Span::styled(line_range, Style::default().fg(Color::DarkGray)),
-                    ])
-                };
+                let mut info_spans = Vec::new();
+                if !results_count.is_empty() {
+                    info_spans.push(Span::styled(
+                        format!("{} ", results_count),
+                        Style::default().fg(Color::Yellow),
+                    ));
+                }
+                info_spans.push(Span::styled(
+                    relevance,
+                    Style::default().fg(Self::relevance_color(result.score)),
+                ));
+                let partial_marker = if result.chunk.partial { "partial " } else { "" };
+                let mut padding = middle_padding.saturating_sub(partial_marker.len());
+                if let Some(section) = Self::chunk_label(&result.chunk) {
+                    let section_width = padding.saturating_sub(2);
+                    let mut label: String = section.chars().take(section_width).collect();
+                    if section.chars().count() > section_width && section_width > 0 {
+                        label.pop();
+                        label.push('…');
+                    }
+                    if !label.is_empty() {
+                        padding -= label.chars().count() + 1;
+                        info_spans.push(Span::styled(
+                            format!(" {}", label),
+                            Style::default().fg(Color::Cyan),
+                        ));
+                    }
+                }
+                info_spans.push(Span::styled(" ".repeat(padding), Style::default()));
+                info_spans.push(Span::styled(
+                    partial_marker,
+                    Style::default().fg(Color::Magenta),
+                ));
+                info_spans.push(Span::styled(
+                    line_range,
+                    Style::default().fg(Color::DarkGray),
+                ));
+                let info_line = Line::from(info_spans);
+
+                let mut path_spans = Vec::new();
+                if is_marked {
+                    path_spans.push(Span::styled("● ", Style::default().fg(Color::Green)));
+                }
+                path_spans.push(Span::styled(file_display_path.to_string(), filename_style));
+                for query in result
+                    .matched_queries
+                    .iter()
+                    .filter_map(|index| engine.search_queries.get(*index))
+                {
+                    path_spans.push(Span::styled(
+                        format!(" [{}]", Self::truncate_label(query, QUERY_TAG_WIDTH)),
+                        Style::default().fg(Color::Magenta),
+                    ));
+                }
 
                 ListItem::new(vec![
-                    Line::from(vec![Span::styled(
-                        file_display_path.to_string(),
-                        filename_style,
-                    )]),
+                    Line::from(path_spans),
                     info_line,
                     Line::from(vec![Span::styled(
                         "─".repeat(available_width),
@@ -210,7 +1444,7 @@ impl UI {
         f.render_widget(list, area);
     }
 
-    fn render_file_preview(f: &mut Frame, area: Rect, engine: &Engine) {
+    fn render_file_preview(f: &mut Frame, area: Rect, engine: &mut Engine) {
         let is_focused = matches!(engine.ui_mode, UIMode::FilePreview);
         let border_color = if is_focused { Color::Red } else { Color::Black };
 
@@ -218,24 +1452,73 @@ impl UI {
             let file_display_path =
                 Self::get_display_path(&selected_result.chunk.file_path, &engine.root_path);
 
-            let content_to_display = if let Some(ref current_content) = engine.current_file_content
-            {
-                if let Some(ref current_path) = engine.current_file_path {
-                    if current_path == &selected_result.chunk.file_path {
-                        current_content.as_str()
-                    } else {
-                        "Loading file..."
-                    }
-                } else {
-                    "Loading file..."
-                }
+            let is_current_file =
+                engine.current_file_path.as_ref() == Some(&selected_result.chunk.file_path);
+            let content_to_display = match engine.current_file_content {
+                Some(ref current_content) if is_current_file => current_content.as_ref(),
+                _ => "Loading file...",
+            };
+
+            let chunk_only = engine.file_preview_chunk_only && is_current_file;
+            let (scroll_offset, visible_lines) = if chunk_only {
+                // Only the matched chunk, padded with context lines from the cached file
+                let chunk = &selected_result.chunk;
+                let start = chunk
+                    .start_line
+                    .saturating_sub(1)
+                    .saturating_sub(engine.file_preview_context_lines);
+                let end = chunk.end_line + engine.file_preview_context_lines;
+                (start, end.saturating_sub(start))
             } else {
-                "Loading file..."
+                (
+                    engine.file_preview_scroll_offset,
+                    area.height.saturating_sub(2) as usize,
+                )
             };
 
-            let title = format!(" {} ", file_display_path);
+            let diff = engine
+                .current_file_diff
+                .as_deref()
+                .filter(|_| is_current_file);
+
+            let mut title = format!(" {} ", file_display_path);
+            let blame = engine.file_preview_blame && is_current_file && diff.is_none();
+            if blame {
+                title.push_str("[blame] ");
+            }
+            if diff.is_some() {
+                title.push_str("[diff vs HEAD] ");
+            } else if chunk_only {
+                title.push_str(&format!(
+                    "[chunk ±{} lines] ",
+                    engine.file_preview_context_lines
+                ));
+            } else if is_current_file {
+                let total_lines = engine.current_file_line_count().max(1);
+                let top_line = (engine.file_preview_scroll_offset + 1).min(total_lines);
+                let bottom_line =
+                    (engine.file_preview_scroll_offset + visible_lines).min(total_lines);
+                title.push_str(&format!(
+                    "[{}/{} {}%] ",
+                    top_line,
+                    total_lines,
+                    bottom_line * 100 / total_lines
+                ));
+            }
+            if !engine.file_preview_wrap {
+                title.push_str(&format!(
+                    "[no wrap, col {}] ",
+                    engine.file_preview_horizontal_offset + 1
+                ));
+            }
+            if let Some(ref goto_input) = engine.file_preview_goto_input {
+                title.push_str(&format!(":{} ", goto_input));
+            }
+            if let Some(ref find_input) = engine.file_preview_find_input {
+                title.push_str(&format!("/{} ", find_input));
+            }
 
-            let preview_block = Block::default()
+            let mut preview_block = Block::default()
                 .borders(Borders::ALL)
                 .border_type(BorderType::Rounded)
                 .border_style(Style::default().fg(border_color))
@@ -246,20 +1529,81 @@ impl UI {
                         .add_modifier(Modifier::BOLD),
                 )
                 .style(Style::default().bg(Color::Reset));
+            if is_current_file {
+                preview_block = preview_block.title_bottom(Line::from(Span::styled(
+                    Self::file_summary(engine, &selected_result.chunk.file_path),
+                    Style::default().fg(Color::DarkGray),
+                )));
+            }
 
-            let content_lines: Vec<Line> = Self::highlight_code_content(
-                content_to_display,
-                &selected_result.chunk.file_path,
-                engine.file_preview_scroll_offset,
-                area.height.saturating_sub(2) as usize,
-                &engine.current_search_query,
-            );
+            let highlighted = engine
+                .current_file_hash
+                .filter(|_| is_current_file)
+                .and_then(|hash| {
+                    engine
+                        .highlighter
+                        .get(&selected_result.chunk.file_path, hash)
+                });
+
+            let content_lines: Vec<Line> = match diff {
+                Some(diff) => Self::diff_lines(
+                    diff,
+                    engine.file_preview_scroll_offset,
+                    area.height.saturating_sub(2) as usize,
+                ),
+                None => Self::highlight_code_content(
+                    content_to_display,
+                    highlighted.as_ref(),
+                    scroll_offset,
+                    engine.file_preview_horizontal_offset,
+                    visible_lines,
+                    &engine.current_search_query,
+                    engine.file_preview_find_pattern.as_deref(),
+                    if is_current_file {
+                        engine.relevant_lines.as_slice()
+                    } else {
+                        &[]
+                    },
+                ),
+            };
+
+            let content_lines = if blame {
+                Self::with_blame_gutter(content_lines, scroll_offset, &engine.current_file_blame)
+            } else {
+                content_lines
+            };
 
-            let preview_para = Paragraph::new(content_lines)
-                .block(preview_block)
-                .wrap(Wrap { trim: false });
+            let image = engine
+                .current_file_image
+                .as_ref()
+                .filter(|image| {
+                    // The explanation popup would be drawn over
+                    is_current_file
+                        && diff.is_none()
+                        && engine.result_explanation.is_none()
+                        && engine.inline_images.supports(image)
+                })
+                .cloned();
+            let inner = preview_block.inner(area);
+
+            let mut preview_para = Paragraph::new(content_lines).block(preview_block);
+            if engine.file_preview_wrap {
+                preview_para = preview_para.wrap(Wrap { trim: false });
+            }
 
             f.render_widget(preview_para, area);
+
+            if let Some(image) = image {
+                // Left to the terminal, which draws the image over them after the frame
+                for y in inner.top()..inner.bottom() {
+                    for x in inner.left()..inner.right() {
+                        if let Some(cell) = f.buffer_mut().cell_mut((x, y)) {
+                            cell.set_skip(true);
+                        }
+                    }
+                }
+                engine.inline_images.request(&image, inner);
+            }
         } else {
             let empty_block = Block::default()
                 .borders(Borders::ALL)
@@ -284,10 +1628,13 @@ impl UI {
 
     fn highlight_code_content(
         content: &str,
-        file_path: &std::path::Path,
+        highlighted: Option<&StyledLines>,
         scroll_offset: usize,
+        horizontal_offset: usize,
         visible_lines: usize,
         search_query: &str,
+        find_pattern: Option<&str>,
+        relevant_lines: &[usize],
     ) -> Vec<Line<'static>> {
         if content.is_empty() {
             return vec![Line::from(vec![Span::styled(
@@ -296,144 +1643,138 @@ impl UI {
             )])];
         }
 
-        let syntax_set = SyntaxSet::load_defaults_newlines();
-        let theme_set = ThemeSet::load_defaults();
-
-        let extension = file_path
-            .extension()
-            .and_then(|ext| ext.to_str())
-            .unwrap_or("");
-
-        let syntax = match syntax_set.find_syntax_by_extension(extension) {
-            Some(s) => s,
-            None => match syntax_set.find_syntax_by_first_line(content) {
-                Some(s) => s,
-                None => syntax_set.find_syntax_plain_text(),
-            },
-        };
-
-        let theme = &theme_set.themes["base16-ocean.dark"];
-        let mut highlighter = HighlightLines::new(syntax, theme);
-
-        let is_semantic_search = !search_query.trim().starts_with('\'');
-
-        let search_terms: Vec<&str> = if is_semantic_search {
-            Vec::new()
-        } else {
-            let query = search_query
-                .trim()
-                .strip_prefix('\'')
-                .unwrap_or(search_query);
-            query
+        let search_terms: Vec<&str> = match QueryMode::parse(search_query) {
+            (QueryMode::Keyword, query) => query
                 .split_whitespace()
-                .filter(|term| !term.is_empty())
-                .collect()
+                .filter(|term| !term.is_empty() && !term.starts_with('-'))
+                .collect(),
+            _ => Vec::new(),
         };
 
-        let total_lines = content.lines().count();
+        let lines: Vec<&str> = content.lines().collect();
+        let total_lines = lines.len();
         let safe_scroll_offset = scroll_offset.min(total_lines.saturating_sub(1));
 
         let line_number_width = (total_lines + safe_scroll_offset).to_string().len().max(3);
 
-        let mut result: Vec<Line> = Vec::new();
-        let lines: Vec<&str> = content.lines().collect();
-
         let start = safe_scroll_offset;
-        let end = (start + visible_lines).min(lines.len());
+        let end = (start + visible_lines).min(total_lines);
 
-        for line_index in start..end {
-            let line = lines[line_index];
+        let mut result: Vec<Line> = Vec::new();
+        for (line_index, line) in lines.iter().enumerate().take(end).skip(start) {
             let line_number = line_index + 1;
             let line_num_str = format!("{:>width$} │ ", line_number, width = line_number_width);
 
-            if is_semantic_search {
-                match highlighter.highlight_line(line, &syntax_set) {
-                    Ok(ranges) => {
-                        let mut spans: Vec<Span> = vec![Span::styled(
-                            line_num_str.clone(),
-                            Style::default().fg(Color::DarkGray),
-                        )];
-
-                        for (style, text) in ranges.iter() {
-                            let fg_color = Color::Rgb(
-                                style.foreground.r,
-                                style.foreground.g,
-                                style.foreground.b,
-                            );
-                            spans.push(Span::styled(
-                                text.to_string(),
-                                Style::default().fg(fg_color),
-                            ));
-                        }
-
-                        result.push(Line::from(spans));
-                    }
-                    Err(_) => {
-                        let spans = vec![
-                            Span::styled(
-                                line_num_str.clone(),
-                                Style::default().fg(Color::DarkGray),
-                            ),
-                            Span::styled(line.to_string(), Style::default()),
-                        ];
-                        result.push(Line::from(spans));
-                    }
-                }
+            // Plain text is shown until the background highlighter has finished the file
+            let content_spans = match highlighted.and_then(|styled| styled.get(line_index)) {
+                Some(spans) => spans.clone(),
+                None => vec![Span::raw(line.to_string())],
+            };
+            let content_spans = if search_terms.is_empty() {
+                content_spans
             } else {
-                match highlighter.highlight_line(line, &syntax_set) {
-                    Ok(ranges) => {
-                        let mut spans: Vec<Span> = vec![Span::styled(
-                            line_num_str.clone(),
-                            Style::default().fg(Color::DarkGray),
-                        )];
-
-                        for (style, text) in ranges.iter() {
-                            let fg_color = Color::Rgb(
-                                style.foreground.r,
-                                style.foreground.g,
-                                style.foreground.b,
-                            );
-                            spans.push(Span::styled(
-                                text.to_string(),
-                                Style::default().fg(fg_color),
-                            ));
-                        }
-
-                        if !search_terms.is_empty() {
-                            let line_num_span = spans[0].clone();
-                            let content_spans = spans[1..].to_vec();
-                            let highlighted_content =
-                                Self::highlight_search_terms(content_spans, &search_terms);
-                            let mut final_spans = vec![line_num_span];
-                            final_spans.extend(highlighted_content);
-                            result.push(Line::from(final_spans));
-                        } else {
-                            result.push(Line::from(spans));
-                        }
-                    }
-                    Err(_) => {
-                        let spans = vec![
-                            Span::styled(
-                                line_num_str.clone(),
-                                Style::default().fg(Color::DarkGray),
-                            ),
-                            Span::styled(line.to_string(), Style::default()),
-                        ];
-
-                        if !search_terms.is_empty() {
-                            let line_num_span = spans[0].clone();
-                            let content_spans = spans[1..].to_vec();
-                            let highlighted_content =
-                                Self::highlight_search_terms(content_spans, &search_terms);
-                            let mut final_spans = vec![line_num_span];
-                            final_spans.extend(highlighted_content);
-                            result.push(Line::from(final_spans));
-                        } else {
-                            result.push(Line::from(spans));
-                        }
-                    }
+                Self::highlight_search_terms(content_spans, &search_terms, Self::match_style())
+            };
+            let content_spans = match find_pattern {
+                Some(pattern) => {
+                    Self::highlight_search_terms(content_spans, &[pattern], Self::find_style())
+                }
+                None => content_spans,
+            };
+            let mut content_spans = Self::skip_columns(content_spans, horizontal_offset);
+
+            let mut line_num_style = Style::default().fg(Color::DarkGray);
+            if relevant_lines.contains(&line_index) {
+                // Tint the lines that contributed most to the semantic match
+                line_num_style = Style::default().fg(Color::Green);
+                for span in &mut content_spans {
+                    span.style = span.style.bg(Color::Rgb(40, 48, 72));
                 }
             }
+
+            let mut spans = vec![Span::styled(line_num_str, line_num_style)];
+            spans.extend(content_spans);
+            result.push(Line::from(spans));
+        }
+
+        result
+    }
+
+    /// Prefixes each line with its author and age; lines still being blamed get a blank gutter.
+    fn with_blame_gutter(
+        lines: Vec<Line<'static>>,
+        first_line: usize,
+        blame: &HashMap<usize, BlameLine>,
+    ) -> Vec<Line<'static>> {
+        lines
+            .into_iter()
+            .enumerate()
+            .map(|(offset, mut line)| {
+                let gutter = match blame.get(&(first_line + offset)) {
+                    Some(blame) => format!(
+                        "{:<BLAME_AUTHOR_WIDTH$.BLAME_AUTHOR_WIDTH$} {:>7} ",
+                        blame.author,
+                        Self::format_age(blame.time)
+                    ),
+                    None => " ".repeat(BLAME_AUTHOR_WIDTH + 9),
+                };
+                line.spans.insert(
+                    0,
+                    Span::styled(gutter, Style::default().fg(Color::DarkGray)),
+                );
+                line
+            })
+            .collect()
+    }
+
+    fn diff_lines(
+        diff: &[DiffLine],
+        scroll_offset: usize,
+        visible_lines: usize,
+    ) -> Vec<Line<'static>> {
+        if diff.is_empty() {
+            return vec![Line::from(Span::styled(
+                "No changes against HEAD",
+                Style::default().fg(Color::DarkGray),
+            ))];
+        }
+
+        diff.iter()
+            .skip(scroll_offset)
+            .take(visible_lines)
+            .map(|line| {
+                let (prefix, text, color) = match line {
+                    DiffLine::Hunk(text) => ("", text, Color::Cyan),
+                    DiffLine::Context(text) => (" ", text, Color::Reset),
+                    DiffLine::Added(text) => ("+", text, Color::Green),
+                    DiffLine::Removed(text) => ("-", text, Color::Red),
+                };
+                Line::from(Span::styled(
+                    format!("{}{}", prefix, text),
+                    Style::default().fg(color),
+                ))
+            })
+            .collect()
+    }
+
+    /// Drops the first `columns` characters of a line, keeping the styling of the rest.
+    fn skip_columns(spans: Vec<Span<'static>>, columns: usize) -> Vec<Span<'static>> {
+        if columns == 0 {
+            return spans;
+        }
+
+        let mut remaining = columns;
+        let mut result = Vec::new();
+        for span in spans {
+            let char_count = span.content.chars().count();
+            if remaining >= char_count {
+                remaining -= char_count;
+                continue;
+            }
+
+            let visible: String = span.content.chars().skip(remaining).collect();
+            remaining = 0;
+            result.push(Span::styled(visible, span.style));
         }
 
         result
@@ -443,17 +1784,37 @@ impl UI {
         let is_focused = matches!(engine.ui_mode, UIMode::SearchInput);
         let border_color = if is_focused { Color::Red } else { Color::Black };
 
-        let mut title = " Search ".to_string();
+        let label = if engine.file_finder {
+            "Find Files"
+        } else {
+            "Search"
+        };
+        let mut label = match engine.query_mode {
+            Some(mode) if !engine.file_finder => format!("{} [{}]", label, mode.label()),
+            _ => label.to_string(),
+        };
+        if engine.index_read_only {
+            label.push_str(" (read-only, another sema is indexing)");
+        } else if !matches!(engine.state, AppStateEnum::Ready) {
+            label.push_str(" (indexing...)");
+        }
+        let mut title = format!(" {} ", label);
         if let Some(ref error) = engine.search_error {
-            title = format!(" Search - {} ", error);
+            title = format!(" {} - {} ", label, error);
+        } else if let Some(ref timing) = engine.search_timing {
+            title = format!(
+                " {} - {} ",
+                label,
+                Self::search_summary(engine.search_results.len(), timing)
+            );
         } else if !engine.search_results.is_empty()
             && !engine.search_input.value().trim().is_empty()
             && matches!(engine.ui_mode, UIMode::SearchInput)
         {
-            title = format!(" Search - {} results ", engine.search_results.len());
+            title = format!(" {} - {} results ", label, engine.search_results.len());
         }
 
-        let search_block = Block::default()
+        let mut search_block = Block::default()
             .borders(Borders::ALL)
             .border_type(BorderType::Rounded)
             .border_style(Style::default().fg(border_color))
@@ -464,11 +1825,27 @@ impl UI {
                     .add_modifier(Modifier::BOLD),
             )
             .style(Style::default().bg(Color::Reset));
+        if let Some(ref suggestion) = engine.query_suggestion {
+            search_block = search_block.title_bottom(Line::from(vec![
+                Span::styled(" Did you mean ", Style::default().fg(Color::DarkGray)),
+                Span::styled(suggestion.clone(), Style::default().fg(Color::Yellow)),
+                Span::styled("? (Tab) ", Style::default().fg(Color::DarkGray)),
+            ]));
+        }
 
         let width = area.width.max(3) - 3;
         let scroll = engine.search_input.visual_scroll(width as usize);
 
-        let input_widget = Paragraph::new(engine.search_input.value())
+        let mut input_spans = vec![Span::raw(engine.search_input.value())];
+        if let Some(ref completion) = engine.completion
+            && is_focused
+        {
+            input_spans.push(Span::styled(
+                completion.as_str(),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        let input_widget = Paragraph::new(Line::from(input_spans))
             .scroll((0, scroll as u16))
             .block(search_block);
 
@@ -493,11 +1870,35 @@ impl UI {
                     "Discovering files in the directory.\nYou can type your search query now.",
                 )
             }
+            AppStateEnum::DownloadingModel { downloaded, total } => {
+                let spinner = Self::get_spinner_char(spinner_frame);
+                const MIB: f64 = 1024.0 * 1024.0;
+                (
+                    format!(
+                        " {} Downloading model ({:.1} / {:.1} MB)... ",
+                        spinner,
+                        *downloaded as f64 / MIB,
+                        *total as f64 / MIB
+                    ),
+                    "Fetching the embedding model on first run.\nLater runs load it from the cache.",
+                )
+            }
             AppStateEnum::Chunking => {
                 let spinner = Self::get_spinner_char(spinner_frame);
                 (
                     format!(" {} Processing files... ", spinner),
-                    "Breaking files into searchable chunks.\nAlmost ready for search!",
+                    "Indexing new and changed files.\nSearches use the existing index meanwhile.",
+                )
+            }
+            AppStateEnum::Confirming(_) => (
+                " Large project ".to_string(),
+                "Choose how to index the project.",
+            ),
+            AppStateEnum::Stopping => {
+                let spinner = Self::get_spinner_char(spinner_frame);
+                (
+                    format!(" {} Stopping... ", spinner),
+                    "Saving indexing progress.\nThe next run will resume from here.",
                 )
             }
             AppStateEnum::Ready => {
@@ -516,28 +1917,194 @@ impl UI {
         }
     }
 
+    /// "N results in X ms", with the time of each index the search asked.
+    fn search_summary(count: usize, timing: &SearchTiming) -> String {
+        let mut summary = format!(
+            "{} {} in {} ms",
+            count,
+            if count == 1 { "result" } else { "results" },
+            timing.total.as_millis()
+        );
+        let stages: Vec<String> = [("lexical", timing.lexical), ("vector", timing.vector)]
+            .into_iter()
+            .filter_map(|(stage, elapsed)| Some(format!("{}: {} ms", stage, elapsed?.as_millis())))
+            .collect();
+        if !stages.is_empty() {
+            summary.push_str(&format!(" ({})", stages.join(", ")));
+        }
+        summary
+    }
+
+    /// One-line summary of the previewed file: size, age, language, chunks and matches.
+    fn file_summary(engine: &Engine, file_path: &std::path::Path) -> String {
+        let content = engine.current_file_content.as_deref().unwrap_or("");
+        let mut parts = Vec::new();
+
+        if let Some(info) = &engine.current_file_info {
+            parts.push(Self::format_size(info.size));
+            if info.modified > 0 {
+                parts.push(Self::format_age(info.modified));
+            }
+        }
+        parts.push(
+            engine
+                .highlighter
+                .language_name(file_path, content)
+                .to_string(),
+        );
+        if let Some(info) = &engine.current_file_info {
+            parts.push(format!("{} chunks", info.chunk_count));
+        }
+
+        let matches = engine
+            .search_results
+            .iter()
+            .filter(|result| result.chunk.file_path == file_path)
+            .map(|result| result.total_matches_in_file)
+            .sum::<usize>();
+        parts.push(format!(
+            "{} {}",
+            matches,
+            if matches == 1 { "match" } else { "matches" }
+        ));
+
+        format!(" {} ", parts.join(" · "))
+    }
+
+    /// Where a result sits: its section in structured documents, otherwise the symbol it
+    /// belongs to, noting chunks that are mostly comments, docs or strings.
+    fn chunk_label(chunk: &Chunk) -> Option<String> {
+        if let Some(section) = &chunk.section {
+            return Some(section.clone());
+        }
+        let symbol = chunk.symbol.as_ref()?;
+        Some(match chunk.kind {
+            Some(kind) if kind != ChunkKind::Code => format!("{} ({})", symbol, kind.label()),
+            _ => symbol.clone(),
+        })
+    }
+
+    fn format_size(bytes: u64) -> String {
+        const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+        let mut size = bytes as f64;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < UNITS.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+        if unit == 0 {
+            format!("{} B", bytes)
+        } else {
+            format!("{:.1} {}", size, UNITS[unit])
+        }
+    }
+
+    fn truncate_label(label: &str, width: usize) -> String {
+        if label.chars().count() <= width {
+            return label.to_string();
+        }
+        let mut truncated: String = label.chars().take(width.saturating_sub(1)).collect();
+        truncated.push('…');
+        truncated
+    }
+
+    /// How long ago a time in seconds since the Unix epoch was, e.g. `3d ago`; `never`
+    /// for 0, which stands for no time recorded.
+    pub fn format_age(time: u64) -> String {
+        if time == 0 {
+            return "never".to_string();
+        }
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let seconds = now.saturating_sub(time);
+        match seconds {
+            0..60 => "just now".to_string(),
+            60..3600 => format!("{}m ago", seconds / 60),
+            3600..86400 => format!("{}h ago", seconds / 3600),
+            86400..2592000 => format!("{}d ago", seconds / 86400),
+            2592000..31536000 => format!("{}mo ago", seconds / 2592000),
+            _ => format!("{}y ago", seconds / 31536000),
+        }
+    }
+
+    fn level_color(level: Level) -> Color {
+        match level {
+            Level::Info => Color::Green,
+            Level::Warning => Color::Yellow,
+        }
+    }
+
+    fn format_relevance(score: f32) -> String {
+        const BAR_WIDTH: usize = 5;
+        let score = score.clamp(0.0, 1.0);
+        let filled = (score * BAR_WIDTH as f32).round() as usize;
+        format!(
+            "{}{} {:>3}%",
+            "▰".repeat(filled),
+            "▱".repeat(BAR_WIDTH - filled),
+            (score * 100.0).round() as u32
+        )
+    }
+
+    fn relevance_color(score: f32) -> Color {
+        if score >= 0.66 {
+            Color::Green
+        } else if score >= 0.33 {
+            Color::Yellow
+        } else {
+            Color::DarkGray
+        }
+    }
+
     fn get_spinner_char(frame: usize) -> char {
         const SPINNER_CHARS: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧'];
         SPINNER_CHARS[frame % SPINNER_CHARS.len()]
     }
 
-    fn highlight_search_terms(spans: Vec<Span>, search_terms: &[&str]) -> Vec<Span<'static>> {
+    fn match_style() -> Style {
+        Style::default()
+            .bg(Color::Yellow)
+            .fg(Color::Black)
+            .add_modifier(Modifier::BOLD)
+    }
+
+    fn find_style() -> Style {
+        Style::default()
+            .bg(Color::Cyan)
+            .fg(Color::Black)
+            .add_modifier(Modifier::BOLD)
+    }
+
+    fn highlight_search_terms(
+        spans: Vec<Span>,
+        search_terms: &[&str],
+        match_style: Style,
+    ) -> Vec<Span<'static>> {
+        // Matched on the text itself, since lowercasing can change a character's length
+        let patterns: Vec<regex::Regex> = search_terms
+            .iter()
+            .filter(|term| !term.is_empty())
+            .filter_map(|term| {
+                regex::RegexBuilder::new(&regex::escape(term))
+                    .case_insensitive(true)
+                    .build()
+                    .ok()
+            })
+            .collect();
         let mut result = Vec::new();
 
         for span in spans {
             let text = span.content.to_string();
             let style = span.style;
-            let text_lower = text.to_lowercase();
 
             let mut matches = Vec::new();
-            for term in search_terms {
-                let term_lower = term.to_lowercase();
-                let mut pos = 0;
-                while let Some(idx) = text_lower[pos..].find(&term_lower) {
-                    let start = pos + idx;
-                    matches.push((start, start + term.len()));
-                    pos = start + 1;
-                }
+            for pattern in &patterns {
+                matches.extend(
+                    pattern
+                        .find_iter(&text)
+                        .map(|found| (found.start(), found.end())),
+                );
             }
 
             if matches.is_empty() {
@@ -570,13 +2137,7 @@ impl UI {
                 if start > pos {
                     result.push(Span::styled(text[pos..start].to_string(), style));
                 }
-                result.push(Span::styled(
-                    text[start..end].to_string(),
-                    Style::default()
-                        .bg(Color::Yellow)
-                        .fg(Color::Black)
-                        .add_modifier(Modifier::BOLD),
-                ));
+                result.push(Span::styled(text[start..end].to_string(), match_style));
                 pos = end;
             }
             if pos < text.len() {
@@ -588,6 +2149,8 @@ impl UI {
     }
 
     fn get_display_path(file_path: &std::path::Path, base_dir: &std::path::Path) -> String {
+        let file_path =
+            &crawler::virtual_path(file_path, base_dir).unwrap_or_else(|| file_path.to_path_buf());
         if let Ok(relative) = file_path.strip_prefix(base_dir) {
             relative.to_string_lossy().to_string()
         } else {
@@ -1,13 +1,32 @@
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::{ChunkStrategy, OversizedFiles, normalize_extension};
+use crate::storage::tokens::TokenBudget;
 
 #[derive(Debug, Clone)]
 pub struct CrawlerConfig {
     pub max_file_size: u64,
     pub follow_symlinks: bool,
+    pub max_symlink_depth: usize,
     pub include_hidden: bool,
     pub file_extensions: Vec<String>,
     pub exclude_patterns: Vec<String>,
     pub ignore_gitignore: bool,
+    pub oversized_files: OversizedFiles,
+    pub index_archives: bool,
+    pub index_images: bool,
+    /// Deepest directory level crawled; 0 for no limit
+    pub max_depth: usize,
+    /// Files after which the crawl stops; 0 for no limit. Only the interface sets it,
+    /// since it can ask before indexing the rest; other crawls would silently index part
+    /// of the project
+    pub max_files: usize,
+    /// Files indexed alongside the crawled ones, such as captured stdin
+    pub extra_files: Vec<PathBuf>,
 }
 
 impl From<&crate::config::GeneralConfig> for CrawlerConfig {
@@ -15,19 +34,96 @@ impl From<&crate::config::GeneralConfig> for CrawlerConfig {
         Self {
             max_file_size: config.max_file_size,
             follow_symlinks: config.follow_symlinks,
+            max_symlink_depth: config.max_symlink_depth,
             include_hidden: config.include_hidden,
             file_extensions: config.file_extensions.clone(),
             exclude_patterns: config.exclude_patterns.clone(),
             ignore_gitignore: config.ignore_gitignore,
+            oversized_files: config.oversized_files,
+            index_archives: config.index_archives,
+            index_images: config.index_images,
+            max_depth: config.max_depth,
+            max_files: 0,
+            extra_files: Vec::new(),
         }
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct ProcessingConfig {
+    pub max_file_size: u64,
+    /// Files a `.sema.toml` gives a size limit other than `max_file_size`, with that limit
+    pub size_limits: HashMap<PathBuf, u64>,
+    pub oversized_files: OversizedFiles,
+    pub oversized_index_bytes: u64,
+    /// Chunker overrides keyed by lowercase extension
+    pub chunk_strategies: HashMap<String, ChunkStrategy>,
+    /// Splits chunks the embedding model would cut short; set once the model is loaded
+    pub token_budget: Option<Arc<TokenBudget>>,
+}
+
+impl ProcessingConfig {
+    /// The size above which `file_path` is oversized.
+    pub fn max_file_size(&self, file_path: &Path) -> u64 {
+        self.size_limits
+            .get(file_path)
+            .copied()
+            .unwrap_or(self.max_file_size)
+    }
+
+    pub fn chunk_strategy(&self, file_path: &Path) -> Option<ChunkStrategy> {
+        let extension = file_path.extension()?.to_str()?;
+        self.chunk_strategies
+            .get(&normalize_extension(extension))
+            .copied()
+    }
+}
+
+impl From<&crate::config::Config> for ProcessingConfig {
+    fn from(config: &crate::config::Config) -> Self {
+        let chunk_strategies = config
+            .languages
+            .iter()
+            .filter_map(|(extension, language)| {
+                Some((normalize_extension(extension), language.chunker?))
+            })
+            .collect();
+
+        Self {
+            max_file_size: config.general.max_file_size,
+            size_limits: HashMap::new(),
+            oversized_files: config.general.oversized_files,
+            oversized_index_bytes: config.general.oversized_index_kib * 1024,
+            chunk_strategies,
+            token_budget: None,
+        }
+    }
+}
+
+/// A crawl too large to index without asking first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LargeCorpus {
+    pub files: usize,
+    pub bytes: u64,
+    /// Set to `max_files` when the crawl stopped there
+    pub file_limit: Option<usize>,
+    /// Rough time to embed the files; None when embeddings are off
+    pub embedding_estimate: Option<Duration>,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum AppState {
     Crawling,
+    /// Waiting for the user to decide how to index a large crawl
+    Confirming(LargeCorpus),
+    /// Fetching the embedding model on first use; bytes received and expected
+    DownloadingModel {
+        downloaded: u64,
+        total: u64,
+    },
     Chunking,
     Ready,
+    Stopping,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -35,21 +131,139 @@ pub enum UIMode {
     SearchInput,
     SearchResults,
     FilePreview,
+    Bookmarks,
+    Annotations,
+    Notifications,
+    SkippedFiles,
+    Projects,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Chunk {
     pub id: String,
     pub file_path: PathBuf,
     pub start_line: usize,
     pub end_line: usize,
     pub content: String,
+    /// Set when the chunk comes from a file that was only partially indexed
+    pub partial: bool,
+    /// Where the chunk sits in a structured document, e.g. a heading path like "Install > Linux"
+    pub section: Option<String>,
+    /// Language of the file, e.g. "Rust", when its extension is recognised
+    pub language: Option<String>,
+    /// Function, type or module the chunk belongs to in source files
+    pub symbol: Option<String>,
+    /// What most of the chunk's lines are; None for files of an unrecognised language
+    pub kind: Option<ChunkKind>,
+    /// Text of the chunk's comments and docstrings without their markers, searched as a
+    /// channel of its own
+    pub docs: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+/// What a chunk mostly consists of, filtered on with `kind:`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChunkKind {
+    Code,
+    Comment,
+    /// Doc comments and docstrings, and the whole of prose files such as Markdown
+    Doc,
+    String,
+}
+
+impl ChunkKind {
+    pub const ALL: [ChunkKind; 4] = [Self::Code, Self::Comment, Self::Doc, Self::String];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Code => "code",
+            Self::Comment => "comment",
+            Self::Doc => "doc",
+            Self::String => "string",
+        }
+    }
+
+    pub fn parse(label: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.label().eq_ignore_ascii_case(label))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileIndex {
     pub file_path: PathBuf,
     pub hash: String,
+    pub size: u64,
+    /// Modification time in seconds since the Unix epoch
+    pub modified: u64,
+    pub chunk_count: usize,
+}
+
+/// Which index answers a query, chosen by its prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum QueryMode {
+    /// No prefix: nearest embeddings merged with keyword matches
+    Hybrid,
+    /// `~query`: nearest embeddings only
+    Semantic,
+    /// `'terms`: keyword matches only
+    Keyword,
+}
+
+impl QueryMode {
+    /// Splits the routing prefix off a query.
+    pub fn parse(query: &str) -> (Self, &str) {
+        let query = query.trim_start();
+        if let Some(rest) = query.strip_prefix('\'') {
+            (Self::Keyword, rest)
+        } else if let Some(rest) = query.strip_prefix('~') {
+            (Self::Semantic, rest)
+        } else {
+            (Self::Hybrid, query)
+        }
+    }
+
+    /// The prefix routing a query to this mode, the inverse of `parse`.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Self::Hybrid => "",
+            Self::Semantic => "~",
+            Self::Keyword => "'",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Hybrid => "hybrid",
+            Self::Semantic => "semantic",
+            Self::Keyword => "keyword",
+        }
+    }
+}
+
+/// Where the time of a search went. A stage is None when the search didn't use it.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct SearchTiming {
+    pub total: Duration,
+    /// Keyword index lookups
+    pub lexical: Option<Duration>,
+    /// Query embedding and vector store lookups
+    pub vector: Option<Duration>,
+}
+
+impl SearchTiming {
+    /// Adds up the time of searches run one after another, such as several queries at once.
+    pub fn add(&mut self, other: &SearchTiming) {
+        self.total += other.total;
+        for (stage, other) in [
+            (&mut self.lexical, other.lexical),
+            (&mut self.vector, other.vector),
+        ] {
+            if let Some(other) = other {
+                *stage.get_or_insert_default() += other;
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -57,4 +271,20 @@ pub struct SearchResult {
     pub chunk: Chunk,
     pub score: f32,
     pub total_matches_in_file: usize,
+    /// Which of several queries searched together found this result, by position
+    pub matched_queries: Vec<usize>,
+}
+
+/// An answer to a `?` question, written by the `[llm]` model from the best matches.
+#[derive(Debug, Clone, Default)]
+pub struct Answer {
+    pub question: String,
+    /// What was searched for: the question, or a follow-up rewritten to stand on its own
+    pub query: String,
+    /// As much of the answer as has streamed in
+    pub text: String,
+    /// The chunks the model was given, cited as [1], [2] and so on
+    pub sources: Vec<Chunk>,
+    pub finished: bool,
+    pub error: Option<String>,
 }